@@ -103,18 +103,100 @@ pub mod v1 {
         Skip,
     }
 
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum DuplicateDownloadActionV1 {
+        #[default]
+        Warn,
+        Skip,
+        Redownload,
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum LiveStreamFormatV1 {
+        #[default]
+        Flv,
+        Hls,
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum OutputContainerV1 {
+        #[default]
+        Mp4,
+        Mkv,
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum AudioLibraryFormatV1 {
+        #[default]
+        Original,
+        Mp3,
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum HistoryDanmakuModeV1 {
+        #[default]
+        Off,
+        DateRange,
+        All,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum SubtitleOutputFormatV1 {
+        Srt,
+        Ass,
+        Vtt,
+        Lrc,
+        Json,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum SkipSegmentCategoryV1 {
+        Sponsor,
+        Intro,
+        Outro,
+        Selfpromo,
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    #[allow(clippy::struct_excessive_bools)]
+    pub struct DanmakuFilterV1 {
+        pub keywords: Vec<String>,
+        pub regexes: Vec<String>,
+        pub blocked_mid_hashes: Vec<String>,
+        pub min_weight: u32,
+        pub block_top: bool,
+        pub block_bottom: bool,
+        pub block_scroll: bool,
+        pub block_colored: bool,
+        pub dedup_window_s: f64,
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct DanmakuAnalysisConfigV1 {
+        pub keywords: Vec<String>,
+        pub window_s: u64,
+        pub threshold: f64,
+        pub max_highlights: usize,
+    }
+
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     #[serde(default)]
     pub struct CanvasConfigV1 {
         pub duration: f64,
+        pub fixed_duration: f64,
         pub width: u32,
         pub height: u32,
+        pub auto_resize: bool,
         pub font: String,
         pub font_size: u32,
         pub width_ratio: f64,
         pub horizontal_gap: f64,
         pub lane_size: u32,
         pub float_percentage: f64,
+        pub top_percentage: f64,
+        pub bottom_percentage: f64,
         pub alpha: f64,
         pub bold: bool,
         pub outline: f64,
@@ -137,10 +219,24 @@ pub mod v1 {
         pub auto_merge: bool,
         pub embed_chapter: bool,
         pub embed_skip: bool,
+        pub embed_highlight: bool,
         pub download_xml_danmaku: bool,
         pub download_ass_danmaku: bool,
         pub download_json_danmaku: bool,
+        pub download_vtt_danmaku: bool,
+        pub download_srt_danmaku: bool,
+        pub download_csv_danmaku: bool,
+        pub download_danmaku_heatmap: bool,
+        pub history_danmaku_mode: HistoryDanmakuModeV1,
+        pub history_danmaku_start_date: String,
+        pub history_danmaku_end_date: String,
+        pub history_danmaku_interval_sec: u64,
         pub download_subtitle: bool,
+        pub subtitle_formats: Vec<SubtitleOutputFormatV1>,
+        pub subtitle_languages: Vec<String>,
+        pub exclude_ai_subtitle: bool,
+        pub bilingual_subtitle_primary: String,
+        pub bilingual_subtitle_secondary: String,
         pub download_cover: bool,
         pub download_nfo: bool,
         pub download_json: bool,
@@ -154,9 +250,27 @@ pub mod v1 {
         pub task_download_interval_sec: u64,
         pub chunk_concurrency: usize,
         pub chunk_download_interval_sec: u64,
+        pub speed_limit_kb_per_sec: u64,
         pub danmaku_config: CanvasConfigV1,
+        pub danmaku_filter: DanmakuFilterV1,
+        pub danmaku_analysis: DanmakuAnalysisConfigV1,
         pub file_exist_action: FileExistActionV1,
         pub auto_start_download_task: bool,
+        pub remote_api_enabled: bool,
+        pub remote_api_bind_address: String,
+        pub remote_api_token: String,
+        pub remote_api_allowed_origins: Vec<String>,
+        pub subscription_check_interval_min: u64,
+        pub mirror_sync_interval_min: u64,
+        pub duplicate_download_action: DuplicateDownloadActionV1,
+        pub live_stream_format: LiveStreamFormatV1,
+        pub live_split_size_mb: u64,
+        pub live_split_duration_min: u64,
+        pub output_container: OutputContainerV1,
+        pub audio_library_mode: bool,
+        pub audio_library_format: AudioLibraryFormatV1,
+        pub cut_skip_categories: Vec<SkipSegmentCategoryV1>,
+        pub skip_segment_server: String,
     }
 
     #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -233,6 +347,18 @@ pub mod v1 {
         pub start: u64,
         pub end: u64,
         pub completed: bool,
+        #[serde(default)]
+        pub segment_index: usize,
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct MediaMirrorV1 {
+        pub url: String,
+        pub success_count: u64,
+        pub failure_count: u64,
+        pub consecutive_failures: u32,
+        pub demoted: bool,
     }
 
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -240,19 +366,32 @@ pub mod v1 {
     pub struct VideoTaskV1 {
         pub selected: bool,
         pub url: String,
+        pub mirrors: Vec<MediaMirrorV1>,
         pub video_quality: VideoQualityV1,
         pub codec_type: CodecTypeV1,
+        pub width: u32,
+        pub height: u32,
         pub content_length: u64,
         pub chunks: Vec<MediaChunkV1>,
+        pub segments: Vec<VideoSegmentV1>,
         pub completed: bool,
         pub skipped: bool,
     }
 
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct VideoSegmentV1 {
+        pub url: String,
+        pub mirrors: Vec<MediaMirrorV1>,
+        pub content_length: u64,
+    }
+
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     #[serde(default)]
     pub struct AudioTaskV1 {
         pub selected: bool,
         pub url: String,
+        pub mirrors: Vec<MediaMirrorV1>,
         pub audio_quality: AudioQualityV1,
         pub content_length: u64,
         pub chunks: Vec<MediaChunkV1>,
@@ -267,6 +406,28 @@ pub mod v1 {
         pub merge_selected: bool,
         pub embed_chapter_selected: bool,
         pub embed_skip_selected: bool,
+        pub embed_highlight_selected: bool,
+        pub output_container: OutputContainerV1,
+        pub cut_skip_categories: Vec<SkipSegmentCategoryV1>,
+        pub cut_ranges: Option<CutRangesV1>,
+        pub completed: bool,
+        pub skipped: bool,
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CutRangeV1 {
+        pub start: u64,
+        pub end: u64,
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CutRangesV1(pub Vec<CutRangeV1>);
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct AudioLibraryTaskV1 {
+        pub selected: bool,
+        pub format: AudioLibraryFormatV1,
         pub completed: bool,
         pub skipped: bool,
     }
@@ -275,6 +436,12 @@ pub mod v1 {
     #[serde(default)]
     pub struct SubtitleTaskV1 {
         pub selected: bool,
+        pub formats: Vec<SubtitleOutputFormatV1>,
+        pub languages: Vec<String>,
+        pub exclude_ai: bool,
+        pub bilingual_primary: String,
+        pub bilingual_secondary: String,
+        pub saved_files: Vec<String>,
         pub completed: bool,
     }
 
@@ -285,6 +452,13 @@ pub mod v1 {
         pub xml_selected: bool,
         pub ass_selected: bool,
         pub json_selected: bool,
+        pub vtt_selected: bool,
+        pub srt_selected: bool,
+        pub csv_selected: bool,
+        pub heatmap_selected: bool,
+        pub history_mode: HistoryDanmakuModeV1,
+        pub history_start_date: String,
+        pub history_end_date: String,
         pub completed: bool,
         pub skipped: bool,
     }
@@ -336,6 +510,7 @@ pub mod v1 {
         pub video_task: VideoTaskV1,
         pub audio_task: AudioTaskV1,
         pub video_process_task: VideoProcessTaskV1,
+        pub audio_library_task: AudioLibraryTaskV1,
         pub subtitle_task: SubtitleTaskV1,
         pub danmaku_task: DanmakuTaskV1,
         pub cover_task: CoverTaskV1,
@@ -345,5 +520,6 @@ pub mod v1 {
         pub completed_ts: Option<u64>,
         pub is_drm: bool,
         pub is_preview: bool,
+        pub speed_limit_kb_per_sec: u64,
     }
 }
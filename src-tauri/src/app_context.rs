@@ -0,0 +1,242 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use eyre::WrapErr;
+use parking_lot::RwLock;
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+#[cfg(feature = "gui")]
+use crate::events::PluginEvent;
+use crate::{
+    bili_client::BiliClient,
+    config::Config,
+    downloader::download_manager::DownloadManager,
+    events::{DownloadEvent, LogEvent},
+    live_recorder::live_recorder_manager::LiveRecorderManager,
+    logger,
+    mirror_sync::mirror_sync_manager::MirrorSyncManager,
+    plugin::plugin_manager::PluginManager,
+    remote_api::RemoteApiServer,
+    subscription::subscription_manager::SubscriptionManager,
+};
+
+/// 下载事件广播的容量，订阅者落后太多时会丢弃最旧的事件
+const DOWNLOAD_EVENT_CAPACITY: usize = 1024;
+
+/// 事件的去向，GUI发送给前端，CLI输出到终端
+pub trait EventEmitter: Send + Sync {
+    fn emit_download_event(&self, event: DownloadEvent);
+    #[cfg(feature = "gui")]
+    fn emit_plugin_event(&self, event: PluginEvent);
+    fn emit_log_event(&self, event: LogEvent);
+}
+
+/// 下载核心的运行环境，不依赖Tauri
+///
+/// 克隆的代价很低，所有克隆共享同一份状态
+#[derive(Clone)]
+pub struct AppContext {
+    inner: Arc<AppContextInner>,
+}
+
+struct AppContextInner {
+    app_data_dir: PathBuf,
+    app_version: String,
+    event_emitter: Box<dyn EventEmitter>,
+    /// 下载事件除了发给`event_emitter`，还会广播给远程API的订阅者
+    download_event_sender: broadcast::Sender<DownloadEvent>,
+    config: OnceLock<RwLock<Config>>,
+    bili_client: OnceLock<BiliClient>,
+    download_manager: OnceLock<DownloadManager>,
+    plugin_manager: OnceLock<PluginManager>,
+    subscription_manager: OnceLock<SubscriptionManager>,
+    mirror_sync_manager: OnceLock<MirrorSyncManager>,
+    live_recorder_manager: OnceLock<LiveRecorderManager>,
+    remote_api_server: OnceLock<RemoteApiServer>,
+}
+
+impl AppContext {
+    pub fn new(
+        app_data_dir: PathBuf,
+        app_version: String,
+        event_emitter: impl EventEmitter + 'static,
+    ) -> Self {
+        let inner = AppContextInner {
+            app_data_dir,
+            app_version,
+            event_emitter: Box::new(event_emitter),
+            download_event_sender: broadcast::Sender::new(DOWNLOAD_EVENT_CAPACITY),
+            config: OnceLock::new(),
+            bili_client: OnceLock::new(),
+            download_manager: OnceLock::new(),
+            plugin_manager: OnceLock::new(),
+            subscription_manager: OnceLock::new(),
+            mirror_sync_manager: OnceLock::new(),
+            live_recorder_manager: OnceLock::new(),
+            remote_api_server: OnceLock::new(),
+        };
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    pub fn app_data_dir(&self) -> &Path {
+        &self.inner.app_data_dir
+    }
+
+    pub fn app_version(&self) -> &str {
+        &self.inner.app_version
+    }
+
+    pub fn manage_config(&self, config: Config) {
+        let _ = self.inner.config.set(RwLock::new(config));
+    }
+
+    pub fn manage_bili_client(&self, bili_client: BiliClient) {
+        let _ = self.inner.bili_client.set(bili_client);
+    }
+
+    pub fn manage_download_manager(&self, download_manager: DownloadManager) {
+        let _ = self.inner.download_manager.set(download_manager);
+    }
+
+    pub fn manage_plugin_manager(&self, plugin_manager: PluginManager) {
+        let _ = self.inner.plugin_manager.set(plugin_manager);
+    }
+
+    pub fn manage_subscription_manager(&self, subscription_manager: SubscriptionManager) {
+        let _ = self.inner.subscription_manager.set(subscription_manager);
+    }
+
+    pub fn manage_mirror_sync_manager(&self, mirror_sync_manager: MirrorSyncManager) {
+        let _ = self.inner.mirror_sync_manager.set(mirror_sync_manager);
+    }
+
+    pub fn manage_live_recorder_manager(&self, live_recorder_manager: LiveRecorderManager) {
+        let _ = self.inner.live_recorder_manager.set(live_recorder_manager);
+    }
+
+    pub fn manage_remote_api_server(&self, remote_api_server: RemoteApiServer) {
+        let _ = self.inner.remote_api_server.set(remote_api_server);
+    }
+
+    pub fn get_config(&self) -> &RwLock<Config> {
+        self.inner.config.get().expect("Config未初始化")
+    }
+
+    pub fn get_bili_client(&self) -> &BiliClient {
+        self.inner.bili_client.get().expect("BiliClient未初始化")
+    }
+
+    pub fn get_download_manager(&self) -> &DownloadManager {
+        self.inner
+            .download_manager
+            .get()
+            .expect("DownloadManager未初始化")
+    }
+
+    pub fn get_plugin_manager(&self) -> &PluginManager {
+        self.inner
+            .plugin_manager
+            .get()
+            .expect("PluginManager未初始化")
+    }
+
+    pub fn get_subscription_manager(&self) -> &SubscriptionManager {
+        self.inner
+            .subscription_manager
+            .get()
+            .expect("SubscriptionManager未初始化")
+    }
+
+    pub fn get_mirror_sync_manager(&self) -> &MirrorSyncManager {
+        self.inner
+            .mirror_sync_manager
+            .get()
+            .expect("MirrorSyncManager未初始化")
+    }
+
+    pub fn get_live_recorder_manager(&self) -> &LiveRecorderManager {
+        self.inner
+            .live_recorder_manager
+            .get()
+            .expect("LiveRecorderManager未初始化")
+    }
+
+    /// 没有创建远程API服务器时为`None`，例如CLI除`serve`以外的子命令
+    pub fn get_remote_api_server(&self) -> Option<&RemoteApiServer> {
+        self.inner.remote_api_server.get()
+    }
+
+    /// 保存新的配置，并让依赖配置的组件立即生效
+    #[instrument(level = "error", skip_all)]
+    pub fn update_config(&self, config: Config) -> eyre::Result<()> {
+        let config_state = self.get_config();
+
+        let enable_file_logger = config.enable_file_logger;
+        let (proxy_changed, file_logger_changed, remote_api_changed) = {
+            let current_config = config_state.read();
+            (
+                current_config.proxy_mode != config.proxy_mode
+                    || current_config.proxy_host != config.proxy_host
+                    || current_config.proxy_port != config.proxy_port,
+                current_config.enable_file_logger != enable_file_logger,
+                current_config.remote_api_enabled != config.remote_api_enabled
+                    || current_config.remote_api_bind_address != config.remote_api_bind_address,
+            )
+        };
+
+        {
+            // 包裹在大括号中，以便自动释放写锁
+            let mut config_state = config_state.write();
+            *config_state = config;
+            config_state.save(self).wrap_err("保存配置失败")?;
+            tracing::debug!("保存配置成功");
+        }
+
+        if proxy_changed {
+            self.get_bili_client().reload_client();
+        }
+
+        let speed_limit_kb_per_sec = config_state.read().speed_limit_kb_per_sec;
+        self.get_download_manager()
+            .speed_limiter
+            .set_limit(speed_limit_kb_per_sec.saturating_mul(1024));
+
+        if file_logger_changed {
+            if enable_file_logger {
+                logger::reload_file_logger().wrap_err("重新加载文件日志失败")?;
+            } else {
+                logger::disable_file_logger().wrap_err("禁用文件日志失败")?;
+            }
+        }
+
+        if remote_api_changed && let Some(remote_api_server) = self.get_remote_api_server() {
+            remote_api_server.reload().wrap_err("重新加载远程API失败")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn subscribe_download_events(&self) -> broadcast::Receiver<DownloadEvent> {
+        self.inner.download_event_sender.subscribe()
+    }
+
+    pub fn emit_download_event(&self, event: DownloadEvent) {
+        // 没有订阅者时发送会失败，忽略即可
+        let _ = self.inner.download_event_sender.send(event.clone());
+        self.inner.event_emitter.emit_download_event(event);
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn emit_plugin_event(&self, event: PluginEvent) {
+        self.inner.event_emitter.emit_plugin_event(event);
+    }
+
+    pub fn emit_log_event(&self, event: LogEvent) {
+        self.inner.event_emitter.emit_log_event(event);
+    }
+}
@@ -0,0 +1,64 @@
+pub mod flac;
+pub mod m4a;
+
+use std::path::Path;
+
+use eyre::{OptionExt, WrapErr};
+
+use crate::mp4_muxer::track::{self, HANDLER_AUDIO, Track};
+
+/// 写入音频文件的标签，为空的字段不写入
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct AudioTags {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: String,
+    pub track_number: Option<u32>,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub description: String,
+    pub cover: Option<CoverArt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverArt {
+    pub data: Vec<u8>,
+    /// 如`image/jpeg`
+    pub media_type: String,
+}
+
+/// 不转码时音频使用的容器
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioContainer {
+    M4a,
+    Flac,
+}
+
+impl AudioContainer {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioContainer::M4a => "m4a",
+            AudioContainer::Flac => "flac",
+        }
+    }
+}
+
+/// FLAC写成`.flac`，AAC和杜比全景声写成`.m4a`
+pub fn native_container(input_path: &Path) -> eyre::Result<AudioContainer> {
+    let track = read_audio_track(input_path)?;
+    let container = if track.sample_entry()?.box_type == *b"fLaC" {
+        AudioContainer::Flac
+    } else {
+        AudioContainer::M4a
+    };
+    Ok(container)
+}
+
+/// 读取`input_path`中的第一个音频轨道，输入可以是DASH的fMP4
+fn read_audio_track(input_path: &Path) -> eyre::Result<Track> {
+    track::read_tracks(input_path)
+        .wrap_err(format!("解析`{}`失败", input_path.display()))?
+        .into_iter()
+        .find(|track| track.handler_type == HANDLER_AUDIO)
+        .ok_or_eyre(format!("`{}`中没有音频轨道", input_path.display()))
+}
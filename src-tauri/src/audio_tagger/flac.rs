@@ -0,0 +1,289 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use eyre::{OptionExt, WrapErr, eyre};
+
+use crate::mp4_muxer::{
+    bmff::{find_box, read_full_box_header},
+    track::{Track, TrackSource},
+};
+
+use super::AudioTags;
+
+const BLOCK_TYPE_STREAMINFO: u8 = 0;
+const BLOCK_TYPE_PADDING: u8 = 1;
+const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+const BLOCK_TYPE_PICTURE: u8 = 6;
+/// 元数据块头部的最高位表示这是最后一个块
+const LAST_BLOCK_FLAG: u8 = 0x80;
+/// `PICTURE`块的图片类型：封面
+const PICTURE_TYPE_FRONT_COVER: u32 = 3;
+const VENDOR: &str = "bilibili-video-downloader";
+
+/// 把MP4中的FLAC轨道还原为带`VORBIS_COMMENT`和封面的原生FLAC文件
+///
+/// MP4的`fLaC`样本就是完整的FLAC帧，元数据块在`dfLa`里，所以不需要重新编码
+pub fn write_flac(input_path: &Path, tags: &AudioTags, output_path: &Path) -> eyre::Result<()> {
+    let track = super::read_audio_track(input_path)?;
+    let TrackSource::File(source_path) = &track.source else {
+        return Err(eyre!("输入轨道不是来自文件"));
+    };
+    let metadata = create_metadata(&track, tags)?;
+
+    let output_file =
+        File::create(output_path).wrap_err(format!("创建文件`{}`失败", output_path.display()))?;
+    let mut writer = BufWriter::new(output_file);
+    writer.write_all(&metadata)?;
+
+    let source_file =
+        File::open(source_path).wrap_err(format!("打开文件`{}`失败", source_path.display()))?;
+    let mut reader = BufReader::new(source_file);
+    for chunk in &track.chunks {
+        reader.seek(SeekFrom::Start(chunk.offset))?;
+        let copied = io::copy(&mut (&mut reader).take(chunk.size), &mut writer)
+            .wrap_err(format!("从`{}`复制FLAC帧失败", source_path.display()))?;
+        if copied != chunk.size {
+            return Err(eyre!(
+                "`{}`中的FLAC帧不完整，需要{}字节，只读到{copied}字节",
+                source_path.display(),
+                chunk.size
+            ));
+        }
+    }
+
+    writer
+        .flush()
+        .wrap_err(format!("写入文件`{}`失败", output_path.display()))?;
+
+    Ok(())
+}
+
+/// `fLaC`标记和所有元数据块，原有的注释、图片和填充块会被替换
+fn create_metadata(track: &Track, tags: &AudioTags) -> eyre::Result<Vec<u8>> {
+    let entry = track.sample_entry()?;
+    if entry.box_type != *b"fLaC" {
+        return Err(eyre!("音频轨道不是FLAC"));
+    }
+    // 音频样本描述(version 0)中子Box之前的固定部分是28字节
+    let children = entry.payload.get(28..).ok_or_eyre("音频样本描述被截断")?;
+    let dfla = find_box(children, *b"dfLa")?.ok_or_eyre("找不到dfLa Box")?;
+    let (_, _, cursor) = read_full_box_header(dfla)?;
+    let mut remaining = &dfla[usize::try_from(cursor.position())?..];
+
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    while !remaining.is_empty() {
+        let header = remaining.get(..4).ok_or_eyre("FLAC元数据块头部被截断")?;
+        let block_type = header[0] & !LAST_BLOCK_FLAG;
+        let len = usize::try_from(BigEndian::read_u24(&header[1..4]))?;
+        let content = remaining.get(4..4 + len).ok_or_eyre("FLAC元数据块被截断")?;
+        remaining = &remaining[4 + len..];
+        if matches!(
+            block_type,
+            BLOCK_TYPE_VORBIS_COMMENT | BLOCK_TYPE_PICTURE | BLOCK_TYPE_PADDING
+        ) {
+            continue;
+        }
+        blocks.push((block_type, content.to_vec()));
+    }
+
+    let stream_info = match blocks.first_mut() {
+        Some((BLOCK_TYPE_STREAMINFO, stream_info)) if stream_info.len() == 34 => stream_info,
+        _ => return Err(eyre!("dfLa的第一个元数据块不是STREAMINFO")),
+    };
+    fill_total_samples(stream_info, track);
+
+    blocks.push((BLOCK_TYPE_VORBIS_COMMENT, create_vorbis_comment(tags)));
+    if let Some(cover) = &tags.cover {
+        blocks.push((
+            BLOCK_TYPE_PICTURE,
+            create_picture(&cover.media_type, &cover.data)?,
+        ));
+    }
+
+    let mut buf = b"fLaC".to_vec();
+    let last_index = blocks.len() - 1;
+    for (i, (block_type, content)) in blocks.iter().enumerate() {
+        let flag = if i == last_index { LAST_BLOCK_FLAG } else { 0 };
+        buf.push(block_type | flag);
+        let len = u32::try_from(content.len())
+            .ok()
+            .filter(|len| *len < 1 << 24)
+            .ok_or_eyre("FLAC元数据块超过16MB")?;
+        buf.write_u24::<BigEndian>(len)?;
+        buf.extend_from_slice(content);
+    }
+
+    Ok(buf)
+}
+
+/// 流式编码的FLAC在`STREAMINFO`里可能没有总样本数，这时用MP4中记录的时长补上，
+/// 否则播放器无法显示时长和拖动进度
+fn fill_total_samples(stream_info: &mut [u8], track: &Track) {
+    // 采样率20位、声道数3位、位深5位、总样本数36位，从第10字节开始
+    let sample_rate = (u32::from(stream_info[10]) << 12)
+        | (u32::from(stream_info[11]) << 4)
+        | (u32::from(stream_info[12]) >> 4);
+    let total_samples = (u64::from(stream_info[13] & 0x0F) << 32)
+        | u64::from(BigEndian::read_u32(&stream_info[14..18]));
+    if total_samples != 0 || sample_rate != track.timescale {
+        return;
+    }
+
+    let total_samples = track.media_duration().min((1 << 36) - 1);
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        stream_info[13] = (stream_info[13] & 0xF0) | (total_samples >> 32) as u8;
+        BigEndian::write_u32(&mut stream_info[14..18], total_samples as u32);
+    }
+}
+
+/// `VORBIS_COMMENT`块，长度都是小端序，末尾没有Ogg中的framing bit
+#[allow(clippy::cast_possible_truncation)]
+fn create_vorbis_comment(tags: &AudioTags) -> Vec<u8> {
+    let track_number = tags.track_number.map(|n| n.to_string());
+    let comments = [
+        ("TITLE", Some(&tags.title)),
+        ("ARTIST", tags.artist.as_ref()),
+        ("ALBUM", Some(&tags.album)),
+        ("TRACKNUMBER", track_number.as_ref()),
+        ("DATE", Some(&tags.date)),
+        ("DESCRIPTION", Some(&tags.description)),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some(format!("{key}={}", value.filter(|v| !v.is_empty())?)))
+    .collect::<Vec<_>>();
+
+    // 标签的长度远小于4GB
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    buf.extend_from_slice(VENDOR.as_bytes());
+    buf.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        buf.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        buf.extend_from_slice(comment.as_bytes());
+    }
+    buf
+}
+
+/// `PICTURE`块，宽高等信息写0表示未知
+fn create_picture(media_type: &str, data: &[u8]) -> eyre::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.write_u32::<BigEndian>(PICTURE_TYPE_FRONT_COVER)?;
+    buf.write_u32::<BigEndian>(u32::try_from(media_type.len())?)?;
+    buf.extend_from_slice(media_type.as_bytes());
+    // 描述、宽、高、色深、索引色数量
+    buf.extend_from_slice(&[0; 20]);
+    buf.write_u32::<BigEndian>(u32::try_from(data.len())?)?;
+    buf.extend_from_slice(data);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mp4_muxer::{
+        bmff::{write_box, write_full_box},
+        track::{Chunk, HANDLER_AUDIO, Sample},
+        writer,
+    };
+
+    use super::*;
+
+    /// 48kHz、双声道、16位，总样本数为0的`STREAMINFO`
+    fn stream_info() -> Vec<u8> {
+        let mut info = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+        info.extend_from_slice(&[0x0B, 0xB8, 0x02, 0xF0, 0, 0, 0, 0]);
+        info.extend_from_slice(&[0xAA; 16]);
+        info
+    }
+
+    fn flac_track() -> Track {
+        let mut stsd = Vec::new();
+        write_full_box(&mut stsd, *b"stsd", 0, 0, |buf| {
+            buf.extend_from_slice(&1u32.to_be_bytes());
+            write_box(buf, *b"fLaC", |buf| {
+                buf.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+                buf.extend_from_slice(&[0; 8]);
+                buf.extend_from_slice(&[0, 2, 0, 16, 0, 0, 0, 0]);
+                buf.extend_from_slice(&(48_000u32 << 16).to_be_bytes());
+                write_full_box(buf, *b"dfLa", 0, 0, |buf| {
+                    buf.push(LAST_BLOCK_FLAG | BLOCK_TYPE_STREAMINFO);
+                    buf.extend_from_slice(&[0, 0, 34]);
+                    buf.extend_from_slice(&stream_info());
+                });
+            });
+        });
+
+        let sample = Sample {
+            size: 4,
+            duration: 4096,
+            composition_offset: 0,
+            is_sync: true,
+        };
+        Track {
+            handler_type: HANDLER_AUDIO,
+            timescale: 48_000,
+            language: 0x55C4,
+            width: 0,
+            height: 0,
+            stsd,
+            media_time: 0,
+            samples: vec![sample; 2],
+            chunks: vec![Chunk {
+                offset: 0,
+                size: 8,
+                first_sample: 0,
+                sample_count: 2,
+                decode_time: 0,
+            }],
+            source: TrackSource::Memory(b"frm1frm2".to_vec()),
+        }
+    }
+
+    #[test]
+    fn write_vorbis_comment_and_picture() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let input_path = temp_dir.path().join("flac.m4a");
+        let output_path = temp_dir.path().join("tagged.flac");
+        writer::write_mp4(&[flac_track()], &[], None, &input_path).unwrap();
+        let tags = AudioTags {
+            title: "标题".to_string(),
+            artist: None,
+            album: "专辑".to_string(),
+            track_number: Some(2),
+            date: String::new(),
+            description: String::new(),
+            cover: Some(crate::audio_tagger::CoverArt {
+                data: vec![0xFF, 0xD8],
+                media_type: "image/jpeg".to_string(),
+            }),
+        };
+
+        write_flac(&input_path, &tags, &output_path).unwrap();
+
+        let mut expected = b"fLaC".to_vec();
+        expected.extend_from_slice(&[BLOCK_TYPE_STREAMINFO, 0, 0, 34]);
+        let mut info = stream_info();
+        // 总样本数用两帧的时长补上
+        info[14..18].copy_from_slice(&8192u32.to_be_bytes());
+        expected.extend_from_slice(&info);
+        let comment = create_vorbis_comment(&tags);
+        expected.extend_from_slice(&[BLOCK_TYPE_VORBIS_COMMENT, 0, 0]);
+        expected.push(u8::try_from(comment.len()).unwrap());
+        expected.extend_from_slice(&comment);
+        let picture = create_picture("image/jpeg", &[0xFF, 0xD8]).unwrap();
+        expected.extend_from_slice(&[LAST_BLOCK_FLAG | BLOCK_TYPE_PICTURE, 0, 0]);
+        expected.push(u8::try_from(picture.len()).unwrap());
+        expected.extend_from_slice(&picture);
+        expected.extend_from_slice(b"frm1frm2");
+        assert_eq!(std::fs::read(&output_path).unwrap(), expected);
+
+        let comment = String::from_utf8_lossy(&comment);
+        assert!(comment.contains("TITLE=标题"));
+        assert!(comment.contains("TRACKNUMBER=2"));
+        assert!(!comment.contains("ARTIST="));
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use eyre::WrapErr;
+
+use crate::mp4_muxer::{
+    bmff::{write_box, write_full_box},
+    writer,
+};
+
+use super::AudioTags;
+
+/// `data` Box的类型：UTF-8文本
+const DATA_TYPE_UTF8: u32 = 1;
+/// `data` Box的类型：由键决定格式，`trkn`用这个
+const DATA_TYPE_IMPLICIT: u32 = 0;
+const DATA_TYPE_JPEG: u32 = 13;
+const DATA_TYPE_PNG: u32 = 14;
+
+/// 把`input_path`中的音频轨道无损复制为带`ilst`标签的M4A
+pub fn write_m4a(input_path: &Path, tags: &AudioTags, output_path: &Path) -> eyre::Result<()> {
+    let track = super::read_audio_track(input_path)?;
+    let meta = create_meta(tags);
+    writer::write_mp4(&[track], &[], Some(&meta), output_path)
+        .wrap_err(format!("写入`{}`失败", output_path.display()))
+}
+
+/// 创建`moov/udta/meta`，标签放在`ilst`里，每个标签的值放在它的`data`子Box里
+fn create_meta(tags: &AudioTags) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_full_box(&mut buf, *b"meta", 0, 0, |buf| {
+        write_full_box(buf, *b"hdlr", 0, 0, |buf| {
+            // pre_defined
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(b"mdir");
+            // reserved，iTunes总是写入`appl`
+            buf.extend_from_slice(b"appl");
+            buf.extend_from_slice(&[0; 8]);
+            // 空的name
+            buf.push(0);
+        });
+        write_box(buf, *b"ilst", |buf| write_ilst_items(buf, tags));
+    });
+    buf
+}
+
+fn write_ilst_items(buf: &mut Vec<u8>, tags: &AudioTags) {
+    let text_items = [
+        (*b"\xA9nam", Some(&tags.title)),
+        (*b"\xA9ART", tags.artist.as_ref()),
+        (*b"\xA9alb", Some(&tags.album)),
+        (*b"\xA9day", Some(&tags.date)),
+        (*b"desc", Some(&tags.description)),
+    ];
+    for (item_type, value) in text_items {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            write_data_item(buf, item_type, DATA_TYPE_UTF8, value.as_bytes());
+        }
+    }
+
+    if let Some(track_number) = tags.track_number {
+        // 保留、曲目号、总曲目数(未知为0)、保留
+        let track_number = u16::try_from(track_number).unwrap_or(u16::MAX);
+        let mut value = vec![0, 0];
+        value.extend_from_slice(&track_number.to_be_bytes());
+        value.extend_from_slice(&[0; 4]);
+        write_data_item(buf, *b"trkn", DATA_TYPE_IMPLICIT, &value);
+    }
+
+    // iTunes只认JPEG和PNG的封面
+    if let Some(cover) = &tags.cover {
+        let data_type = match cover.media_type.as_str() {
+            "image/jpeg" => Some(DATA_TYPE_JPEG),
+            "image/png" => Some(DATA_TYPE_PNG),
+            _ => None,
+        };
+        if let Some(data_type) = data_type {
+            write_data_item(buf, *b"covr", data_type, &cover.data);
+        }
+    }
+}
+
+fn write_data_item(buf: &mut Vec<u8>, item_type: [u8; 4], data_type: u32, value: &[u8]) {
+    write_box(buf, item_type, |buf| {
+        write_box(buf, *b"data", |buf| {
+            buf.extend_from_slice(&data_type.to_be_bytes());
+            // locale
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(value);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        audio_tagger::CoverArt,
+        mp4_muxer::{bmff, track::read_tracks},
+        test_support::fixture_path,
+    };
+
+    use super::*;
+
+    #[test]
+    fn write_itunes_tags() {
+        let input_path = fixture_path("mp4_muxer/audio.m4s");
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("tagged.m4a");
+        let tags = AudioTags {
+            title: "第一话".to_string(),
+            artist: Some("UP主".to_string()),
+            album: "合集".to_string(),
+            track_number: Some(3),
+            date: "2024-01-02".to_string(),
+            description: String::new(),
+            cover: Some(CoverArt {
+                data: vec![0x89, b'P', b'N', b'G'],
+                media_type: "image/png".to_string(),
+            }),
+        };
+
+        write_m4a(&input_path, &tags, &output_path).unwrap();
+
+        let output = std::fs::read(&output_path).unwrap();
+        let ilst = bmff::find_box_path(&output, &[*b"moov", *b"udta", *b"meta"])
+            .unwrap()
+            .and_then(|meta| bmff::find_box(&meta[4..], *b"ilst").unwrap())
+            .unwrap();
+        let item_types: Vec<[u8; 4]> = bmff::iter_boxes(ilst)
+            .map(|item| item.unwrap().box_type)
+            .collect();
+        // 空的简介不写入
+        assert_eq!(
+            item_types,
+            [
+                *b"\xA9nam",
+                *b"\xA9ART",
+                *b"\xA9alb",
+                *b"\xA9day",
+                *b"trkn",
+                *b"covr"
+            ]
+        );
+        let title = bmff::find_box_path(ilst, &[*b"\xA9nam", *b"data"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(&title[8..], "第一话".as_bytes());
+        let trkn = bmff::find_box_path(ilst, &[*b"trkn", *b"data"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(&trkn[8..], [0, 0, 0, 3, 0, 0, 0, 0]);
+
+        // 音频样本原样保留
+        let input_track = &read_tracks(&input_path).unwrap()[0];
+        let output_track = &read_tracks(&output_path).unwrap()[0];
+        assert_eq!(output_track.samples, input_track.samples);
+    }
+}
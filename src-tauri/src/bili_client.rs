@@ -1,38 +1,65 @@
-use std::time::Duration;
+use std::{sync::atomic::Ordering, time::Duration};
 
 use base64::{Engine, engine::general_purpose};
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use eyre::{OptionExt, WrapErr, eyre};
 use parking_lot::RwLock;
 use prost::Message;
-use reqwest::{Client, StatusCode};
+use reqwest::{
+    Client, StatusCode,
+    header::{HeaderMap, HeaderValue},
+};
 use reqwest_middleware::ClientWithMiddleware;
 use reqwest_retry::{Jitter, RetryTransientMiddleware, policies::ExponentialBackoff};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tauri::{
-    AppHandle,
-    http::{HeaderMap, HeaderValue},
-};
 use tokio::task::JoinSet;
 use tracing::{Instrument, instrument};
 
+#[cfg(feature = "gui")]
+use crate::types::{
+    bangumi_follow_info::BangumiFollowInfo, fav_folders::FavFolders,
+    get_bangumi_follow_info_params::GetBangumiFollowInfoParams,
+    get_history_info_params::GetHistoryInfoParams, history_info::HistoryInfo,
+};
 use crate::{
+    app_context::AppContext,
     config::ProxyMode,
-    extensions::{AppHandleExt, EyreReportToMessage},
+    downloader::speed_limiter::SpeedLimiter,
+    extensions::EyreReportToMessage,
     protobuf::DmSegMobileReply,
     types::{
-        bangumi_follow_info::BangumiFollowInfo, bangumi_info::BangumiInfo,
-        bangumi_media_url::BangumiMediaUrl, bangumi_media_url_v2::BangumiMediaUrlV2,
-        cheese_info::CheeseInfo, cheese_media_url::CheeseMediaUrl, fav_folders::FavFolders,
-        fav_info::FavInfo, get_bangumi_follow_info_params::GetBangumiFollowInfoParams,
-        get_bangumi_info_params::GetBangumiInfoParams, get_cheese_info_params::GetCheeseInfoParams,
-        get_fav_info_params::GetFavInfoParams, get_history_info_params::GetHistoryInfoParams,
+        bangumi_info::{BangumiInfo, EpInBangumi},
+        bangumi_media_url::BangumiMediaUrl,
+        bangumi_media_url_v2::BangumiMediaUrlV2,
+        cheese_info::CheeseInfo,
+        cheese_media_url::CheeseMediaUrl,
+        fav_info::FavInfo,
+        get_bangumi_info_params::GetBangumiInfoParams,
+        get_cheese_info_params::GetCheeseInfoParams,
+        get_fav_info_params::GetFavInfoParams,
         get_normal_info_params::GetNormalInfoParams,
-        get_user_video_info_params::GetUserVideoInfoParams, history_info::HistoryInfo,
-        normal_info::NormalInfo, normal_media_url::NormalMediaUrl, player_info::PlayerInfo,
-        qrcode_data::QrcodeData, qrcode_status::QrcodeStatus, skip_segments::SkipSegments,
-        subtitle::Subtitle, tags::Tags, user_info::UserInfo, user_video_info::UserVideoInfo,
+        get_user_video_info_params::GetUserVideoInfoParams,
+        keyword_search_info::{KeywordSearchInfo, KeywordSearchItems},
+        live_danmu_info::LiveDanmuInfo,
+        live_play_info::LivePlayInfo,
+        live_room_info::LiveRoomInfo,
+        normal_info::NormalInfo,
+        normal_media_url::NormalMediaUrl,
+        player_info::PlayerInfo,
+        qrcode_data::QrcodeData,
+        qrcode_status::QrcodeStatus,
+        search_by_keyword_params::{KeywordSearchType, SearchByKeywordParams},
+        search_params::SearchParams,
+        search_result::{
+            BangumiSearchResult, CheeseSearchResult, FavSearchResult, KeywordSearchResult,
+            NormalSearchResult, SearchResult, UserVideoSearchResult,
+        },
+        skip_segments::SkipSegments,
+        subtitle::Subtitle,
+        tags::Tags,
+        user_info::UserInfo,
+        user_video_info::UserVideoInfo,
         watch_later_info::WatchLaterInfo,
     },
 };
@@ -41,14 +68,14 @@ const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/
 const REFERRER: &str = "https://www.bilibili.com/";
 
 pub struct BiliClient {
-    pub app: AppHandle,
+    pub app: AppContext,
     pub api_client: RwLock<ClientWithMiddleware>,
     pub media_client: RwLock<ClientWithMiddleware>,
     pub content_length_client: RwLock<Client>,
 }
 
 impl BiliClient {
-    pub fn new(app: AppHandle) -> Self {
+    pub fn new(app: AppContext) -> Self {
         let api_client = create_api_client(&app);
         let api_client = RwLock::new(api_client);
 
@@ -598,6 +625,7 @@ impl BiliClient {
         Ok(player_info)
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all)]
     pub async fn get_fav_folders(&self, uid: i64) -> eyre::Result<FavFolders> {
         let params = json!({"up_mid": uid});
@@ -711,6 +739,7 @@ impl BiliClient {
         Ok(watch_later_info)
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all)]
     pub async fn get_bangumi_follow_info(
         &self,
@@ -756,6 +785,7 @@ impl BiliClient {
         Ok(bangumi_follow_info)
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all)]
     pub async fn get_history_info(
         &self,
@@ -810,22 +840,34 @@ impl BiliClient {
         media_url: &str,
         start: u64,
         end: u64,
+        speed_limiters: &[&SpeedLimiter],
     ) -> eyre::Result<Bytes> {
         let request = self
             .media_client
             .read()
             .get(media_url)
             .header("range", format!("bytes={start}-{end}"));
-        let http_resp = request.send().await?;
+        let mut http_resp = request.send().await?;
         // 检查http响应状态码
         let status = http_resp.status();
         if status != StatusCode::PARTIAL_CONTENT {
-            return Err(eyre!("预料之外的状态码({status})"));
+            return Err(MediaStatusError(status).into());
         }
 
-        let bytes = http_resp.bytes().await?;
+        let byte_per_sec = self.app.get_download_manager().byte_per_sec.clone();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut chunk_data = BytesMut::with_capacity((end - start + 1) as usize);
+        // 边收边限速，而不是一次性读完整个分片
+        while let Some(bytes) = http_resp.chunk().await? {
+            let len = bytes.len() as u64;
+            for speed_limiter in speed_limiters {
+                speed_limiter.acquire(len).await;
+            }
+            byte_per_sec.fetch_add(len, Ordering::Relaxed);
+            chunk_data.extend_from_slice(&bytes);
+        }
 
-        Ok(bytes)
+        Ok(chunk_data.freeze())
     }
 
     #[instrument(level = "error", skip_all, fields(media_url = media_url))]
@@ -976,8 +1018,88 @@ impl BiliClient {
         Ok(replies)
     }
 
+    /// 获取`month`(格式为`YYYY-MM`)中有历史弹幕的日期，格式为`YYYY-MM-DD`，需要登录
+    #[instrument(level = "error", skip_all, fields(cid = cid, month = month))]
+    pub async fn get_danmaku_history_dates(
+        &self,
+        cid: i64,
+        month: &str,
+    ) -> eyre::Result<Vec<String>> {
+        // 发送获取历史弹幕日期的请求
+        let params = json!({
+            "type": 1,
+            "oid": cid,
+            "month": month,
+        });
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.bilibili.com/x/v2/dm/history/index")
+            .query(&params)
+            .header("cookie", self.get_cookie());
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为BiliResp
+        let bili_resp: BiliResp =
+            serde_json::from_str(&body).wrap_err(format!("将body解析为BiliResp失败: {body}"))?;
+        // 检查BiliResp的code字段
+        if bili_resp.code == -101 {
+            return Err(eyre!("获取历史弹幕需要登录: {bili_resp:?}"));
+        } else if bili_resp.code != 0 {
+            return Err(eyre!("预料之外的code: {bili_resp:?}"));
+        }
+        // 这个月没有历史弹幕时data为null
+        let Some(data) = bili_resp.data.filter(|data| !data.is_null()) else {
+            return Ok(Vec::new());
+        };
+        // 尝试将data解析为Vec<String>
+        let data_str = data.to_string();
+        let dates: Vec<String> = serde_json::from_str(&data_str)
+            .wrap_err(format!("将data解析为Vec<String>失败: {data_str}"))?;
+
+        Ok(dates)
+    }
+
+    /// 获取`date`(格式为`YYYY-MM-DD`)当天的弹幕快照，需要登录
+    #[instrument(level = "error", skip_all, fields(cid = cid, date = date))]
+    pub async fn get_history_danmaku(
+        &self,
+        cid: i64,
+        date: &str,
+    ) -> eyre::Result<DmSegMobileReply> {
+        // 发送获取历史弹幕的请求
+        let params = json!({
+            "type": 1,
+            "oid": cid,
+            "date": date,
+        });
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.bilibili.com/x/v2/dm/web/history/seg.so")
+            .query(&params)
+            .header("cookie", self.get_cookie());
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        if status != StatusCode::OK {
+            let body = http_resp.text().await?;
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        let body = http_resp.bytes().await?;
+        let reply = DmSegMobileReply::decode(body).wrap_err("将body解析为DmSegMobileReply失败")?;
+
+        Ok(reply)
+    }
+
+    /// 同时返回原始的body，保存JSON格式的字幕时原样写入
     #[instrument(level = "error", skip_all, fields(url = url))]
-    pub async fn get_subtitle(&self, url: &str) -> eyre::Result<Subtitle> {
+    pub async fn get_subtitle(&self, url: &str) -> eyre::Result<(Subtitle, String)> {
         let request = self.api_client.read().get(url);
         let http_resp = request.send().await?;
         let status = http_resp.status();
@@ -989,7 +1111,7 @@ impl BiliClient {
         let subtitle: Subtitle =
             serde_json::from_str(&body).wrap_err(format!("将body解析为Subtitle失败: {body}"))?;
 
-        Ok(subtitle)
+        Ok((subtitle, body))
     }
 
     #[instrument(level = "error", skip_all, fields(url = url))]
@@ -1074,11 +1196,9 @@ impl BiliClient {
             params["cid"] = cid.into();
         }
 
-        let request = self
-            .api_client
-            .read()
-            .get("https://bsbsb.top/api/skipSegments")
-            .query(&params);
+        let server = self.app.get_config().read().skip_segment_server.clone();
+        let url = format!("{}/api/skipSegments", server.trim_end_matches('/'));
+        let request = self.api_client.read().get(url).query(&params);
         let http_resp = request.send().await?;
         // 检查http响应状态码
         let status = http_resp.status();
@@ -1095,13 +1215,299 @@ impl BiliClient {
         Ok(skip_segments)
     }
 
+    #[instrument(level = "error", skip_all, fields(room_id = room_id))]
+    pub async fn get_live_room_info(&self, room_id: i64) -> eyre::Result<LiveRoomInfo> {
+        // 发送获取直播间信息的请求，`room_id`可以是短号
+        let params = json!({"room_id": room_id});
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.live.bilibili.com/room/v1/Room/get_info")
+            .query(&params)
+            .header("cookie", self.get_cookie());
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为BiliResp
+        let bili_resp: BiliResp =
+            serde_json::from_str(&body).wrap_err(format!("将body解析为BiliResp失败: {body}"))?;
+        // 检查BiliResp的code字段
+        if bili_resp.code != 0 {
+            return Err(eyre!("预料之外的code: {bili_resp:?}"));
+        }
+        // 检查BiliResp的data是否存在
+        let Some(data) = bili_resp.data else {
+            return Err(eyre!("BiliResp中不存在data字段: {bili_resp:?}"));
+        };
+        // 尝试将data解析为LiveRoomInfo
+        let data_str = data.to_string();
+        let live_room_info: LiveRoomInfo = serde_json::from_str(&data_str)
+            .wrap_err(format!("将data解析为LiveRoomInfo失败: {data_str}"))?;
+
+        Ok(live_room_info)
+    }
+
+    #[instrument(level = "error", skip_all, fields(room_id = room_id))]
+    pub async fn get_live_play_info(&self, room_id: i64) -> eyre::Result<LivePlayInfo> {
+        // 发送获取直播流地址的请求，同时请求FLV和HLS，最高画质
+        let params = json!({
+            "room_id": room_id,
+            "protocol": "0,1",
+            "format": "0,1,2",
+            "codec": "0,1",
+            "qn": 10000,
+            "platform": "web",
+            "ptype": 8,
+        });
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.live.bilibili.com/xlive/web-room/v2/index/getRoomPlayInfo")
+            .query(&params)
+            .header("cookie", self.get_cookie());
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为BiliResp
+        let bili_resp: BiliResp =
+            serde_json::from_str(&body).wrap_err(format!("将body解析为BiliResp失败: {body}"))?;
+        // 检查BiliResp的code字段
+        if bili_resp.code != 0 {
+            return Err(eyre!("预料之外的code: {bili_resp:?}"));
+        }
+        // 检查BiliResp的data是否存在
+        let Some(data) = bili_resp.data else {
+            return Err(eyre!("BiliResp中不存在data字段: {bili_resp:?}"));
+        };
+        // 尝试将data解析为LivePlayInfo
+        let data_str = data.to_string();
+        let live_play_info: LivePlayInfo = serde_json::from_str(&data_str)
+            .wrap_err(format!("将data解析为LivePlayInfo失败: {data_str}"))?;
+
+        Ok(live_play_info)
+    }
+
+    #[instrument(level = "error", skip_all, fields(room_id = room_id))]
+    pub async fn get_live_danmu_info(&self, room_id: i64) -> eyre::Result<LiveDanmuInfo> {
+        let mut params: Vec<(&str, String)> =
+            vec![("id", room_id.to_string()), ("type", "0".to_string())];
+        self.wbi(&mut params).await?;
+        // 发送获取弹幕服务器地址的请求
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.live.bilibili.com/xlive/web-room/v1/index/getDanmuInfo")
+            .query(&params)
+            .header("cookie", self.get_cookie());
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为BiliResp
+        let bili_resp: BiliResp =
+            serde_json::from_str(&body).wrap_err(format!("将body解析为BiliResp失败: {body}"))?;
+        // 检查BiliResp的code字段
+        if bili_resp.code != 0 {
+            return Err(eyre!("预料之外的code: {bili_resp:?}"));
+        }
+        // 检查BiliResp的data是否存在
+        let Some(data) = bili_resp.data else {
+            return Err(eyre!("BiliResp中不存在data字段: {bili_resp:?}"));
+        };
+        // 尝试将data解析为LiveDanmuInfo
+        let data_str = data.to_string();
+        let live_danmu_info: LiveDanmuInfo = serde_json::from_str(&data_str)
+            .wrap_err(format!("将data解析为LiveDanmuInfo失败: {data_str}"))?;
+
+        Ok(live_danmu_info)
+    }
+
+    #[instrument(level = "error", skip_all, fields(keyword = params.keyword, search_type = ?params.search_type))]
+    pub async fn search_by_keyword(
+        &self,
+        params: &SearchByKeywordParams,
+    ) -> eyre::Result<KeywordSearchInfo> {
+        let search_type = params.search_type;
+        let mut query: Vec<(&str, String)> = vec![
+            ("search_type", search_type.as_param().to_string()),
+            ("keyword", params.keyword.clone()),
+            ("page", params.pn.to_string()),
+        ];
+        if let Some(order) = params.order.as_param(search_type) {
+            query.push(("order", order.to_string()));
+        }
+        if search_type == KeywordSearchType::Video {
+            query.push(("duration", params.duration.as_param().to_string()));
+        }
+        self.wbi(&mut query).await?;
+        // 搜索接口的cookie中没有buvid3时会返回-412，随便生成一个就行
+        let buvid3 = format!("{}infoc", uuid::Uuid::new_v4().to_string().to_uppercase());
+        // 发送关键词搜索的请求
+        let request = self
+            .api_client
+            .read()
+            .get("https://api.bilibili.com/x/web-interface/wbi/search/type")
+            .query(&query)
+            .header("cookie", format!("{}; buvid3={buvid3}", self.get_cookie()));
+        let http_resp = request.send().await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为BiliResp
+        let bili_resp: BiliResp =
+            serde_json::from_str(&body).wrap_err(format!("将body解析为BiliResp失败: {body}"))?;
+        // 检查BiliResp的code字段
+        if bili_resp.code != 0 {
+            return Err(eyre!("预料之外的code: {bili_resp:?}"));
+        }
+        // 检查BiliResp的data是否存在
+        let Some(data) = bili_resp.data else {
+            return Err(eyre!("BiliResp中不存在data字段: {bili_resp:?}"));
+        };
+        // 尝试将data解析为KeywordSearchInfo，此时还不包括搜索结果
+        let data_str = data.to_string();
+        let mut keyword_search_info: KeywordSearchInfo = serde_json::from_str(&data_str)
+            .wrap_err(format!("将data解析为KeywordSearchInfo失败: {data_str}"))?;
+        // 没有搜索结果时不存在result字段，不同搜索类型的result结构不同
+        let result = data
+            .get("result")
+            .filter(|result| !result.is_null())
+            .cloned()
+            .unwrap_or_else(|| json!([]));
+        let result_str = result.to_string();
+        let items = match search_type {
+            KeywordSearchType::Video => {
+                serde_json::from_value(result).map(KeywordSearchItems::Video)
+            }
+            KeywordSearchType::Bangumi => {
+                serde_json::from_value(result).map(KeywordSearchItems::Bangumi)
+            }
+            KeywordSearchType::MediaFt => {
+                serde_json::from_value(result).map(KeywordSearchItems::MediaFt)
+            }
+            KeywordSearchType::User => serde_json::from_value(result).map(KeywordSearchItems::User),
+        };
+        keyword_search_info.items = items.wrap_err(format!(
+            "将result解析为KeywordSearchItems失败: {result_str}"
+        ))?;
+        keyword_search_info.remove_keyword_highlight();
+
+        Ok(keyword_search_info)
+    }
+
+    /// 请求`b23.tv`短链接并跟随重定向，返回最终的链接
+    #[instrument(level = "error", skip_all, fields(short_link = short_link))]
+    pub async fn resolve_short_link(&self, short_link: &str) -> eyre::Result<String> {
+        // 发送请求，只需要重定向后的地址，不读取body
+        let request = self.api_client.read().get(short_link);
+        let http_resp = request.send().await?;
+        // 失效的短链接会返回404，不会重定向
+        let status = http_resp.status();
+        if status != StatusCode::OK {
+            let body = http_resp.text().await?;
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+
+        Ok(http_resp.url().to_string())
+    }
+
     pub fn get_cookie(&self) -> String {
         let sessdata = self.app.get_config().read().sessdata.clone();
         format!("SESSDATA={}", sessdata.trim_end_matches(';'))
     }
+
+    #[instrument(level = "error", skip_all)]
+    pub async fn search(&self, params: SearchParams) -> eyre::Result<SearchResult> {
+        use SearchParams::{Bangumi, Cheese, Fav, Keyword, Normal, UserVideo};
+        let search_result = match params {
+            Normal(params) => {
+                let info = self
+                    .get_normal_info(params)
+                    .await
+                    .wrap_err("获取普通视频信息失败")?;
+                SearchResult::Normal(NormalSearchResult(info))
+            }
+            Bangumi(GetBangumiInfoParams::EpId(ep_id)) => {
+                let info = self
+                    .get_bangumi_info(GetBangumiInfoParams::EpId(ep_id))
+                    .await
+                    .wrap_err("获取番剧视频信息失败")?;
+                let episodes: Vec<&EpInBangumi> = info
+                    .episodes
+                    .iter()
+                    .chain(
+                        info.section
+                            .iter()
+                            .flat_map(|sections| sections.iter())
+                            .flat_map(|section| section.episodes.iter()),
+                    )
+                    .collect();
+                let ep = episodes.iter().find(|ep| ep.id == ep_id).copied().cloned();
+                SearchResult::Bangumi(BangumiSearchResult { ep, info })
+            }
+            Bangumi(GetBangumiInfoParams::SeasonId(season_id)) => {
+                let info = self
+                    .get_bangumi_info(GetBangumiInfoParams::SeasonId(season_id))
+                    .await
+                    .wrap_err("获取番剧视频信息失败")?;
+                SearchResult::Bangumi(BangumiSearchResult { ep: None, info })
+            }
+            Cheese(GetCheeseInfoParams::EpId(ep_id)) => {
+                let info = self
+                    .get_cheese_info(GetCheeseInfoParams::EpId(ep_id))
+                    .await
+                    .wrap_err("获取课程视频信息失败")?;
+                let ep = info.episodes.iter().find(|ep| ep.id == ep_id).cloned();
+                SearchResult::Cheese(CheeseSearchResult { ep, info })
+            }
+            Cheese(GetCheeseInfoParams::SeasonId(season_id)) => {
+                let info = self
+                    .get_cheese_info(GetCheeseInfoParams::SeasonId(season_id))
+                    .await
+                    .wrap_err("获取课程视频信息失败")?;
+                SearchResult::Cheese(CheeseSearchResult { ep: None, info })
+            }
+            UserVideo(params) => {
+                let info = self
+                    .get_user_video_info(params)
+                    .await
+                    .wrap_err("获取用户视频信息失败")?;
+                SearchResult::UserVideo(UserVideoSearchResult(info))
+            }
+            Fav(params) => {
+                let info = self
+                    .get_fav_info(params)
+                    .await
+                    .wrap_err("获取收藏夹内容失败")?;
+                SearchResult::Fav(FavSearchResult(info))
+            }
+            Keyword(params) => {
+                let info = self
+                    .search_by_keyword(&params)
+                    .await
+                    .wrap_err(format!("搜索关键词`{}`失败", params.keyword))?;
+                SearchResult::Keyword(KeywordSearchResult(info))
+            }
+        };
+        Ok(search_result)
+    }
 }
 
-fn create_api_client(app: &AppHandle) -> ClientWithMiddleware {
+fn create_api_client(app: &AppContext) -> ClientWithMiddleware {
     let retry_policy = ExponentialBackoff::builder()
         .base(1)
         .jitter(Jitter::Bounded)
@@ -1123,7 +1529,7 @@ fn create_api_client(app: &AppHandle) -> ClientWithMiddleware {
         .build()
 }
 
-fn create_media_client(app: &AppHandle) -> ClientWithMiddleware {
+fn create_media_client(app: &AppContext) -> ClientWithMiddleware {
     let retry_policy = ExponentialBackoff::builder()
         .base(1)
         .jitter(Jitter::Bounded)
@@ -1144,7 +1550,7 @@ fn create_media_client(app: &AppHandle) -> ClientWithMiddleware {
         .build()
 }
 
-fn create_content_length_client(app: &AppHandle) -> Client {
+fn create_content_length_client(app: &AppContext) -> Client {
     let mut headers = HeaderMap::new();
     headers.insert("user-agent", HeaderValue::from_static(USER_AGENT));
     headers.insert("referer", HeaderValue::from_static(REFERRER));
@@ -1158,17 +1564,17 @@ fn create_content_length_client(app: &AppHandle) -> Client {
 }
 
 trait ClientBuilderExt {
-    fn set_proxy(self, app: &AppHandle, client_name: &str) -> Self;
+    fn set_proxy(self, app: &AppContext, client_name: &str) -> Self;
 }
 
 impl ClientBuilderExt for reqwest::ClientBuilder {
-    fn set_proxy(self, app: &AppHandle, client_name: &str) -> reqwest::ClientBuilder {
+    fn set_proxy(self, app: &AppContext, client_name: &str) -> reqwest::ClientBuilder {
         let proxy_mode = app.get_config().read().proxy_mode;
         match proxy_mode {
             ProxyMode::NoProxy => self.no_proxy(),
             ProxyMode::System => self,
             ProxyMode::Custom => {
-                let config = app.get_config().inner().read();
+                let config = app.get_config().read();
                 let proxy_host = &config.proxy_host;
                 let proxy_port = &config.proxy_port;
                 let proxy_url = format!("http://{proxy_host}:{proxy_port}");
@@ -1195,3 +1601,25 @@ pub struct BiliResp {
     #[serde(alias = "result")]
     pub data: Option<serde_json::Value>,
 }
+
+/// 请求媒体分片时返回了预料之外的状态码
+#[derive(Debug)]
+pub struct MediaStatusError(pub StatusCode);
+
+impl MediaStatusError {
+    /// 链接过期后B站会返回403、404或410
+    pub fn is_link_expired(&self) -> bool {
+        matches!(
+            self.0,
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND | StatusCode::GONE
+        )
+    }
+}
+
+impl std::fmt::Display for MediaStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "预料之外的状态码({})", self.0)
+    }
+}
+
+impl std::error::Error for MediaStatusError {}
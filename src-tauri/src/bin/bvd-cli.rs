@@ -0,0 +1,6 @@
+fn main() {
+    if let Err(err) = bilibili_video_downloader_lib::cli::run() {
+        eprintln!("{err:?}");
+        std::process::exit(1);
+    }
+}
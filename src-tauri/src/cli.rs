@@ -0,0 +1,814 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
+use eyre::{OptionExt, WrapErr, eyre};
+use fs4::fs_std::FileExt;
+use parking_lot::Mutex;
+use qrcode::{QrCode, render::unicode::Dense1x2};
+
+use crate::{
+    app_context::{AppContext, EventEmitter},
+    bili_client::BiliClient,
+    config::Config,
+    downloader::{download_manager::DownloadManager, download_task_state::DownloadTaskState},
+    errors::install_custom_eyre_handler,
+    events::{DownloadEvent, LogEvent},
+    link_parser,
+    live_recorder::live_recorder_manager::LiveRecorderManager,
+    logger,
+    mirror_sync::mirror_sync_manager::MirrorSyncManager,
+    plugin::plugin_manager::PluginManager,
+    remote_api::RemoteApiServer,
+    subscription::subscription_manager::SubscriptionManager,
+    types::{
+        create_download_task_params::{
+            CreateBangumiDownloadTaskParams, CreateCheeseDownloadTaskParams,
+            CreateDownloadTaskParams, CreateNormalDownloadTaskParams,
+        },
+        get_bangumi_info_params::GetBangumiInfoParams,
+        get_normal_info_params::GetNormalInfoParams,
+        keyword_search_info::{KeywordSearchInfo, KeywordSearchItems},
+        parsed_target::ParsedTarget,
+        search_by_keyword_params::{
+            KeywordSearchDuration, KeywordSearchOrder, KeywordSearchType, SearchByKeywordParams,
+        },
+        search_params::SearchParams,
+        search_result::{
+            BangumiSearchResult, CheeseSearchResult, KeywordSearchResult, NormalSearchResult,
+            SearchResult,
+        },
+    },
+};
+
+/// 与GUI的identifier保持一致，这样两者共用同一份配置和下载任务
+const APP_IDENTIFIER: &str = "com.lanyeeee.bilibili-video-downloader";
+/// `pause`写入待暂停的任务ID，正在下载的会话读取后暂停对应的任务
+const PAUSE_REQUESTS_FILENAME: &str = ".暂停请求.json";
+/// 同一时间只允许一个会话下载，避免多个进程同时写同一个任务的文件
+const SESSION_LOCK_FILENAME: &str = ".下载会话.lock";
+
+#[derive(Parser)]
+#[command(name = "bvd-cli", version, about = "哔哩哔哩视频下载器的命令行版本")]
+struct Cli {
+    /// 数据目录，默认与GUI版本相同
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 查看视频、番剧或课程的信息
+    Search {
+        /// 链接、BV号、av号、ep号、ss号，或包含多个链接的分享文本
+        target: String,
+    },
+    /// 下载视频、番剧或课程，并等待下载结束
+    Download {
+        /// 链接、BV号、av号、ep号、ss号，或包含多个链接的分享文本
+        target: String,
+    },
+    /// 按关键词搜索视频、番剧、影视或用户
+    Find {
+        keyword: String,
+        #[arg(long = "type", value_enum, default_value_t = KeywordSearchType::Video)]
+        search_type: KeywordSearchType,
+        /// 番剧和影视搜索不支持排序
+        #[arg(long, value_enum, default_value_t = KeywordSearchOrder::Default)]
+        order: KeywordSearchOrder,
+        /// 只对视频搜索有效
+        #[arg(long, value_enum, default_value_t = KeywordSearchDuration::All)]
+        duration: KeywordSearchDuration,
+        /// 页码，从1开始
+        #[arg(long, default_value_t = 1)]
+        page: i64,
+        /// 下载这一页搜索到的视频、番剧或影视，并等待下载结束
+        #[arg(long)]
+        download: bool,
+    },
+    /// 列出所有下载任务
+    List,
+    /// 暂停正在下载的任务
+    Pause {
+        #[arg(required = true)]
+        task_ids: Vec<String>,
+    },
+    /// 继续下载任务并等待下载结束，不指定ID时继续所有未完成的任务
+    Resume { task_ids: Vec<String> },
+    /// 扫码登录
+    Login,
+    /// 启动远程API并定时检查订阅、同步收藏夹，按Ctrl+C退出
+    Serve,
+    /// 录制直播和弹幕，断线会自动重连，按Ctrl+C结束录制
+    Record {
+        /// 直播间号或直播间链接
+        room: String,
+    },
+}
+
+/// 命令行的入口
+///
+/// # Errors
+///
+/// 初始化失败或子命令执行失败时返回错误
+pub fn run() -> eyre::Result<()> {
+    install_custom_eyre_handler()?;
+
+    let cli = Cli::parse();
+    let runtime = tokio::runtime::Runtime::new().wrap_err("创建tokio运行时失败")?;
+    runtime.block_on(cli.run())
+}
+
+impl Cli {
+    async fn run(self) -> eyre::Result<()> {
+        let app_data_dir = match self.data_dir {
+            Some(data_dir) => data_dir,
+            None => default_data_dir()?,
+        };
+
+        let emitter = Arc::new(CliEventEmitter::default());
+        let create_app = || init_context(app_data_dir.clone(), emitter.clone());
+
+        match self.command {
+            // 暂停只需要把请求交给正在下载的会话，不需要初始化下载核心
+            Command::Pause { task_ids } => request_pause(&app_data_dir, &task_ids),
+            Command::Search { target } => search(&create_app()?, &target).await,
+            Command::Download { target } => download(&create_app()?, &emitter, &target).await,
+            Command::Find {
+                keyword,
+                search_type,
+                order,
+                duration,
+                page,
+                download,
+            } => {
+                let params = SearchByKeywordParams {
+                    keyword,
+                    search_type,
+                    order,
+                    duration,
+                    pn: page,
+                };
+                find(&create_app()?, &emitter, params, download).await
+            }
+            Command::List => list(&create_app()?),
+            Command::Resume { task_ids } => resume(&create_app()?, &emitter, task_ids).await,
+            Command::Login => login(&create_app()?).await,
+            Command::Serve => serve(&create_app()?, &emitter).await,
+            Command::Record { room } => record(&create_app()?, &room).await,
+        }
+    }
+}
+
+/// 把下载核心的事件输出到终端
+#[derive(Default)]
+struct CliEventEmitter {
+    /// 只输出这些任务的状态变化，避免恢复任务时刷屏
+    watched_task_ids: Mutex<HashSet<String>>,
+    /// 为`true`时输出所有任务的状态变化
+    watch_all: AtomicBool,
+    task_names: Mutex<HashMap<String, String>>,
+    speed: Mutex<String>,
+}
+
+impl CliEventEmitter {
+    fn watch(&self, task_ids: &[String]) {
+        self.watched_task_ids
+            .lock()
+            .extend(task_ids.iter().cloned());
+    }
+
+    fn watch_all(&self) {
+        self.watch_all.store(true, Ordering::Relaxed);
+    }
+
+    fn task_name(&self, task_id: &str) -> String {
+        self.task_names
+            .lock()
+            .get(task_id)
+            .cloned()
+            .unwrap_or_else(|| task_id.to_string())
+    }
+}
+
+impl EventEmitter for Arc<CliEventEmitter> {
+    fn emit_download_event(&self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Speed { speed } => *self.speed.lock() = speed,
+            DownloadEvent::TaskCreate { progress, .. }
+            | DownloadEvent::ProgressUpdate { progress } => {
+                self.task_names
+                    .lock()
+                    .insert(progress.task_id, progress.filename);
+            }
+            DownloadEvent::TaskStateUpdate { task_id, state } => {
+                if self.watch_all.load(Ordering::Relaxed)
+                    || self.watched_task_ids.lock().contains(&task_id)
+                {
+                    let task_name = self.task_name(&task_id);
+                    eprintln!("[{}] {task_name}", state_label(state));
+                }
+            }
+            DownloadEvent::TaskSleeping { .. }
+            | DownloadEvent::TaskDelete { .. }
+            | DownloadEvent::ProgressPreparing { .. } => {}
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    fn emit_plugin_event(&self, _event: crate::events::PluginEvent) {}
+
+    fn emit_log_event(&self, event: LogEvent) {
+        // 控制台日志已关闭，这里只输出警告和错误
+        let Ok(log) = serde_json::from_str::<serde_json::Value>(&event.json_raw) else {
+            return;
+        };
+        let fields = &log["fields"];
+        let message = fields["message"].as_str().unwrap_or_default();
+        match log["level"].as_str() {
+            Some("ERROR") => {
+                let err_title = fields["err_title"].as_str().unwrap_or_default();
+                eprintln!("[错误] {err_title}: {message}");
+            }
+            Some("WARN") => eprintln!("[警告] {message}"),
+            _ => {}
+        }
+    }
+}
+
+fn init_context(app_data_dir: PathBuf, emitter: Arc<CliEventEmitter>) -> eyre::Result<AppContext> {
+    std::fs::create_dir_all(&app_data_dir).wrap_err(format!(
+        "创建app_data_dir目录`{}`失败",
+        app_data_dir.display()
+    ))?;
+
+    let app_version = env!("CARGO_PKG_VERSION").to_string();
+    let app = AppContext::new(app_data_dir, app_version, emitter);
+
+    let config = Config::new(&app)?;
+    app.manage_config(config);
+
+    let bili_client = BiliClient::new(app.clone());
+    app.manage_bili_client(bili_client);
+
+    let download_manager = DownloadManager::new(app.clone());
+    app.manage_download_manager(download_manager);
+
+    logger::init(&app, false)?;
+
+    let plugin_manager = PluginManager::new(&app)?;
+    app.manage_plugin_manager(plugin_manager);
+
+    Ok(app)
+}
+
+/// 与Tauri的`app_data_dir`规则一致
+fn default_data_dir() -> eyre::Result<PathBuf> {
+    let data_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+    };
+
+    let data_dir = data_dir.ok_or_eyre("获取数据目录失败，请用`--data-dir`指定")?;
+    Ok(data_dir.join(APP_IDENTIFIER))
+}
+
+/// 解析链接、ID或分享文本中的所有目标，短链接会被解析为真实链接
+async fn parse_targets(app: &AppContext, text: &str) -> eyre::Result<Vec<ParsedTarget>> {
+    let bili_client = app.get_bili_client();
+    let targets = link_parser::parse_text(text, |short_link| async move {
+        bili_client.resolve_short_link(&short_link).await
+    })
+    .await;
+
+    if targets.is_empty() {
+        return Err(eyre!(
+            "无法识别`{text}`，请输入链接、BV号、av号、ep号或ss号"
+        ));
+    }
+    Ok(targets)
+}
+
+async fn search(app: &AppContext, text: &str) -> eyre::Result<()> {
+    for target in parse_targets(app, text).await? {
+        let search_result = app
+            .get_bili_client()
+            .search(target.params)
+            .await
+            .wrap_err("搜索失败")?;
+        print_search_result(&search_result);
+    }
+
+    Ok(())
+}
+
+fn print_search_result(search_result: &SearchResult) {
+    match search_result {
+        SearchResult::Normal(NormalSearchResult(info)) => {
+            println!("{} ({})", info.title, info.bvid);
+            println!("UP主: {}", info.owner.name);
+            for page in &info.pages {
+                println!("  P{} {}", page.page, page.part);
+            }
+        }
+        SearchResult::Bangumi(BangumiSearchResult { info, .. }) => {
+            println!("{}", info.title);
+            for ep in &info.episodes {
+                let long_title = ep.long_title.as_deref().unwrap_or_default();
+                println!("  ep{} {} {long_title}", ep.id, ep.title);
+            }
+        }
+        SearchResult::Cheese(CheeseSearchResult { info, .. }) => {
+            println!("{}", info.title);
+            for ep in &info.episodes {
+                println!("  ep{} {}", ep.id, ep.title);
+            }
+        }
+        SearchResult::Keyword(KeywordSearchResult(info)) => print_keyword_search_info(info),
+        SearchResult::UserVideo(_) | SearchResult::Fav(_) => {}
+    }
+}
+
+fn print_keyword_search_info(info: &KeywordSearchInfo) {
+    match &info.items {
+        KeywordSearchItems::Video(videos) => {
+            for video in videos {
+                let (bvid, title, author) = (&video.bvid, &video.title, &video.author);
+                println!("{bvid} {title} - {author} ({})", video.duration);
+            }
+        }
+        KeywordSearchItems::Bangumi(medias) | KeywordSearchItems::MediaFt(medias) => {
+            for media in medias {
+                let (season_id, title) = (media.season_id, &media.title);
+                println!("ss{season_id} {title} ({})", media.season_type_name);
+            }
+        }
+        KeywordSearchItems::User(users) => {
+            for user in users {
+                let (mid, uname, fans) = (user.mid, &user.uname, user.fans);
+                println!("uid{mid} {uname} 粉丝: {fans} 投稿: {}", user.videos);
+            }
+        }
+    }
+    println!(
+        "第{}/{}页，共{}个结果",
+        info.page, info.num_pages, info.num_results
+    );
+}
+
+/// 按关键词搜索，`download`为`true`时下载这一页的所有搜索结果
+async fn find(
+    app: &AppContext,
+    emitter: &CliEventEmitter,
+    params: SearchByKeywordParams,
+    download: bool,
+) -> eyre::Result<()> {
+    if download && params.search_type == KeywordSearchType::User {
+        return Err(eyre!("不支持下载用户搜索结果，请用UP主订阅"));
+    }
+
+    let search_result = app
+        .get_bili_client()
+        .search(SearchParams::Keyword(params))
+        .await
+        .wrap_err("搜索失败")?;
+    let SearchResult::Keyword(KeywordSearchResult(info)) = search_result else {
+        return Err(eyre!("关键词搜索返回了预料之外的结果"));
+    };
+    print_keyword_search_info(&info);
+
+    if !download {
+        return Ok(());
+    }
+    let params: Vec<SearchParams> = match info.items {
+        KeywordSearchItems::Video(videos) => videos
+            .into_iter()
+            .map(|video| SearchParams::Normal(GetNormalInfoParams::Bvid(video.bvid)))
+            .collect(),
+        KeywordSearchItems::Bangumi(medias) | KeywordSearchItems::MediaFt(medias) => medias
+            .into_iter()
+            .map(|media| SearchParams::Bangumi(GetBangumiInfoParams::SeasonId(media.season_id)))
+            .collect(),
+        KeywordSearchItems::User(_) => Vec::new(),
+    };
+    let targets = params
+        .into_iter()
+        .map(|params| ParsedTarget { params, page: None })
+        .collect();
+    download_targets(app, emitter, targets).await
+}
+
+async fn download(app: &AppContext, emitter: &CliEventEmitter, text: &str) -> eyre::Result<()> {
+    let targets = parse_targets(app, text).await?;
+    download_targets(app, emitter, targets).await
+}
+
+async fn download_targets(
+    app: &AppContext,
+    emitter: &CliEventEmitter,
+    targets: Vec<ParsedTarget>,
+) -> eyre::Result<()> {
+    let _session_lock = lock_session(app.app_data_dir())?;
+
+    let download_manager = app.get_download_manager();
+    let mut task_ids = Vec::new();
+    let mut skipped_count = 0;
+    for target in targets {
+        let search_result = app
+            .get_bili_client()
+            .search(target.params)
+            .await
+            .wrap_err("搜索失败")?;
+        let params = create_download_task_params(search_result, target.page)?;
+
+        let created = download_manager.create_download_tasks(&params);
+        skipped_count += created.skipped_duplicates.len();
+        task_ids.extend(created.tasks.iter().map(|task| task.task_id.clone()));
+    }
+    if task_ids.is_empty() {
+        if skipped_count > 0 {
+            println!("下载历史中已有相同的下载，跳过了{skipped_count}个下载任务");
+            return Ok(());
+        }
+        return Err(eyre!("没有创建任何下载任务"));
+    }
+    emitter.watch(&task_ids);
+
+    let auto_start = app.get_config().read().auto_start_download_task;
+    if !auto_start {
+        download_manager.resume_download_tasks(&task_ids);
+    }
+
+    eprintln!("已创建{}个下载任务", task_ids.len());
+    wait_for_tasks(app, emitter, task_ids).await
+}
+
+/// `page`是链接中指定的分P，只对普通视频有效
+fn create_download_task_params(
+    search_result: SearchResult,
+    page: Option<i64>,
+) -> eyre::Result<CreateDownloadTaskParams> {
+    let params = match search_result {
+        SearchResult::Normal(NormalSearchResult(info)) => {
+            // 指定了分P时只下载这一P，否则下载所有分P
+            let cid = match page {
+                Some(page) => {
+                    let page_info = info
+                        .pages
+                        .iter()
+                        .find(|page_info| page_info.page == page)
+                        .ok_or_eyre(format!("`{}`没有P{page}", info.bvid))?;
+                    Some(page_info.cid)
+                }
+                None => None,
+            };
+            CreateDownloadTaskParams::Normal(CreateNormalDownloadTaskParams {
+                aid_cid_pairs: vec![(info.aid, cid)],
+                info,
+            })
+        }
+        SearchResult::Bangumi(BangumiSearchResult { ep, info }) => {
+            // 指定了ep号时只下载这一集，否则下载整季
+            let ep_ids = match ep {
+                Some(ep) => vec![ep.id],
+                None => info.episodes.iter().map(|ep| ep.id).collect(),
+            };
+            CreateDownloadTaskParams::Bangumi(CreateBangumiDownloadTaskParams { ep_ids, info })
+        }
+        SearchResult::Cheese(CheeseSearchResult { ep, info }) => {
+            let ep_ids = match ep {
+                Some(ep) => vec![ep.id],
+                None => info.episodes.iter().map(|ep| ep.id).collect(),
+            };
+            CreateDownloadTaskParams::Cheese(CreateCheeseDownloadTaskParams { ep_ids, info })
+        }
+        SearchResult::UserVideo(_) | SearchResult::Fav(_) | SearchResult::Keyword(_) => {
+            return Err(eyre!("不支持下载UP主投稿、收藏夹或关键词搜索结果"));
+        }
+    };
+
+    Ok(params)
+}
+
+fn list(app: &AppContext) -> eyre::Result<()> {
+    let download_manager = app.get_download_manager();
+    download_manager
+        .restore_download_tasks()
+        .wrap_err("恢复下载任务失败")?;
+
+    let tasks = download_manager.download_tasks.read();
+    let mut tasks: Vec<_> = tasks.values().collect();
+    tasks.sort_by_key(|task| task.progress.read().create_ts);
+
+    for task in tasks {
+        let state = *task.state_sender.borrow();
+        let filename = task.progress.read().filename.clone();
+        println!("{}  {}  {filename}", task.task_id, state_label(state));
+    }
+
+    Ok(())
+}
+
+async fn resume(
+    app: &AppContext,
+    emitter: &CliEventEmitter,
+    task_ids: Vec<String>,
+) -> eyre::Result<()> {
+    let _session_lock = lock_session(app.app_data_dir())?;
+
+    let download_manager = app.get_download_manager();
+    download_manager
+        .restore_download_tasks()
+        .wrap_err("恢复下载任务失败")?;
+
+    let task_ids = {
+        let tasks = download_manager.download_tasks.read();
+        if task_ids.is_empty() {
+            tasks
+                .values()
+                .filter(|task| *task.state_sender.borrow() != DownloadTaskState::Completed)
+                .map(|task| task.task_id.clone())
+                .collect()
+        } else {
+            if let Some(task_id) = task_ids.iter().find(|id| !tasks.contains_key(*id)) {
+                return Err(eyre!("未找到ID为`{task_id}`的下载任务"));
+            }
+            task_ids
+        }
+    };
+    if task_ids.is_empty() {
+        eprintln!("没有未完成的下载任务");
+        return Ok(());
+    }
+    // 丢弃之前遗留的暂停请求，免得刚继续就被暂停
+    take_pause_requests(app.app_data_dir(), &task_ids)?;
+
+    emitter.watch(&task_ids);
+    download_manager.resume_download_tasks(&task_ids);
+
+    eprintln!("继续下载{}个任务", task_ids.len());
+    wait_for_tasks(app, emitter, task_ids).await
+}
+
+/// 等待任务结束，期间处理`pause`发来的暂停请求
+async fn wait_for_tasks(
+    app: &AppContext,
+    emitter: &CliEventEmitter,
+    mut task_ids: Vec<String>,
+) -> eyre::Result<()> {
+    let download_manager = app.get_download_manager();
+    let mut paused_count = 0;
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut tick: u64 = 0;
+
+    loop {
+        interval.tick().await;
+        tick += 1;
+
+        let pause_requests = take_pause_requests(app.app_data_dir(), &task_ids)?;
+        if !pause_requests.is_empty() {
+            download_manager.pause_download_tasks(&pause_requests);
+            task_ids.retain(|task_id| !pause_requests.contains(task_id));
+            paused_count += pause_requests.len();
+        }
+
+        let states: Vec<DownloadTaskState> = {
+            let tasks = download_manager.download_tasks.read();
+            task_ids
+                .iter()
+                .filter_map(|task_id| tasks.get(task_id))
+                .map(|task| *task.state_sender.borrow())
+                .collect()
+        };
+        let completed_count = count_state(&states, DownloadTaskState::Completed);
+        let failed_count = count_state(&states, DownloadTaskState::Failed);
+
+        if completed_count + failed_count == states.len() {
+            eprintln!(
+                "下载结束: 完成{completed_count}个，失败{failed_count}个，暂停{paused_count}个"
+            );
+            if failed_count > 0 {
+                return Err(eyre!(
+                    "有{failed_count}个任务下载失败，可以用`resume`断点续传"
+                ));
+            }
+            return Ok(());
+        }
+
+        if tick.is_multiple_of(5) {
+            let speed = emitter.speed.lock().clone();
+            eprintln!("进度: {completed_count}/{}，速度: {speed}", states.len());
+        }
+    }
+}
+
+fn count_state(states: &[DownloadTaskState], state: DownloadTaskState) -> usize {
+    states.iter().filter(|s| **s == state).count()
+}
+
+fn state_label(state: DownloadTaskState) -> &'static str {
+    match state {
+        DownloadTaskState::Pending => "排队中",
+        DownloadTaskState::Downloading => "下载中",
+        DownloadTaskState::Paused => "已暂停",
+        DownloadTaskState::Completed => "已完成",
+        DownloadTaskState::Failed => "失败",
+    }
+}
+
+/// 锁住下载会话，返回的文件被drop时自动解锁
+fn lock_session(app_data_dir: &Path) -> eyre::Result<File> {
+    let lock_path = app_data_dir.join(SESSION_LOCK_FILENAME);
+    let lock_file = File::create(&lock_path)
+        .wrap_err(format!("创建会话锁文件`{}`失败", lock_path.display()))?;
+    if !lock_file.try_lock_exclusive()? {
+        return Err(eyre!("已有另一个bvd-cli正在下载，请等它结束后再试"));
+    }
+    Ok(lock_file)
+}
+
+fn request_pause(app_data_dir: &Path, task_ids: &[String]) -> eyre::Result<()> {
+    let mut pause_requests = read_pause_requests(app_data_dir)?;
+    for task_id in task_ids {
+        if !pause_requests.contains(task_id) {
+            pause_requests.push(task_id.clone());
+        }
+    }
+    write_pause_requests(app_data_dir, &pause_requests)?;
+
+    eprintln!(
+        "已请求暂停{}个任务，正在下载的bvd-cli会在1秒内暂停它们",
+        task_ids.len()
+    );
+    Ok(())
+}
+
+/// 取出属于`task_ids`的暂停请求，其他请求留给别的会话
+fn take_pause_requests(app_data_dir: &Path, task_ids: &[String]) -> eyre::Result<Vec<String>> {
+    let pause_requests = read_pause_requests(app_data_dir)?;
+    if pause_requests.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (taken, remaining): (Vec<String>, Vec<String>) = pause_requests
+        .into_iter()
+        .partition(|task_id| task_ids.contains(task_id));
+    if !taken.is_empty() {
+        write_pause_requests(app_data_dir, &remaining)?;
+    }
+
+    Ok(taken)
+}
+
+fn read_pause_requests(app_data_dir: &Path) -> eyre::Result<Vec<String>> {
+    let path = app_data_dir.join(PAUSE_REQUESTS_FILENAME);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let json = std::fs::read_to_string(&path)
+        .wrap_err(format!("读取暂停请求文件`{}`失败", path.display()))?;
+    let pause_requests = serde_json::from_str(&json)
+        .wrap_err(format!("将`{}`解析为暂停请求失败: {json}", path.display()))?;
+    Ok(pause_requests)
+}
+
+fn write_pause_requests(app_data_dir: &Path, pause_requests: &[String]) -> eyre::Result<()> {
+    let path = app_data_dir.join(PAUSE_REQUESTS_FILENAME);
+    if pause_requests.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .wrap_err(format!("删除暂停请求文件`{}`失败", path.display()))?;
+        }
+        return Ok(());
+    }
+
+    let json = serde_json::to_string(pause_requests)?;
+    std::fs::write(&path, json).wrap_err(format!("保存暂停请求文件`{}`失败", path.display()))?;
+    Ok(())
+}
+
+async fn serve(app: &AppContext, emitter: &CliEventEmitter) -> eyre::Result<()> {
+    let _session_lock = lock_session(app.app_data_dir())?;
+
+    let download_manager = app.get_download_manager();
+    download_manager
+        .restore_download_tasks()
+        .wrap_err("恢复下载任务失败")?;
+    // 通过远程API创建的任务事先不知道ID
+    emitter.watch_all();
+
+    let subscription_manager = SubscriptionManager::new(app.clone())?;
+    app.manage_subscription_manager(subscription_manager);
+
+    let mirror_sync_manager = MirrorSyncManager::new(app.clone())?;
+    app.manage_mirror_sync_manager(mirror_sync_manager);
+
+    let remote_api_server = RemoteApiServer::new(app.clone());
+    let local_addr = remote_api_server
+        .reload()
+        .wrap_err("启动远程API失败")?
+        .ok_or_eyre("远程API未开启，请先在config.json中将`remote_api_enabled`设为`true`")?;
+    app.manage_remote_api_server(remote_api_server);
+
+    eprintln!("远程API正在监听{local_addr}，按Ctrl+C退出");
+    tokio::signal::ctrl_c().await.wrap_err("等待Ctrl+C失败")?;
+
+    Ok(())
+}
+
+async fn record(app: &AppContext, room: &str) -> eyre::Result<()> {
+    let live_recorder_manager = LiveRecorderManager::new(app.clone());
+    app.manage_live_recorder_manager(live_recorder_manager);
+    let live_recorder_manager = app.get_live_recorder_manager();
+
+    let recording = live_recorder_manager
+        .start_live_recording(room)
+        .await
+        .wrap_err("开始录制直播失败")?;
+    eprintln!(
+        "开始录制直播间{}「{}」，保存到`{}`，按Ctrl+C结束录制",
+        recording.room_id,
+        recording.title,
+        recording.output_dir.display()
+    );
+
+    tokio::signal::ctrl_c().await.wrap_err("等待Ctrl+C失败")?;
+    eprintln!("正在保存录制的文件和弹幕");
+    live_recorder_manager
+        .stop_live_recording(recording.room_id)
+        .await
+        .wrap_err("停止录制直播失败")?;
+
+    Ok(())
+}
+
+async fn login(app: &AppContext) -> eyre::Result<()> {
+    let bili_client = app.get_bili_client();
+
+    let qrcode_data = bili_client
+        .generate_qrcode()
+        .await
+        .wrap_err("生成二维码失败")?;
+    let qrcode = QrCode::new(qrcode_data.url.as_bytes()).wrap_err("绘制二维码失败")?;
+    // 终端通常是深色背景，反转颜色后手机更容易识别
+    let qrcode_art = qrcode
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{qrcode_art}");
+    eprintln!("请使用哔哩哔哩APP扫描二维码登录");
+
+    let mut scanned = false;
+    let sessdata = loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let qrcode_status = bili_client
+            .get_qrcode_status(&qrcode_data.qrcode_key)
+            .await
+            .wrap_err("获取二维码状态失败")?;
+        match qrcode_status.code {
+            0 => break qrcode_status.sessdata,
+            86090 if !scanned => {
+                scanned = true;
+                eprintln!("已扫码，请在手机上确认登录");
+            }
+            86038 => return Err(eyre!("二维码已过期，请重新登录")),
+            _ => {}
+        }
+    };
+
+    let user_info = bili_client
+        .get_user_info(&sessdata)
+        .await
+        .wrap_err("获取用户信息失败")?;
+
+    {
+        let mut config = app.get_config().write();
+        config.sessdata = sessdata;
+        config.save(app).wrap_err("保存配置失败")?;
+    }
+
+    eprintln!("登录成功: {}", user_info.uname);
+    Ok(())
+}
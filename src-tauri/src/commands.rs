@@ -4,44 +4,47 @@ use std::{
 };
 
 use eyre::WrapErr;
-use parking_lot::RwLock;
 use tauri::AppHandle;
 use tauri_plugin_opener::OpenerExt;
 use tracing::instrument;
 
 use crate::{
     config::Config,
+    downloader::download_history::DownloadHistoryQueryResult,
     errors::{CommandError, CommandResult},
     extensions::AppHandleExt,
-    logger,
+    link_parser, logger,
     types::{
         available_media_formats::AvailableMediaFormats,
         bangumi_follow_info::BangumiFollowInfo,
-        bangumi_info::{BangumiInfo, EpInBangumi},
+        bangumi_info::BangumiInfo,
         create_download_task_params::CreateDownloadTaskParams,
         fav_folders::FavFolders,
         fav_info::FavInfo,
         get_available_media_formats_params::GetAvailableMediaFormatsParams,
         get_bangumi_follow_info_params::GetBangumiFollowInfoParams,
         get_bangumi_info_params::GetBangumiInfoParams,
-        get_cheese_info_params::GetCheeseInfoParams,
         get_fav_info_params::GetFavInfoParams,
         get_history_info_params::GetHistoryInfoParams,
         get_normal_info_params::GetNormalInfoParams,
         get_user_video_info_params::GetUserVideoInfoParams,
         history_info::HistoryInfo,
+        live_recording::LiveRecording,
         log_metadata::LogMetadata,
+        mirror_sync::{
+            AddMirrorSyncParams, ArchivedItem, MirrorSource, MirrorSync, UpdateMirrorSyncParams,
+        },
         normal_info::NormalInfo,
+        parsed_target::ParsedTarget,
         plugin_info::PluginInfo,
         qrcode_data::QrcodeData,
         qrcode_status::QrcodeStatus,
+        query_download_history_params::QueryDownloadHistoryParams,
         restart_download_task_params::RestartDownloadTaskParams,
         search_params::SearchParams,
-        search_result::{
-            BangumiSearchResult, CheeseSearchResult, FavSearchResult, NormalSearchResult,
-            SearchResult, UserVideoSearchResult,
-        },
+        search_result::SearchResult,
         skip_segments::SkipSegments,
+        subscription::{AddSubscriptionParams, Subscription, UpdateSubscriptionParams},
         user_info::UserInfo,
         user_video_info::UserVideoInfo,
         watch_later_info::WatchLaterInfo,
@@ -52,9 +55,8 @@ use crate::{
 #[specta::specta]
 #[allow(clippy::needless_pass_by_value)]
 #[instrument(level = "error", skip_all)]
-// TODO: 改成 app.get_config_manager().read().clone()
-pub fn get_config(config: tauri::State<RwLock<Config>>) -> Config {
-    config.read().clone()
+pub fn get_config(app: AppHandle) -> Config {
+    app.get_config().read().clone()
 }
 
 #[tauri::command(async)]
@@ -62,44 +64,9 @@ pub fn get_config(config: tauri::State<RwLock<Config>>) -> Config {
 #[allow(clippy::needless_pass_by_value)]
 #[instrument(level = "error", skip_all)]
 pub fn save_config(app: AppHandle, config: Config) -> CommandResult<()> {
-    let bili_client = app.get_bili_client();
-    let config_state = app.get_config();
-
-    let enable_file_logger = config.enable_file_logger;
-    let (proxy_changed, file_logger_changed) = {
-        let current_config = config_state.read();
-        (
-            current_config.proxy_mode != config.proxy_mode
-                || current_config.proxy_host != config.proxy_host
-                || current_config.proxy_port != config.proxy_port,
-            current_config.enable_file_logger != enable_file_logger,
-        )
-    };
-
-    {
-        // 包裹在大括号中，以便自动释放写锁
-        let mut config_state = config_state.write();
-        *config_state = config;
-        config_state
-            .save(&app)
-            .map_err(|err| CommandError::from("保存配置失败", err))?;
-        tracing::debug!("保存配置成功");
-    }
-
-    if proxy_changed {
-        bili_client.reload_client();
-    }
-
-    if file_logger_changed {
-        if enable_file_logger {
-            logger::reload_file_logger()
-                .map_err(|err| CommandError::from("重新加载文件日志失败", err))?;
-        } else {
-            logger::disable_file_logger()
-                .map_err(|err| CommandError::from("禁用文件日志失败", err))?;
-        }
-    }
-
+    app.get_context()
+        .update_config(config)
+        .map_err(|err| CommandError::from("保存配置失败", err))?;
     Ok(())
 }
 
@@ -288,6 +255,19 @@ pub fn delete_download_tasks(app: AppHandle, task_ids: Vec<String>) {
     download_manager.delete_download_tasks(&task_ids);
 }
 
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn set_download_tasks_speed_limit(
+    app: AppHandle,
+    task_ids: Vec<String>,
+    speed_limit_kb_per_sec: u64,
+) {
+    let download_manager = app.get_download_manager();
+    download_manager.set_download_tasks_speed_limit(&task_ids, speed_limit_kb_per_sec);
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -323,82 +303,31 @@ pub fn restore_download_tasks(app: AppHandle) -> CommandResult<()> {
 #[specta::specta]
 #[instrument(level = "error", skip_all)]
 pub async fn search(app: AppHandle, params: SearchParams) -> CommandResult<SearchResult> {
-    use SearchParams::{Bangumi, Cheese, Fav, Normal, UserVideo};
     let bili_client = app.get_bili_client();
-    let search_result = match params {
-        Normal(params) => {
-            let info = bili_client
-                .get_normal_info(params)
-                .await
-                .map_err(|err| CommandError::from("获取普通视频信息失败", err))?;
-            SearchResult::Normal(NormalSearchResult(info))
-        }
-        Bangumi(GetBangumiInfoParams::EpId(ep_id)) => {
-            let info = bili_client
-                .get_bangumi_info(GetBangumiInfoParams::EpId(ep_id))
-                .await
-                .map_err(|err| CommandError::from("获取番剧视频信息失败", err))?;
-            let episodes: Vec<&EpInBangumi> = info
-                .episodes
-                .iter()
-                .chain(
-                    info.section
-                        .iter()
-                        .flat_map(|sections| sections.iter())
-                        .flat_map(|section| section.episodes.iter()),
-                )
-                .collect();
-            let ep = episodes.iter().find(|ep| ep.id == ep_id).copied().cloned();
-            SearchResult::Bangumi(BangumiSearchResult { ep, info })
-        }
-        Bangumi(GetBangumiInfoParams::SeasonId(season_id)) => {
-            let info = bili_client
-                .get_bangumi_info(GetBangumiInfoParams::SeasonId(season_id))
-                .await
-                .map_err(|err| CommandError::from("获取番剧视频信息失败", err))?;
-            SearchResult::Bangumi(BangumiSearchResult { ep: None, info })
-        }
-        Cheese(GetCheeseInfoParams::EpId(ep_id)) => {
-            let info = bili_client
-                .get_cheese_info(GetCheeseInfoParams::EpId(ep_id))
-                .await
-                .map_err(|err| CommandError::from("获取课程视频信息失败", err))?;
-            let ep = info.episodes.iter().find(|ep| ep.id == ep_id).cloned();
-            SearchResult::Cheese(CheeseSearchResult { ep, info })
-        }
-        Cheese(GetCheeseInfoParams::SeasonId(season_id)) => {
-            let info = bili_client
-                .get_cheese_info(GetCheeseInfoParams::SeasonId(season_id))
-                .await
-                .map_err(|err| CommandError::from("获取课程视频信息失败", err))?;
-            SearchResult::Cheese(CheeseSearchResult { ep: None, info })
-        }
-        UserVideo(params) => {
-            let info = bili_client
-                .get_user_video_info(params)
-                .await
-                .map_err(|err| CommandError::from("获取用户视频信息失败", err))?;
-            SearchResult::UserVideo(UserVideoSearchResult(info))
-        }
-        Fav(params) => {
-            let info = bili_client
-                .get_fav_info(params)
-                .await
-                .map_err(|err| CommandError::from("获取收藏夹内容失败", err))?;
-            SearchResult::Fav(FavSearchResult(info))
-        }
-    };
+    let search_result = bili_client
+        .search(params)
+        .await
+        .map_err(|err| CommandError::from("搜索失败", err))?;
     Ok(search_result)
 }
 
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub async fn parse_links(app: AppHandle, text: String) -> Vec<ParsedTarget> {
+    let bili_client = app.get_bili_client();
+    link_parser::parse_text(&text, |short_link| async move {
+        bili_client.resolve_short_link(&short_link).await
+    })
+    .await
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
 #[instrument(level = "error", skip_all)]
 pub fn get_logs_dir_size(app: AppHandle) -> CommandResult<u64> {
-    let logs_dir = logger::logs_dir(&app)
-        .wrap_err("获取日志目录失败")
-        .map_err(|err| CommandError::from("获取日志目录大小失败", err))?;
+    let logs_dir = logger::logs_dir(&app.get_context());
     let logs_dir_size = std::fs::read_dir(&logs_dir)
         .wrap_err(format!("读取日志目录`{}`失败", logs_dir.display()))
         .map_err(|err| CommandError::from("获取日志目录大小失败", err))?
@@ -422,6 +351,22 @@ pub fn show_path_in_file_manager(app: AppHandle, path: &str) -> CommandResult<()
     Ok(())
 }
 
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all, fields(cid = cid, month = month))]
+pub async fn get_danmaku_history_dates(
+    app: AppHandle,
+    cid: i64,
+    month: String,
+) -> CommandResult<Vec<String>> {
+    let bili_client = app.get_bili_client();
+    let dates = bili_client
+        .get_danmaku_history_dates(cid, &month)
+        .await
+        .map_err(|err| CommandError::from("获取历史弹幕日期失败", err))?;
+    Ok(dates)
+}
+
 #[tauri::command(async)]
 #[specta::specta]
 #[instrument(level = "error", skip_all, fields(bvid = bvid, cid = cid))]
@@ -576,3 +521,174 @@ pub fn set_plugin_priority(
 
     Ok(())
 }
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[instrument(level = "error", skip_all)]
+pub fn get_subscriptions(app: AppHandle) -> Vec<Subscription> {
+    app.get_subscription_manager().get_subscriptions()
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn add_subscription(app: AppHandle, params: AddSubscriptionParams) -> CommandResult<()> {
+    let subscription_manager = app.get_subscription_manager();
+
+    subscription_manager
+        .add_subscription(params)
+        .map_err(|err| CommandError::from("添加订阅失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn update_subscription(app: AppHandle, params: UpdateSubscriptionParams) -> CommandResult<()> {
+    let subscription_manager = app.get_subscription_manager();
+
+    subscription_manager
+        .update_subscription(params)
+        .map_err(|err| CommandError::from("修改订阅失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all, fields(mid = mid))]
+pub fn remove_subscription(app: AppHandle, mid: i64) -> CommandResult<()> {
+    let subscription_manager = app.get_subscription_manager();
+
+    subscription_manager
+        .remove_subscription(mid)
+        .map_err(|err| CommandError::from("删除订阅失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub async fn check_subscriptions(app: AppHandle) {
+    app.get_subscription_manager().check_subscriptions().await;
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[instrument(level = "error", skip_all)]
+pub fn get_mirror_syncs(app: AppHandle) -> Vec<MirrorSync> {
+    app.get_mirror_sync_manager().get_mirror_syncs()
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[instrument(level = "error", skip_all)]
+pub fn get_archived_items(app: AppHandle, source: MirrorSource) -> Vec<ArchivedItem> {
+    app.get_mirror_sync_manager().get_archived_items(&source)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn add_mirror_sync(app: AppHandle, params: AddMirrorSyncParams) -> CommandResult<()> {
+    let mirror_sync_manager = app.get_mirror_sync_manager();
+
+    mirror_sync_manager
+        .add_mirror_sync(params)
+        .map_err(|err| CommandError::from("添加同步失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn update_mirror_sync(app: AppHandle, params: UpdateMirrorSyncParams) -> CommandResult<()> {
+    let mirror_sync_manager = app.get_mirror_sync_manager();
+
+    mirror_sync_manager
+        .update_mirror_sync(&params)
+        .map_err(|err| CommandError::from("修改同步失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub fn remove_mirror_sync(app: AppHandle, source: MirrorSource) -> CommandResult<()> {
+    let mirror_sync_manager = app.get_mirror_sync_manager();
+
+    mirror_sync_manager
+        .remove_mirror_sync(&source)
+        .map_err(|err| CommandError::from("删除同步失败", err))?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub async fn sync_mirrors(app: AppHandle) {
+    app.get_mirror_sync_manager().sync_all().await;
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[instrument(level = "error", skip_all)]
+pub fn query_download_history(
+    app: AppHandle,
+    params: QueryDownloadHistoryParams,
+) -> DownloadHistoryQueryResult {
+    let download_manager = app.get_download_manager();
+    download_manager.download_history.query(&params)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[instrument(level = "error", skip_all)]
+pub fn get_live_recordings(app: AppHandle) -> Vec<LiveRecording> {
+    app.get_live_recorder_manager().get_live_recordings()
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all)]
+pub async fn start_live_recording(app: AppHandle, room: String) -> CommandResult<LiveRecording> {
+    let live_recorder_manager = app.get_live_recorder_manager();
+    let recording = live_recorder_manager
+        .start_live_recording(&room)
+        .await
+        .map_err(|err| CommandError::from("开始录制直播失败", err))?;
+    Ok(recording)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+#[instrument(level = "error", skip_all, fields(room_id = room_id))]
+pub async fn stop_live_recording(app: AppHandle, room_id: i64) -> CommandResult<()> {
+    let live_recorder_manager = app.get_live_recorder_manager();
+
+    live_recorder_manager
+        .stop_live_recording(room_id)
+        .await
+        .map_err(|err| CommandError::from("停止录制直播失败", err))?;
+
+    Ok(())
+}
@@ -2,11 +2,14 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
 use tracing::instrument;
+use uuid::Uuid;
 
 use crate::{
-    danmaku_xml_to_ass::canvas::CanvasConfig,
+    app_context::AppContext,
+    danmaku_xml_to_ass::{
+        analysis::DanmakuAnalysisConfig, canvas::CanvasConfig, filter::DanmakuFilter,
+    },
     types::{audio_quality::AudioQuality, codec_type::CodecType, video_quality::VideoQuality},
 };
 
@@ -25,10 +28,37 @@ pub struct Config {
     pub auto_merge: bool,
     pub embed_chapter: bool,
     pub embed_skip: bool,
+    /// 根据弹幕密度找出高光片段，作为章节嵌入
+    pub embed_highlight: bool,
     pub download_xml_danmaku: bool,
     pub download_ass_danmaku: bool,
     pub download_json_danmaku: bool,
+    pub download_vtt_danmaku: bool,
+    pub download_srt_danmaku: bool,
+    /// 导出包含时间、类型、颜色、发送者哈希和内容的CSV，方便分析
+    pub download_csv_danmaku: bool,
+    /// 导出每秒弹幕数、关键词爆发和高光片段的JSON
+    pub download_danmaku_heatmap: bool,
+    /// 历史弹幕会与实时弹幕按`id_str`去重合并，需要登录
+    pub history_danmaku_mode: HistoryDanmakuMode,
+    /// 格式为`YYYY-MM-DD`，为空时从视频发布的那天开始
+    pub history_danmaku_start_date: String,
+    /// 格式为`YYYY-MM-DD`，为空时到今天为止
+    pub history_danmaku_end_date: String,
+    /// 获取历史弹幕时每次请求之间休息多久，包括查询每个月哪些天有历史弹幕，避免请求太频繁被风控
+    pub history_danmaku_interval_sec: u64,
     pub download_subtitle: bool,
+    /// 字幕保存为哪些格式
+    pub subtitle_formats: Vec<SubtitleOutputFormat>,
+    /// 只保存这些语言的字幕，如`zh-CN`、`en-US`、`ai-zh`，为空时保存全部
+    pub subtitle_languages: Vec<String>,
+    /// 不保存语言代码以`ai-`开头的AI字幕
+    pub exclude_ai_subtitle: bool,
+    /// 双语字幕上面一行的语言，与`bilingual_subtitle_secondary`都不为空时，
+    /// 把两种语言合并为`{filename}.{上}+{下}.srt`和`.ass`，只生成`subtitle_formats`中选了的格式
+    pub bilingual_subtitle_primary: String,
+    /// 双语字幕下面一行的语言
+    pub bilingual_subtitle_secondary: String,
     pub download_cover: bool,
     pub download_nfo: bool,
     pub download_json: bool,
@@ -42,15 +72,49 @@ pub struct Config {
     pub task_download_interval_sec: u64,
     pub chunk_concurrency: usize,
     pub chunk_download_interval_sec: u64,
+    pub speed_limit_kb_per_sec: u64,
     pub danmaku_config: CanvasConfig,
+    /// 生成ASS弹幕前的屏蔽规则
+    pub danmaku_filter: DanmakuFilter,
+    /// 弹幕热度和高光片段的统计参数
+    pub danmaku_analysis: DanmakuAnalysisConfig,
     pub file_exist_action: FileExistAction,
     pub auto_start_download_task: bool,
+    pub remote_api_enabled: bool,
+    /// 远程API监听的地址，例如`127.0.0.1:5006`，局域网访问需要改为`0.0.0.0:5006`
+    pub remote_api_bind_address: String,
+    /// 访问令牌，请求时放在`Authorization`头或`token`查询参数中
+    pub remote_api_token: String,
+    /// 允许跨域访问远程API的网页来源，如`https://www.bilibili.com`，为空时不允许任何网页跨域访问
+    pub remote_api_allowed_origins: Vec<String>,
+    /// 检查订阅的间隔(分钟)，为0时不自动检查
+    pub subscription_check_interval_min: u64,
+    /// 同步收藏夹和稍后再看的间隔(分钟)，为0时不自动同步
+    pub mirror_sync_interval_min: u64,
+    /// 创建下载任务时，如果下载历史中已经有相同的下载该怎么办
+    pub duplicate_download_action: DuplicateDownloadAction,
+    /// 录制直播时优先使用的直播流格式，没有时会使用另一种
+    pub live_stream_format: LiveStreamFormat,
+    /// 录制的文件超过这个大小(MB)就分段，为0时不按大小分段
+    pub live_split_size_mb: u64,
+    /// 录制的文件超过这个时长(分钟)就分段，为0时不按时长分段
+    pub live_split_duration_min: u64,
+    /// 视频处理后输出的容器格式，选MKV时字幕、弹幕和封面也会封装进去
+    pub output_container: OutputContainer,
+    /// 音频库模式，只下载音频，并写入标题、UP主、合集、序号、日期、简介和封面等标签
+    pub audio_library_mode: bool,
+    /// 音频库模式输出的格式
+    pub audio_library_format: AudioLibraryFormat,
+    /// 从视频中剪掉这些类型的空降助手片段，为空时不剪切
+    pub cut_skip_categories: Vec<SkipSegmentCategory>,
+    /// 空降助手的服务器地址
+    pub skip_segment_server: String,
 }
 
 impl Config {
     #[instrument(level = "error", skip_all)]
-    pub fn new(app: &AppHandle) -> eyre::Result<Config> {
-        let app_data_dir = app.path().app_data_dir()?;
+    pub fn new(app: &AppContext) -> eyre::Result<Config> {
+        let app_data_dir = app.app_data_dir();
         let config_path = app_data_dir.join("config.json");
 
         let config = if config_path.exists() {
@@ -60,18 +124,18 @@ impl Config {
                 Ok(config) => config,
                 // 否则，将默认配置与文件中已有的配置合并
                 // 以免新版本添加了新的配置项，用户升级到新版本后，所有配置项都被重置
-                Err(_) => Config::merge_config(&config_string, &app_data_dir),
+                Err(_) => Config::merge_config(&config_string, app_data_dir),
             }
         } else {
-            Config::default(&app_data_dir)
+            Config::default(app_data_dir)
         };
         config.save(app)?;
         Ok(config)
     }
 
     #[instrument(level = "error", skip_all)]
-    pub fn save(&self, app: &AppHandle) -> eyre::Result<()> {
-        let app_data_dir = app.path().app_data_dir()?;
+    pub fn save(&self, app: &AppContext) -> eyre::Result<()> {
+        let app_data_dir = app.app_data_dir();
         let config_path = app_data_dir.join("config.json");
         let config_string = serde_json::to_string_pretty(self)?;
         std::fs::write(config_path, config_string)?;
@@ -138,10 +202,24 @@ impl Config {
             auto_merge: true,
             embed_chapter: true,
             embed_skip: true,
+            embed_highlight: false,
             download_xml_danmaku: true,
             download_ass_danmaku: true,
             download_json_danmaku: true,
+            download_vtt_danmaku: false,
+            download_srt_danmaku: false,
+            download_csv_danmaku: false,
+            download_danmaku_heatmap: false,
+            history_danmaku_mode: HistoryDanmakuMode::Off,
+            history_danmaku_start_date: String::new(),
+            history_danmaku_end_date: String::new(),
+            history_danmaku_interval_sec: 1,
             download_subtitle: true,
+            subtitle_formats: vec![SubtitleOutputFormat::Srt],
+            subtitle_languages: Vec::new(),
+            exclude_ai_subtitle: false,
+            bilingual_subtitle_primary: String::new(),
+            bilingual_subtitle_secondary: String::new(),
             download_cover: true,
             download_nfo: true,
             download_json: true,
@@ -155,9 +233,27 @@ impl Config {
             task_download_interval_sec: 0,
             chunk_concurrency: 16,
             chunk_download_interval_sec: 0,
+            speed_limit_kb_per_sec: 0,
             danmaku_config: CanvasConfig::default(),
+            danmaku_filter: DanmakuFilter::default(),
+            danmaku_analysis: DanmakuAnalysisConfig::default(),
             file_exist_action: FileExistAction::Overwrite,
             auto_start_download_task: true,
+            remote_api_enabled: false,
+            remote_api_bind_address: "127.0.0.1:5006".to_string(),
+            remote_api_token: Uuid::new_v4().simple().to_string(),
+            remote_api_allowed_origins: vec!["https://www.bilibili.com".to_string()],
+            subscription_check_interval_min: 30,
+            mirror_sync_interval_min: 60,
+            duplicate_download_action: DuplicateDownloadAction::Warn,
+            live_stream_format: LiveStreamFormat::Flv,
+            live_split_size_mb: 0,
+            live_split_duration_min: 60,
+            output_container: OutputContainer::Mp4,
+            audio_library_mode: false,
+            audio_library_format: AudioLibraryFormat::Original,
+            cut_skip_categories: Vec::new(),
+            skip_segment_server: "https://bsbsb.top".to_string(),
         }
     }
 }
@@ -176,3 +272,85 @@ pub enum FileExistAction {
     Overwrite,
     Skip,
 }
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum DuplicateDownloadAction {
+    /// 照常创建下载任务，但在日志中警告
+    #[default]
+    Warn,
+    /// 不创建下载任务
+    Skip,
+    /// 照常创建下载任务
+    Redownload,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum LiveStreamFormat {
+    #[default]
+    Flv,
+    Hls,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum OutputContainer {
+    #[default]
+    Mp4,
+    Mkv,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum AudioLibraryFormat {
+    /// 不转码，FLAC输出为`.flac`，其他输出为`.m4a`
+    #[default]
+    Original,
+    /// 转码为`.mp3`，需要带有`libmp3lame`编码器的`FFmpeg`
+    Mp3,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum HistoryDanmakuMode {
+    /// 只下载实时弹幕
+    #[default]
+    Off,
+    /// 下载日期范围内每一天的历史弹幕
+    DateRange,
+    /// 下载从视频发布到今天每一天的历史弹幕，尽可能收集所有发过的弹幕
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum SubtitleOutputFormat {
+    Srt,
+    /// 带有B站字幕的字号、颜色和背景样式
+    Ass,
+    Vtt,
+    /// 只有开始时间，适合音乐区的歌词
+    Lrc,
+    /// B站返回的JSON，视频被剪切过时时间轴也会调整
+    Json,
+}
+
+/// 可以从视频中剪掉的空降助手片段类型
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum SkipSegmentCategory {
+    /// 广告
+    Sponsor,
+    /// 过场/开场动画
+    Intro,
+    /// 鸣谢/结束画面
+    Outro,
+    /// 无偿/自我推广
+    Selfpromo,
+}
+
+impl SkipSegmentCategory {
+    /// 空降助手API中的`category`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipSegmentCategory::Sponsor => "sponsor",
+            SkipSegmentCategory::Intro => "intro",
+            SkipSegmentCategory::Outro => "outro",
+            SkipSegmentCategory::Selfpromo => "selfpromo",
+        }
+    }
+}
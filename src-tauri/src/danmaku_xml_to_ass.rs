@@ -1,14 +1,19 @@
+pub mod advanced;
+pub mod analysis;
 pub mod ass_writer;
 pub mod canvas;
 pub mod danmaku;
 pub mod drawable;
+pub mod export;
+pub mod filter;
 
 use std::{cmp::Ordering, fs::File};
 
 use ass_writer::AssWriter;
 use canvas::CanvasConfig;
 use danmaku::{Danmaku, DanmakuType};
-use eyre::eyre;
+use eyre::{WrapErr, eyre};
+use filter::DanmakuFilter;
 use tracing::instrument;
 use yaserde::{YaDeserialize, YaSerialize};
 
@@ -35,18 +40,12 @@ pub fn xml_to_ass(
     ass_file: File,
     title: String,
     config: CanvasConfig,
+    filter: &DanmakuFilter,
 ) -> eyre::Result<()> {
     let mut writer = AssWriter::new(ass_file, title, config.clone())?;
     let mut canvas = config.canvas();
 
-    let mut danmakus: Vec<Danmaku> = xml_to_danmakus(xml)?;
-    danmakus.sort_by(|a, b| {
-        a.timeline_s
-            .partial_cmp(&b.timeline_s)
-            .unwrap_or(Ordering::Equal)
-    });
-
-    for danmaku in danmakus {
+    for danmaku in sorted_danmakus(xml, filter)? {
         if let Some(drawable) = canvas.draw(danmaku) {
             writer.write(drawable)?;
         }
@@ -55,6 +54,17 @@ pub fn xml_to_ass(
     Ok(())
 }
 
+/// 解析`xml`，按时间排序后过滤
+fn sorted_danmakus(xml: &str, filter: &DanmakuFilter) -> eyre::Result<Vec<Danmaku>> {
+    let mut danmakus: Vec<Danmaku> = xml_to_danmakus(xml)?;
+    danmakus.sort_by(|a, b| {
+        a.timeline_s
+            .partial_cmp(&b.timeline_s)
+            .unwrap_or(Ordering::Equal)
+    });
+    filter.apply(danmakus).wrap_err("过滤弹幕失败")
+}
+
 trait ToDanmakuType {
     fn to_danmaku_type(&self) -> eyre::Result<DanmakuType>;
 }
@@ -67,6 +77,7 @@ impl ToDanmakuType for u32 {
             4 => Ok(DanmakuType::Bottom),
             5 => Ok(DanmakuType::Top),
             6 => Ok(DanmakuType::Reverse),
+            7 => Ok(DanmakuType::Advanced),
             _ => Err(eyre!("未知的弹幕类型：{self}")),
         }
     }
@@ -78,6 +89,8 @@ pub fn xml_to_danmakus(xml: &str) -> eyre::Result<Vec<Danmaku>> {
     let i_tag: DanmakuXmlITag = yaserde::de::from_str(&xml).map_err(|e| eyre!(e))?;
 
     let mut danmakus = Vec::new();
+    // 代码弹幕、BAS弹幕等无法转换的弹幕
+    let mut skipped_count = 0;
 
     for elem in i_tag.elems {
         let Some(content) = elem.body else {
@@ -90,13 +103,17 @@ pub fn xml_to_danmakus(xml: &str) -> eyre::Result<Vec<Danmaku>> {
             return Err(eyre!("弹幕`{content}`的p属性中没有时间"));
         };
 
-        let Some(r#type) = p_attr
-            .next()
-            .and_then(|s| s.parse::<u32>().ok())
-            .and_then(|num| num.to_danmaku_type().ok())
-        else {
+        let Some(mode) = p_attr.next().and_then(|s| s.parse::<u32>().ok()) else {
             return Err(eyre!("弹幕`{content}`的p属性中没有弹幕类型"));
         };
+        let r#type = match mode.to_danmaku_type() {
+            Ok(r#type) => r#type,
+            Err(err) => {
+                tracing::debug!("跳过弹幕`{content}`: {err}");
+                skipped_count += 1;
+                continue;
+            }
+        };
 
         let Some(fontsize) = p_attr.next().and_then(|s| s.parse::<u32>().ok()) else {
             return Err(eyre!("弹幕`{content}`的p属性中没有字体大小"));
@@ -106,6 +123,26 @@ pub fn xml_to_danmakus(xml: &str) -> eyre::Result<Vec<Danmaku>> {
             return Err(eyre!("弹幕`{content}`的p属性中没有颜色"));
         };
 
+        // 依次是发送时间、弹幕池、发送者UID的哈希、弹幕ID，权重是后来才加上的，直播弹幕的权重为0
+        let mid_hash = p_attr.nth(2).unwrap_or_default().to_string();
+        let weight = p_attr
+            .nth(1)
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|&weight| weight > 0);
+
+        let (content, advanced) = if r#type == DanmakuType::Advanced {
+            match advanced::parse(&content) {
+                Ok((text, advanced)) => (text, Some(advanced)),
+                Err(err) => {
+                    tracing::debug!("跳过无法解析的高级弹幕`{content}`: {err}");
+                    skipped_count += 1;
+                    continue;
+                }
+            }
+        } else {
+            (content, None)
+        };
+
         // rgb 是个数字，类似 0x010203
         let r = (rgb >> 16) & 0xff;
         let g = (rgb >> 8) & 0xff;
@@ -117,11 +154,18 @@ pub fn xml_to_danmakus(xml: &str) -> eyre::Result<Vec<Danmaku>> {
             r#type,
             fontsize,
             rgb: (r as u8, g as u8, b as u8),
+            mid_hash,
+            weight,
+            advanced,
         };
 
         danmakus.push(danmaku);
     }
 
+    if skipped_count > 0 {
+        tracing::warn!("跳过了{skipped_count}条不支持的弹幕");
+    }
+
     Ok(danmakus)
 }
 
@@ -138,3 +182,95 @@ fn sanitize_xml(s: &str) -> String {
     }
     s.chars().filter(|&c| is_valid_xml_char(c)).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{read_fixture, small_canvas_config};
+
+    use super::*;
+
+    /// 把`{name}.xml`转换为ASS，与`{name}.ass`比较
+    fn assert_ass_snapshot(name: &str) {
+        let xml = read_fixture(&format!("danmaku_xml_to_ass/{name}.xml"));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let ass_path = temp_dir.path().join(format!("{name}.ass"));
+
+        let ass_file = File::create(&ass_path).unwrap();
+        xml_to_ass(
+            &xml,
+            ass_file,
+            name.to_string(),
+            small_canvas_config(),
+            &DanmakuFilter::default(),
+        )
+        .unwrap();
+
+        let ass = std::fs::read_to_string(&ass_path).unwrap();
+        assert_eq!(ass, read_fixture(&format!("danmaku_xml_to_ass/{name}.ass")));
+    }
+
+    #[test]
+    fn render_float_danmaku() {
+        assert_ass_snapshot("float");
+    }
+
+    #[test]
+    fn render_top_danmaku() {
+        assert_ass_snapshot("top");
+    }
+
+    #[test]
+    fn render_bottom_danmaku() {
+        assert_ass_snapshot("bottom");
+    }
+
+    #[test]
+    fn render_reverse_danmaku() {
+        assert_ass_snapshot("reverse");
+    }
+
+    /// 代码弹幕、BAS弹幕和无法解析的高级弹幕都会被跳过
+    #[test]
+    fn render_advanced_danmaku() {
+        assert_ass_snapshot("advanced");
+    }
+
+    #[test]
+    fn fit_canvas_to_video_resolution() {
+        let size = |config: &CanvasConfig| {
+            (
+                config.width,
+                config.height,
+                config.font_size,
+                config.lane_size,
+            )
+        };
+
+        let config = CanvasConfig::default().fit_resolution(3840, 2160);
+        assert_eq!(size(&config), (3840, 2160, 75, 96));
+        // 缩放后槽位数量不变
+        assert_eq!(config.canvas().float_lanes.len(), 11);
+
+        let config = CanvasConfig::default().fit_resolution(640, 360);
+        assert_eq!(size(&config), (640, 360, 13, 16));
+        assert!((config.horizontal_gap - 10.0).abs() < f64::EPSILON);
+
+        // 竖屏视频按短边缩放，画布更高，槽位更多
+        let config = CanvasConfig::default().fit_resolution(1080, 1920);
+        assert_eq!(size(&config), (1080, 1920, 38, 48));
+        assert_eq!(config.canvas().float_lanes.len(), 20);
+
+        let config = CanvasConfig {
+            auto_resize: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            size(&config.fit_resolution(3840, 2160)),
+            (1280, 720, 25, 32)
+        );
+        assert_eq!(
+            size(&CanvasConfig::default().fit_resolution(0, 0)),
+            (1280, 720, 25, 32)
+        );
+    }
+}
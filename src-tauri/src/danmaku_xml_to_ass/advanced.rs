@@ -0,0 +1,168 @@
+//! 高级弹幕(mode 7)，弹幕内容是一个JSON数组，依次为
+//! `[x, y, "透明度起-止", 存活秒数, 文字, Z轴旋转, Y轴旋转, 终点x, 终点y, 移动耗时毫秒, 移动延迟毫秒, 描边, 字体, 线性加速]`，
+//! 从第6项开始都可以省略
+
+use eyre::{OptionExt, eyre};
+use serde_json::Value;
+
+/// 高级弹幕的坐标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coordinate {
+    /// 相对于播放器大小的比例，0~1
+    Ratio(f64),
+    /// 以旧版播放器大小为准的像素
+    Pixel(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdvancedDanmaku {
+    pub start: (Coordinate, Coordinate),
+    /// 不移动的弹幕为`None`
+    pub end: Option<(Coordinate, Coordinate)>,
+    /// 起止的不透明度，0~1
+    pub alpha: (f64, f64),
+    pub lifetime_s: f64,
+    /// 单位为度，顺时针为正
+    pub rotate_z: f64,
+    pub rotate_y: f64,
+    pub move_duration_ms: u64,
+    pub move_delay_ms: u64,
+    pub stroke: bool,
+    pub font: Option<String>,
+}
+
+/// 解析高级弹幕的JSON，返回要显示的文字和弹幕的参数
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+pub fn parse(content: &str) -> eyre::Result<(String, AdvancedDanmaku)> {
+    let values: Vec<Value> = serde_json::from_str(content).map_err(|e| eyre!(e))?;
+    let get = |index: usize| values.get(index).filter(|value| !value.is_null());
+
+    let start = (
+        get(0)
+            .and_then(coordinate)
+            .ok_or_eyre("高级弹幕缺少起点x")?,
+        get(1)
+            .and_then(coordinate)
+            .ok_or_eyre("高级弹幕缺少起点y")?,
+    );
+    let alpha = match get(2).and_then(Value::as_str) {
+        Some(alpha) => {
+            let (from, to) = alpha.split_once('-').unwrap_or((alpha, alpha));
+            let parse_alpha = |s: &str| s.trim().parse::<f64>().ok().map(|a| a.clamp(0.0, 1.0));
+            (
+                parse_alpha(from).unwrap_or(1.0),
+                parse_alpha(to).unwrap_or(1.0),
+            )
+        }
+        None => (1.0, 1.0),
+    };
+    let lifetime_s = get(3).and_then(number).unwrap_or(4.5).max(0.0);
+    let text = get(4)
+        .and_then(Value::as_str)
+        .ok_or_eyre("高级弹幕缺少文字")?
+        // B站用`/n`表示换行
+        .replace("/n", "\n");
+
+    let end = match (get(7).and_then(coordinate), get(8).and_then(coordinate)) {
+        (Some(x), Some(y)) => Some((x, y)),
+        _ => None,
+    };
+    let move_duration_ms = get(9)
+        .and_then(number)
+        .map_or(lifetime_s * 1000.0, |ms| ms.max(0.0)) as u64;
+    let move_delay_ms = get(10).and_then(number).unwrap_or(0.0).max(0.0) as u64;
+    let stroke = match get(11) {
+        Some(Value::Bool(stroke)) => *stroke,
+        Some(value) => number(value).is_none_or(|stroke| stroke != 0.0),
+        None => true,
+    };
+    let font = get(12)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|font| !font.is_empty())
+        .map(ToString::to_string);
+
+    let danmaku = AdvancedDanmaku {
+        start,
+        end,
+        alpha,
+        lifetime_s,
+        rotate_z: get(5).and_then(number).unwrap_or(0.0),
+        rotate_y: get(6).and_then(number).unwrap_or(0.0),
+        move_duration_ms,
+        move_delay_ms,
+        stroke,
+        font,
+    };
+    Ok((text, danmaku))
+}
+
+/// 数字可能直接是数字，也可能是字符串
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// 整数或大于1的小数是像素，不大于1的小数是比例
+#[allow(clippy::cast_precision_loss)]
+fn coordinate(value: &Value) -> Option<Coordinate> {
+    let integer = match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    };
+    if let Some(integer) = integer {
+        return Some(Coordinate::Pixel(integer as f64));
+    }
+
+    let float = number(value)?;
+    if float > 1.0 {
+        Some(Coordinate::Pixel(float))
+    } else {
+        Some(Coordinate::Ratio(float))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_advanced_danmaku() {
+        let (text, danmaku) = parse(
+            r#"["0.1","20","1-0.5","6","第一行/n第二行",90,0,"300","0.8",1000,500,0,"微软雅黑",1]"#,
+        )
+        .unwrap();
+
+        assert_eq!(text, "第一行\n第二行");
+        assert_eq!(
+            danmaku,
+            AdvancedDanmaku {
+                start: (Coordinate::Ratio(0.1), Coordinate::Pixel(20.0)),
+                end: Some((Coordinate::Pixel(300.0), Coordinate::Ratio(0.8))),
+                alpha: (1.0, 0.5),
+                lifetime_s: 6.0,
+                rotate_z: 90.0,
+                rotate_y: 0.0,
+                move_duration_ms: 1000,
+                move_delay_ms: 500,
+                stroke: false,
+                font: Some("微软雅黑".to_string()),
+            }
+        );
+
+        // 省略的部分使用默认值
+        let (text, danmaku) = parse(r#"[0.5,0.5,"1-1",3,"静止"]"#).unwrap();
+        assert_eq!(text, "静止");
+        assert_eq!(danmaku.end, None);
+        assert_eq!(danmaku.move_duration_ms, 3000);
+        assert!(danmaku.stroke);
+
+        assert!(parse("不是JSON").is_err());
+        assert!(parse(r"[0.5,0.5]").is_err());
+    }
+}
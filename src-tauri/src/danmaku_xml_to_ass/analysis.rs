@@ -0,0 +1,286 @@
+//! 统计弹幕随时间的密度，找出弹幕突然变多的高光片段
+
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::instrument;
+
+use crate::downloader::chapter_segments::ChapterSegment;
+
+use super::{danmaku::Danmaku, xml_to_danmakus};
+
+/// 窗口内的弹幕少于这个数时，即使超过了阈值也不算高光，避免弹幕很少的视频到处都是高光
+const MIN_PEAK_COUNT: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct DanmakuAnalysisConfig {
+    /// 单独统计密度和爆发的关键词，不区分大小写
+    pub keywords: Vec<String>,
+    /// 统计高光和爆发时的窗口长度，单位为秒
+    pub window_s: u64,
+    /// 窗口内的弹幕数达到所有窗口平均值的多少倍才算高光
+    pub threshold: f64,
+    /// 最多找出几个高光片段，每个关键词最多找出几次爆发
+    pub max_highlights: usize,
+}
+
+impl Default for DanmakuAnalysisConfig {
+    fn default() -> Self {
+        Self {
+            keywords: ["名场面", "233", "awsl", "哈哈哈", "高能", "泪目"]
+                .map(ToString::to_string)
+                .to_vec(),
+            window_s: 10,
+            threshold: 2.0,
+            max_highlights: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DanmakuHeatmap {
+    pub window_s: u64,
+    /// 第i项为第i秒内的弹幕数
+    pub density: Vec<u32>,
+    pub keywords: Vec<KeywordHeatmap>,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordHeatmap {
+    pub keyword: String,
+    /// 第i项为第i秒内包含这个关键词的弹幕数
+    pub density: Vec<u32>,
+    pub bursts: Vec<Peak>,
+}
+
+/// 弹幕数明显高于平均值的时间段，单位为秒，左闭右开
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Peak {
+    pub start: u64,
+    pub end: u64,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Highlight {
+    #[serde(flatten)]
+    pub peak: Peak,
+    /// 这段时间里出现最多的关键词，没有关键词时为`None`
+    pub keyword: Option<String>,
+}
+
+impl DanmakuAnalysisConfig {
+    /// 统计`danmakus`的密度，`duration_s`为视频时长，超出视频时长的弹幕也会统计
+    pub fn analyze(&self, danmakus: &[Danmaku], duration_s: u64) -> DanmakuHeatmap {
+        let keywords: Vec<String> = self
+            .keywords
+            .iter()
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+
+        let last_second = danmakus.iter().map(second_of).max().map_or(0, |s| s + 1);
+        let len = usize::try_from(duration_s)
+            .unwrap_or(usize::MAX)
+            .max(last_second);
+        let mut density = vec![0; len];
+        let mut keyword_densities = vec![vec![0; len]; keywords.len()];
+        for danmaku in danmakus {
+            let second = second_of(danmaku);
+            density[second] += 1;
+            let content = danmaku.content.to_lowercase();
+            for (keyword, keyword_density) in keywords.iter().zip(&mut keyword_densities) {
+                if content.contains(keyword.as_str()) {
+                    keyword_density[second] += 1;
+                }
+            }
+        }
+
+        let window = usize::try_from(self.window_s).unwrap_or(usize::MAX);
+        let find_peaks =
+            |series: &[u32]| find_peaks(series, window, self.threshold, self.max_highlights);
+
+        let highlights = find_peaks(&density)
+            .into_iter()
+            .map(|peak| {
+                let range = to_usize(peak.start)..to_usize(peak.end);
+                // 出现次数相同时取排在前面的关键词
+                let keyword = keywords
+                    .iter()
+                    .zip(&keyword_densities)
+                    .map(|(keyword, density)| (keyword, density[range.clone()].iter().sum::<u32>()))
+                    .filter(|&(_, count)| count > 0)
+                    .min_by_key(|&(_, count)| Reverse(count))
+                    .map(|(keyword, _)| keyword.clone());
+                Highlight { peak, keyword }
+            })
+            .collect();
+
+        let keywords = keywords
+            .into_iter()
+            .zip(keyword_densities)
+            .map(|(keyword, density)| KeywordHeatmap {
+                bursts: find_peaks(&density),
+                keyword,
+                density,
+            })
+            .collect();
+
+        DanmakuHeatmap {
+            window_s: self.window_s,
+            density,
+            keywords,
+            highlights,
+        }
+    }
+}
+
+impl DanmakuHeatmap {
+    /// 把高光片段转换为章节，标题为`高光`或`高光：关键词`
+    pub fn highlight_chapters(&self) -> Vec<ChapterSegment> {
+        self.highlights
+            .iter()
+            .map(|highlight| ChapterSegment {
+                title: match &highlight.keyword {
+                    Some(keyword) => format!("高光：{keyword}"),
+                    None => "高光".to_string(),
+                },
+                start: i64::try_from(highlight.peak.start).unwrap_or(i64::MAX),
+                end: i64::try_from(highlight.peak.end).unwrap_or(i64::MAX),
+            })
+            .collect()
+    }
+}
+
+/// 解析`xml`并统计弹幕密度，不经过屏蔽规则，所有弹幕都算在内
+#[instrument(level = "error", skip_all)]
+pub fn xml_to_heatmap(
+    xml: &str,
+    config: &DanmakuAnalysisConfig,
+    duration_s: u64,
+) -> eyre::Result<DanmakuHeatmap> {
+    let danmakus = xml_to_danmakus(xml)?;
+    Ok(config.analyze(&danmakus, duration_s))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn second_of(danmaku: &Danmaku) -> usize {
+    danmaku.timeline_s.max(0.0) as usize
+}
+
+fn to_usize(second: u64) -> usize {
+    usize::try_from(second).unwrap_or(usize::MAX)
+}
+
+/// 用长为`window`秒的滑动窗口找出弹幕数不低于所有窗口平均值`ratio`倍的时间段
+///
+/// 从弹幕最多的窗口开始贪心地选，选出的时间段互不重叠，最多选`max_count`个，结果按时间排序
+#[allow(clippy::cast_precision_loss)]
+fn find_peaks(series: &[u32], window: usize, ratio: f64, max_count: usize) -> Vec<Peak> {
+    if series.is_empty() || window == 0 {
+        return Vec::new();
+    }
+    let window = window.min(series.len());
+
+    let sums: Vec<u32> = series.windows(window).map(|w| w.iter().sum()).collect();
+    let mean = sums.iter().map(|&sum| f64::from(sum)).sum::<f64>() / sums.len() as f64;
+    let threshold = (mean * ratio).max(f64::from(MIN_PEAK_COUNT));
+
+    // 稳定排序，弹幕数相同时先选时间靠前的窗口
+    let mut starts: Vec<usize> = (0..sums.len()).collect();
+    starts.sort_by_key(|&start| Reverse(sums[start]));
+
+    let mut peaks: Vec<Peak> = Vec::new();
+    for start in starts {
+        if peaks.len() >= max_count || f64::from(sums[start]) < threshold {
+            break;
+        }
+        let (start, end) = (start as u64, (start + window) as u64);
+        if peaks
+            .iter()
+            .any(|peak| start < peak.end && peak.start < end)
+        {
+            continue;
+        }
+        peaks.push(Peak {
+            start,
+            end,
+            count: sums[to_usize(start)],
+        });
+    }
+    peaks.sort_by_key(|peak| peak.start);
+
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::read_fixture;
+
+    use super::*;
+
+    fn heatmap(config: &DanmakuAnalysisConfig) -> DanmakuHeatmap {
+        let xml = read_fixture("danmaku_xml_to_ass/heatmap.xml");
+        xml_to_heatmap(&xml, config, 120).unwrap()
+    }
+
+    #[test]
+    fn count_danmakus_per_second() {
+        let heatmap = heatmap(&DanmakuAnalysisConfig::default());
+
+        assert_eq!(heatmap.density.len(), 120);
+        assert_eq!(heatmap.density[..5], [1, 0, 0, 0, 0]);
+        assert_eq!(heatmap.density[30..35], [6, 6, 6, 6, 6]);
+        assert_eq!(heatmap.density.iter().sum::<u32>(), 69);
+
+        let awsl = &heatmap.keywords[2];
+        assert_eq!(awsl.keyword, "awsl");
+        // 不区分大小写
+        assert_eq!(awsl.density[30..35], [4, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn find_highlights_and_keyword_bursts() {
+        let heatmap = heatmap(&DanmakuAnalysisConfig::default());
+
+        let highlights: Vec<(u64, u64, Option<&str>)> = heatmap
+            .highlights
+            .iter()
+            .map(|h| (h.peak.start, h.peak.end, h.keyword.as_deref()))
+            .collect();
+        assert_eq!(highlights, [(25, 35, Some("awsl")), (75, 85, Some("233"))]);
+
+        let bursts = |keyword: &str| -> Vec<(u64, u64)> {
+            let keyword = heatmap
+                .keywords
+                .iter()
+                .find(|k| k.keyword == keyword)
+                .unwrap();
+            keyword.bursts.iter().map(|b| (b.start, b.end)).collect()
+        };
+        assert_eq!(bursts("awsl"), [(25, 35)]);
+        assert_eq!(bursts("233"), [(75, 85)]);
+        // 只出现一次的关键词不算爆发
+        assert!(bursts("名场面").is_empty());
+
+        let chapters = heatmap.highlight_chapters();
+        assert_eq!(chapters[0].title, "高光：awsl");
+        assert_eq!((chapters[1].start, chapters[1].end), (75, 85));
+    }
+
+    #[test]
+    fn limit_highlight_count() {
+        let config = DanmakuAnalysisConfig {
+            max_highlights: 1,
+            ..Default::default()
+        };
+        let heatmap = heatmap(&config);
+
+        assert_eq!(heatmap.highlights.len(), 1);
+        assert_eq!(heatmap.highlights[0].peak.start, 25);
+    }
+}
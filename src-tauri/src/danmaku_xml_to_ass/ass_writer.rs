@@ -30,13 +30,45 @@ struct AssEffect {
 }
 impl fmt::Display for AssEffect {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.effect {
+        match &self.effect {
             DrawEffect::Move { start, end } => {
                 let (x0, y0) = start;
                 let (x1, y1) = end;
                 write!(f, "\\move({x0}, {y0}, {x1}, {y1})")
             }
-            DrawEffect::Fixed {} => fmt::Result::Err(fmt::Error),
+            DrawEffect::Fixed { pos: (x, y) } => write!(f, "\\pos({x}, {y})"),
+            DrawEffect::Advanced(effect) => {
+                let (x0, y0) = effect.start;
+                match effect.motion {
+                    Some(((x1, y1), t1, t2)) => {
+                        write!(f, "\\move({x0}, {y0}, {x1}, {y1}, {t1}, {t2})")?;
+                    }
+                    None => write!(f, "\\pos({x0}, {y0})")?,
+                }
+                let (a0, a1) = effect.alpha;
+                if a0 == a1 {
+                    write!(f, "\\alpha&H{a0:02X}&")?;
+                } else {
+                    // `\fad`的完整形式，在整个存活时间内从起始透明度渐变到结束透明度
+                    let t = effect.duration_ms;
+                    write!(f, "\\fade({a0}, {a1}, {a1}, 0, {t}, {t}, {t})")?;
+                }
+                // B站的旋转以顺时针为正，ASS以逆时针为正
+                if effect.rotate_z.abs() > f64::EPSILON {
+                    write!(f, "\\frz{}", -effect.rotate_z)?;
+                }
+                if effect.rotate_y.abs() > f64::EPSILON {
+                    write!(f, "\\fry{}", effect.rotate_y)?;
+                }
+                if let Some(font) = &effect.font {
+                    write!(f, "\\fn{font}")?;
+                }
+                write!(f, "\\fs{}", effect.font_size)?;
+                if !effect.stroke {
+                    write!(f, "\\bord0")?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -60,9 +92,18 @@ impl CanvasConfig {
                 outline = self.outline,
             ),
             format!(
-                "Style: Bottom,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
+                "Style: Advanced,{font},{font_size},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
                 {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
                 {outline}, 0, 7, 0, 0, 0, 1",
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+                outline = self.outline,
+            ),
+            format!(
+                "Style: Bottom,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                {outline}, 0, 2, 0, 0, 0, 1",
                 a = opacity,
                 font = self.font,
                 font_size = self.font_size,
@@ -72,7 +113,7 @@ impl CanvasConfig {
             format!(
                 "Style: Top,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
                 {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
-                {outline}, 0, 7, 0, 0, 0, 1",
+                {outline}, 0, 8, 0, 0, 0, 1",
                 a = opacity,
                 font = self.font,
                 font_size = self.font_size,
@@ -1,4 +1,4 @@
-use crate::danmaku_xml_to_ass::danmaku::Danmaku;
+use crate::danmaku_xml_to_ass::danmaku::{Danmaku, DanmakuType};
 
 use super::CanvasConfig;
 
@@ -24,6 +24,8 @@ pub enum Collision {
 pub struct Lane {
     last_shoot_time: f64,
     last_length: f64,
+    /// 上一条弹幕是否是从左往右移动的逆向弹幕
+    reverse: bool,
 }
 
 impl Lane {
@@ -31,17 +33,23 @@ impl Lane {
         Lane {
             last_shoot_time: danmaku.timeline_s,
             last_length: danmaku.length(config),
+            reverse: danmaku.r#type == DanmakuType::Reverse,
         }
     }
     /// 如底部弹幕等不需要记录长度的
-    #[allow(dead_code)]
     pub fn draw_fixed(danmaku: &Danmaku) -> Self {
         Lane {
             last_shoot_time: danmaku.timeline_s,
             last_length: 0.0,
+            reverse: false,
         }
     }
 
+    /// 顶部、底部弹幕还需要等多久才能在这个槽位里画，不大于0表示可以直接画
+    pub fn fixed_time_needed(&self, other: &Danmaku, config: &CanvasConfig) -> f64 {
+        self.last_shoot_time + config.fixed_duration - other.timeline_s
+    }
+
     /// 这个槽位是否可以发射另外一条弹幕，返回可能的情形
     #[allow(clippy::cast_lossless)]
     pub fn available_for(&self, other: &Danmaku, config: &super::CanvasConfig) -> Collision {
@@ -61,6 +69,13 @@ impl Lane {
         let v2 = (W + l2) / T;
 
         let delta_t = t2 - t1;
+        // 方向相反的两条弹幕迎面而来，只能等上一条完全离开屏幕
+        if self.reverse != (other.r#type == DanmakuType::Reverse) {
+            return Collision::Collide {
+                time_needed: T - delta_t,
+            };
+        }
+        // 方向相同时，逆向弹幕与滚动弹幕是镜像的，可以用同样的方法计算
         // 第一条弹幕右边到屏幕右边的距离
         let delta_x = v1 * delta_t - l1;
         // 没有足够的空间，必定碰撞
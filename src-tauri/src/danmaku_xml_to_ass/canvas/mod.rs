@@ -6,19 +6,29 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 
 use super::{
-    danmaku::Danmaku,
-    drawable::{DrawEffect, Drawable},
+    advanced::Coordinate,
+    danmaku::{Danmaku, DanmakuType},
+    drawable::{AdvancedEffect, DrawEffect, Drawable},
 };
 mod lane;
 
+/// 高级弹幕的坐标是以旧版播放器的大小为准的
+const BILI_PLAYER_SIZE: (f64, f64) = (672.0, 438.0);
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
 pub struct CanvasConfig {
     /// 弹幕在屏幕上的【持续时间】，单位为秒，可以有小数
     pub duration: f64,
+    /// 顶部、底部弹幕在屏幕上的【持续时间】，单位为秒，可以有小数
+    pub fixed_duration: f64,
     /// 渲染的屏幕分辨率，这个并不会影响渲染区域的大小，只是字体的相对大小，可以不用改动
     pub width: u32,
     /// 渲染的屏幕分辨率，这个并不会影响渲染区域的大小，只是字体的相对大小，可以不用改动
     pub height: u32,
+    /// 按视频的实际分辨率调整画布，字体、行高、间距和描边按视频短边与`width`、`height`短边的比例缩放，
+    /// 此时上面的分辨率和各项尺寸只作为参考值
+    pub auto_resize: bool,
     /// 使用字体名称
     pub font: String,
     /// 弹幕字体大小
@@ -33,8 +43,9 @@ pub struct CanvasConfig {
     pub lane_size: u32,
     /// 【正常弹幕的屏幕填充占比】，默认为 50%，即“半屏填充”。
     pub float_percentage: f64,
+    /// 屏幕上顶部弹幕最多高度百分比
+    pub top_percentage: f64,
     /// 屏幕上底部弹幕最多高度百分比
-    #[serde(skip)]
     pub bottom_percentage: f64,
     /// 弹幕的不透明度，越小越透明，越大越不透明
     pub alpha: f64,
@@ -50,14 +61,17 @@ impl Default for CanvasConfig {
     fn default() -> Self {
         CanvasConfig {
             duration: 15.0,
+            fixed_duration: 4.0,
             width: 1280,
             height: 720,
+            auto_resize: true,
             font: "黑体".to_string(),
             font_size: 25,
             width_ratio: 1.2,
             horizontal_gap: 20.0,
             lane_size: 32,
             float_percentage: 0.5,
+            top_percentage: 0.3,
             bottom_percentage: 0.3,
             alpha: 0.7,
             bold: false,
@@ -74,16 +88,42 @@ impl CanvasConfig {
     pub fn canvas(self) -> Canvas {
         let float_lanes_cnt =
             (self.float_percentage * self.height as f64 / self.lane_size as f64) as usize;
+        let top_lanes_cnt =
+            (self.top_percentage * self.height as f64 / self.lane_size as f64) as usize;
         let bottom_lanes_cnt =
             (self.bottom_percentage * self.height as f64 / self.lane_size as f64) as usize;
 
         Canvas {
             config: self,
             float_lanes: vec![None; float_lanes_cnt],
+            top_lanes: vec![None; top_lanes_cnt],
             bottom_lanes: vec![None; bottom_lanes_cnt],
         }
     }
 
+    /// 开启了`auto_resize`时把画布换成视频的分辨率`width`x`height`，并等比缩放各项尺寸，
+    /// 横屏和竖屏视频都以短边计算比例
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn fit_resolution(self, width: u32, height: u32) -> Self {
+        let reference = self.width.min(self.height);
+        if !self.auto_resize || width == 0 || height == 0 || reference == 0 {
+            return self;
+        }
+        let scale = f64::from(width.min(height)) / f64::from(reference);
+        let scale_size = |size: u32| ((f64::from(size) * scale).round() as u32).max(1);
+
+        Self {
+            width,
+            height,
+            font_size: scale_size(self.font_size),
+            lane_size: scale_size(self.lane_size),
+            horizontal_gap: self.horizontal_gap * scale,
+            outline: self.outline * scale,
+            ..self
+        }
+    }
+
     #[allow(clippy::cast_possible_truncation)]
     #[allow(clippy::cast_sign_loss)]
     pub fn get_opacity(&self) -> u8 {
@@ -93,26 +133,25 @@ impl CanvasConfig {
 
 pub struct Canvas {
     pub config: CanvasConfig,
+    /// 滚动弹幕和逆向弹幕共用的槽位，从屏幕顶部往下排
     pub float_lanes: Vec<Option<Lane>>,
-    #[allow(dead_code)]
+    /// 从屏幕顶部往下排
+    pub top_lanes: Vec<Option<Lane>>,
+    /// 从屏幕底部往上排
     pub bottom_lanes: Vec<Option<Lane>>,
 }
 
 impl Canvas {
     pub fn draw(&mut self, mut danmaku: Danmaku) -> Option<Drawable> {
-        use super::danmaku::DanmakuType::{Bottom, Float, Reverse, Top};
+        use super::danmaku::DanmakuType::{Advanced, Bottom, Float, Reverse, Top};
         danmaku.timeline_s += self.config.time_offset;
         if danmaku.timeline_s < 0.0 {
             return None;
         }
         match danmaku.r#type {
-            Float => self.draw_float(danmaku),
-            Bottom | Top | Reverse => {
-                // 不喜欢底部弹幕，直接转成 Float
-                // 这是 feature 不是 bug
-                danmaku.r#type = Float;
-                self.draw_float(danmaku)
-            }
+            Float | Reverse => self.draw_float(danmaku),
+            Top | Bottom => self.draw_fixed(danmaku),
+            Advanced => self.draw_advanced(danmaku),
         }
     }
 
@@ -155,15 +194,121 @@ impl Canvas {
     fn draw_float_in_lane(&mut self, danmaku: Danmaku, lane_idx: usize) -> Drawable {
         self.float_lanes[lane_idx] = Some(Lane::draw(&danmaku, &self.config));
         let y = lane_idx as i32 * self.config.lane_size as i32;
-        let l = danmaku.length(&self.config);
+        let l = danmaku.length(&self.config) as i32;
+        let w = self.config.width as i32;
+        // 逆向弹幕从左往右移动
+        let (start, end) = if danmaku.r#type == DanmakuType::Reverse {
+            ((-l, y), (w, y))
+        } else {
+            ((w, y), (-l, y))
+        };
         Drawable::new(
             danmaku,
             self.config.duration,
             "Float",
-            DrawEffect::Move {
-                start: (self.config.width as i32, y),
-                end: (-(l as i32), y),
-            },
+            DrawEffect::Move { start, end },
+        )
+    }
+
+    /// 顶部和底部弹幕不移动，只要槽位里上一条弹幕已经消失就可以画
+    fn draw_fixed(&mut self, mut danmaku: Danmaku) -> Option<Drawable> {
+        let lanes = if danmaku.r#type == DanmakuType::Top {
+            &self.top_lanes
+        } else {
+            &self.bottom_lanes
+        };
+        let mut min_time_needed: Option<(FloatOrd<f64>, usize)> = None;
+        for (idx, lane) in lanes.iter().enumerate() {
+            let time_needed = lane
+                .as_ref()
+                .map_or(0.0, |l| l.fixed_time_needed(&danmaku, &self.config));
+            if time_needed <= 0.0 {
+                return Some(self.draw_fixed_in_lane(danmaku, idx));
+            }
+            if min_time_needed.is_none_or(|(min, _)| FloatOrd(time_needed) < min) {
+                min_time_needed = Some((FloatOrd(time_needed), idx));
+            }
+        }
+        // 与滚动弹幕一样，只允许延迟 1s
+        if let Some((FloatOrd(time_needed), lane_idx)) = min_time_needed
+            && time_needed < 1.0
+        {
+            danmaku.timeline_s += time_needed + 0.01;
+            return Some(self.draw_fixed_in_lane(danmaku, lane_idx));
+        }
+        None
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    fn draw_fixed_in_lane(&mut self, danmaku: Danmaku, lane_idx: usize) -> Drawable {
+        let x = self.config.width as i32 / 2;
+        let offset = lane_idx as i32 * self.config.lane_size as i32;
+        // 顶部弹幕以上边缘定位，底部弹幕以下边缘定位，具体由样式的Alignment决定
+        let (y, style_name) = if danmaku.r#type == DanmakuType::Top {
+            self.top_lanes[lane_idx] = Some(Lane::draw_fixed(&danmaku));
+            (offset, "Top")
+        } else {
+            self.bottom_lanes[lane_idx] = Some(Lane::draw_fixed(&danmaku));
+            (self.config.height as i32 - offset, "Bottom")
+        };
+        Drawable::new(
+            danmaku,
+            self.config.fixed_duration,
+            style_name,
+            DrawEffect::Fixed { pos: (x, y) },
         )
     }
+
+    /// 高级弹幕不占用槽位，坐标以旧版播放器的大小为准，等比缩放到画布中间
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    fn draw_advanced(&self, mut danmaku: Danmaku) -> Option<Drawable> {
+        let advanced = danmaku.advanced.take()?;
+        let (width, height) = (f64::from(self.config.width), f64::from(self.config.height));
+        let (player_width, player_height) = BILI_PLAYER_SIZE;
+        let zoom = (width / player_width).min(height / player_height);
+        let offset_x = (width - player_width * zoom) / 2.0;
+        let offset_y = (height - player_height * zoom) / 2.0;
+
+        let to_pixel = |coordinate: Coordinate, player_size: f64, offset: f64| {
+            let pixel = match coordinate {
+                Coordinate::Ratio(ratio) => ratio * player_size,
+                Coordinate::Pixel(pixel) => pixel,
+            };
+            (offset + pixel * zoom).round() as i32
+        };
+        let to_point = |(x, y): (Coordinate, Coordinate)| {
+            (
+                to_pixel(x, player_width, offset_x),
+                to_pixel(y, player_height, offset_y),
+            )
+        };
+        let to_ass_alpha = |alpha: f64| (255.0 - alpha * 255.0).round() as u8;
+
+        let motion = advanced.end.map(|end| {
+            let move_end_ms = advanced.move_delay_ms + advanced.move_duration_ms;
+            (to_point(end), advanced.move_delay_ms, move_end_ms)
+        });
+        let effect = AdvancedEffect {
+            start: to_point(advanced.start),
+            motion,
+            alpha: (
+                to_ass_alpha(advanced.alpha.0),
+                to_ass_alpha(advanced.alpha.1),
+            ),
+            duration_ms: (advanced.lifetime_s * 1000.0).round() as u64,
+            rotate_z: advanced.rotate_z,
+            rotate_y: advanced.rotate_y,
+            font: advanced.font,
+            font_size: (f64::from(danmaku.fontsize) * zoom).round() as u32,
+            stroke: advanced.stroke,
+        };
+        Some(Drawable::new(
+            danmaku,
+            advanced.lifetime_s,
+            "Advanced",
+            DrawEffect::Advanced(effect),
+        ))
+    }
 }
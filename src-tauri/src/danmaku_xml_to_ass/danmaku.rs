@@ -1,6 +1,6 @@
 //! 一个弹幕实例，但是没有位置信息
 
-use super::canvas::CanvasConfig;
+use super::{advanced::AdvancedDanmaku, canvas::CanvasConfig};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum DanmakuType {
@@ -9,6 +9,8 @@ pub enum DanmakuType {
     Top,
     Bottom,
     Reverse,
+    /// 高级弹幕，位置和动画由弹幕自己决定
+    Advanced,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -20,6 +22,12 @@ pub struct Danmaku {
     /// 否在在调节分辨率的时候字体会发生变化。
     pub fontsize: u32,
     pub rgb: (u8, u8, u8),
+    /// 发送者UID的哈希
+    pub mid_hash: String,
+    /// 弹幕的权重，旧版的XML和直播弹幕没有
+    pub weight: Option<u32>,
+    /// 只有高级弹幕才有
+    pub advanced: Option<AdvancedDanmaku>,
 }
 
 impl Danmaku {
@@ -33,6 +33,24 @@ pub enum DrawEffect {
         start: (i32, i32),
         end: (i32, i32),
     },
-    #[allow(dead_code)]
-    Fixed {},
+    /// 固定在`pos`，顶部和底部弹幕用
+    Fixed {
+        pos: (i32, i32),
+    },
+    /// 高级弹幕
+    Advanced(AdvancedEffect),
+}
+
+pub struct AdvancedEffect {
+    pub start: (i32, i32),
+    /// 终点，以及相对于弹幕出现时间的移动起止时间(毫秒)
+    pub motion: Option<((i32, i32), u64, u64)>,
+    /// 起止的ASS透明度，0为不透明，255为全透明
+    pub alpha: (u8, u8),
+    pub duration_ms: u64,
+    pub rotate_z: f64,
+    pub rotate_y: f64,
+    pub font: Option<String>,
+    pub font_size: u32,
+    pub stroke: bool,
 }
@@ -0,0 +1,213 @@
+//! 把弹幕导出为WebVTT、SRT和CSV
+//!
+//! WebVTT和SRT没法让弹幕滚动，所以滚动弹幕和逆向弹幕会像顶部弹幕一样排在顶部的槽位里，
+//! 高级弹幕无法表示，直接丢弃
+
+use std::fmt::Write;
+
+use tracing::instrument;
+
+use crate::utils;
+
+use super::{
+    canvas::CanvasConfig, danmaku::DanmakuType, drawable::DrawEffect, filter::DanmakuFilter,
+    sorted_danmakus, xml_to_danmakus,
+};
+
+/// 一条排好位置的弹幕
+struct Cue {
+    start_s: f64,
+    end_s: f64,
+    /// 顶部弹幕是上边缘的位置，底部弹幕是下边缘的位置
+    y: i32,
+    bottom: bool,
+    text: String,
+}
+
+/// 每条弹幕单独一个cue，用`line`定位到它所在的槽位
+#[instrument(level = "error", skip_all)]
+pub fn xml_to_vtt(xml: &str, config: CanvasConfig, filter: &DanmakuFilter) -> eyre::Result<String> {
+    let height = f64::from(config.height);
+    let cues = layout_cues(xml, config, filter)?;
+
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        let line = f64::from(cue.y) / height * 100.0;
+        // 底部弹幕以下边缘对齐
+        let line_align = if cue.bottom { ",end" } else { "" };
+        let _ = write!(
+            vtt,
+            "{} --> {} line:{line:.2}%{line_align}\n{}\n\n",
+            utils::seconds_to_vtt_time(cue.start_s),
+            utils::seconds_to_vtt_time(cue.end_s),
+            escape_vtt_text(&cue.text),
+        );
+    }
+
+    Ok(vtt)
+}
+
+/// SRT不能定位，同一时刻显示的弹幕按槽位从上到下叠成多行，放在同一条字幕里
+#[instrument(level = "error", skip_all)]
+pub fn xml_to_srt(xml: &str, config: CanvasConfig, filter: &DanmakuFilter) -> eyre::Result<String> {
+    let mut cues = layout_cues(xml, config, filter)?;
+    cues.sort_by(|a, b| a.start_s.total_cmp(&b.start_s));
+
+    // 每个时间点都可能改变同时显示的弹幕
+    let mut boundaries: Vec<f64> = cues
+        .iter()
+        .flat_map(|cue| [cue.start_s, cue.end_s])
+        .collect();
+    boundaries.sort_by(f64::total_cmp);
+    boundaries.dedup();
+
+    let mut srt = String::new();
+    let mut index = 0;
+    let mut next_cue = 0;
+    let mut active: Vec<&Cue> = Vec::new();
+    for window in boundaries.windows(2) {
+        let (start_s, end_s) = (window[0], window[1]);
+        active.retain(|cue| cue.end_s > start_s);
+        while let Some(cue) = cues.get(next_cue)
+            && cue.start_s <= start_s
+        {
+            active.push(cue);
+            next_cue += 1;
+        }
+        // 太短的片段在SRT的毫秒精度下没有意义
+        if active.is_empty() || end_s - start_s < 0.001 {
+            continue;
+        }
+
+        active.sort_by_key(|cue| (cue.bottom, cue.y));
+        let text = active
+            .iter()
+            .map(|cue| cue.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        index += 1;
+        let _ = write!(
+            srt,
+            "{index}\n{} --> {}\n{text}\n\n",
+            utils::seconds_to_srt_time(start_s),
+            utils::seconds_to_srt_time(end_s),
+        );
+    }
+
+    Ok(srt)
+}
+
+/// 按时间排序的所有弹幕，不做任何过滤，方便分析
+#[instrument(level = "error", skip_all)]
+pub fn xml_to_csv(xml: &str) -> eyre::Result<String> {
+    let mut danmakus = xml_to_danmakus(xml)?;
+    danmakus.sort_by(|a, b| a.timeline_s.total_cmp(&b.timeline_s));
+
+    let mut csv = String::from("time,type,color,mid_hash,content\n");
+    for danmaku in danmakus {
+        let r#type = match danmaku.r#type {
+            DanmakuType::Float => "float",
+            DanmakuType::Top => "top",
+            DanmakuType::Bottom => "bottom",
+            DanmakuType::Reverse => "reverse",
+            DanmakuType::Advanced => "advanced",
+        };
+        let (r, g, b) = danmaku.rgb;
+        let _ = writeln!(
+            csv,
+            "{:.3},{type},#{r:02X}{g:02X}{b:02X},{},{}",
+            danmaku.timeline_s,
+            escape_csv_field(&danmaku.mid_hash),
+            escape_csv_field(&danmaku.content),
+        );
+    }
+
+    Ok(csv)
+}
+
+/// 用`Canvas`的槽位给弹幕排好位置，滚动弹幕当作顶部弹幕处理
+fn layout_cues(xml: &str, config: CanvasConfig, filter: &DanmakuFilter) -> eyre::Result<Vec<Cue>> {
+    let danmakus = sorted_danmakus(xml, filter)?;
+    // 滚动弹幕也要排在顶部，所以顶部的区域要能放下滚动弹幕
+    let config = CanvasConfig {
+        top_percentage: config.top_percentage.max(config.float_percentage),
+        ..config
+    };
+    let mut canvas = config.canvas();
+
+    let cues = danmakus
+        .into_iter()
+        .filter(|danmaku| danmaku.r#type != DanmakuType::Advanced)
+        .filter_map(|mut danmaku| {
+            if matches!(danmaku.r#type, DanmakuType::Float | DanmakuType::Reverse) {
+                danmaku.r#type = DanmakuType::Top;
+            }
+            canvas.draw(danmaku)
+        })
+        .filter_map(|drawable| {
+            let DrawEffect::Fixed { pos: (_, y) } = drawable.effect else {
+                return None;
+            };
+            let text = drawable
+                .danmaku
+                .content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Some(Cue {
+                start_s: drawable.danmaku.timeline_s,
+                end_s: drawable.danmaku.timeline_s + drawable.duration,
+                y,
+                bottom: drawable.danmaku.r#type == DanmakuType::Bottom,
+                text,
+            })
+        })
+        .filter(|cue| !cue.text.is_empty())
+        .collect();
+
+    Ok(cues)
+}
+
+fn escape_vtt_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{read_fixture, small_canvas_config};
+
+    use super::*;
+
+    #[test]
+    fn export_vtt() {
+        let xml = read_fixture("danmaku_xml_to_ass/export.xml");
+        let vtt = xml_to_vtt(&xml, small_canvas_config(), &DanmakuFilter::default()).unwrap();
+        assert_eq!(vtt, read_fixture("danmaku_xml_to_ass/export.vtt"));
+    }
+
+    #[test]
+    fn export_srt() {
+        let xml = read_fixture("danmaku_xml_to_ass/export.xml");
+        let srt = xml_to_srt(&xml, small_canvas_config(), &DanmakuFilter::default()).unwrap();
+        assert_eq!(srt, read_fixture("danmaku_xml_to_ass/export.srt"));
+    }
+
+    #[test]
+    fn export_csv() {
+        let xml = read_fixture("danmaku_xml_to_ass/export.xml");
+        let csv = xml_to_csv(&xml).unwrap();
+        assert_eq!(csv, read_fixture("danmaku_xml_to_ass/export.csv"));
+    }
+}
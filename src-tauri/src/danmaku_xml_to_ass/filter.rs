@@ -0,0 +1,235 @@
+//! 生成ASS之前按配置屏蔽、合并弹幕
+
+use std::collections::HashMap;
+
+use eyre::WrapErr;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::danmaku::{Danmaku, DanmakuType};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DanmakuFilter {
+    /// 屏蔽包含这些关键词的弹幕
+    pub keywords: Vec<String>,
+    /// 屏蔽匹配这些正则表达式的弹幕
+    pub regexes: Vec<String>,
+    /// 屏蔽这些用户发送的弹幕，值为弹幕XML中的`midHash`
+    pub blocked_mid_hashes: Vec<String>,
+    /// 屏蔽权重低于这个值的弹幕，B站的权重范围是1~11，为0时不按权重屏蔽
+    pub min_weight: u32,
+    /// 屏蔽顶部弹幕
+    pub block_top: bool,
+    /// 屏蔽底部弹幕
+    pub block_bottom: bool,
+    /// 屏蔽滚动弹幕，包括逆向弹幕
+    pub block_scroll: bool,
+    /// 屏蔽不是白色的弹幕
+    pub block_colored: bool,
+    /// 把这么多秒内内容相同的弹幕合并为一条，并在后面加上`×N`，为0时不合并
+    pub dedup_window_s: f64,
+}
+
+impl DanmakuFilter {
+    /// 去掉被屏蔽的弹幕，再合并重复的弹幕，`danmakus`必须已经按时间排序
+    pub fn apply(&self, danmakus: Vec<Danmaku>) -> eyre::Result<Vec<Danmaku>> {
+        let regexes = self
+            .regexes
+            .iter()
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| Regex::new(pattern).wrap_err(format!("无效的正则表达式`{pattern}`")))
+            .collect::<eyre::Result<Vec<Regex>>>()?;
+
+        let danmakus: Vec<Danmaku> = danmakus
+            .into_iter()
+            .filter(|danmaku| !self.is_blocked(danmaku, &regexes))
+            .collect();
+
+        if self.dedup_window_s > 0.0 {
+            Ok(dedup(danmakus, self.dedup_window_s))
+        } else {
+            Ok(danmakus)
+        }
+    }
+
+    fn is_blocked(&self, danmaku: &Danmaku, regexes: &[Regex]) -> bool {
+        let type_blocked = match danmaku.r#type {
+            DanmakuType::Top => self.block_top,
+            DanmakuType::Bottom => self.block_bottom,
+            DanmakuType::Float | DanmakuType::Reverse => self.block_scroll,
+            DanmakuType::Advanced => false,
+        };
+        let color_blocked = self.block_colored && danmaku.rgb != (255, 255, 255);
+        // 没有权重的弹幕(比如旧版的XML)不按权重屏蔽
+        let weight_blocked = danmaku
+            .weight
+            .is_some_and(|weight| weight < self.min_weight);
+        let sender_blocked = self.blocked_mid_hashes.contains(&danmaku.mid_hash);
+        let keyword_blocked = self
+            .keywords
+            .iter()
+            .any(|keyword| !keyword.is_empty() && danmaku.content.contains(keyword.as_str()));
+        let regex_blocked = regexes.iter().any(|regex| regex.is_match(&danmaku.content));
+
+        type_blocked
+            || color_blocked
+            || weight_blocked
+            || sender_blocked
+            || keyword_blocked
+            || regex_blocked
+    }
+}
+
+/// 从每组重复弹幕的第一条开始算，`window_s`秒内内容相同的弹幕都合并到第一条上
+fn dedup(danmakus: Vec<Danmaku>, window_s: f64) -> Vec<Danmaku> {
+    let mut result: Vec<Danmaku> = Vec::with_capacity(danmakus.len());
+    let mut counts: Vec<usize> = Vec::with_capacity(danmakus.len());
+    // 内容 -> 这组重复弹幕第一条在`result`中的下标
+    let mut first_indexes: HashMap<String, usize> = HashMap::new();
+
+    for danmaku in danmakus {
+        // 高级弹幕通常是一组拼成图案的弹幕，不能合并
+        if danmaku.r#type == DanmakuType::Advanced {
+            result.push(danmaku);
+            counts.push(1);
+            continue;
+        }
+        let content = danmaku.content.trim().to_string();
+        if let Some(&index) = first_indexes.get(&content)
+            && danmaku.timeline_s - result[index].timeline_s <= window_s
+        {
+            counts[index] += 1;
+            continue;
+        }
+        first_indexes.insert(content, result.len());
+        result.push(danmaku);
+        counts.push(1);
+    }
+
+    for (danmaku, count) in result.iter_mut().zip(counts) {
+        if count > 1 {
+            danmaku.content = format!("{}×{count}", danmaku.content.trim());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{danmaku_xml_to_ass::xml_to_danmakus, test_support::read_fixture};
+
+    use super::*;
+
+    fn filtered_contents(filter: &DanmakuFilter) -> Vec<String> {
+        let xml = read_fixture("danmaku_xml_to_ass/filter.xml");
+        let danmakus = xml_to_danmakus(&xml).unwrap();
+        filter
+            .apply(danmakus)
+            .unwrap()
+            .into_iter()
+            .map(|danmaku| danmaku.content)
+            .collect()
+    }
+
+    fn all_contents() -> Vec<String> {
+        filtered_contents(&DanmakuFilter::default())
+    }
+
+    fn without(blocked: &[&str]) -> Vec<String> {
+        all_contents()
+            .into_iter()
+            .filter(|content| !blocked.contains(&content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn default_filter_keeps_everything() {
+        assert_eq!(all_contents().len(), 14);
+    }
+
+    #[test]
+    fn block_keywords_and_regexes() {
+        let filter = DanmakuFilter {
+            keywords: vec!["高能".to_string(), String::new()],
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["前方高能"]));
+
+        let filter = DanmakuFilter {
+            regexes: vec![r"\d{6,}".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["加群12345678"]));
+
+        let filter = DanmakuFilter {
+            regexes: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.apply(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn block_senders_and_low_weight() {
+        let filter = DanmakuFilter {
+            blocked_mid_hashes: vec!["bbbb2222".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            filtered_contents(&filter),
+            without(&["顶部弹幕", "底部弹幕"])
+        );
+
+        // 旧版XML没有权重的弹幕不会被屏蔽
+        let filter = DanmakuFilter {
+            min_weight: 3,
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["低权重弹幕"]));
+    }
+
+    #[test]
+    fn block_by_type() {
+        let filter = DanmakuFilter {
+            block_top: true,
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["顶部弹幕"]));
+
+        let filter = DanmakuFilter {
+            block_bottom: true,
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["底部弹幕"]));
+
+        let filter = DanmakuFilter {
+            block_scroll: true,
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), ["顶部弹幕", "底部弹幕"]);
+
+        let filter = DanmakuFilter {
+            block_colored: true,
+            ..Default::default()
+        };
+        assert_eq!(filtered_contents(&filter), without(&["红色弹幕"]));
+    }
+
+    #[test]
+    fn merge_repeated_danmakus() {
+        let filter = DanmakuFilter {
+            dedup_window_s: 5.0,
+            ..Default::default()
+        };
+        let contents = filtered_contents(&filter);
+
+        // 10、11、15秒的合并为一条，30秒的离第一条太远，单独保留
+        assert_eq!(contents.iter().filter(|c| c.starts_with("awsl")).count(), 2);
+        assert!(contents.contains(&"awsl×3".to_string()));
+        assert!(contents.contains(&"awsl".to_string()));
+        assert_eq!(contents.len(), all_contents().len() - 2);
+    }
+}
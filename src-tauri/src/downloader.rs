@@ -1,5 +1,7 @@
 pub mod chapter_segments;
+pub mod cut_ranges;
 pub mod download_chunk_task;
+pub mod download_history;
 pub mod download_manager;
 pub mod download_progress;
 pub mod download_task;
@@ -7,5 +9,8 @@ pub mod download_task_state;
 pub mod episode_info;
 pub mod episode_type;
 pub mod fmt_params;
+pub mod link_refresh;
 pub mod media_chunk;
+pub mod media_mirror;
+pub mod speed_limiter;
 pub mod tasks;
@@ -1,3 +1,5 @@
+use super::cut_ranges::CutRanges;
+
 pub struct ChapterSegments {
     pub segments: Vec<ChapterSegment>,
 }
@@ -72,35 +74,29 @@ impl ChapterSegments {
         self.segments = processed_segments;
     }
 
-    pub fn generate_chapter_metadata(&self, video_duration: u64) -> String {
-        use std::fmt::Write;
-
-        fn write_segment(content: &mut String, title: &str, start: i64, end: i64) {
-            let _ = writeln!(
-                content,
-                "[CHAPTER]\nTIMEBASE=1/1\nSTART={start}\nEND={end}\ntitle={title}\n"
-            );
+    /// 返回覆盖整个视频的章节，片段之间以及最后一个片段到视频结尾的空白用标题为空格的章节填充
+    pub fn fill_gaps(&self, video_duration: u64) -> Vec<ChapterSegment> {
+        fn blank_segment(start: i64, end: i64) -> ChapterSegment {
+            ChapterSegment {
+                title: " ".to_string(),
+                start,
+                end,
+            }
         }
 
         let video_duration = i64::try_from(video_duration).unwrap_or(i64::MAX);
 
-        let mut metadata_content = ";FFMETADATA1\n".to_string();
+        let mut chapters = Vec::with_capacity(self.segments.len() * 2 + 1);
 
         let mut last_end = 0;
         for segment in &self.segments {
             // 检查当前片段的开始时间与上一个片段的结束时间之间是否有间隙
             if segment.start > last_end {
                 // 如果有间隙，则插入一个标题为空格的空白片段
-                write_segment(&mut metadata_content, " ", last_end, segment.start);
+                chapters.push(blank_segment(last_end, segment.start));
             }
 
-            // 写入当前片段
-            write_segment(
-                &mut metadata_content,
-                &segment.title,
-                segment.start,
-                segment.end,
-            );
+            chapters.push(segment.clone());
 
             // 更新上一个片段的结束时间
             last_end = segment.end;
@@ -109,12 +105,51 @@ impl ChapterSegments {
         // 循环结束后，检查最后一个片段的结尾与视频总时长之间是否还有间隙
         if video_duration > last_end {
             // 如果有，则填充从 last_end 到视频结尾的剩余部分
-            write_segment(&mut metadata_content, " ", last_end, video_duration);
+            chapters.push(blank_segment(last_end, video_duration));
+        }
+
+        chapters
+    }
+
+    pub fn generate_chapter_metadata(&self, video_duration: u64) -> String {
+        use std::fmt::Write;
+
+        let mut metadata_content = ";FFMETADATA1\n".to_string();
+
+        for segment in self.fill_gaps(video_duration) {
+            let (title, start, end) = (segment.title, segment.start, segment.end);
+            let _ = writeln!(
+                metadata_content,
+                "[CHAPTER]\nTIMEBASE=1/1\nSTART={start}\nEND={end}\ntitle={title}\n"
+            );
         }
 
         metadata_content
     }
 
+    /// 把章节映射到剪切后的时间轴上，完全被剪掉的章节会被丢弃
+    pub fn remap(&self, cut_ranges: &CutRanges) -> ChapterSegments {
+        let map = |seconds: i64| {
+            let ms = u64::try_from(seconds).unwrap_or(0).saturating_mul(1000);
+            // 四舍五入到秒
+            let mapped = (cut_ranges.map_time_clamped(ms) + 500) / 1000;
+            i64::try_from(mapped).unwrap_or(i64::MAX)
+        };
+
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| ChapterSegment {
+                title: segment.title.clone(),
+                start: map(segment.start),
+                end: map(segment.end),
+            })
+            .filter(|segment| segment.start < segment.end)
+            .collect();
+
+        ChapterSegments { segments }
+    }
+
     /// 检查两个片段是否重叠。
     fn overlaps(s1: &ChapterSegment, s2: &ChapterSegment) -> bool {
         s1.start < s1.end && s2.start < s2.end && s1.start < s2.end && s2.start < s1.end
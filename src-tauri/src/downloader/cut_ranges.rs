@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 从视频中剪掉的一段，单位是毫秒，`start`和`end`都是剪切前的时间
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub struct CutRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// 按时间排序且互不重叠的剪切范围
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct CutRanges(pub Vec<CutRange>);
+
+impl CutRanges {
+    /// 把要剪掉的片段(毫秒)向内收缩到剪切点上，然后合并重叠的部分
+    ///
+    /// 不重新编码就只能在关键帧处剪切，`cut_points`是可以剪切的时间，必须是升序的。
+    /// 开头对齐到不早于它的第一个剪切点，结尾对齐到不晚于它的最后一个剪切点，
+    /// 这样片段外的内容不会被剪掉，收缩后变为空的片段会被丢弃
+    pub fn from_segments(segments: &[(u64, u64)], cut_points: &[u64]) -> Self {
+        let snap_forward = |time: u64| {
+            let index = cut_points.partition_point(|&point| point < time);
+            cut_points.get(index).copied()
+        };
+        let snap_backward = |time: u64| {
+            let index = cut_points.partition_point(|&point| point <= time);
+            index.checked_sub(1).map(|i| cut_points[i])
+        };
+
+        let mut ranges: Vec<CutRange> = segments
+            .iter()
+            .filter_map(|&(start, end)| {
+                Some(CutRange {
+                    start: snap_forward(start)?,
+                    end: snap_backward(end)?,
+                })
+            })
+            .filter(|range| range.start < range.end)
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<CutRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        Self(merged)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, time: u64) -> bool {
+        self.0
+            .iter()
+            .any(|range| range.start <= time && time < range.end)
+    }
+
+    /// 把剪切前的时间换算为剪切后的时间，被剪掉的时间返回`None`
+    pub fn map_time(&self, time: u64) -> Option<u64> {
+        if self.contains(time) {
+            return None;
+        }
+        Some(self.map_time_clamped(time))
+    }
+
+    /// 与[`Self::map_time`]相同，但被剪掉的时间会变成那一段剪掉后的位置
+    pub fn map_time_clamped(&self, time: u64) -> u64 {
+        let removed: u64 = self
+            .0
+            .iter()
+            .take_while(|range| range.start < time)
+            .map(|range| range.end.min(time) - range.start)
+            .sum();
+        time - removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrink_segments_to_cut_points() {
+        let cut_points = [0, 2000, 4000, 6000, 8000, 9500];
+
+        let ranges = CutRanges::from_segments(
+            &[
+                (5900, 8200),
+                (1000, 4100),
+                (1000, 2900),
+                (2500, 2600),
+                (9000, 9600),
+            ],
+            &cut_points,
+        );
+
+        // 片段外的内容不能被剪掉，(1000, 2900)、(2500, 2600)和(9000, 9600)收缩后变为空
+        assert_eq!(
+            ranges.0,
+            [
+                CutRange {
+                    start: 2000,
+                    end: 4000,
+                },
+                CutRange {
+                    start: 6000,
+                    end: 8000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_and_adjacent_segments() {
+        let cut_points = [0, 2000, 4000, 6000, 8000];
+
+        let ranges =
+            CutRanges::from_segments(&[(4000, 8000), (1500, 4500), (3000, 7000)], &cut_points);
+
+        assert_eq!(
+            ranges.0,
+            [CutRange {
+                start: 2000,
+                end: 8000,
+            }]
+        );
+    }
+
+    #[test]
+    fn segments_outside_cut_points_are_dropped() {
+        let ranges = CutRanges::from_segments(&[(100, 900), (9000, 9900)], &[1000, 5000]);
+        assert!(ranges.is_empty());
+
+        assert!(CutRanges::from_segments(&[(0, 1000)], &[]).is_empty());
+    }
+
+    #[test]
+    fn map_times_onto_new_timeline() {
+        let ranges = CutRanges(vec![
+            CutRange {
+                start: 1000,
+                end: 2000,
+            },
+            CutRange {
+                start: 5000,
+                end: 8000,
+            },
+        ]);
+
+        assert_eq!(ranges.map_time(500), Some(500));
+        assert_eq!(ranges.map_time(1500), None);
+        assert_eq!(ranges.map_time(2000), Some(1000));
+        assert_eq!(ranges.map_time(9000), Some(5000));
+        assert_eq!(ranges.map_time_clamped(1500), 1000);
+        assert_eq!(ranges.map_time_clamped(6000), 4000);
+    }
+}
@@ -1,24 +1,56 @@
 use std::{
     fs::File,
+    future::Future,
     io::{Seek, Write},
     sync::Arc,
     time::Duration,
 };
 
+use eyre::{OptionExt, eyre};
 use parking_lot::Mutex;
-use tokio::{sync::SemaphorePermit, time::sleep};
+use tokio::{
+    sync::{Semaphore, watch},
+    time::sleep,
+};
 use tracing::instrument;
 
 use crate::{
-    downloader::{download_task::DownloadTask, download_task_state::DownloadTaskState},
-    extensions::AppHandleExt,
+    downloader::{
+        download_task::DownloadTask, download_task_state::DownloadTaskState,
+        media_mirror::MirrorPool,
+    },
+    extensions::EyreReportToMessage,
 };
 
+/// 分片任务的结果
+///
+/// 只有`Downloaded`的分片才能被标记为已下载，
+/// `Interrupted`的分片可能只写入了一部分数据，下次需要重新下载
+#[derive(Debug)]
+pub enum DownloadChunkResult {
+    /// 分片的所有数据都已写入文件
+    Downloaded(usize),
+    /// 分片被重来或删除打断
+    Interrupted,
+    Failed(eyre::Report),
+}
+
+/// 一轮下载所有未完成分片的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadChunksOutcome {
+    /// 所有分片都下载过一次，但不一定都下载成功
+    Finished,
+    /// 有分片因为链接过期而下载失败
+    LinkExpired,
+    /// 有分片被重来或删除打断
+    Interrupted,
+}
+
 pub struct DownloadChunkTask {
     pub download_task: Arc<DownloadTask>,
     pub start: u64,
     pub end: u64,
-    pub url: String,
+    pub mirror_pool: Arc<MirrorPool>,
     pub file: Arc<Mutex<File>>,
     pub chunk_index: usize,
 }
@@ -28,75 +60,73 @@ impl DownloadChunkTask {
         level = "error",
         skip_all,
         fields(
-            url = self.url,
             chunk_index = ?self.chunk_index,
             start = self.start,
             end = self.end,
         )
     )]
-    pub async fn process(self) -> eyre::Result<usize> {
-        let download_chunk_task = self.download_chunk();
-        tokio::pin!(download_chunk_task);
+    pub async fn process(self) -> DownloadChunkResult {
+        let download_manager = self.download_task.app.get_download_manager();
 
-        let mut state_receiver = self.download_task.state_sender.subscribe();
-        state_receiver.mark_changed();
+        let state_receiver = self.download_task.state_sender.subscribe();
+        let restart_receiver = self.download_task.restart_sender.subscribe();
+        let delete_receiver = self.download_task.delete_sender.subscribe();
 
-        let mut restart_receiver = self.download_task.restart_sender.subscribe();
-        let mut delete_receiver = self.download_task.delete_sender.subscribe();
+        run_chunk(
+            self.chunk_index,
+            self.download_chunk(),
+            &download_manager.media_chunk_sem,
+            state_receiver,
+            restart_receiver,
+            delete_receiver,
+        )
+        .await
+    }
 
-        let mut permit = None;
+    #[instrument(level = "error", skip_all)]
+    async fn download_chunk(&self) -> eyre::Result<()> {
+        let app = &self.download_task.app;
+        let bili_client = app.get_bili_client();
+        let download_manager = app.get_download_manager();
+        // 同时受全局限速和任务限速的约束
+        let speed_limiters = [
+            &download_manager.speed_limiter,
+            &self.download_task.speed_limiter,
+        ];
+        // 当前镜像下载失败时轮换到下一个镜像，每个镜像最多尝试一次
+        let mut tried = Vec::new();
+        let chunk_data = loop {
+            let (mirror_index, url) = self
+                .mirror_pool
+                .pick(&tried)
+                .ok_or_eyre("没有可用的镜像地址")?;
+            tried.push(mirror_index);
 
-        loop {
-            let state_is_downloading = *state_receiver.borrow() == DownloadTaskState::Downloading;
-            tokio::select! {
-                result = &mut download_chunk_task, if state_is_downloading && permit.is_some() => break result,
+            let result = bili_client
+                .get_media_chunk(&url, self.start, self.end, &speed_limiters)
+                .await
+                .and_then(|chunk_data| {
+                    check_chunk_len(self.start, self.end, &chunk_data)?;
+                    Ok(chunk_data)
+                });
 
-                result = self.acquire_chunk_permit(&mut permit), if state_is_downloading && permit.is_none() => {
-                    match result {
-                        Ok(()) => {},
-                        Err(err) => break Err(err),
-                    }
-                },
-
-                _ = state_receiver.changed() => {
-                    if *state_receiver.borrow() == DownloadTaskState::Paused {
-                        // 稍微等一下再释放permit
-                        sleep(Duration::from_millis(100)).await;
-                        if let Some(permit) = permit.take() {
-                            drop(permit);
-                        }
+            match result {
+                Ok(chunk_data) => {
+                    self.mirror_pool.report_success(mirror_index);
+                    break chunk_data;
+                }
+                Err(err) => {
+                    self.mirror_pool.report_failure(mirror_index);
+                    if tried.len() >= self.mirror_pool.len() {
+                        return Err(err);
                     }
-                },
-                // FIXME: 直接返回chunk_index存在进度误标风险
-                // 上层会将这个分片标记为已下载，而分片其实是被打断的
-                // 应该把返回值改成 enum DownloadChunkResult { Downloaded(idx), Interrupted }
-                // 然后由上层处理
-                _ = restart_receiver.changed() => break Ok(self.chunk_index),
-
-                _ = delete_receiver.changed() => break Ok(self.chunk_index),
+                    let message = err.to_message();
+                    tracing::info!(url, message, "从镜像下载分片失败，切换到下一个镜像");
+                }
             }
-        }
-    }
+        };
 
-    #[instrument(level = "error", skip_all)]
-    async fn download_chunk(&self) -> eyre::Result<usize> {
-        let bili_client = self.download_task.app.get_bili_client();
-        let chunk_data = bili_client
-            .get_media_chunk(&self.url, self.start, self.end)
-            .await?;
-
-        let len = chunk_data.len() as u64;
-        self.download_task
-            .app
-            .get_download_manager()
-            .byte_per_sec
-            .fetch_add(len, std::sync::atomic::Ordering::Relaxed);
-        // 将下载的内容写入文件
-        {
-            let mut file = self.file.lock();
-            file.seek(std::io::SeekFrom::Start(self.start))?;
-            file.write_all(&chunk_data)?;
-        }
+        write_chunk(&self.file, self.start, self.end, &chunk_data)?;
 
         let chunk_download_interval_sec = self
             .download_task
@@ -106,29 +136,258 @@ impl DownloadChunkTask {
             .chunk_download_interval_sec;
         sleep(Duration::from_secs(chunk_download_interval_sec)).await;
 
-        Ok(self.chunk_index)
+        Ok(())
     }
+}
 
-    #[instrument(level = "error", skip_all)]
-    async fn acquire_chunk_permit<'a>(
-        &'a self,
-        permit: &mut Option<SemaphorePermit<'a>>,
-    ) -> eyre::Result<()> {
-        *permit = match permit.take() {
-            // 如果有permit，则直接用
-            Some(permit) => Some(permit),
-            // 如果没有permit，则获取permit
-            None => Some(
-                self.download_task
-                    .app
-                    .get_download_manager()
-                    .inner()
-                    .media_chunk_sem
-                    .acquire()
-                    .await?,
-            ),
-        };
+/// 在任务处于`Downloading`且拿到permit时推进`download_chunk_task`
+///
+/// 收到重来或删除信号时立即返回`Interrupted`，此时`download_chunk_task`会被drop
+async fn run_chunk(
+    chunk_index: usize,
+    download_chunk_task: impl Future<Output = eyre::Result<()>>,
+    media_chunk_sem: &Semaphore,
+    mut state_receiver: watch::Receiver<DownloadTaskState>,
+    mut restart_receiver: watch::Receiver<()>,
+    mut delete_receiver: watch::Receiver<()>,
+) -> DownloadChunkResult {
+    tokio::pin!(download_chunk_task);
 
-        Ok(())
+    state_receiver.mark_changed();
+
+    let mut permit = None;
+
+    loop {
+        let state_is_downloading = *state_receiver.borrow() == DownloadTaskState::Downloading;
+        tokio::select! {
+            result = &mut download_chunk_task, if state_is_downloading && permit.is_some() => {
+                break match result {
+                    Ok(()) => DownloadChunkResult::Downloaded(chunk_index),
+                    Err(err) => DownloadChunkResult::Failed(err),
+                };
+            },
+
+            result = media_chunk_sem.acquire(), if state_is_downloading && permit.is_none() => {
+                match result {
+                    Ok(chunk_permit) => permit = Some(chunk_permit),
+                    Err(err) => break DownloadChunkResult::Failed(err.into()),
+                }
+            },
+
+            _ = state_receiver.changed() => {
+                if *state_receiver.borrow() == DownloadTaskState::Paused {
+                    // 稍微等一下再释放permit
+                    sleep(Duration::from_millis(100)).await;
+                    if let Some(permit) = permit.take() {
+                        drop(permit);
+                    }
+                }
+            },
+
+            _ = restart_receiver.changed() => break DownloadChunkResult::Interrupted,
+
+            _ = delete_receiver.changed() => break DownloadChunkResult::Interrupted,
+        }
+    }
+}
+
+/// 将分片数据写入文件的`start..=end`
+///
+/// 数据长度与分片长度不一致时不写入，避免文件中留下空洞
+fn write_chunk(file: &Mutex<File>, start: u64, end: u64, chunk_data: &[u8]) -> eyre::Result<()> {
+    check_chunk_len(start, end, chunk_data)?;
+
+    let mut file = file.lock();
+    file.seek(std::io::SeekFrom::Start(start))?;
+    file.write_all(chunk_data)?;
+
+    Ok(())
+}
+
+fn check_chunk_len(start: u64, end: u64, chunk_data: &[u8]) -> eyre::Result<()> {
+    let expected_len = end - start + 1;
+    if chunk_data.len() as u64 != expected_len {
+        return Err(eyre!(
+            "分片数据长度不正确，预期`{expected_len}`字节，实际`{}`字节",
+            chunk_data.len()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::pending, io::Read};
+
+    use tokio::task::JoinSet;
+
+    use super::*;
+
+    const CHUNK_SIZE: u64 = 16;
+
+    struct Senders {
+        state: watch::Sender<DownloadTaskState>,
+        restart: watch::Sender<()>,
+        delete: watch::Sender<()>,
+    }
+
+    impl Senders {
+        fn new() -> Self {
+            Self {
+                state: watch::Sender::new(DownloadTaskState::Downloading),
+                restart: watch::Sender::new(()),
+                delete: watch::Sender::new(()),
+            }
+        }
+    }
+
+    fn chunk_data(chunk_index: usize) -> Vec<u8> {
+        #[allow(clippy::cast_possible_truncation)]
+        let byte = chunk_index as u8 + 1;
+        vec![byte; usize::try_from(CHUNK_SIZE).unwrap()]
+    }
+
+    /// 模拟下载`chunk_count`个分片，`stall_from`及之后的分片写入一半数据后卡住
+    ///
+    /// 等已完成的分片都返回后调用`interrupt`，返回每个分片是否被标记为已下载
+    async fn simulate(
+        chunk_count: usize,
+        stall_from: usize,
+        file: Arc<Mutex<File>>,
+        interrupt: impl FnOnce(&Senders),
+    ) -> Vec<bool> {
+        let senders = Senders::new();
+        let media_chunk_sem = Arc::new(Semaphore::new(chunk_count));
+
+        let mut join_set = JoinSet::new();
+        for chunk_index in 0..chunk_count {
+            let start = chunk_index as u64 * CHUNK_SIZE;
+            let end = start + CHUNK_SIZE - 1;
+            let file = file.clone();
+            let download_chunk_task = async move {
+                let data = chunk_data(chunk_index);
+                if chunk_index >= stall_from {
+                    // 只写入一半数据，然后一直卡在网络请求上
+                    let half = data.len() / 2;
+                    {
+                        let mut file = file.lock();
+                        file.seek(std::io::SeekFrom::Start(start))?;
+                        file.write_all(&data[..half])?;
+                    }
+                    pending::<()>().await;
+                }
+                write_chunk(&file, start, end, &data)
+            };
+
+            let media_chunk_sem = media_chunk_sem.clone();
+            let state_receiver = senders.state.subscribe();
+            let restart_receiver = senders.restart.subscribe();
+            let delete_receiver = senders.delete.subscribe();
+            join_set.spawn(async move {
+                run_chunk(
+                    chunk_index,
+                    download_chunk_task,
+                    &media_chunk_sem,
+                    state_receiver,
+                    restart_receiver,
+                    delete_receiver,
+                )
+                .await
+            });
+        }
+
+        let mut completed = vec![false; chunk_count];
+        let mut interrupt = Some(interrupt);
+        let mut finished = 0;
+        while let Some(join_result) = join_set.join_next().await {
+            match join_result.unwrap() {
+                DownloadChunkResult::Downloaded(i) => completed[i] = true,
+                DownloadChunkResult::Interrupted => {}
+                DownloadChunkResult::Failed(err) => panic!("{err:?}"),
+            }
+            finished += 1;
+            if finished == stall_from
+                && let Some(interrupt) = interrupt.take()
+            {
+                interrupt(&senders);
+            }
+        }
+
+        completed
+    }
+
+    fn assert_no_holes(file: &Mutex<File>, completed: &[bool]) {
+        let mut content = Vec::new();
+        let mut file = file.lock();
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut content).unwrap();
+
+        for (chunk_index, chunk_content) in content
+            .chunks(usize::try_from(CHUNK_SIZE).unwrap())
+            .enumerate()
+        {
+            if completed[chunk_index] {
+                assert_eq!(chunk_content, chunk_data(chunk_index));
+            }
+        }
+    }
+
+    fn temp_file(chunk_count: usize) -> Arc<Mutex<File>> {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(chunk_count as u64 * CHUNK_SIZE).unwrap();
+        Arc::new(Mutex::new(file))
+    }
+
+    #[tokio::test]
+    async fn restart_mid_chunk_is_not_marked_downloaded() {
+        let file = temp_file(4);
+        let completed = simulate(4, 2, file.clone(), |senders| {
+            senders.restart.send(()).unwrap();
+        })
+        .await;
+
+        assert_eq!(completed, [true, true, false, false]);
+        assert_no_holes(&file, &completed);
+    }
+
+    #[tokio::test]
+    async fn delete_mid_chunk_is_not_marked_downloaded() {
+        let file = temp_file(4);
+        let completed = simulate(4, 1, file.clone(), |senders| {
+            senders.delete.send(()).unwrap();
+        })
+        .await;
+
+        assert_eq!(completed, [true, false, false, false]);
+        assert_no_holes(&file, &completed);
+    }
+
+    #[tokio::test]
+    async fn interrupt_while_paused_is_not_marked_downloaded() {
+        let file = temp_file(3);
+        let completed = simulate(3, 1, file.clone(), |senders| {
+            senders.state.send(DownloadTaskState::Paused).unwrap();
+            senders.restart.send(()).unwrap();
+        })
+        .await;
+
+        assert_eq!(completed, [true, false, false]);
+        assert_no_holes(&file, &completed);
+    }
+
+    #[test]
+    fn short_chunk_is_rejected() {
+        let file = temp_file(1);
+        let data = chunk_data(0);
+
+        let result = write_chunk(&file, 0, CHUNK_SIZE - 1, &data[..data.len() - 1]);
+        assert!(result.is_err());
+        // 数据不完整时文件不能被改动
+        let mut content = Vec::new();
+        let mut file = file.lock();
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut content).unwrap();
+        assert!(content.iter().all(|&b| b == 0));
     }
 }
@@ -0,0 +1,534 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eyre::WrapErr;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::instrument;
+
+use crate::{
+    config::{AudioLibraryFormat, Config, OutputContainer, SkipSegmentCategory},
+    extensions::EyreReportToMessage,
+    types::{
+        audio_quality::AudioQuality, codec_type::CodecType,
+        query_download_history_params::QueryDownloadHistoryParams, video_quality::VideoQuality,
+    },
+};
+
+use super::{download_progress::DownloadProgress, episode_type::EpisodeType};
+
+/// 下载任务选择了哪些输出，以及影响输出内容的选项
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct DownloadOutputs {
+    pub video: bool,
+    pub audio: bool,
+    pub merge: bool,
+    pub embed_chapter: bool,
+    pub embed_skip: bool,
+    pub embed_highlight: bool,
+    pub xml_danmaku: bool,
+    pub ass_danmaku: bool,
+    pub json_danmaku: bool,
+    pub vtt_danmaku: bool,
+    pub srt_danmaku: bool,
+    pub csv_danmaku: bool,
+    pub danmaku_heatmap: bool,
+    pub subtitle: bool,
+    pub cover: bool,
+    pub nfo: bool,
+    pub json: bool,
+    pub output_container: OutputContainer,
+    pub audio_library: bool,
+    pub audio_library_format: AudioLibraryFormat,
+    pub cut_skip_categories: Vec<SkipSegmentCategory>,
+}
+
+impl DownloadOutputs {
+    pub fn from_progress(progress: &DownloadProgress) -> Self {
+        Self {
+            video: progress.video_task.selected,
+            audio: progress.audio_task.selected,
+            merge: progress.video_process_task.merge_selected,
+            embed_chapter: progress.video_process_task.embed_chapter_selected,
+            embed_skip: progress.video_process_task.embed_skip_selected,
+            embed_highlight: progress.video_process_task.embed_highlight_selected,
+            xml_danmaku: progress.danmaku_task.xml_selected,
+            ass_danmaku: progress.danmaku_task.ass_selected,
+            json_danmaku: progress.danmaku_task.json_selected,
+            vtt_danmaku: progress.danmaku_task.vtt_selected,
+            srt_danmaku: progress.danmaku_task.srt_selected,
+            csv_danmaku: progress.danmaku_task.csv_selected,
+            danmaku_heatmap: progress.danmaku_task.heatmap_selected,
+            subtitle: progress.subtitle_task.selected,
+            cover: progress.cover_task.selected,
+            nfo: progress.nfo_task.selected,
+            json: progress.json_task.selected,
+            output_container: progress.video_process_task.output_container,
+            audio_library: progress.audio_library_task.selected,
+            audio_library_format: progress.audio_library_task.format,
+            cut_skip_categories: progress.video_process_task.cut_skip_categories.clone(),
+        }
+    }
+
+    /// `other`选择的输出是否都包含在`self`中，且影响输出内容的选项都相同
+    pub fn covers(&self, other: &Self) -> bool {
+        let same_options = self.output_container == other.output_container
+            && self.audio_library == other.audio_library
+            && self.audio_library_format == other.audio_library_format
+            && self.cut_skip_categories.len() == other.cut_skip_categories.len()
+            && self
+                .cut_skip_categories
+                .iter()
+                .all(|category| other.cut_skip_categories.contains(category));
+        if !same_options {
+            return false;
+        }
+
+        let pairs = [
+            (self.video, other.video),
+            (self.audio, other.audio),
+            (self.merge, other.merge),
+            (self.embed_chapter, other.embed_chapter),
+            (self.embed_skip, other.embed_skip),
+            (self.embed_highlight, other.embed_highlight),
+            (self.xml_danmaku, other.xml_danmaku),
+            (self.ass_danmaku, other.ass_danmaku),
+            (self.json_danmaku, other.json_danmaku),
+            (self.vtt_danmaku, other.vtt_danmaku),
+            (self.srt_danmaku, other.srt_danmaku),
+            (self.csv_danmaku, other.csv_danmaku),
+            (self.danmaku_heatmap, other.danmaku_heatmap),
+            (self.subtitle, other.subtitle),
+            (self.cover, other.cover),
+            (self.nfo, other.nfo),
+            (self.json, other.json),
+        ];
+        pairs.iter().all(|&(this, other)| this || !other)
+    }
+}
+
+/// 按优先级选择画质、编码和音质时使用的优先级
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct QualityPriority {
+    pub video_quality: Vec<VideoQuality>,
+    pub codec_type: Vec<CodecType>,
+    pub audio_quality: Vec<AudioQuality>,
+}
+
+impl QualityPriority {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            video_quality: config.video_quality_priority.clone(),
+            codec_type: config.codec_type_priority.clone(),
+            audio_quality: config.audio_quality_priority.clone(),
+        }
+    }
+}
+
+/// 一条下载完成的记录
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct DownloadHistoryRecord {
+    pub task_id: String,
+    pub episode_type: EpisodeType,
+    pub aid: i64,
+    pub bvid: Option<String>,
+    pub cid: i64,
+    pub ep_id: Option<i64>,
+    pub collection_title: String,
+    pub episode_title: String,
+    pub up_name: Option<String>,
+    /// 没有下载视频时为`None`
+    pub video_quality: Option<VideoQuality>,
+    pub codec_type: Option<CodecType>,
+    /// 没有下载音频时为`None`
+    pub audio_quality: Option<AudioQuality>,
+    pub outputs: DownloadOutputs,
+    /// 下载时的画质、编码和音质优先级，优先级变了就不算重复的下载
+    pub quality_priority: QualityPriority,
+    pub output_paths: Vec<PathBuf>,
+    pub completed_ts: u64,
+}
+
+impl DownloadHistoryRecord {
+    pub fn from_progress(progress: &DownloadProgress, quality_priority: QualityPriority) -> Self {
+        let video_task = &progress.video_task;
+        let audio_task = &progress.audio_task;
+        let video_downloaded = video_task.selected && !video_task.skipped;
+        let audio_downloaded = audio_task.selected && !audio_task.skipped;
+
+        Self {
+            task_id: progress.task_id.clone(),
+            episode_type: progress.episode_type,
+            aid: progress.aid,
+            bvid: progress.bvid.clone(),
+            cid: progress.cid,
+            ep_id: progress.ep_id,
+            collection_title: progress.collection_title.clone(),
+            episode_title: progress.episode_title.clone(),
+            up_name: progress.up_name.clone(),
+            video_quality: video_downloaded.then_some(video_task.video_quality),
+            codec_type: video_downloaded.then_some(video_task.codec_type),
+            audio_quality: audio_downloaded.then_some(audio_task.audio_quality),
+            outputs: DownloadOutputs::from_progress(progress),
+            quality_priority,
+            output_paths: progress.output_paths(),
+            completed_ts: progress.completed_ts.unwrap_or_default(),
+        }
+    }
+
+    /// 是否为同一个视频的同一集
+    fn is_same_episode(&self, progress: &DownloadProgress) -> bool {
+        self.episode_type == progress.episode_type
+            && self.aid == progress.aid
+            && self.cid == progress.cid
+            && self.ep_id == progress.ep_id
+    }
+
+    /// 重新下载`progress`时是否会选到和这条记录相同的画质、编码和音质
+    ///
+    /// 指定了画质的下载要完全相同，按优先级选择的下载要求优先级和这条记录下载时相同
+    fn has_same_quality(
+        &self,
+        progress: &DownloadProgress,
+        quality_priority: &QualityPriority,
+    ) -> bool {
+        let video_task = &progress.video_task;
+        let audio_task = &progress.audio_task;
+        if video_task.selected
+            && video_task.video_quality != VideoQuality::Unknown
+            && (self.video_quality != Some(video_task.video_quality)
+                || self.codec_type != Some(video_task.codec_type))
+        {
+            return false;
+        }
+        if audio_task.selected
+            && audio_task.audio_quality != AudioQuality::Unknown
+            && self.audio_quality != Some(audio_task.audio_quality)
+        {
+            return false;
+        }
+
+        self.quality_priority == *quality_priority
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct DownloadHistoryQueryResult {
+    /// 满足条件的记录总数
+    pub total: usize,
+    /// 按完成时间从新到旧排列
+    pub records: Vec<DownloadHistoryRecord>,
+}
+
+/// 下载历史，每行一条记录，只追加不修改
+pub struct DownloadHistory {
+    path: PathBuf,
+    records: RwLock<Vec<DownloadHistoryRecord>>,
+}
+
+impl DownloadHistory {
+    /// 读取失败或某行解析失败时只记录错误，不影响下载
+    #[instrument(level = "error", skip_all)]
+    pub fn new(app_data_dir: &Path) -> Self {
+        let path = app_data_dir.join("download_history.jsonl");
+        let records = match load_records(&path) {
+            Ok(records) => records,
+            Err(err) => {
+                let err_title = "读取下载历史失败";
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+                Vec::new()
+            }
+        };
+
+        Self {
+            path,
+            records: RwLock::new(records),
+        }
+    }
+
+    #[instrument(level = "error", skip_all, fields(task_id = record.task_id))]
+    pub fn add_record(&self, record: DownloadHistoryRecord) -> eyre::Result<()> {
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        let mut records = self.records.write();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err(format!("打开`{}`失败", self.path.display()))?;
+        file.write_all(line.as_bytes())
+            .wrap_err(format!("写入`{}`失败", self.path.display()))?;
+        records.push(record);
+
+        Ok(())
+    }
+
+    /// 找到同一集、输出包含`progress`选择的输出、画质相同、且输出文件都还在的最新记录
+    pub fn find_duplicate(
+        &self,
+        progress: &DownloadProgress,
+        quality_priority: &QualityPriority,
+    ) -> Option<DownloadHistoryRecord> {
+        let outputs = DownloadOutputs::from_progress(progress);
+        self.records
+            .read()
+            .iter()
+            .rev()
+            .find(|record| {
+                record.is_same_episode(progress)
+                    && record.outputs.covers(&outputs)
+                    && record.has_same_quality(progress, quality_priority)
+                    && !record.output_paths.is_empty()
+                    && record.output_paths.iter().all(|path| path.exists())
+            })
+            .cloned()
+    }
+
+    pub fn find_by_task_id(&self, task_id: &str) -> Option<DownloadHistoryRecord> {
+        self.records
+            .read()
+            .iter()
+            .rev()
+            .find(|record| record.task_id == task_id)
+            .cloned()
+    }
+
+    pub fn query(&self, params: &QueryDownloadHistoryParams) -> DownloadHistoryQueryResult {
+        let keyword = params
+            .keyword
+            .as_ref()
+            .map(|keyword| keyword.to_lowercase());
+        let records = self.records.read();
+        let matched: Vec<&DownloadHistoryRecord> = records
+            .iter()
+            .rev()
+            .filter(|record| params.aid.is_none_or(|aid| record.aid == aid))
+            .filter(|record| params.ep_id.is_none_or(|ep_id| record.ep_id == Some(ep_id)))
+            .filter(|record| {
+                keyword
+                    .as_ref()
+                    .is_none_or(|keyword| record_contains_keyword(record, keyword))
+            })
+            .collect();
+
+        let total = matched.len();
+        let records = if params.ps == 0 {
+            matched.into_iter().cloned().collect()
+        } else {
+            let skip = params.pn.saturating_sub(1).saturating_mul(params.ps);
+            matched
+                .into_iter()
+                .skip(skip)
+                .take(params.ps)
+                .cloned()
+                .collect()
+        };
+
+        DownloadHistoryQueryResult { total, records }
+    }
+}
+
+fn load_records(path: &Path) -> eyre::Result<Vec<DownloadHistoryRecord>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content =
+        std::fs::read_to_string(path).wrap_err(format!("读取`{}`失败", path.display()))?;
+    let mut records = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            // 写入时被中断可能会留下不完整的行
+            Err(err) => tracing::warn!(line_number = i + 1, %err, "跳过无法解析的下载历史"),
+        }
+    }
+
+    Ok(records)
+}
+
+/// `keyword`已经转换为小写
+fn record_contains_keyword(record: &DownloadHistoryRecord, keyword: &str) -> bool {
+    let fields = [
+        Some(&record.collection_title),
+        Some(&record.episode_title),
+        record.up_name.as_ref(),
+        record.bvid.as_ref(),
+    ];
+    fields
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(aid: i64, cid: i64, episode_dir: &Path) -> DownloadProgress {
+        let mut progress = DownloadProgress {
+            aid,
+            cid,
+            episode_dir: episode_dir.to_path_buf(),
+            filename: format!("{aid}-{cid}"),
+            completed_ts: Some(u64::try_from(aid).unwrap()),
+            ..Default::default()
+        };
+        progress.video_task.selected = true;
+        progress.audio_task.selected = true;
+        progress
+    }
+
+    fn priority() -> QualityPriority {
+        QualityPriority {
+            video_quality: vec![VideoQuality::Video1080P, VideoQuality::Video720P],
+            codec_type: vec![CodecType::AVC],
+            audio_quality: vec![AudioQuality::Audio192K],
+        }
+    }
+
+    #[test]
+    fn records_survive_reload_and_skip_broken_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let history = DownloadHistory::new(temp_dir.path());
+        let record =
+            DownloadHistoryRecord::from_progress(&progress(1, 10, temp_dir.path()), priority());
+        history.add_record(record.clone()).unwrap();
+        // 模拟写入时被中断
+        let mut file = OpenOptions::new().append(true).open(&history.path).unwrap();
+        file.write_all(b"{\"task_id\":").unwrap();
+
+        let history = DownloadHistory::new(temp_dir.path());
+        let result = history.query(&QueryDownloadHistoryParams::default());
+        assert_eq!(result.records, [record]);
+    }
+
+    #[test]
+    fn output_paths_exclude_files_of_other_videos() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for name in ["1-10.mp4", "1-10.nfo", "1-10-xxx.mp4", "1-10.5.mp4"] {
+            std::fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+
+        let record =
+            DownloadHistoryRecord::from_progress(&progress(1, 10, temp_dir.path()), priority());
+        assert_eq!(
+            record.output_paths,
+            [
+                temp_dir.path().join("1-10.mp4"),
+                temp_dir.path().join("1-10.nfo")
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_requires_same_episode_outputs_quality_and_existing_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let history = DownloadHistory::new(temp_dir.path());
+        let downloaded = progress(1, 10, temp_dir.path());
+        std::fs::write(temp_dir.path().join("1-10.mp4"), "").unwrap();
+        history
+            .add_record(DownloadHistoryRecord::from_progress(
+                &downloaded,
+                priority(),
+            ))
+            .unwrap();
+
+        assert!(history.find_duplicate(&downloaded, &priority()).is_some());
+        // 不同的分P
+        assert!(
+            history
+                .find_duplicate(&progress(1, 11, temp_dir.path()), &priority())
+                .is_none()
+        );
+        // 选择了之前没有下载的输出
+        let mut with_cover = downloaded.clone();
+        with_cover.cover_task.selected = true;
+        assert!(history.find_duplicate(&with_cover, &priority()).is_none());
+        // 选择的输出更少
+        let mut audio_only = downloaded.clone();
+        audio_only.video_task.selected = false;
+        assert!(history.find_duplicate(&audio_only, &priority()).is_some());
+        // 影响输出内容的选项不同
+        let mut mkv = downloaded.clone();
+        mkv.video_process_task.output_container = OutputContainer::Mkv;
+        assert!(history.find_duplicate(&mkv, &priority()).is_none());
+        let mut audio_library = downloaded.clone();
+        audio_library.audio_library_task.selected = true;
+        assert!(
+            history
+                .find_duplicate(&audio_library, &priority())
+                .is_none()
+        );
+        let mut cut = downloaded.clone();
+        cut.video_process_task.cut_skip_categories = vec![SkipSegmentCategory::Sponsor];
+        assert!(history.find_duplicate(&cut, &priority()).is_none());
+        // 画质优先级变了
+        let mut codec_changed = priority();
+        codec_changed.codec_type = vec![CodecType::HEVC];
+        assert!(
+            history
+                .find_duplicate(&downloaded, &codec_changed)
+                .is_none()
+        );
+        // 指定了和记录不同的画质
+        let mut video_720p = downloaded.clone();
+        video_720p.video_task.video_quality = VideoQuality::Video720P;
+        video_720p.video_task.codec_type = CodecType::AVC;
+        assert!(history.find_duplicate(&video_720p, &priority()).is_none());
+        // 文件被删除后不算重复
+        std::fs::remove_file(temp_dir.path().join("1-10.mp4")).unwrap();
+        assert!(history.find_duplicate(&downloaded, &priority()).is_none());
+    }
+
+    #[test]
+    fn query_filters_and_pages_from_newest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let history = DownloadHistory::new(temp_dir.path());
+        for aid in 1..=5 {
+            let mut progress = progress(aid, aid * 10, temp_dir.path());
+            progress.collection_title = if aid % 2 == 0 { "Rust教程" } else { "其他" }.to_string();
+            history
+                .add_record(DownloadHistoryRecord::from_progress(&progress, priority()))
+                .unwrap();
+        }
+
+        let aids = |params: QueryDownloadHistoryParams| {
+            let result = history.query(&params);
+            let aids: Vec<i64> = result.records.iter().map(|r| r.aid).collect();
+            (result.total, aids)
+        };
+
+        let params = QueryDownloadHistoryParams {
+            pn: 2,
+            ps: 2,
+            ..Default::default()
+        };
+        assert_eq!(aids(params), (5, vec![3, 2]));
+
+        let params = QueryDownloadHistoryParams {
+            keyword: Some("rust".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(aids(params), (2, vec![4, 2]));
+
+        let params = QueryDownloadHistoryParams {
+            aid: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(aids(params), (1, vec![3]));
+    }
+}
@@ -10,49 +10,58 @@ use std::{
 
 use eyre::{WrapErr, eyre};
 use parking_lot::RwLock;
-use tauri::{AppHandle, Manager};
-use tauri_specta::Event;
 use tokio::sync::Semaphore;
 use tracing::instrument;
 
+#[cfg(feature = "gui")]
+use crate::types::restart_download_task_params::RestartDownloadTaskParams;
 use crate::{
-    events::DownloadEvent,
-    extensions::{AppHandleExt, EyreReportToMessage},
-    types::{
-        create_download_task_params::CreateDownloadTaskParams,
-        restart_download_task_params::RestartDownloadTaskParams,
-    },
+    app_context::AppContext, events::DownloadEvent, extensions::EyreReportToMessage,
+    types::create_download_task_params::CreateDownloadTaskParams,
 };
 
 use super::{
-    download_progress::DownloadProgress, download_task::DownloadTask,
+    download_history::DownloadHistory,
+    download_progress::DownloadProgress,
+    download_task::{CreatedDownloadTasks, DownloadTask},
     download_task_state::DownloadTaskState,
+    speed_limiter::SpeedLimiter,
 };
 
 pub struct DownloadManager {
-    pub app: AppHandle,
+    pub app: AppContext,
     pub task_sem: Arc<Semaphore>,
     pub media_chunk_sem: Arc<Semaphore>,
     pub byte_per_sec: Arc<AtomicU64>,
+    pub speed_limiter: SpeedLimiter,
     pub download_tasks: RwLock<HashMap<String, Arc<DownloadTask>>>,
+    pub download_history: DownloadHistory,
 }
 
 impl DownloadManager {
-    pub fn new(app: AppHandle) -> Self {
-        let (task_concurrency, chunk_concurrency) = {
-            let config = app.get_config().inner().read();
-            (config.task_concurrency, config.chunk_concurrency)
+    pub fn new(app: AppContext) -> Self {
+        let (task_concurrency, chunk_concurrency, speed_limit_kb_per_sec) = {
+            let config = app.get_config().read();
+            (
+                config.task_concurrency,
+                config.chunk_concurrency,
+                config.speed_limit_kb_per_sec,
+            )
         };
 
+        let download_history = DownloadHistory::new(app.app_data_dir());
+
         let manager = Self {
             app,
             task_sem: Arc::new(Semaphore::new(task_concurrency)),
             media_chunk_sem: Arc::new(Semaphore::new(chunk_concurrency)),
             byte_per_sec: Arc::new(AtomicU64::new(0)),
+            speed_limiter: SpeedLimiter::new(speed_limit_kb_per_sec.saturating_mul(1024)),
             download_tasks: RwLock::new(HashMap::new()),
+            download_history,
         };
 
-        tauri::async_runtime::spawn(Self::emit_download_speed_loop(
+        tokio::spawn(Self::emit_download_speed_loop(
             manager.app.clone(),
             manager.byte_per_sec.clone(),
         ));
@@ -62,7 +71,7 @@ impl DownloadManager {
 
     #[instrument(level = "error", skip_all)]
     pub fn restore_download_tasks(&self) -> eyre::Result<()> {
-        let task_dir = self.get_task_dir()?;
+        let task_dir = self.get_task_dir();
         std::fs::create_dir_all(&task_dir)
             .wrap_err(format!("创建下载任务目录`{}`失败", task_dir.display()))?;
 
@@ -98,12 +107,14 @@ impl DownloadManager {
         Ok(())
     }
 
-    pub fn create_download_tasks(&self, params: &CreateDownloadTaskParams) {
-        let new_tasks = DownloadTask::from_params(&self.app, params);
+    /// 返回新创建的下载任务
+    pub fn create_download_tasks(&self, params: &CreateDownloadTaskParams) -> CreatedDownloadTasks {
+        let created = DownloadTask::from_params(&self.app, params);
         let mut tasks = self.download_tasks.write();
-        for new_task in new_tasks {
-            tasks.insert(new_task.task_id.clone(), new_task);
+        for new_task in &created.tasks {
+            tasks.insert(new_task.task_id.clone(), new_task.clone());
         }
+        created
     }
 
     #[instrument(level = "error", skip_all)]
@@ -182,6 +193,7 @@ impl DownloadManager {
         }
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all)]
     pub fn restart_download_tasks(&self, task_ids: &Vec<String>) {
         let tasks = self.download_tasks.read();
@@ -209,6 +221,7 @@ impl DownloadManager {
         }
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all, fields(task_id = params.task_id))]
     pub fn restart_download_task(&self, params: &RestartDownloadTaskParams) {
         let task_id = &params.task_id;
@@ -231,10 +244,15 @@ impl DownloadManager {
             progress.video_process_task.merge_selected = params.merge_selected;
             progress.video_process_task.embed_chapter_selected = params.embed_chapter_selected;
             progress.video_process_task.embed_skip_selected = params.embed_skip_selected;
+            progress.video_process_task.embed_highlight_selected = params.embed_highlight_selected;
             progress.subtitle_task.selected = params.subtitle_task_selected;
             progress.danmaku_task.xml_selected = params.xml_danmaku_selected;
             progress.danmaku_task.ass_selected = params.ass_danmaku_selected;
             progress.danmaku_task.json_selected = params.json_danmaku_selected;
+            progress.danmaku_task.vtt_selected = params.vtt_danmaku_selected;
+            progress.danmaku_task.srt_selected = params.srt_danmaku_selected;
+            progress.danmaku_task.csv_selected = params.csv_danmaku_selected;
+            progress.danmaku_task.heatmap_selected = params.danmaku_heatmap_selected;
             progress.cover_task.selected = params.cover_task_selected;
             progress.nfo_task.selected = params.nfo_task_selected;
             progress.json_task.selected = params.json_task_selected;
@@ -255,7 +273,31 @@ impl DownloadManager {
         tracing::debug!("已通知ID对应的下载任务重来");
     }
 
-    async fn emit_download_speed_loop(app: AppHandle, byte_per_sec: Arc<AtomicU64>) {
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(speed_limit_kb_per_sec = speed_limit_kb_per_sec))]
+    pub fn set_download_tasks_speed_limit(
+        &self,
+        task_ids: &Vec<String>,
+        speed_limit_kb_per_sec: u64,
+    ) {
+        let tasks = self.download_tasks.read();
+        for task_id in task_ids {
+            let span = tracing::error_span!("set_download_task_speed_limit", task_id = task_id);
+            let _enter = span.enter();
+
+            let Some(task) = tasks.get(task_id) else {
+                let err = eyre!("未找到ID对应的下载任务");
+                let err_title = "设置下载任务限速失败";
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+                continue;
+            };
+            task.set_speed_limit(speed_limit_kb_per_sec);
+            tracing::debug!("已设置ID对应的下载任务的限速");
+        }
+    }
+
+    async fn emit_download_speed_loop(app: AppContext, byte_per_sec: Arc<AtomicU64>) {
         let mut interval = tokio::time::interval(Duration::from_secs(1));
 
         loop {
@@ -264,20 +306,17 @@ impl DownloadManager {
             #[allow(clippy::cast_precision_loss)]
             let mega_byte_per_sec = byte_per_sec as f64 / 1024.0 / 1024.0;
             let speed = format!("{mega_byte_per_sec:.2}MB/s");
-            let _ = DownloadEvent::Speed { speed }.emit(&app);
+            app.emit_download_event(DownloadEvent::Speed { speed });
         }
     }
 
-    #[instrument(level = "error", skip_all)]
-    fn get_task_dir(&self) -> eyre::Result<PathBuf> {
-        let app_data_dir = self.app.path().app_data_dir()?;
-        let task_dir = app_data_dir.join(".下载任务");
-        Ok(task_dir)
+    fn get_task_dir(&self) -> PathBuf {
+        self.app.app_data_dir().join(".下载任务")
     }
 
     #[instrument(level = "error", skip_all, fields(task_id = task_id))]
     fn delete_progress_file(&self, task_id: &str) -> eyre::Result<()> {
-        let task_dir = self.get_task_dir()?;
+        let task_dir = self.get_task_dir();
         let task_file = task_dir.join(format!("{task_id}.json"));
         if task_file.exists() {
             std::fs::remove_file(task_file)?;
@@ -7,38 +7,71 @@ use std::{
 use eyre::{OptionExt, WrapErr, eyre};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
-use tauri_specta::Event;
 use tracing::instrument;
 use uuid::Uuid;
 
 use crate::{
-    config::Config,
+    app_context::AppContext,
+    config::{Config, OutputContainer},
     downloader::{
-        download_task::DownloadTask,
+        download_task::{DownloadOutcome, DownloadTask},
         tasks::{
-            audio_task::AudioTask, cover_task::CoverTask, danmaku_task::DanmakuTask,
-            json_task::JsonTask, nfo_task::NfoTask, subtitle_task::SubtitleTask,
-            video_process_task::VideoProcessTask, video_task::VideoTask,
+            audio_library_task::AudioLibraryTask, audio_task::AudioTask, cover_task::CoverTask,
+            danmaku_task::DanmakuTask, json_task::JsonTask, nfo_task::NfoTask,
+            subtitle_task::SubtitleTask, video_process_task::VideoProcessTask,
+            video_task::VideoTask,
         },
     },
     events::DownloadEvent,
-    extensions::AppHandleExt,
     plugin::hook_context::{
         AfterPrepareContext, BeforeVideoProcessContext, HookContext, OnCompletedContext,
     },
     types::{
         audio_quality::AudioQuality,
         bangumi_info::BangumiInfo,
+        bangumi_media_url::BangumiMediaUrl,
         cheese_info::CheeseInfo,
+        cheese_media_url::CheeseMediaUrl,
         codec_type::CodecType,
         normal_info::{NormalInfo, UgcSeason},
+        normal_media_url::NormalMediaUrl,
+        player_info::PlayerInfo,
         video_quality::VideoQuality,
     },
 };
 
 use super::{episode_type::EpisodeType, fmt_params::FmtParams};
 
+/// 下载任务会以`{filename}{后缀}`命名的文件，包括下载和处理过程中的临时文件，字幕另外记录在`SubtitleTask`中
+const OUTPUT_SUFFIXES: &[&str] = &[
+    ".mp4",
+    ".m4a",
+    ".mkv",
+    ".mp3",
+    ".flac",
+    ".tagged.m4a",
+    ".mp4.com.lanyeeee.bilibili-video-downloader",
+    ".m4a.com.lanyeeee.bilibili-video-downloader",
+    ".concat.com.lanyeeee.bilibili-video-downloader",
+    "-merged.mp4",
+    "-embed.mp4",
+    "-cut.m4a",
+    ".FFMETA.ini",
+    ".弹幕.xml",
+    ".弹幕.ass",
+    ".弹幕.json",
+    ".弹幕.vtt",
+    ".弹幕.srt",
+    ".弹幕.csv",
+    ".弹幕热度.json",
+    ".jpg",
+    ".png",
+    ".webp",
+    ".avif",
+    ".nfo",
+    "-元数据.json",
+];
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(default)]
 pub struct DownloadProgress {
@@ -63,6 +96,7 @@ pub struct DownloadProgress {
     pub video_task: VideoTask,
     pub audio_task: AudioTask,
     pub video_process_task: VideoProcessTask,
+    pub audio_library_task: AudioLibraryTask,
     pub subtitle_task: SubtitleTask,
     pub danmaku_task: DanmakuTask,
     pub cover_task: CoverTask,
@@ -72,12 +106,13 @@ pub struct DownloadProgress {
     pub completed_ts: Option<u64>,
     pub is_drm: bool,
     pub is_preview: bool,
+    pub speed_limit_kb_per_sec: u64,
 }
 
 impl DownloadProgress {
     #[instrument(level = "error", skip_all)]
     pub fn from_normal(
-        app: &AppHandle,
+        app: &AppContext,
         info: &NormalInfo,
         aid: i64,
         cid: Option<i64>,
@@ -93,7 +128,7 @@ impl DownloadProgress {
 
     #[allow(clippy::cast_possible_wrap)]
     #[instrument(level = "error", skip_all)]
-    pub fn from_bangumi(app: &AppHandle, info: &BangumiInfo, ep_id: i64) -> eyre::Result<Self> {
+    pub fn from_bangumi(app: &AppContext, info: &BangumiInfo, ep_id: i64) -> eyre::Result<Self> {
         let (episode, episode_order) = info.get_episode_with_order(ep_id)?;
         let Some(duration) = episode.duration else {
             return Err(eyre!("duration为None"));
@@ -138,6 +173,7 @@ impl DownloadProgress {
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -147,13 +183,14 @@ impl DownloadProgress {
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         Ok(progress)
     }
 
     #[instrument(level = "error", skip_all)]
-    pub fn from_cheese(app: &AppHandle, info: &CheeseInfo, ep_id: i64) -> eyre::Result<Self> {
+    pub fn from_cheese(app: &AppContext, info: &CheeseInfo, ep_id: i64) -> eyre::Result<Self> {
         let episode = info
             .episodes
             .iter()
@@ -188,6 +225,7 @@ impl DownloadProgress {
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -197,6 +235,7 @@ impl DownloadProgress {
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         Ok(progress)
@@ -204,12 +243,14 @@ impl DownloadProgress {
 
     #[instrument(level = "error", skip_all)]
     #[allow(clippy::too_many_lines)]
-    pub async fn process(&mut self, download_task: &Arc<DownloadTask>) -> eyre::Result<()> {
+    pub async fn process(
+        &mut self,
+        download_task: &Arc<DownloadTask>,
+    ) -> eyre::Result<DownloadOutcome> {
         let app = &download_task.app;
-        let _ = DownloadEvent::ProgressPreparing {
+        app.emit_download_event(DownloadEvent::ProgressPreparing {
             task_id: self.task_id.clone(),
-        }
-        .emit(app);
+        });
 
         self.prepare(app).await.wrap_err("准备下载失败")?;
 
@@ -231,18 +272,26 @@ impl DownloadProgress {
         let mut episode_info = None;
 
         if !self.video_task.is_completed() && self.video_task.content_length != 0 {
-            self.video_task
+            let outcome = self
+                .video_task
                 .process(download_task, self)
                 .await
                 .wrap_err("下载视频文件失败")?;
+            if outcome == DownloadOutcome::Interrupted {
+                return Ok(outcome);
+            }
             tracing::debug!("视频下载任务完成");
         }
 
         if !self.audio_task.is_completed() && self.audio_task.content_length != 0 {
-            self.audio_task
+            let outcome = self
+                .audio_task
                 .process(download_task, self)
                 .await
                 .wrap_err("下载音频文件失败")?;
+            if outcome == DownloadOutcome::Interrupted {
+                return Ok(outcome);
+            }
             tracing::debug!("音频下载任务完成");
         }
 
@@ -257,6 +306,30 @@ impl DownloadProgress {
         }
 
         let video_process_task_is_completed = self.video_process_task.is_completed();
+        // 弹幕和字幕要按剪切后的时间轴调整，所以要在它们之前确定剪切范围
+        let cut_selected = !self.video_process_task.cut_skip_categories.is_empty();
+        if cut_selected
+            && self.video_process_task.cut_ranges.is_none()
+            && !self.is_drm
+            && !video_process_task_is_completed
+        {
+            let cut_ranges = self
+                .video_process_task
+                .prepare_cut(app, self)
+                .await
+                .wrap_err("确定剪切范围失败")?;
+            self.video_process_task.cut_ranges = Some(cut_ranges.clone());
+            download_task.update_progress(|p| p.video_process_task.cut_ranges = Some(cut_ranges));
+        }
+        // 封装MKV时弹幕、字幕和封面也要封装进去，所以要先下载它们
+        let mux_into_mkv = self.video_process_task.output_container == OutputContainer::Mkv
+            && !self.is_drm
+            && !video_process_task_is_completed;
+        if mux_into_mkv {
+            self.process_danmaku_subtitle_and_cover(download_task, &mut player_info)
+                .await?;
+        }
+
         if self.is_drm && !video_process_task_is_completed {
             download_task.update_progress(|p| {
                 p.video_process_task.skipped = true;
@@ -271,28 +344,17 @@ impl DownloadProgress {
             tracing::debug!("视频处理任务完成");
         }
 
-        if !self.danmaku_task.is_completed() {
-            self.danmaku_task
-                .process(download_task, self)
-                .await
-                .wrap_err("下载弹幕失败")?;
-            tracing::debug!("弹幕下载任务完成");
-        }
-
-        if !self.subtitle_task.is_completed() {
-            self.subtitle_task
-                .process(download_task, self, &mut player_info)
+        if !self.audio_library_task.is_completed() {
+            self.audio_library_task
+                .process(download_task, self, &mut episode_info)
                 .await
-                .wrap_err("下载字幕失败")?;
-            tracing::debug!("字幕下载任务完成");
+                .wrap_err("生成音频库文件失败")?;
+            tracing::debug!("音频库任务完成");
         }
 
-        if !self.cover_task.is_completed() {
-            self.cover_task
-                .process(download_task, self)
-                .await
-                .wrap_err("下载封面失败")?;
-            tracing::debug!("封面下载任务完成");
+        if !mux_into_mkv {
+            self.process_danmaku_subtitle_and_cover(download_task, &mut player_info)
+                .await?;
         }
 
         if !self.nfo_task.is_completed() {
@@ -328,11 +390,44 @@ impl DownloadProgress {
             download_task.update_progress(|p| *p = self.clone());
         }
 
+        Ok(DownloadOutcome::Completed)
+    }
+
+    #[instrument(level = "error", skip_all)]
+    async fn process_danmaku_subtitle_and_cover(
+        &self,
+        download_task: &Arc<DownloadTask>,
+        player_info: &mut Option<PlayerInfo>,
+    ) -> eyre::Result<()> {
+        if !self.danmaku_task.is_completed() {
+            self.danmaku_task
+                .process(download_task, self)
+                .await
+                .wrap_err("下载弹幕失败")?;
+            tracing::debug!("弹幕下载任务完成");
+        }
+
+        if !self.subtitle_task.is_completed() {
+            self.subtitle_task
+                .process(download_task, self, player_info)
+                .await
+                .wrap_err("下载字幕失败")?;
+            tracing::debug!("字幕下载任务完成");
+        }
+
+        if !self.cover_task.is_completed() {
+            self.cover_task
+                .process(download_task, self)
+                .await
+                .wrap_err("下载封面失败")?;
+            tracing::debug!("封面下载任务完成");
+        }
+
         Ok(())
     }
 
     #[instrument(level = "error", skip_all)]
-    async fn prepare(&mut self, app: &AppHandle) -> eyre::Result<()> {
+    async fn prepare(&mut self, app: &AppContext) -> eyre::Result<()> {
         let video_selected = self.video_task.selected;
         let video_completed = self.video_task.completed;
         let audio_selected = self.audio_task.selected;
@@ -345,18 +440,8 @@ impl DownloadProgress {
             return Ok(());
         }
 
-        let bili_client = app.get_bili_client();
-
-        match self.episode_type {
-            EpisodeType::Normal => {
-                let Some(bvid) = &self.bvid else {
-                    return Err(eyre!("progress中的bvid为None，无法获取视频链接"));
-                };
-                let media_url = bili_client
-                    .get_normal_url(bvid, self.cid)
-                    .await
-                    .wrap_err("获取视频链接失败")?;
-
+        match self.get_media_url(app).await? {
+            MediaUrl::Normal(media_url) => {
                 self.is_preview = !media_url.durl.is_empty() && media_url.dash.video.is_empty();
 
                 if video_selected && !video_completed {
@@ -369,12 +454,7 @@ impl DownloadProgress {
                     self.audio_task.prepare_normal(app, &media_url).await;
                 }
             }
-            EpisodeType::Bangumi => {
-                let media_url = bili_client
-                    .get_bangumi_url(self.cid)
-                    .await
-                    .wrap_err("获取番剧视频链接失败")?;
-
+            MediaUrl::Bangumi(media_url) => {
                 self.is_preview = media_url.is_preview != 0;
 
                 if video_selected && !video_completed {
@@ -387,15 +467,7 @@ impl DownloadProgress {
                     self.audio_task.prepare_bangumi(app, &media_url).await;
                 }
             }
-            EpisodeType::Cheese => {
-                let Some(ep_id) = self.ep_id else {
-                    return Err(eyre!("progress中的ep_id为None，无法获取课程视频链接"));
-                };
-                let media_url = bili_client
-                    .get_cheese_url(ep_id)
-                    .await
-                    .wrap_err("获取课程视频链接失败")?;
-
+            MediaUrl::Cheese(media_url) => {
                 self.is_drm = media_url.is_drm;
                 self.is_preview = media_url.is_preview != 0;
 
@@ -417,8 +489,46 @@ impl DownloadProgress {
         Ok(())
     }
 
+    /// 根据剧集类型获取视频链接
+    #[instrument(level = "error", skip_all)]
+    pub async fn get_media_url(&self, app: &AppContext) -> eyre::Result<MediaUrl> {
+        let bili_client = app.get_bili_client();
+
+        let media_url = match self.episode_type {
+            EpisodeType::Normal => {
+                let Some(bvid) = &self.bvid else {
+                    return Err(eyre!("progress中的bvid为None，无法获取视频链接"));
+                };
+                let media_url = bili_client
+                    .get_normal_url(bvid, self.cid)
+                    .await
+                    .wrap_err("获取视频链接失败")?;
+                MediaUrl::Normal(media_url)
+            }
+            EpisodeType::Bangumi => {
+                let media_url = bili_client
+                    .get_bangumi_url(self.cid)
+                    .await
+                    .wrap_err("获取番剧视频链接失败")?;
+                MediaUrl::Bangumi(media_url)
+            }
+            EpisodeType::Cheese => {
+                let Some(ep_id) = self.ep_id else {
+                    return Err(eyre!("progress中的ep_id为None，无法获取课程视频链接"));
+                };
+                let media_url = bili_client
+                    .get_cheese_url(ep_id)
+                    .await
+                    .wrap_err("获取课程视频链接失败")?;
+                MediaUrl::Cheese(media_url)
+            }
+        };
+
+        Ok(media_url)
+    }
+
     #[instrument(level = "error", skip_all)]
-    fn update_fmt_fields(&mut self, app: &AppHandle) -> eyre::Result<()> {
+    fn update_fmt_fields(&mut self, app: &AppContext) -> eyre::Result<()> {
         let fmt_params = self.create_fmt_params();
 
         let config = app.get_config().read().clone();
@@ -455,11 +565,11 @@ impl DownloadProgress {
     }
 
     #[instrument(level = "error", skip_all)]
-    pub fn save(&self, app: &AppHandle, allow_create: bool) -> eyre::Result<()> {
+    pub fn save(&self, app: &AppContext, allow_create: bool) -> eyre::Result<()> {
         let progress = self.clone();
         let file_name = format!("{}.json", progress.task_id);
 
-        let app_data_dir = app.path().app_data_dir()?;
+        let app_data_dir = app.app_data_dir();
         let tasks_dir = app_data_dir.join(".下载任务");
         std::fs::create_dir_all(&tasks_dir)?;
 
@@ -478,6 +588,7 @@ impl DownloadProgress {
         self.video_task.is_completed()
             && self.audio_task.is_completed()
             && self.video_process_task.is_completed()
+            && self.audio_library_task.is_completed()
             && self.danmaku_task.is_completed()
             && self.subtitle_task.is_completed()
             && self.cover_task.is_completed()
@@ -489,12 +600,34 @@ impl DownloadProgress {
         self.video_task.mark_uncompleted();
         self.audio_task.mark_uncompleted();
         self.video_process_task.mark_uncompleted();
+        self.audio_library_task.mark_uncompleted();
         self.danmaku_task.mark_uncompleted();
         self.subtitle_task.mark_uncompleted();
         self.cover_task.mark_uncompleted();
         self.nfo_task.mark_uncompleted();
         self.json_task.mark_uncompleted();
     }
+
+    /// 这个下载任务生成的、目前还存在的文件
+    ///
+    /// 只按确切的文件名查找，同一目录下文件名以`filename`开头的其他视频的文件不会被包含
+    pub fn output_paths(&self) -> Vec<PathBuf> {
+        let (episode_dir, filename) = (&self.episode_dir, &self.filename);
+        let mut output_paths: Vec<PathBuf> = OUTPUT_SUFFIXES
+            .iter()
+            .map(|suffix| episode_dir.join(format!("{filename}{suffix}")))
+            .chain(
+                self.subtitle_task
+                    .saved_files
+                    .iter()
+                    .map(|file| episode_dir.join(file)),
+            )
+            .filter(|path| path.is_file())
+            .collect();
+        output_paths.sort();
+        output_paths.dedup();
+        output_paths
+    }
 }
 
 #[allow(clippy::too_many_lines)]
@@ -535,6 +668,7 @@ fn create_normal_progresses_for_single(
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -544,6 +678,7 @@ fn create_normal_progresses_for_single(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         return Ok(vec![progress]);
@@ -573,6 +708,7 @@ fn create_normal_progresses_for_single(
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -582,6 +718,7 @@ fn create_normal_progresses_for_single(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         return Ok(vec![progress]);
@@ -611,6 +748,7 @@ fn create_normal_progresses_for_single(
             video_task: tasks.video.clone(),
             audio_task: tasks.audio.clone(),
             video_process_task: tasks.video_process.clone(),
+            audio_library_task: tasks.audio_library.clone(),
             danmaku_task: tasks.danmaku.clone(),
             subtitle_task: tasks.subtitle.clone(),
             cover_task: tasks.cover.clone(),
@@ -620,6 +758,7 @@ fn create_normal_progresses_for_single(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         progresses.push(progress);
@@ -682,6 +821,7 @@ fn create_normal_progresses_for_season(
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -691,6 +831,7 @@ fn create_normal_progresses_for_season(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         return Ok(vec![progress]);
@@ -720,6 +861,7 @@ fn create_normal_progresses_for_season(
             video_task: tasks.video,
             audio_task: tasks.audio,
             video_process_task: tasks.video_process,
+            audio_library_task: tasks.audio_library,
             danmaku_task: tasks.danmaku,
             subtitle_task: tasks.subtitle,
             cover_task: tasks.cover,
@@ -729,6 +871,7 @@ fn create_normal_progresses_for_season(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         return Ok(vec![progress]);
@@ -759,6 +902,7 @@ fn create_normal_progresses_for_season(
             video_task: tasks.video.clone(),
             audio_task: tasks.audio.clone(),
             video_process_task: tasks.video_process.clone(),
+            audio_library_task: tasks.audio_library.clone(),
             danmaku_task: tasks.danmaku.clone(),
             subtitle_task: tasks.subtitle.clone(),
             cover_task: tasks.cover.clone(),
@@ -768,6 +912,7 @@ fn create_normal_progresses_for_season(
             completed_ts: None,
             is_drm: false,
             is_preview: false,
+            speed_limit_kb_per_sec: 0,
         };
 
         progresses.push(progress);
@@ -775,10 +920,17 @@ fn create_normal_progresses_for_season(
     Ok(progresses)
 }
 
+pub enum MediaUrl {
+    Normal(NormalMediaUrl),
+    Bangumi(BangumiMediaUrl),
+    Cheese(CheeseMediaUrl),
+}
+
 struct Tasks {
     video: VideoTask,
     audio: AudioTask,
     video_process: VideoProcessTask,
+    audio_library: AudioLibraryTask,
     danmaku: DanmakuTask,
     subtitle: SubtitleTask,
     cover: CoverTask,
@@ -789,19 +941,25 @@ struct Tasks {
 impl Tasks {
     fn new(config: &Config, cover_url: &str) -> Self {
         let video = VideoTask {
-            selected: config.download_video,
+            // 音频库模式只下载音频
+            selected: config.download_video && !config.audio_library_mode,
             url: String::new(),
+            mirrors: Vec::new(),
             video_quality: VideoQuality::Unknown,
             codec_type: CodecType::Unknown,
+            width: 0,
+            height: 0,
             content_length: 0,
             chunks: Vec::new(),
+            segments: Vec::new(),
             completed: false,
             skipped: false,
         };
 
         let audio = AudioTask {
-            selected: config.download_audio,
+            selected: config.download_audio || config.audio_library_mode,
             url: String::new(),
+            mirrors: Vec::new(),
             audio_quality: AudioQuality::Unknown,
             content_length: 0,
             chunks: Vec::new(),
@@ -813,6 +971,17 @@ impl Tasks {
             merge_selected: config.auto_merge,
             embed_chapter_selected: config.embed_chapter,
             embed_skip_selected: config.embed_skip,
+            embed_highlight_selected: config.embed_highlight,
+            output_container: config.output_container,
+            cut_skip_categories: config.cut_skip_categories.clone(),
+            cut_ranges: None,
+            completed: false,
+            skipped: false,
+        };
+
+        let audio_library = AudioLibraryTask {
+            selected: config.audio_library_mode,
+            format: config.audio_library_format,
             completed: false,
             skipped: false,
         };
@@ -821,12 +990,25 @@ impl Tasks {
             xml_selected: config.download_xml_danmaku,
             ass_selected: config.download_ass_danmaku,
             json_selected: config.download_json_danmaku,
+            vtt_selected: config.download_vtt_danmaku,
+            srt_selected: config.download_srt_danmaku,
+            csv_selected: config.download_csv_danmaku,
+            heatmap_selected: config.download_danmaku_heatmap,
+            history_mode: config.history_danmaku_mode,
+            history_start_date: config.history_danmaku_start_date.clone(),
+            history_end_date: config.history_danmaku_end_date.clone(),
             completed: false,
             skipped: false,
         };
 
         let subtitle = SubtitleTask {
             selected: config.download_subtitle,
+            formats: config.subtitle_formats.clone(),
+            languages: config.subtitle_languages.clone(),
+            exclude_ai: config.exclude_ai_subtitle,
+            bilingual_primary: config.bilingual_subtitle_primary.clone(),
+            bilingual_secondary: config.bilingual_subtitle_secondary.clone(),
+            saved_files: Vec::new(),
             completed: false,
         };
 
@@ -851,6 +1033,7 @@ impl Tasks {
             video,
             audio,
             video_process,
+            audio_library,
             danmaku,
             subtitle,
             cover,
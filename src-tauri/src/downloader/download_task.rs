@@ -2,8 +2,6 @@
 
 use eyre::WrapErr;
 use parking_lot::RwLock;
-use tauri::AppHandle;
-use tauri_specta::Event;
 use tokio::{
     sync::{SemaphorePermit, watch},
     time::sleep,
@@ -11,29 +9,45 @@ use tokio::{
 use tracing::instrument;
 
 use crate::{
-    downloader::episode_type::EpisodeType,
-    events::DownloadEvent,
-    extensions::{AppHandleExt, EyreReportToMessage},
+    app_context::AppContext, config::DuplicateDownloadAction,
+    downloader::episode_type::EpisodeType, events::DownloadEvent, extensions::EyreReportToMessage,
     types::create_download_task_params::CreateDownloadTaskParams,
 };
 
-use super::{download_progress::DownloadProgress, download_task_state::DownloadTaskState};
+use super::{
+    download_history::{DownloadHistoryRecord, QualityPriority},
+    download_progress::DownloadProgress,
+    download_task_state::DownloadTaskState,
+    speed_limiter::SpeedLimiter,
+};
+
+/// 下载的结果，失败时为`Err`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadOutcome {
+    Completed,
+    /// 被重来或删除打断，不算失败，重来和删除由`DownloadTask::process`处理
+    Interrupted,
+}
 
 pub struct DownloadTask {
-    pub app: AppHandle,
+    pub app: AppContext,
     pub state_sender: watch::Sender<DownloadTaskState>,
     pub restart_sender: watch::Sender<()>,
     pub cancel_sender: watch::Sender<()>,
     pub delete_sender: watch::Sender<()>,
     pub task_id: String,
     pub trace_fields: DownloadTaskTraceFields,
+    pub speed_limiter: SpeedLimiter,
     pub progress: RwLock<DownloadProgress>,
 }
 
 impl DownloadTask {
     #[allow(clippy::too_many_lines)]
     #[instrument(level = "error", skip_all)]
-    pub fn from_params(app: &AppHandle, params: &CreateDownloadTaskParams) -> Vec<Arc<Self>> {
+    pub fn from_params(
+        app: &AppContext,
+        params: &CreateDownloadTaskParams,
+    ) -> CreatedDownloadTasks {
         use CreateDownloadTaskParams::{Bangumi, Cheese, Normal};
 
         let mut progresses = Vec::new();
@@ -115,7 +129,7 @@ impl DownloadTask {
             }
         }
 
-        let mut tasks = Vec::new();
+        let mut created = CreatedDownloadTasks::default();
         for progress in progresses {
             let span = tracing::error_span!(
                 "create_tasks",
@@ -135,6 +149,11 @@ impl DownloadTask {
             );
             let _enter = span.enter();
 
+            if should_skip_duplicate(app, &progress) {
+                created.skipped_duplicates.push(progress);
+                continue;
+            }
+
             if let Err(err) = progress.save(app, true) {
                 let err_title = "保存下载进度到文件失败";
                 let message = err.to_message();
@@ -161,18 +180,21 @@ impl DownloadTask {
                 delete_sender,
                 task_id: progress.task_id.clone(),
                 trace_fields: DownloadTaskTraceFields::from(&progress),
+                speed_limiter: SpeedLimiter::new(
+                    progress.speed_limit_kb_per_sec.saturating_mul(1024),
+                ),
                 progress: RwLock::new(progress),
             });
 
-            tauri::async_runtime::spawn(task.clone().process());
+            tokio::spawn(task.clone().process());
 
-            tasks.push(task);
+            created.tasks.push(task);
         }
 
-        tasks
+        created
     }
 
-    pub fn from_progress(app: AppHandle, progress: DownloadProgress) -> Arc<Self> {
+    pub fn from_progress(app: AppContext, progress: DownloadProgress) -> Arc<Self> {
         let init_state = if progress.is_completed() {
             DownloadTaskState::Completed
         } else {
@@ -191,10 +213,11 @@ impl DownloadTask {
             delete_sender,
             task_id: progress.task_id.clone(),
             trace_fields: DownloadTaskTraceFields::from(&progress),
+            speed_limiter: SpeedLimiter::new(progress.speed_limit_kb_per_sec.saturating_mul(1024)),
             progress: RwLock::new(progress),
         });
 
-        tauri::async_runtime::spawn(task.clone().process());
+        tokio::spawn(task.clone().process());
 
         task
     }
@@ -221,7 +244,8 @@ impl DownloadTask {
     async fn process(self: Arc<Self>) {
         let state = *self.state_sender.borrow();
         let progress = self.progress.read().clone();
-        let _ = DownloadEvent::TaskCreate { state, progress }.emit(&self.app);
+        self.app
+            .emit_download_event(DownloadEvent::TaskCreate { state, progress });
 
         let mut state_receiver = self.state_sender.subscribe();
         state_receiver.mark_changed();
@@ -267,10 +291,9 @@ impl DownloadTask {
                 _ = cancel_receiver.changed() => return,
 
                 _ = delete_receiver.changed() => {
-                    let _ = DownloadEvent::TaskDelete {
+                    self.app.emit_download_event(DownloadEvent::TaskDelete {
                         task_id: self.task_id.clone(),
-                    }
-                    .emit(&self.app);
+                    });
 
                     if permit.is_some() {
                         // 如果有permit则稍微等一下再退出
@@ -296,18 +319,38 @@ impl DownloadTask {
         }
 
         tracing::debug!("开始下载");
-        if let Err(err) = progress
+        let result = progress
             .process(self)
             .await
-            .wrap_err("[继续]失败的任务可以断点续传")
-        {
-            let err_title = "下载失败";
-            let message = err.to_message();
-            tracing::error!(err_title, message);
+            .wrap_err("[继续]失败的任务可以断点续传");
+        self.handle_download_result(result).await;
+    }
 
-            self.set_state(DownloadTaskState::Failed);
+    async fn handle_download_result(&self, result: eyre::Result<DownloadOutcome>) {
+        match result {
+            Ok(DownloadOutcome::Completed) => {}
+            Ok(DownloadOutcome::Interrupted) => {
+                tracing::debug!("下载被打断");
+                return;
+            }
+            Err(err) => {
+                let err_title = "下载失败";
+                let message = err.to_message();
+                tracing::error!(err_title, message);
 
-            return;
+                self.set_state(DownloadTaskState::Failed);
+
+                return;
+            }
+        }
+
+        let quality_priority = QualityPriority::from_config(&self.app.get_config().read());
+        let record = DownloadHistoryRecord::from_progress(&self.progress.read(), quality_priority);
+        let download_history = &self.app.get_download_manager().download_history;
+        if let Err(err) = download_history.add_record(record) {
+            let err_title = "保存下载历史失败";
+            let message = err.to_message();
+            tracing::error!(err_title, message);
         }
 
         self.sleep_between_task().await;
@@ -321,11 +364,10 @@ impl DownloadTask {
         let mut remaining_sec = self.app.get_config().read().task_download_interval_sec;
         while remaining_sec > 0 {
             // 发送章节休眠事件
-            let _ = DownloadEvent::TaskSleeping {
+            self.app.emit_download_event(DownloadEvent::TaskSleeping {
                 task_id: task_id.clone(),
                 remaining_sec,
-            }
-            .emit(&self.app);
+            });
             sleep(Duration::from_secs(1)).await;
             remaining_sec -= 1;
         }
@@ -340,7 +382,6 @@ impl DownloadTask {
             None => match self
                 .app
                 .get_download_manager()
-                .inner()
                 .task_sem
                 .acquire()
                 .await
@@ -384,7 +425,8 @@ impl DownloadTask {
     ) {
         let state = *state_receiver.borrow();
         let task_id = self.task_id.clone();
-        let _ = DownloadEvent::TaskStateUpdate { task_id, state }.emit(&self.app);
+        self.app
+            .emit_download_event(DownloadEvent::TaskStateUpdate { task_id, state });
 
         if state == DownloadTaskState::Paused {
             // 稍微等一下再释放permit
@@ -432,6 +474,14 @@ impl DownloadTask {
         }
     }
 
+    /// 修改任务的限速，正在下载的分片也会立即按新的限速下载
+    #[cfg(feature = "gui")]
+    pub fn set_speed_limit(&self, speed_limit_kb_per_sec: u64) {
+        self.speed_limiter
+            .set_limit(speed_limit_kb_per_sec.saturating_mul(1024));
+        self.update_progress(|p| p.speed_limit_kb_per_sec = speed_limit_kb_per_sec);
+    }
+
     #[instrument(level = "error", skip_all)]
     pub fn update_progress(&self, update_fn: impl FnOnce(&mut DownloadProgress)) {
         // 修改数据
@@ -445,10 +495,9 @@ impl DownloadTask {
             progress
         };
         // 发送更新事件并保存到文件
-        let _ = DownloadEvent::ProgressUpdate {
+        self.app.emit_download_event(DownloadEvent::ProgressUpdate {
             progress: updated_progress.clone(),
-        }
-        .emit(&self.app);
+        });
 
         if let Err(err) = updated_progress.save(&self.app, false) {
             let err_title = "保存下载进度到文件失败";
@@ -458,6 +507,48 @@ impl DownloadTask {
     }
 }
 
+/// `DownloadTask::from_params`的结果
+#[derive(Default)]
+pub struct CreatedDownloadTasks {
+    pub tasks: Vec<Arc<DownloadTask>>,
+    /// 按`DuplicateDownloadAction::Skip`没有创建下载任务的下载的进度，不算创建失败
+    pub skipped_duplicates: Vec<DownloadProgress>,
+}
+
+impl CreatedDownloadTasks {
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty() && self.skipped_duplicates.is_empty()
+    }
+}
+
+/// 按`duplicate_download_action`处理下载历史中已有的下载，返回是否跳过创建下载任务
+fn should_skip_duplicate(app: &AppContext, progress: &DownloadProgress) -> bool {
+    let (duplicate_download_action, quality_priority) = {
+        let config = app.get_config().read();
+        (
+            config.duplicate_download_action,
+            QualityPriority::from_config(&config),
+        )
+    };
+    if duplicate_download_action == DuplicateDownloadAction::Redownload {
+        return false;
+    }
+
+    let download_history = &app.get_download_manager().download_history;
+    let Some(record) = download_history.find_duplicate(progress, &quality_priority) else {
+        return false;
+    };
+
+    let completed_ts = record.completed_ts;
+    if duplicate_download_action == DuplicateDownloadAction::Skip {
+        tracing::warn!(completed_ts, "下载历史中已有相同的下载，跳过创建下载任务");
+        return true;
+    }
+
+    tracing::warn!(completed_ts, "下载历史中已有相同的下载，仍然创建下载任务");
+    false
+}
+
 pub struct DownloadTaskTraceFields {
     pub task_id: String,
     pub episode_type: EpisodeType,
@@ -493,3 +584,45 @@ impl From<&DownloadProgress> for DownloadTaskTraceFields {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use eyre::eyre;
+
+    use crate::{downloader::tasks::video_task::VideoTask, test_support::create_test_app};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn interrupted_download_is_not_failed() {
+        let (app, _data_dir) = create_test_app(|config| config.task_download_interval_sec = 0);
+        let progress = DownloadProgress {
+            task_id: "task-1".to_string(),
+            video_task: VideoTask {
+                selected: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let task = DownloadTask::from_progress(app.clone(), progress);
+        let download_history = &app.get_download_manager().download_history;
+        // 没有接收者时`watch::Sender::send`会失败，所以自己订阅一个
+        let state_receiver = task.state_sender.subscribe();
+        let state = || *state_receiver.borrow();
+        assert_eq!(state(), DownloadTaskState::Paused);
+
+        task.handle_download_result(Ok(DownloadOutcome::Interrupted))
+            .await;
+        assert_eq!(state(), DownloadTaskState::Paused);
+        assert!(download_history.find_by_task_id("task-1").is_none());
+
+        task.handle_download_result(Err(eyre!("分片下载失败")))
+            .await;
+        assert_eq!(state(), DownloadTaskState::Failed);
+
+        task.handle_download_result(Ok(DownloadOutcome::Completed))
+            .await;
+        assert_eq!(state(), DownloadTaskState::Completed);
+        assert!(download_history.find_by_task_id("task-1").is_some());
+    }
+}
@@ -1,10 +1,9 @@
 use eyre::{OptionExt, WrapErr};
-use tauri::AppHandle;
 use tracing::instrument;
 
 use crate::{
+    app_context::AppContext,
     downloader::{download_progress::DownloadProgress, episode_type::EpisodeType},
-    extensions::AppHandleExt,
     types::{
         bangumi_info::BangumiInfo, cheese_info::CheeseInfo,
         get_bangumi_info_params::GetBangumiInfoParams, get_cheese_info_params::GetCheeseInfoParams,
@@ -19,10 +18,21 @@ pub enum EpisodeInfo {
     Cheese(CheeseInfo, i64),
 }
 
+impl EpisodeInfo {
+    /// 普通视频的简介、番剧的简介或课程的副标题
+    pub fn description(&self) -> String {
+        match self {
+            EpisodeInfo::Normal(info) => info.desc.clone(),
+            EpisodeInfo::Bangumi(info, _ep_id) => info.evaluate.clone(),
+            EpisodeInfo::Cheese(info, _ep_id) => info.subtitle.clone(),
+        }
+    }
+}
+
 pub trait GetOrInitEpisodeInfo {
     async fn get_or_init<'a>(
         &'a mut self,
-        app: &AppHandle,
+        app: &AppContext,
         progress: &DownloadProgress,
     ) -> eyre::Result<&'a mut EpisodeInfo>;
 }
@@ -31,7 +41,7 @@ impl GetOrInitEpisodeInfo for Option<EpisodeInfo> {
     #[instrument(level = "error", skip_all)]
     async fn get_or_init<'a>(
         &'a mut self,
-        app: &AppHandle,
+        app: &AppContext,
         progress: &DownloadProgress,
     ) -> eyre::Result<&'a mut EpisodeInfo> {
         if let Some(info) = self {
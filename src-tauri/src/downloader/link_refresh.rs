@@ -0,0 +1,165 @@
+use std::future::Future;
+
+use crate::{
+    app_context::AppContext,
+    bili_client::MediaStatusError,
+    downloader::{
+        download_chunk_task::DownloadChunksOutcome,
+        download_progress::{DownloadProgress, MediaUrl},
+        download_task::DownloadOutcome,
+    },
+};
+
+/// 链接过期后最多刷新几次
+const MAX_REFRESH_COUNT: u32 = 2;
+
+/// 链接过期后可以重新获取链接的媒体，视频和音频都是
+pub trait RefreshableMedia: Clone + Sync {
+    /// 从`media_url`中选择媒体
+    fn prepare(
+        &mut self,
+        app: &AppContext,
+        media_url: MediaUrl,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    /// 检查重新选择的媒体与`previous`是否相同，不同时已下载的分片不能继续使用
+    fn ensure_same_media(&self, previous: &Self) -> eyre::Result<()>;
+}
+
+/// 分片下载失败的原因是否为链接过期
+pub fn is_link_expired(err: &eyre::Report) -> bool {
+    err.downcast_ref::<MediaStatusError>()
+        .is_some_and(MediaStatusError::is_link_expired)
+}
+
+/// 重新获取链接并选择与`media`相同的媒体
+///
+/// 只有媒体的大小也不变时才能继续使用已下载的分片
+pub async fn refresh_media<T: RefreshableMedia>(
+    app: &AppContext,
+    progress: &DownloadProgress,
+    media: &T,
+) -> eyre::Result<T> {
+    let mut refreshed = media.clone();
+    refreshed
+        .prepare(app, progress.get_media_url(app).await?)
+        .await?;
+    refreshed.ensure_same_media(media)?;
+    Ok(refreshed)
+}
+
+/// 用`download_chunks`下载所有未完成的分片，链接过期时用`refresh_url`刷新链接后继续，
+/// 最多刷新`MAX_REFRESH_COUNT`次
+///
+/// `mirror_pools`是下载分片用的镜像池，刷新链接后换成`refresh_url`返回的新镜像池。
+/// 返回`Completed`时不代表所有分片都已下载
+pub async fn download_with_refresh<P, DownloadFut, RefreshFut>(
+    mut mirror_pools: P,
+    mut download_chunks: impl FnMut(P) -> DownloadFut,
+    mut refresh_url: impl FnMut() -> RefreshFut,
+) -> eyre::Result<DownloadOutcome>
+where
+    P: Clone,
+    DownloadFut: Future<Output = eyre::Result<DownloadChunksOutcome>>,
+    RefreshFut: Future<Output = eyre::Result<P>>,
+{
+    let mut refresh_count = 0;
+    loop {
+        match download_chunks(mirror_pools.clone()).await? {
+            DownloadChunksOutcome::Interrupted => return Ok(DownloadOutcome::Interrupted),
+            DownloadChunksOutcome::LinkExpired if refresh_count < MAX_REFRESH_COUNT => {
+                refresh_count += 1;
+                tracing::info!("链接已过期，重新获取链接");
+                mirror_pools = refresh_url().await?;
+            }
+            _ => return Ok(DownloadOutcome::Completed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    /// 模拟一轮分片下载，`status`是失败分片的状态码，为`None`时全部下载成功
+    fn download_chunks_outcome(status: Option<StatusCode>) -> DownloadChunksOutcome {
+        let Some(status) = status else {
+            return DownloadChunksOutcome::Finished;
+        };
+        let err = eyre::Report::new(MediaStatusError(status)).wrap_err("分片`1/1`下载失败");
+        if is_link_expired(&err) {
+            DownloadChunksOutcome::LinkExpired
+        } else {
+            DownloadChunksOutcome::Finished
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_expired_link_and_continue() {
+        // 第一轮链接过期，刷新后第二轮下载成功
+        let statuses = RefCell::new(vec![None, Some(StatusCode::FORBIDDEN)]);
+        let used_pools = RefCell::new(Vec::new());
+        let refresh_count = Cell::new(0);
+
+        let outcome = download_with_refresh(
+            0,
+            |mirror_pools| {
+                used_pools.borrow_mut().push(mirror_pools);
+                let status = statuses.borrow_mut().pop().unwrap();
+                async move { Ok(download_chunks_outcome(status)) }
+            },
+            || {
+                refresh_count.set(refresh_count.get() + 1);
+                async { Ok(refresh_count.get()) }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome, DownloadOutcome::Completed);
+        assert_eq!(refresh_count.get(), 1);
+        // 刷新后用新的镜像池下载
+        assert_eq!(*used_pools.borrow(), [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn stop_refreshing_after_max_refresh_count() {
+        let download_count = Cell::new(0);
+        let refresh_count = Cell::new(0);
+
+        let outcome = download_with_refresh(
+            (),
+            |()| {
+                download_count.set(download_count.get() + 1);
+                async { Ok(download_chunks_outcome(Some(StatusCode::GONE))) }
+            },
+            || {
+                refresh_count.set(refresh_count.get() + 1);
+                async { Ok(()) }
+            },
+        )
+        .await
+        .unwrap();
+
+        // 刷新次数用完后不再刷新，由调用者检查分片是否都已下载
+        assert_eq!(outcome, DownloadOutcome::Completed);
+        assert_eq!(refresh_count.get(), MAX_REFRESH_COUNT);
+        assert_eq!(download_count.get(), MAX_REFRESH_COUNT + 1);
+    }
+
+    #[test]
+    fn only_expired_link_status_triggers_refresh() {
+        assert_eq!(
+            download_chunks_outcome(Some(StatusCode::NOT_FOUND)),
+            DownloadChunksOutcome::LinkExpired
+        );
+        assert_eq!(
+            download_chunks_outcome(Some(StatusCode::INTERNAL_SERVER_ERROR)),
+            DownloadChunksOutcome::Finished
+        );
+    }
+}
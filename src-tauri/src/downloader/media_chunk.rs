@@ -6,4 +6,7 @@ pub struct MediaChunk {
     pub start: u64,
     pub end: u64,
     pub completed: bool,
+    /// 分段视频(durl)中分片所属的段，其余为0
+    #[serde(default)]
+    pub segment_index: usize,
 }
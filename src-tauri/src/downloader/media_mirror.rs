@@ -0,0 +1,171 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 连续失败多少次后降级镜像
+const DEMOTE_THRESHOLD: u32 = 3;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct MediaMirror {
+    pub url: String,
+    pub success_count: u64,
+    pub failure_count: u64,
+    pub consecutive_failures: u32,
+    /// 被降级的镜像只有在其他镜像都试过后才会被使用
+    pub demoted: bool,
+}
+
+impl MediaMirror {
+    /// 用`preferred_url`和其他内容长度一致的地址创建镜像列表，`preferred_url`排在最前面
+    pub fn create_mirrors(
+        url_with_content_length: &[(String, u64)],
+        preferred_url: &str,
+        content_length: u64,
+    ) -> Vec<MediaMirror> {
+        let mut urls = vec![preferred_url];
+        for (url, len) in url_with_content_length {
+            // 内容长度不一致的地址不是同一个文件，不能混用
+            if *len == content_length && url != preferred_url {
+                urls.push(url);
+            }
+        }
+
+        urls.into_iter()
+            .map(|url| MediaMirror {
+                url: url.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// 下载过程中各分片共享的镜像池
+pub struct MirrorPool {
+    mirrors: Mutex<Vec<MediaMirror>>,
+}
+
+impl MirrorPool {
+    pub fn new(mirrors: Vec<MediaMirror>) -> Self {
+        Self {
+            mirrors: Mutex::new(mirrors),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mirrors.lock().len()
+    }
+
+    /// 挑选一个不在`tried`中的镜像，返回镜像的下标和地址
+    ///
+    /// 优先按顺序选择未降级的镜像，都试过了再选择连续失败次数最少的降级镜像
+    pub fn pick(&self, tried: &[usize]) -> Option<(usize, String)> {
+        let mirrors = self.mirrors.lock();
+        let untried = || {
+            mirrors
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !tried.contains(i))
+        };
+
+        untried()
+            .find(|(_, mirror)| !mirror.demoted)
+            .or_else(|| untried().min_by_key(|(_, mirror)| mirror.consecutive_failures))
+            .map(|(i, mirror)| (i, mirror.url.clone()))
+    }
+
+    pub fn report_success(&self, index: usize) {
+        let mut mirrors = self.mirrors.lock();
+        if let Some(mirror) = mirrors.get_mut(index) {
+            mirror.success_count += 1;
+            mirror.consecutive_failures = 0;
+        }
+    }
+
+    pub fn report_failure(&self, index: usize) {
+        let mut mirrors = self.mirrors.lock();
+        if let Some(mirror) = mirrors.get_mut(index) {
+            mirror.failure_count += 1;
+            mirror.consecutive_failures += 1;
+            if mirror.consecutive_failures >= DEMOTE_THRESHOLD {
+                mirror.demoted = true;
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<MediaMirror> {
+        self.mirrors.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror_pool(count: usize) -> MirrorPool {
+        let mirrors = (0..count)
+            .map(|i| MediaMirror {
+                url: format!("https://mirror-{i}"),
+                ..Default::default()
+            })
+            .collect();
+        MirrorPool::new(mirrors)
+    }
+
+    fn report_failures(pool: &MirrorPool, index: usize, count: u32) {
+        for _ in 0..count {
+            pool.report_failure(index);
+        }
+    }
+
+    #[test]
+    fn demoted_after_threshold_failures() {
+        let pool = mirror_pool(2);
+
+        report_failures(&pool, 0, DEMOTE_THRESHOLD - 1);
+        assert!(!pool.snapshot()[0].demoted);
+
+        pool.report_failure(0);
+        let mirror = &pool.snapshot()[0];
+        assert!(mirror.demoted);
+        assert_eq!(mirror.failure_count, u64::from(DEMOTE_THRESHOLD));
+    }
+
+    #[test]
+    fn success_resets_consecutive_failures() {
+        let pool = mirror_pool(2);
+
+        report_failures(&pool, 0, DEMOTE_THRESHOLD - 1);
+        pool.report_success(0);
+        assert_eq!(pool.snapshot()[0].consecutive_failures, 0);
+
+        // 成功之后要重新连续失败够次数才会被降级
+        report_failures(&pool, 0, DEMOTE_THRESHOLD - 1);
+        let mirror = &pool.snapshot()[0];
+        assert!(!mirror.demoted);
+        assert_eq!(mirror.success_count, 1);
+        assert_eq!(mirror.failure_count, u64::from(2 * (DEMOTE_THRESHOLD - 1)));
+    }
+
+    #[test]
+    fn pick_rotates_to_next_healthy_mirror() {
+        let pool = mirror_pool(3);
+        assert_eq!(pool.pick(&[]), Some((0, "https://mirror-0".to_string())));
+        assert_eq!(pool.pick(&[0]), Some((1, "https://mirror-1".to_string())));
+
+        report_failures(&pool, 0, DEMOTE_THRESHOLD);
+        assert_eq!(pool.pick(&[]).map(|(i, _)| i), Some(1));
+        assert_eq!(pool.pick(&[1]).map(|(i, _)| i), Some(2));
+    }
+
+    #[test]
+    fn pick_falls_back_to_least_failed_demoted_mirror() {
+        let pool = mirror_pool(2);
+        report_failures(&pool, 0, DEMOTE_THRESHOLD + 1);
+        report_failures(&pool, 1, DEMOTE_THRESHOLD);
+
+        assert_eq!(pool.pick(&[]).map(|(i, _)| i), Some(1));
+        assert_eq!(pool.pick(&[1]).map(|(i, _)| i), Some(0));
+        assert_eq!(pool.pick(&[0, 1]), None);
+    }
+}
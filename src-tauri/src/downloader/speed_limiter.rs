@@ -0,0 +1,163 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use tokio::time::{Instant, sleep};
+
+/// 单次等待的最长时间
+///
+/// 等待期间限速值可能被修改，分段等待可以让新的限速值尽快生效
+const MAX_WAIT: Duration = Duration::from_millis(200);
+
+/// 令牌桶限速器，令牌的单位为字节
+///
+/// 允许欠账：只要桶里的令牌不为负数就直接放行并扣除令牌，
+/// 后来者需要等到欠账还清才能继续，这样任意大小的数据块都能通过
+pub struct SpeedLimiter {
+    /// 每秒补充的令牌数，0表示不限速
+    byte_per_sec: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl SpeedLimiter {
+    pub fn new(byte_per_sec: u64) -> Self {
+        Self {
+            byte_per_sec: AtomicU64::new(byte_per_sec),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_limit(&self, byte_per_sec: u64) {
+        let old_byte_per_sec = self.byte_per_sec.swap(byte_per_sec, Ordering::Relaxed);
+        if old_byte_per_sec != byte_per_sec {
+            // 限速值变了，之前积攒的令牌或欠账都作废
+            let mut bucket = self.bucket.lock();
+            bucket.tokens = 0.0;
+            bucket.last_refill = Instant::now();
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.byte_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// 获取`bytes`个令牌，令牌不足时会等待
+    #[allow(clippy::cast_precision_loss)]
+    pub async fn acquire(&self, bytes: u64) {
+        loop {
+            let byte_per_sec = self.limit();
+            if byte_per_sec == 0 {
+                return;
+            }
+            let byte_per_sec = byte_per_sec as f64;
+
+            let wait = {
+                let mut bucket = self.bucket.lock();
+                // 补充令牌，最多积攒1秒的量
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * byte_per_sec).min(byte_per_sec);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 0.0 {
+                    bucket.tokens -= bytes as f64;
+                    return;
+                }
+                // 还在欠账，等欠账还清
+                Duration::from_secs_f64(-bucket.tokens / byte_per_sec)
+            };
+
+            sleep(wait.min(MAX_WAIT)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const CHUNK_SIZE: u64 = 16 * 1024;
+
+    /// 一块一块地获取令牌，直到过去`duration`，返回放行的字节数
+    async fn acquire_for(limiter: &SpeedLimiter, duration: Duration) -> u64 {
+        let start = Instant::now();
+        let mut acquired = 0;
+        while start.elapsed() < duration {
+            limiter.acquire(CHUNK_SIZE).await;
+            acquired += CHUNK_SIZE;
+        }
+        acquired
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn assert_near(acquired: u64, expected: u64) {
+        let ratio = acquired as f64 / expected as f64;
+        assert!(
+            (0.95..=1.05).contains(&ratio),
+            "acquired {acquired} bytes, expected about {expected} bytes"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throughput_stays_near_limit() {
+        let limiter = SpeedLimiter::new(100 * 1024);
+
+        let acquired = acquire_for(&limiter, Duration::from_secs(10)).await;
+
+        assert_near(acquired, 10 * 100 * 1024);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_limit_is_unlimited() {
+        let limiter = SpeedLimiter::new(0);
+        let start = Instant::now();
+
+        for _ in 0..1000 {
+            limiter.acquire(u64::MAX / 2).await;
+        }
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_limit_takes_effect_mid_download() {
+        let limiter = SpeedLimiter::new(100 * 1024);
+
+        let acquired = acquire_for(&limiter, Duration::from_secs(10)).await;
+        assert_near(acquired, 10 * 100 * 1024);
+
+        limiter.set_limit(1000 * 1024);
+        let acquired = acquire_for(&limiter, Duration::from_secs(10)).await;
+        assert_near(acquired, 10 * 1000 * 1024);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_limit_wakes_waiting_acquire() {
+        let limiter = Arc::new(SpeedLimiter::new(1024));
+        // 欠下1000秒的账，下一次获取需要等很久
+        limiter.acquire(1000 * 1024).await;
+
+        let start = Instant::now();
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(CHUNK_SIZE).await }
+        });
+        sleep(Duration::from_secs(1)).await;
+        limiter.set_limit(0);
+        waiting.await.unwrap();
+
+        assert!(start.elapsed() <= Duration::from_secs(1) + MAX_WAIT);
+    }
+}
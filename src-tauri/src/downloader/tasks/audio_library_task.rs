@@ -0,0 +1,281 @@
+use std::{path::Path, sync::Arc};
+
+use chrono::DateTime;
+use eyre::{WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::instrument;
+
+use crate::{
+    audio_tagger::{self, AudioContainer, AudioTags, CoverArt},
+    config::AudioLibraryFormat,
+    downloader::{
+        download_progress::DownloadProgress,
+        download_task::DownloadTask,
+        episode_info::{EpisodeInfo, GetOrInitEpisodeInfo},
+    },
+    utils,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct AudioLibraryTask {
+    pub selected: bool,
+    pub format: AudioLibraryFormat,
+    pub completed: bool,
+    pub skipped: bool,
+}
+
+impl AudioLibraryTask {
+    pub fn mark_uncompleted(&mut self) {
+        self.completed = false;
+        self.skipped = false;
+    }
+
+    pub fn is_completed(&self) -> bool {
+        !self.selected || self.completed
+    }
+
+    /// 给下载好的音频写入标签，FLAC会还原为`.flac`，选了MP3时用`FFmpeg`转码
+    #[instrument(level = "error", skip_all)]
+    pub async fn process(
+        &self,
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+        episode_info: &mut Option<EpisodeInfo>,
+    ) -> eyre::Result<()> {
+        let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
+
+        let audio_path = episode_dir.join(format!("{filename}.m4a"));
+        if !audio_path.exists() {
+            tracing::debug!("音频文件不存在，跳过音频库处理");
+            download_task.update_progress(|p| {
+                p.audio_library_task.skipped = true;
+                p.audio_library_task.completed = true;
+            });
+            return Ok(());
+        }
+
+        let tags = create_tags(download_task, progress, episode_info)
+            .await
+            .wrap_err("获取音频标签失败")?;
+
+        match self.format {
+            AudioLibraryFormat::Original => write_original(&audio_path, tags).await?,
+            AudioLibraryFormat::Mp3 => {
+                let output_path = episode_dir.join(format!("{filename}.mp3"));
+                let result = transcode_to_mp3(&audio_path, tags, &output_path).await;
+                if let Err(err) = result {
+                    // 删掉写了一半的文件
+                    let _ = std::fs::remove_file(&output_path);
+                    return Err(err);
+                }
+                std::fs::remove_file(&audio_path)
+                    .wrap_err(format!("删除音频文件`{}`失败", audio_path.display()))?;
+            }
+        }
+
+        download_task.update_progress(|p| p.audio_library_task.completed = true);
+
+        Ok(())
+    }
+}
+
+#[instrument(level = "error", skip_all)]
+async fn create_tags(
+    download_task: &Arc<DownloadTask>,
+    progress: &DownloadProgress,
+    episode_info: &mut Option<EpisodeInfo>,
+) -> eyre::Result<AudioTags> {
+    let title = match &progress.part_title {
+        Some(part_title) if !part_title.is_empty() => part_title.clone(),
+        _ => progress.episode_title.clone(),
+    };
+    let track_number = progress.part_order.unwrap_or(progress.episode_order);
+
+    let ts = progress.pub_ts;
+    let date = DateTime::from_timestamp(ts, 0)
+        .ok_or_else(|| eyre!("将发布时间戳转换为日期时间失败: {ts}"))?
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d")
+        .to_string();
+
+    let description = episode_info
+        .get_or_init(&download_task.app, progress)
+        .await?
+        .description();
+
+    let bili_client = download_task.app.get_bili_client();
+    let (cover_data, ext) = bili_client
+        .get_cover_data_and_ext(&progress.cover_task.url)
+        .await
+        .wrap_err("获取封面失败")?;
+    let media_type = match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        _ => return Err(eyre!("不支持的封面格式`{ext}`")),
+    };
+
+    Ok(AudioTags {
+        title,
+        artist: progress.up_name.clone(),
+        album: progress.collection_title.clone(),
+        track_number: u32::try_from(track_number).ok(),
+        date,
+        description,
+        cover: Some(CoverArt {
+            data: cover_data.to_vec(),
+            media_type: media_type.to_string(),
+        }),
+    })
+}
+
+/// 不转码，FLAC写成`{filename}.flac`后删除M4A，其他编码直接给M4A写入标签
+#[instrument(level = "error", skip_all)]
+async fn write_original(audio_path: &Path, tags: AudioTags) -> eyre::Result<()> {
+    let audio_path = audio_path.to_path_buf();
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _enter = current_span.enter();
+
+        let container = audio_tagger::native_container(&audio_path)?;
+        let output_path = match container {
+            AudioContainer::Flac => audio_path.with_extension("flac"),
+            AudioContainer::M4a => audio_path.with_extension("tagged.m4a"),
+        };
+        let result = match container {
+            AudioContainer::Flac => {
+                audio_tagger::flac::write_flac(&audio_path, &tags, &output_path)
+            }
+            AudioContainer::M4a => audio_tagger::m4a::write_m4a(&audio_path, &tags, &output_path),
+        };
+        if let Err(err) = result {
+            // 删掉写了一半的文件
+            let _ = std::fs::remove_file(&output_path);
+            return Err(err.wrap_err(format!("写入{}标签失败", container.extension())));
+        }
+
+        std::fs::remove_file(&audio_path)
+            .wrap_err(format!("删除音频文件`{}`失败", audio_path.display()))?;
+        if container == AudioContainer::M4a {
+            std::fs::rename(&output_path, &audio_path).wrap_err(format!(
+                "将`{}`重命名为`{}`失败",
+                output_path.display(),
+                audio_path.display()
+            ))?;
+        }
+
+        Ok(())
+    })
+    .await?
+}
+
+/// 内置的FFmpeg只有封装功能，没有MP3编码器，要转码就需要换成完整版的FFmpeg
+#[instrument(level = "error", skip_all)]
+async fn transcode_to_mp3(
+    audio_path: &Path,
+    tags: AudioTags,
+    output_path: &Path,
+) -> eyre::Result<()> {
+    let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
+    if !ffmpeg_program.exists() {
+        return Err(eyre!("找不到FFmpeg`{}`", ffmpeg_program.display()));
+    }
+
+    // FFmpeg只能从文件读取封面
+    let cover_path = tags.cover.as_ref().map(|cover| {
+        let ext = cover.media_type.trim_start_matches("image/");
+        audio_path.with_extension(format!("cover.{ext}"))
+    });
+    if let (Some(cover), Some(cover_path)) = (&tags.cover, &cover_path) {
+        std::fs::write(cover_path, &cover.data)
+            .wrap_err(format!("保存封面到`{}`失败", cover_path.display()))?;
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let audio_path_clone = audio_path.to_path_buf();
+    let cover_path_clone = cover_path.clone();
+    let output_path_clone = output_path.to_path_buf();
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _enter = current_span.enter();
+        let mut command = create_mp3_command(
+            &ffmpeg_program,
+            &audio_path_clone,
+            cover_path_clone.as_deref(),
+            &tags,
+            &output_path_clone,
+        );
+        let _ = tx.send(command.output());
+    });
+    let output = rx.await?;
+
+    if let Some(cover_path) = cover_path {
+        std::fs::remove_file(&cover_path)
+            .wrap_err(format!("删除封面`{}`失败", cover_path.display()))?;
+    }
+
+    let output = output?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let err = eyre!(format!("STDOUT: {stdout}"))
+            .wrap_err(format!("STDERR: {stderr}"))
+            .wrap_err("转码为MP3失败，内置的FFmpeg没有MP3编码器，可以把它替换为带有libmp3lame的完整版FFmpeg");
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn create_mp3_command(
+    ffmpeg_program: &Path,
+    audio_path: &Path,
+    cover_path: Option<&Path>,
+    tags: &AudioTags,
+    output_path: &Path,
+) -> std::process::Command {
+    let mut command = std::process::Command::new(ffmpeg_program);
+
+    command.arg("-i").arg(audio_path);
+    if let Some(cover_path) = cover_path {
+        command.arg("-i").arg(cover_path);
+    }
+    command.arg("-map").arg("0:a:0");
+    if cover_path.is_some() {
+        command.arg("-map").arg("1:v:0");
+        command.arg("-c:v").arg("copy");
+        command.arg("-disposition:v").arg("attached_pic");
+    }
+    command.arg("-c:a").arg("libmp3lame").arg("-q:a").arg("0");
+    // 很多播放器不认ID3v2.4
+    command.arg("-id3v2_version").arg("3");
+
+    let track_number = tags.track_number.map(|n| n.to_string());
+    let metadata = [
+        ("title", Some(&tags.title)),
+        ("artist", tags.artist.as_ref()),
+        ("album", Some(&tags.album)),
+        ("track", track_number.as_ref()),
+        ("date", Some(&tags.date)),
+        ("comment", Some(&tags.description)),
+    ];
+    for (key, value) in metadata {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            command.arg("-metadata").arg(format!("{key}={value}"));
+        }
+    }
+
+    command.arg(output_path).arg("-y");
+
+    #[cfg(target_os = "windows")]
+    {
+        // 隐藏窗口
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x0800_0000);
+    }
+
+    command
+}
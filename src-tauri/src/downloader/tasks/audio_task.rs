@@ -9,17 +9,21 @@ use fs4::fs_std::FileExt;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::AppHandle;
 use tokio::task::JoinSet;
 use tracing::{Instrument, instrument};
 
 use crate::{
+    app_context::AppContext,
     config::FileExistAction,
     downloader::{
-        download_chunk_task::DownloadChunkTask, download_progress::DownloadProgress,
-        download_task::DownloadTask, media_chunk::MediaChunk,
+        download_chunk_task::{DownloadChunkResult, DownloadChunkTask, DownloadChunksOutcome},
+        download_progress::{DownloadProgress, MediaUrl},
+        download_task::{DownloadOutcome, DownloadTask},
+        link_refresh::{self, RefreshableMedia},
+        media_chunk::MediaChunk,
+        media_mirror::{MediaMirror, MirrorPool},
     },
-    extensions::{AppHandleExt, EyreReportToMessage},
+    extensions::EyreReportToMessage,
     types::{
         audio_quality::AudioQuality, bangumi_media_url::BangumiMediaUrl,
         cheese_media_url::CheeseMediaUrl, normal_media_url::NormalMediaUrl,
@@ -34,6 +38,7 @@ const CHUNK_SIZE: u64 = 2 * 1024 * 1024; // 2MB
 pub struct AudioTask {
     pub selected: bool,
     pub url: String,
+    pub mirrors: Vec<MediaMirror>,
     pub audio_quality: AudioQuality,
     pub content_length: u64,
     pub chunks: Vec<MediaChunk>,
@@ -42,7 +47,7 @@ pub struct AudioTask {
 }
 
 impl AudioTask {
-    pub async fn prepare_normal(&mut self, app: &AppHandle, media_url: &NormalMediaUrl) {
+    pub async fn prepare_normal(&mut self, app: &AppContext, media_url: &NormalMediaUrl) {
         let mut join_set = JoinSet::new();
 
         if let Some(medias) = &media_url.dash.audio {
@@ -127,7 +132,7 @@ impl AudioTask {
         self.prepare(app, &medias);
     }
 
-    pub async fn prepare_bangumi(&mut self, app: &AppHandle, media_url: &BangumiMediaUrl) {
+    pub async fn prepare_bangumi(&mut self, app: &AppContext, media_url: &BangumiMediaUrl) {
         let Some(dash) = &media_url.dash else {
             // 如果没有音频，则直接返回
             self.completed = true;
@@ -183,7 +188,7 @@ impl AudioTask {
         self.prepare(app, &medias);
     }
 
-    pub async fn prepare_cheese(&mut self, app: &AppHandle, media_url: &CheeseMediaUrl) {
+    pub async fn prepare_cheese(&mut self, app: &AppContext, media_url: &CheeseMediaUrl) {
         let Some(dash) = &media_url.dash else {
             // 如果没有音频，则直接返回
             self.completed = true;
@@ -239,7 +244,7 @@ impl AudioTask {
         self.prepare(app, &medias);
     }
 
-    fn prepare(&mut self, app: &AppHandle, medias: &[MediaForPrepare]) {
+    fn prepare(&mut self, app: &AppContext, medias: &[MediaForPrepare]) {
         if medias.is_empty() {
             self.completed = true;
             return;
@@ -268,6 +273,8 @@ impl AudioTask {
             .unwrap_or(&media.url_with_content_length[0])
             .clone();
 
+        self.mirrors =
+            MediaMirror::create_mirrors(&media.url_with_content_length, &url, content_length);
         self.url = url;
 
         if self.content_length != content_length {
@@ -282,6 +289,7 @@ impl AudioTask {
                     start,
                     end,
                     completed: false,
+                    segment_index: 0,
                 });
             }
 
@@ -308,7 +316,7 @@ impl AudioTask {
         &self,
         download_task: &Arc<DownloadTask>,
         progress: &DownloadProgress,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<DownloadOutcome> {
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
         let audio_task = progress.audio_task.clone();
 
@@ -320,7 +328,7 @@ impl AudioTask {
                 p.audio_task.skipped = true;
                 p.audio_task.completed = true;
             });
-            return Ok(());
+            return Ok(DownloadOutcome::Completed);
         }
 
         let temp_file_path = episode_dir.join(format!(
@@ -346,47 +354,28 @@ impl AudioTask {
         };
         let file = Arc::new(Mutex::new(file));
 
-        let chunk_count = audio_task.chunks.len();
-
-        let mut join_set = JoinSet::new();
-        for (chunk_index, chunk) in audio_task.chunks.iter().enumerate() {
-            if chunk.completed {
-                continue;
-            }
-
-            let (start, end) = (chunk.start, chunk.end);
-
-            let download_chunk_task = DownloadChunkTask {
-                download_task: download_task.clone(),
-                start,
-                end,
-                url: audio_task.url.clone(),
-                file: file.clone(),
-                chunk_index,
-            };
-
-            let chunk_order = chunk_index + 1;
-            let chunk_task = async move {
-                download_chunk_task.process().await.wrap_err(format!(
-                    "分片`{chunk_order}/{chunk_count}`下载失败({start}-{end})"
-                ))
-            };
-            join_set.spawn(chunk_task.in_current_span());
-        }
-
-        while let Some(join_result) = join_set.join_next().await {
-            let Ok(download_audio_result) = join_result else {
-                continue;
-            };
-
-            match download_audio_result {
-                Ok(i) => download_task.update_progress(|p| p.audio_task.chunks[i].completed = true),
-                Err(err) => {
-                    let err_title = "音频的一个分片下载失败";
-                    let message = err.to_message();
-                    tracing::error!(err_title, message);
-                }
-            }
+        let mirrors = if audio_task.mirrors.is_empty() {
+            MediaMirror::create_mirrors(&[], &audio_task.url, audio_task.content_length)
+        } else {
+            audio_task.mirrors.clone()
+        };
+        let mirror_pool = Arc::new(MirrorPool::new(mirrors));
+
+        let outcome = link_refresh::download_with_refresh(
+            mirror_pool,
+            |mirror_pool| {
+                let file = &file;
+                async move { Self::download_chunks(download_task, file, &mirror_pool).await }
+            },
+            || async {
+                Self::refresh_url(download_task, progress)
+                    .await
+                    .wrap_err("刷新音频链接失败")
+            },
+        )
+        .await?;
+        if outcome == DownloadOutcome::Interrupted {
+            return Ok(DownloadOutcome::Interrupted);
         }
 
         let download_completed = download_task
@@ -429,6 +418,128 @@ impl AudioTask {
 
         download_task.update_progress(|p| p.audio_task.completed = true);
 
+        Ok(DownloadOutcome::Completed)
+    }
+
+    /// 下载所有未完成的分片
+    async fn download_chunks(
+        download_task: &Arc<DownloadTask>,
+        file: &Arc<Mutex<File>>,
+        mirror_pool: &Arc<MirrorPool>,
+    ) -> eyre::Result<DownloadChunksOutcome> {
+        let chunks = download_task.progress.read().audio_task.chunks.clone();
+        let chunk_count = chunks.len();
+
+        let mut join_set = JoinSet::new();
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            if chunk.completed {
+                continue;
+            }
+
+            let (start, end) = (chunk.start, chunk.end);
+
+            let download_chunk_task = DownloadChunkTask {
+                download_task: download_task.clone(),
+                start,
+                end,
+                mirror_pool: mirror_pool.clone(),
+                file: file.clone(),
+                chunk_index,
+            };
+
+            let chunk_order = chunk_index + 1;
+            let chunk_task = async move {
+                match download_chunk_task.process().await {
+                    DownloadChunkResult::Failed(err) => DownloadChunkResult::Failed(err.wrap_err(
+                        format!("分片`{chunk_order}/{chunk_count}`下载失败({start}-{end})"),
+                    )),
+                    result => result,
+                }
+            };
+            join_set.spawn(chunk_task.in_current_span());
+        }
+
+        let mut interrupted = false;
+        let mut link_expired = false;
+        while let Some(join_result) = join_set.join_next().await {
+            let Ok(download_chunk_result) = join_result else {
+                continue;
+            };
+
+            match download_chunk_result {
+                DownloadChunkResult::Downloaded(i) => download_task.update_progress(|p| {
+                    p.audio_task.chunks[i].completed = true;
+                    p.audio_task.mirrors = mirror_pool.snapshot();
+                }),
+                // 被打断的分片可能只写入了一部分，不能标记为已下载
+                DownloadChunkResult::Interrupted => interrupted = true,
+                DownloadChunkResult::Failed(err) => {
+                    download_task
+                        .update_progress(|p| p.audio_task.mirrors = mirror_pool.snapshot());
+                    if link_refresh::is_link_expired(&err) {
+                        link_expired = true;
+                    }
+                    let err_title = "音频的一个分片下载失败";
+                    let message = err.to_message();
+                    tracing::error!(err_title, message);
+                }
+            }
+        }
+
+        let outcome = if interrupted {
+            DownloadChunksOutcome::Interrupted
+        } else if link_expired {
+            DownloadChunksOutcome::LinkExpired
+        } else {
+            DownloadChunksOutcome::Finished
+        };
+
+        Ok(outcome)
+    }
+
+    /// 刷新音频链接，返回新的镜像池
+    async fn refresh_url(
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+    ) -> eyre::Result<Arc<MirrorPool>> {
+        let audio_task = download_task.progress.read().audio_task.clone();
+        let refreshed =
+            link_refresh::refresh_media(&download_task.app, progress, &audio_task).await?;
+
+        let mirror_pool = Arc::new(MirrorPool::new(refreshed.mirrors.clone()));
+        download_task.update_progress(|p| {
+            p.audio_task.url = refreshed.url;
+            p.audio_task.mirrors = refreshed.mirrors;
+        });
+
+        Ok(mirror_pool)
+    }
+}
+
+impl RefreshableMedia for AudioTask {
+    async fn prepare(&mut self, app: &AppContext, media_url: MediaUrl) -> eyre::Result<()> {
+        match media_url {
+            MediaUrl::Normal(media_url) => self.prepare_normal(app, &media_url).await,
+            MediaUrl::Bangumi(media_url) => self.prepare_bangumi(app, &media_url).await,
+            MediaUrl::Cheese(media_url) => self.prepare_cheese(app, &media_url).await,
+        }
+        Ok(())
+    }
+
+    fn ensure_same_media(&self, previous: &Self) -> eyre::Result<()> {
+        // 新的链接中没有音频时`completed`会被设为true
+        if self.completed || self.audio_quality != previous.audio_quality {
+            return Err(eyre!("新的链接中没有与之前相同音质的音频"));
+        }
+
+        if self.content_length != previous.content_length {
+            return Err(eyre!(
+                "新的链接中音频大小发生了变化({} -> {})",
+                previous.content_length,
+                self.content_length
+            ));
+        }
+
         Ok(())
     }
 }
@@ -452,7 +563,7 @@ fn select_exact_match_media(
 }
 
 fn select_media_by_priority(
-    app: &AppHandle,
+    app: &AppContext,
     medias: &[MediaForPrepare],
 ) -> Option<MediaForPrepare> {
     let quality_priority = app.get_config().read().audio_quality_priority.clone();
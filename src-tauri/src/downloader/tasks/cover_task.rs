@@ -5,10 +5,7 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tracing::instrument;
 
-use crate::{
-    downloader::{download_progress::DownloadProgress, download_task::DownloadTask},
-    extensions::AppHandleExt,
-};
+use crate::downloader::{download_progress::DownloadProgress, download_task::DownloadTask};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(default)]
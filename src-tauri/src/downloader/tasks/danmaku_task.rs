@@ -1,15 +1,26 @@
-use std::{fs::File, sync::Arc};
+use std::{collections::HashSet, fs::File, sync::Arc, time::Duration};
 
-use eyre::WrapErr;
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate};
+use eyre::{WrapErr, eyre};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tokio::time::sleep;
 use tracing::instrument;
 
 use crate::{
-    config::FileExistAction,
-    danmaku_xml_to_ass::xml_to_ass,
-    downloader::{download_progress::DownloadProgress, download_task::DownloadTask},
-    extensions::AppHandleExt,
+    app_context::AppContext,
+    config::{FileExistAction, HistoryDanmakuMode},
+    danmaku_xml_to_ass::{
+        analysis::xml_to_heatmap,
+        export::{xml_to_csv, xml_to_srt, xml_to_vtt},
+        xml_to_ass,
+    },
+    downloader::{
+        cut_ranges::CutRanges, download_progress::DownloadProgress, download_task::DownloadTask,
+    },
+    extensions::EyreReportToMessage,
+    mp4_muxer::track::{self, HANDLER_VIDEO},
+    protobuf::{DanmakuElem, DmSegMobileReply},
     utils::ToXml,
 };
 
@@ -20,6 +31,13 @@ pub struct DanmakuTask {
     pub xml_selected: bool,
     pub ass_selected: bool,
     pub json_selected: bool,
+    pub vtt_selected: bool,
+    pub srt_selected: bool,
+    pub csv_selected: bool,
+    pub heatmap_selected: bool,
+    pub history_mode: HistoryDanmakuMode,
+    pub history_start_date: String,
+    pub history_end_date: String,
     pub completed: bool,
     pub skipped: bool,
 }
@@ -31,10 +49,18 @@ impl DanmakuTask {
     }
 
     pub fn is_completed(&self) -> bool {
-        !self.xml_selected && !self.ass_selected && !self.json_selected || self.completed
+        let selected = self.xml_selected
+            || self.ass_selected
+            || self.json_selected
+            || self.vtt_selected
+            || self.srt_selected
+            || self.csv_selected
+            || self.heatmap_selected;
+        !selected || self.completed
     }
 
     #[instrument(level = "error", skip_all)]
+    #[allow(clippy::too_many_lines)]
     pub async fn process(
         &self,
         download_task: &Arc<DownloadTask>,
@@ -46,14 +72,23 @@ impl DanmakuTask {
         let xml_path = episode_dir.join(format!("{filename}.弹幕.xml"));
         let ass_path = episode_dir.join(format!("{filename}.弹幕.ass"));
         let json_path = episode_dir.join(format!("{filename}.弹幕.json"));
+        let vtt_path = episode_dir.join(format!("{filename}.弹幕.vtt"));
+        let srt_path = episode_dir.join(format!("{filename}.弹幕.srt"));
+        let csv_path = episode_dir.join(format!("{filename}.弹幕.csv"));
+        let heatmap_path = episode_dir.join(format!("{filename}.弹幕热度.json"));
 
         let file_exist_action = download_task.app.get_config().read().file_exist_action;
         if file_exist_action == FileExistAction::Skip {
             let skip_xml = !danmaku_task.xml_selected || xml_path.exists();
             let skip_ass = !danmaku_task.ass_selected || ass_path.exists();
             let skip_json = !danmaku_task.json_selected || json_path.exists();
+            let skip_vtt = !danmaku_task.vtt_selected || vtt_path.exists();
+            let skip_srt = !danmaku_task.srt_selected || srt_path.exists();
+            let skip_csv = !danmaku_task.csv_selected || csv_path.exists();
+            let skip_heatmap = !danmaku_task.heatmap_selected || heatmap_path.exists();
 
-            if skip_xml && skip_ass && skip_json {
+            if skip_xml && skip_ass && skip_json && skip_vtt && skip_srt && skip_csv && skip_heatmap
+            {
                 tracing::debug!("弹幕文件已存在，跳过下载");
                 download_task.update_progress(|p| {
                     p.danmaku_task.skipped = true;
@@ -63,11 +98,10 @@ impl DanmakuTask {
             }
         }
 
-        let bili_client = download_task.app.get_bili_client();
-        let replies = bili_client
-            .get_danmaku(progress.aid, progress.cid, progress.duration)
-            .await
-            .wrap_err("获取弹幕失败")?;
+        let mut replies = get_merged_replies(&download_task.app, progress).await?;
+        if let Some(cut_ranges) = &progress.video_process_task.cut_ranges {
+            remap_danmaku(&mut replies, cut_ranges);
+        }
 
         let xml = replies
             .to_xml(progress.cid)
@@ -78,12 +112,26 @@ impl DanmakuTask {
                 .wrap_err(format!("保存弹幕XML到`{}`失败", xml_path.display()))?;
         }
 
+        let (config, filter, analysis_config) = {
+            let config = download_task.app.get_config().read();
+            (
+                config.danmaku_config.clone(),
+                config.danmaku_filter.clone(),
+                config.danmaku_analysis.clone(),
+            )
+        };
+        let config = match video_resolution(progress).await {
+            Some((width, height)) => config.fit_resolution(width, height),
+            None => config,
+        };
+
         if danmaku_task.ass_selected {
-            let config = download_task.app.get_config().read().danmaku_config.clone();
+            let config = config.clone();
             let ass_file = File::create(&ass_path)
                 .wrap_err(format!("创建弹幕ASS文件`{}`失败", ass_path.display()))?;
             let title = filename.clone();
-            xml_to_ass(&xml, ass_file, title, config).wrap_err("将弹幕XML转换为ASS失败")?;
+            xml_to_ass(&xml, ass_file, title, config, &filter)
+                .wrap_err("将弹幕XML转换为ASS失败")?;
         }
 
         if danmaku_task.json_selected {
@@ -92,8 +140,265 @@ impl DanmakuTask {
                 .wrap_err(format!("保存弹幕JSON到`{}`失败", json_path.display()))?;
         }
 
+        if danmaku_task.vtt_selected {
+            let vtt =
+                xml_to_vtt(&xml, config.clone(), &filter).wrap_err("将弹幕XML转换为WebVTT失败")?;
+            std::fs::write(&vtt_path, vtt)
+                .wrap_err(format!("保存弹幕WebVTT到`{}`失败", vtt_path.display()))?;
+        }
+
+        if danmaku_task.srt_selected {
+            let srt = xml_to_srt(&xml, config, &filter).wrap_err("将弹幕XML转换为SRT失败")?;
+            std::fs::write(&srt_path, srt)
+                .wrap_err(format!("保存弹幕SRT到`{}`失败", srt_path.display()))?;
+        }
+
+        if danmaku_task.csv_selected {
+            let csv = xml_to_csv(&xml).wrap_err("将弹幕XML转换为CSV失败")?;
+            std::fs::write(&csv_path, csv)
+                .wrap_err(format!("保存弹幕CSV到`{}`失败", csv_path.display()))?;
+        }
+
+        if danmaku_task.heatmap_selected {
+            let heatmap = xml_to_heatmap(&xml, &analysis_config, progress.duration)
+                .wrap_err("统计弹幕热度失败")?;
+            let json_string =
+                serde_json::to_string(&heatmap).wrap_err("将弹幕热度转换为JSON失败")?;
+            std::fs::write(&heatmap_path, json_string).wrap_err(format!(
+                "保存弹幕热度JSON到`{}`失败",
+                heatmap_path.display()
+            ))?;
+        }
+
         download_task.update_progress(|p| p.danmaku_task.completed = true);
 
         Ok(())
     }
 }
+
+/// 优先读取已下载的视频文件的分辨率，读取不到时使用选择视频流时记录的分辨率
+#[instrument(level = "error", skip_all)]
+async fn video_resolution(progress: &DownloadProgress) -> Option<(u32, u32)> {
+    let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
+    let video_path = episode_dir.join(format!("{filename}.mp4"));
+
+    if video_path.exists() {
+        let current_span = tracing::Span::current();
+        let probe_result = tokio::task::spawn_blocking(move || {
+            let _enter = current_span.enter();
+            let video_track = track::read_tracks(&video_path)
+                .wrap_err(format!("解析`{}`失败", video_path.display()))?
+                .into_iter()
+                .find(|track| track.handler_type == HANDLER_VIDEO)
+                .ok_or_else(|| eyre!("`{}`中没有视频轨道", video_path.display()))?;
+            // `tkhd`中的宽高是16.16定点数
+            Ok::<_, eyre::Report>((video_track.width >> 16, video_track.height >> 16))
+        })
+        .await;
+        match probe_result {
+            Ok(Ok((width, height))) if width > 0 && height > 0 => return Some((width, height)),
+            Ok(Err(err)) => {
+                let err_title = "读取视频分辨率失败，改用视频流的分辨率";
+                let message = err.to_message();
+                tracing::warn!(err_title, message);
+            }
+            _ => {}
+        }
+    }
+
+    let video_task = &progress.video_task;
+    (video_task.width > 0 && video_task.height > 0).then_some((video_task.width, video_task.height))
+}
+
+/// 获取实时弹幕，开启了历史弹幕时再合并历史弹幕，时间轴是剪切前的
+///
+/// 保存弹幕和统计高光片段都用这些弹幕，两者的结果才一致
+pub async fn get_merged_replies(
+    app: &AppContext,
+    progress: &DownloadProgress,
+) -> eyre::Result<Vec<DmSegMobileReply>> {
+    let mut replies = app
+        .get_bili_client()
+        .get_danmaku(progress.aid, progress.cid, progress.duration)
+        .await
+        .wrap_err("获取弹幕失败")?;
+    if progress.danmaku_task.history_mode != HistoryDanmakuMode::Off {
+        match get_history_replies(app, progress).await {
+            Ok(history_replies) => {
+                // 实时弹幕放在前面，去重时优先保留
+                replies.extend(history_replies);
+                replies = merge_replies(replies);
+            }
+            Err(err) => {
+                // 例如没有登录时获取不了历史弹幕，此时只使用实时弹幕
+                let err_title = "获取历史弹幕失败，只使用实时弹幕";
+                let message = err.to_message();
+                tracing::warn!(err_title, message);
+            }
+        }
+    }
+    Ok(replies)
+}
+
+/// 按`history_mode`获取范围内每一天的历史弹幕快照
+///
+/// 获取有历史弹幕的日期失败时返回错误，某一天的弹幕获取失败时只跳过那一天
+#[instrument(level = "error", skip_all)]
+async fn get_history_replies(
+    app: &AppContext,
+    progress: &DownloadProgress,
+) -> eyre::Result<Vec<DmSegMobileReply>> {
+    let danmaku_task = &progress.danmaku_task;
+
+    let ts = progress.pub_ts;
+    let pub_date = DateTime::from_timestamp(ts, 0)
+        .ok_or_else(|| eyre!("将发布时间戳转换为日期时间失败: {ts}"))?
+        .with_timezone(&Local)
+        .date_naive();
+    let today = Local::now().date_naive();
+    let parse_date = |date: &str, default: NaiveDate| {
+        if date.trim().is_empty() {
+            return Ok(default);
+        }
+        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .wrap_err(format!("日期`{date}`的格式不是YYYY-MM-DD"))
+    };
+    let (start, end) = match danmaku_task.history_mode {
+        HistoryDanmakuMode::Off => return Ok(Vec::new()),
+        HistoryDanmakuMode::DateRange => (
+            parse_date(&danmaku_task.history_start_date, pub_date)?,
+            parse_date(&danmaku_task.history_end_date, today)?,
+        ),
+        HistoryDanmakuMode::All => (pub_date, today),
+    };
+
+    let bili_client = app.get_bili_client();
+    let interval = Duration::from_secs(app.get_config().read().history_danmaku_interval_sec);
+    // 每个月份和每一天的请求之间都休息一会，避免请求太频繁被风控
+    let mut is_first_request = true;
+    let mut wait_before_request = async || {
+        if !std::mem::take(&mut is_first_request) {
+            sleep(interval).await;
+        }
+    };
+
+    let mut dates = Vec::new();
+    for month in months_between(start, end) {
+        wait_before_request().await;
+        let month_dates = bili_client
+            .get_danmaku_history_dates(progress.cid, &month)
+            .await
+            .wrap_err(format!("获取{month}有历史弹幕的日期失败"))?;
+        dates.extend(month_dates.into_iter().filter(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .is_ok_and(|date| start <= date && date <= end)
+        }));
+    }
+    tracing::debug!("共有{}天的历史弹幕", dates.len());
+
+    let mut replies = Vec::with_capacity(dates.len());
+    for date in &dates {
+        wait_before_request().await;
+        match bili_client.get_history_danmaku(progress.cid, date).await {
+            Ok(reply) => replies.push(reply),
+            Err(err) => {
+                let message = err.to_message();
+                tracing::warn!(date, message, "获取历史弹幕失败，跳过这一天");
+            }
+        }
+    }
+
+    Ok(replies)
+}
+
+/// `start`到`end`之间的所有月份，格式为`YYYY-MM`
+fn months_between(start: NaiveDate, end: NaiveDate) -> Vec<String> {
+    let mut months = Vec::new();
+    let mut month = start.with_day(1).unwrap_or(start);
+    while month <= end {
+        months.push(month.format("%Y-%m").to_string());
+        let Some(next_month) = month.checked_add_months(Months::new(1)) else {
+            break;
+        };
+        month = next_month;
+    }
+    months
+}
+
+/// 按`id_str`去重，合并为一个按时间排序的分段，重复的弹幕保留先出现的那条
+fn merge_replies(replies: Vec<DmSegMobileReply>) -> Vec<DmSegMobileReply> {
+    let mut seen_ids = HashSet::new();
+    let mut elems: Vec<DanmakuElem> = replies
+        .into_iter()
+        .flat_map(|reply| reply.elems)
+        .filter(|elem| {
+            let id = if elem.id_str.is_empty() {
+                elem.id.to_string()
+            } else {
+                elem.id_str.clone()
+            };
+            seen_ids.insert(id)
+        })
+        .collect();
+    elems.sort_by_key(|elem| elem.progress);
+
+    vec![DmSegMobileReply {
+        elems,
+        ..Default::default()
+    }]
+}
+
+/// 视频被剪切过时，删掉被剪掉部分的弹幕，其他弹幕按剪切后的时间轴前移
+fn remap_danmaku(replies: &mut [DmSegMobileReply], cut_ranges: &CutRanges) {
+    for reply in replies {
+        reply.elems.retain_mut(|elem| {
+            let Ok(progress) = u64::try_from(elem.progress) else {
+                return true;
+            };
+            let Some(mapped) = cut_ranges.map_time(progress) else {
+                return false;
+            };
+            elem.progress = i32::try_from(mapped).unwrap_or(i32::MAX);
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_history_snapshots_by_id() {
+        let elem = |id: i64, progress: i32, content: &str| DanmakuElem {
+            id,
+            id_str: id.to_string(),
+            progress,
+            content: content.to_string(),
+            ..Default::default()
+        };
+        let realtime = DmSegMobileReply {
+            elems: vec![elem(3, 3000, "实时"), elem(1, 1000, "都有")],
+            ..Default::default()
+        };
+        let history = DmSegMobileReply {
+            elems: vec![elem(1, 1000, "快照里的旧内容"), elem(2, 2000, "只在快照里")],
+            ..Default::default()
+        };
+
+        let merged = merge_replies(vec![realtime, history]);
+
+        let contents: Vec<&str> = merged[0].elems.iter().map(|e| e.content.as_str()).collect();
+        assert_eq!(contents, ["都有", "只在快照里", "实时"]);
+    }
+
+    #[test]
+    fn list_months_between_dates() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        let months = months_between(date("2023-11-30"), date("2024-02-01"));
+
+        assert_eq!(months, ["2023-11", "2023-12", "2024-01", "2024-02"]);
+        assert!(months_between(date("2024-02-01"), date("2024-01-31")).is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod audio_library_task;
 pub mod audio_task;
 pub mod cover_task;
 pub mod danmaku_task;
@@ -14,7 +14,6 @@ use crate::{
         download_task::DownloadTask,
         episode_info::{EpisodeInfo, GetOrInitEpisodeInfo},
     },
-    extensions::AppHandleExt,
     types::{
         bangumi_info::BangumiInfo, cheese_info::CheeseInfo, normal_info::NormalInfo, tags::Tags,
     },
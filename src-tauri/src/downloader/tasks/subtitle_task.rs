@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, path::Path, sync::Arc};
 
 use eyre::WrapErr;
 use serde::{Deserialize, Serialize};
@@ -6,19 +6,47 @@ use specta::Type;
 use tracing::instrument;
 
 use crate::{
-    downloader::{download_progress::DownloadProgress, download_task::DownloadTask},
-    extensions::{AppHandleExt, GetOrInitPlayerInfo},
-    types::player_info::PlayerInfo,
+    config::SubtitleOutputFormat,
+    downloader::{
+        cut_ranges::CutRanges, download_progress::DownloadProgress, download_task::DownloadTask,
+    },
+    extensions::GetOrInitPlayerInfo,
+    subtitle_converter,
+    types::{player_info::PlayerInfo, subtitle::Subtitle},
     utils,
 };
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(default)]
 pub struct SubtitleTask {
     pub selected: bool,
+    pub formats: Vec<SubtitleOutputFormat>,
+    /// 为空时保存全部语言
+    pub languages: Vec<String>,
+    pub exclude_ai: bool,
+    pub bilingual_primary: String,
+    pub bilingual_secondary: String,
+    /// 已经保存的字幕文件名，删除本地文件时只删除这些字幕
+    pub saved_files: Vec<String>,
     pub completed: bool,
 }
 
+impl Default for SubtitleTask {
+    fn default() -> Self {
+        Self {
+            selected: false,
+            // 旧版本创建的任务没有这个字段，和以前一样只保存SRT
+            formats: vec![SubtitleOutputFormat::Srt],
+            languages: Vec::new(),
+            exclude_ai: false,
+            bilingual_primary: String::new(),
+            bilingual_secondary: String::new(),
+            saved_files: Vec::new(),
+            completed: false,
+        }
+    }
+}
+
 impl SubtitleTask {
     pub fn mark_uncompleted(&mut self) {
         self.completed = false;
@@ -28,6 +56,43 @@ impl SubtitleTask {
         !self.selected || self.completed
     }
 
+    /// 是否要单独保存语言为`lan`的字幕
+    pub fn keeps(&self, lan: &str) -> bool {
+        if self.exclude_ai && lan.starts_with("ai-") {
+            return false;
+        }
+        self.languages.is_empty() || self.languages.iter().any(|l| l == lan)
+    }
+
+    /// 双语字幕的上下两种语言，有一个为空时不生成双语字幕
+    pub fn bilingual_lans(&self) -> Option<(&str, &str)> {
+        let (primary, secondary) = (
+            self.bilingual_primary.trim(),
+            self.bilingual_secondary.trim(),
+        );
+        (!primary.is_empty() && !secondary.is_empty()).then_some((primary, secondary))
+    }
+
+    /// 双语字幕保存的格式，只支持SRT和ASS，都没选时保存为SRT
+    fn bilingual_formats(&self) -> Vec<SubtitleOutputFormat> {
+        let formats: Vec<SubtitleOutputFormat> = self
+            .formats
+            .iter()
+            .copied()
+            .filter(|format| {
+                matches!(
+                    format,
+                    SubtitleOutputFormat::Srt | SubtitleOutputFormat::Ass
+                )
+            })
+            .collect();
+        if !formats.is_empty() {
+            return formats;
+        }
+        tracing::warn!("双语字幕只支持SRT和ASS格式，选择的格式中没有这两种，保存为SRT");
+        vec![SubtitleOutputFormat::Srt]
+    }
+
     #[instrument(level = "error", skip_all)]
     pub async fn process(
         &self,
@@ -35,8 +100,6 @@ impl SubtitleTask {
         progress: &DownloadProgress,
         player_info: &mut Option<PlayerInfo>,
     ) -> eyre::Result<()> {
-        use std::fmt::Write;
-
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
 
         let player_info = player_info
@@ -44,33 +107,175 @@ impl SubtitleTask {
             .await?;
 
         let bili_client = download_task.app.get_bili_client();
+        let bilingual_lans = self.bilingual_lans();
+        // 语言 -> 字幕，合并双语字幕时使用
+        let mut bilingual_subtitles: HashMap<&str, Subtitle> = HashMap::new();
+        let mut saved_files = Vec::new();
 
         for subtitle_detail in &player_info.subtitle.subtitles {
+            let lan = subtitle_detail.lan.as_str();
+            let kept = self.keeps(lan);
+            let in_bilingual = bilingual_lans
+                .is_some_and(|(primary, secondary)| lan == primary || lan == secondary);
+            if !kept && !in_bilingual {
+                continue;
+            }
+
             let url = format!("http:{}", subtitle_detail.subtitle_url);
-            let subtitle = bili_client
+            let (mut subtitle, raw_json) = bili_client
                 .get_subtitle(&url)
                 .await
                 .wrap_err("获取字幕失败")?;
+            if let Some(cut_ranges) = &progress.video_process_task.cut_ranges {
+                remap_subtitle(&mut subtitle, cut_ranges);
+            }
 
-            let mut srt_content = String::new();
-            for (i, b) in subtitle.body.iter().enumerate() {
-                let index = i + 1;
-                let content = &b.content;
-                let start_time = utils::seconds_to_srt_time(b.from);
-                let end_time = utils::seconds_to_srt_time(b.to);
-                let _ = writeln!(
-                    &mut srt_content,
-                    "{index}\n{start_time} --> {end_time}\n{content}\n"
-                );
+            if kept {
+                let stem = format!("{filename}.{}", utils::filename_filter(lan));
+                let files = save_subtitle(
+                    &subtitle,
+                    Some(&raw_json),
+                    &self.formats,
+                    episode_dir,
+                    &stem,
+                    filename,
+                )
+                .wrap_err(format!("保存`{lan}`字幕失败"))?;
+                saved_files.extend(files);
             }
+            if in_bilingual {
+                bilingual_subtitles.insert(lan, subtitle);
+            }
+        }
 
-            let lan = utils::filename_filter(&subtitle_detail.lan);
-            let save_path = episode_dir.join(format!("{filename}.{lan}.srt"));
-            std::fs::write(save_path, srt_content)?;
+        if let Some((primary, secondary)) = bilingual_lans {
+            if let (Some(primary_subtitle), Some(secondary_subtitle)) = (
+                bilingual_subtitles.get(primary),
+                bilingual_subtitles.get(secondary),
+            ) {
+                let merged =
+                    subtitle_converter::merge_bilingual(primary_subtitle, secondary_subtitle);
+                let formats = self.bilingual_formats();
+                let stem = bilingual_stem(filename, primary, secondary);
+                let files = save_subtitle(&merged, None, &formats, episode_dir, &stem, filename)
+                    .wrap_err("保存双语字幕失败")?;
+                saved_files.extend(files);
+            } else {
+                tracing::debug!("没有`{primary}`或`{secondary}`字幕，跳过生成双语字幕");
+            }
         }
 
-        download_task.update_progress(|p| p.subtitle_task.completed = true);
+        download_task.update_progress(|p| {
+            for file in saved_files {
+                if !p.subtitle_task.saved_files.contains(&file) {
+                    p.subtitle_task.saved_files.push(file);
+                }
+            }
+            p.subtitle_task.completed = true;
+        });
 
         Ok(())
     }
 }
+
+/// 双语字幕文件名中扩展名前的部分
+pub fn bilingual_stem(filename: &str, primary: &str, secondary: &str) -> String {
+    let (primary, secondary) = (
+        utils::filename_filter(primary),
+        utils::filename_filter(secondary),
+    );
+    format!("{filename}.{primary}+{secondary}")
+}
+
+/// 按`formats`把`subtitle`保存为`{stem}.{扩展名}`，返回保存的文件名
+///
+/// JSON格式写入`raw_json`，即API返回的原始字幕，为`None`时跳过JSON格式
+fn save_subtitle(
+    subtitle: &Subtitle,
+    raw_json: Option<&str>,
+    formats: &[SubtitleOutputFormat],
+    episode_dir: &Path,
+    stem: &str,
+    title: &str,
+) -> eyre::Result<Vec<String>> {
+    let mut saved_files = Vec::new();
+    for format in formats {
+        let (extension, content) = match format {
+            SubtitleOutputFormat::Srt => ("srt", subtitle_converter::to_srt(subtitle)),
+            SubtitleOutputFormat::Ass => ("ass", subtitle_converter::to_ass(subtitle, title)),
+            SubtitleOutputFormat::Vtt => ("vtt", subtitle_converter::to_vtt(subtitle)),
+            SubtitleOutputFormat::Lrc => ("lrc", subtitle_converter::to_lrc(subtitle, title)),
+            SubtitleOutputFormat::Json => match raw_json {
+                Some(raw_json) => ("json", raw_json.to_string()),
+                None => continue,
+            },
+        };
+        let file_name = format!("{stem}.{extension}");
+        let save_path = episode_dir.join(&file_name);
+        std::fs::write(&save_path, content)
+            .wrap_err(format!("保存字幕到`{}`失败", save_path.display()))?;
+        saved_files.push(file_name);
+    }
+    Ok(saved_files)
+}
+
+/// 视频被剪切过时，把字幕按剪切后的时间轴前移，完全被剪掉的字幕会被删掉
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn remap_subtitle(subtitle: &mut Subtitle, cut_ranges: &CutRanges) {
+    let map = |seconds: f64| {
+        let ms = (seconds.max(0.0) * 1000.0).round() as u64;
+        cut_ranges.map_time_clamped(ms) as f64 / 1000.0
+    };
+    subtitle.body.retain_mut(|body| {
+        body.from = map(body.from);
+        body.to = map(body.to);
+        body.from < body.to
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_subtitle_languages() {
+        let task = SubtitleTask {
+            exclude_ai: true,
+            ..Default::default()
+        };
+        assert!(task.keeps("zh-CN"));
+        assert!(!task.keeps("ai-zh"));
+
+        let task = SubtitleTask {
+            languages: vec!["en-US".to_string(), "ai-zh".to_string()],
+            ..Default::default()
+        };
+        assert!(task.keeps("ai-zh"));
+        assert!(!task.keeps("zh-CN"));
+
+        let task = SubtitleTask {
+            bilingual_primary: "zh-CN".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(task.bilingual_lans(), None);
+    }
+
+    #[test]
+    fn bilingual_formats_fall_back_to_srt() {
+        let task = SubtitleTask {
+            formats: vec![SubtitleOutputFormat::Vtt, SubtitleOutputFormat::Json],
+            ..Default::default()
+        };
+        assert_eq!(task.bilingual_formats(), [SubtitleOutputFormat::Srt]);
+
+        let task = SubtitleTask {
+            formats: vec![SubtitleOutputFormat::Lrc, SubtitleOutputFormat::Ass],
+            ..Default::default()
+        };
+        assert_eq!(task.bilingual_formats(), [SubtitleOutputFormat::Ass]);
+    }
+}
@@ -1,20 +1,32 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use eyre::{WrapErr, eyre};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::AppHandle;
 use tracing::instrument;
 
 use crate::{
+    app_context::AppContext,
+    config::{OutputContainer, SkipSegmentCategory},
+    danmaku_xml_to_ass::analysis::{DanmakuHeatmap, xml_to_heatmap},
     downloader::{
         chapter_segments::{ChapterSegment, ChapterSegments},
+        cut_ranges::CutRanges,
         download_progress::DownloadProgress,
         download_task::DownloadTask,
+        tasks::{danmaku_task::get_merged_replies, subtitle_task::bilingual_stem},
+    },
+    extensions::{EyreReportToMessage, GetOrInitPlayerInfo},
+    mkv_muxer::{self, MkvSources, SubtitleFormat, SubtitleSource},
+    mp4_muxer::{
+        self, cut,
+        track::{self, HANDLER_VIDEO},
     },
-    extensions::{AppHandleExt, GetOrInitPlayerInfo},
-    types::player_info::PlayerInfo,
-    utils,
+    types::{player_info::PlayerInfo, skip_segments::SkipSegment},
+    utils::{self, ToXml},
 };
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
@@ -24,6 +36,13 @@ pub struct VideoProcessTask {
     pub merge_selected: bool,
     pub embed_chapter_selected: bool,
     pub embed_skip_selected: bool,
+    /// 根据弹幕密度找出的高光片段也作为章节嵌入
+    pub embed_highlight_selected: bool,
+    pub output_container: OutputContainer,
+    /// 要从视频中剪掉的空降助手片段类型，为空时不剪切
+    pub cut_skip_categories: Vec<SkipSegmentCategory>,
+    /// 视频下载完成后根据关键帧计算出的剪切范围，弹幕和字幕也按它调整时间
+    pub cut_ranges: Option<CutRanges>,
     pub completed: bool,
     pub skipped: bool,
 }
@@ -32,11 +51,61 @@ impl VideoProcessTask {
     pub fn mark_uncompleted(&mut self) {
         self.completed = false;
         self.skipped = false;
+        self.cut_ranges = None;
     }
 
     pub fn is_completed(&self) -> bool {
-        !self.merge_selected && !self.embed_chapter_selected && !self.embed_skip_selected
-            || self.completed
+        // 选了MKV时，即使不合并也不嵌入章节，也要把视频封装为MKV
+        let nothing_to_do = !self.merge_selected
+            && !self.embed_chapter_selected
+            && !self.embed_skip_selected
+            && !self.embed_highlight_selected
+            && self.cut_skip_categories.is_empty()
+            && self.output_container == OutputContainer::Mp4;
+        nothing_to_do || self.completed
+    }
+
+    /// 获取要剪掉的空降助手片段，并把它们向内收缩到视频的关键帧上
+    #[instrument(level = "error", skip_all)]
+    pub async fn prepare_cut(
+        &self,
+        app: &AppContext,
+        progress: &DownloadProgress,
+    ) -> eyre::Result<CutRanges> {
+        let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
+
+        let video_path = episode_dir.join(format!("{filename}.mp4"));
+        let (Some(bvid), true) = (&progress.bvid, video_path.exists()) else {
+            return Ok(CutRanges::default());
+        };
+
+        let bili_client = app.get_bili_client();
+        let skip_segments = bili_client
+            .get_skip_segments(bvid, Some(progress.cid))
+            .await
+            .wrap_err("获取空降助手片段失败")?;
+        let segments: Vec<(u64, u64)> = skip_segments
+            .0
+            .iter()
+            .filter(|segment| segment.is_in_categories(&self.cut_skip_categories))
+            .filter_map(SkipSegment::time_range_ms)
+            .collect();
+        if segments.is_empty() {
+            return Ok(CutRanges::default());
+        }
+
+        let current_span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _enter = current_span.enter();
+            let video_track = track::read_tracks(&video_path)
+                .wrap_err(format!("解析`{}`失败", video_path.display()))?
+                .into_iter()
+                .find(|track| track.handler_type == HANDLER_VIDEO)
+                .ok_or_else(|| eyre!("`{}`中没有视频轨道", video_path.display()))?;
+            let cut_points = cut::cut_points(&video_track);
+            Ok(CutRanges::from_segments(&segments, &cut_points))
+        })
+        .await?
     }
 
     #[instrument(level = "error", skip_all)]
@@ -46,9 +115,22 @@ impl VideoProcessTask {
         progress: &DownloadProgress,
         player_info: &mut Option<PlayerInfo>,
     ) -> eyre::Result<()> {
-        let embed_selected = self.embed_chapter_selected || self.embed_skip_selected;
+        if self.output_container == OutputContainer::Mkv {
+            self.mux_mkv(download_task, progress, player_info)
+                .await
+                .wrap_err("封装MKV失败")?;
+            return Ok(());
+        }
+
+        let embed_selected = self.embed_chapter_selected
+            || self.embed_skip_selected
+            || self.embed_highlight_selected;
+        let cut_selected = self
+            .cut_ranges
+            .as_ref()
+            .is_some_and(|ranges| !ranges.is_empty());
 
-        if self.merge_selected && embed_selected {
+        if self.merge_selected && (embed_selected || cut_selected) {
             self.merge_and_embed(download_task, progress, player_info)
                 .await
                 .wrap_err("自动合并+嵌入章节元数据失败")?;
@@ -56,10 +138,13 @@ impl VideoProcessTask {
             self.merge(download_task, progress)
                 .await
                 .wrap_err("自动合并失败")?;
-        } else if embed_selected {
+        } else if embed_selected || cut_selected {
             self.embed(download_task, progress, player_info)
                 .await
                 .wrap_err("嵌入章节元数据失败")?;
+        } else {
+            // 只选了剪切，但没有要剪掉的片段
+            download_task.update_progress(|p| p.video_process_task.completed = true);
         }
 
         Ok(())
@@ -74,8 +159,6 @@ impl VideoProcessTask {
     ) -> eyre::Result<()> {
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
 
-        let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
-
         let video_path = episode_dir.join(format!("{filename}.mp4"));
         if !video_path.exists() {
             download_task.update_progress(|p| p.video_process_task.completed = true);
@@ -91,60 +174,21 @@ impl VideoProcessTask {
             return Ok(());
         }
 
-        let metadata_path = self
-            .create_chapter_metadata(&download_task.app, progress, player_info)
+        let chapter_segments = self
+            .create_chapter_segments(&download_task.app, progress, player_info)
             .await
-            .wrap_err("创建章节元数据失败")?;
+            .wrap_err("获取章节失败")?;
 
         let output_path = episode_dir.join(format!("{filename}-merged.mp4"));
 
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let video_path_clone = video_path.clone();
-        let audio_path_clone = audio_path.clone();
-        let metadata_path_clone = metadata_path.clone();
-        let output_path_clone = output_path.clone();
-
-        let current_span = tracing::Span::current();
-        tauri::async_runtime::spawn_blocking(move || {
-            let _enter = current_span.enter();
-
-            let mut command = std::process::Command::new(ffmpeg_program);
-
-            command.arg("-i").arg(video_path_clone);
-            command.arg("-i").arg(audio_path_clone);
-            if let Some(metadata_path) = metadata_path_clone {
-                command.arg("-i").arg(metadata_path);
-                command.arg("-map_metadata").arg("2");
-            }
-
-            command.arg("-c").arg("copy");
-            command.arg("-map").arg("0:v:0");
-            command.arg("-map").arg("1:a:0");
-
-            command.arg(output_path_clone).arg("-y");
-
-            #[cfg(target_os = "windows")]
-            {
-                // 隐藏窗口
-                use std::os::windows::process::CommandExt;
-                command.creation_flags(0x0800_0000);
-            }
-
-            let output = command.output();
-
-            let _ = tx.send(output);
-        });
-
-        let output = rx.await??;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let err = eyre!(format!("STDOUT: {stdout}"))
-                .wrap_err(format!("STDERR: {stderr}"))
-                .wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试");
-            return Err(err);
-        }
+        remux(
+            progress,
+            vec![video_path.clone(), audio_path.clone()],
+            chapter_segments,
+            self.cut_ranges.clone().unwrap_or_default(),
+            &output_path,
+        )
+        .await?;
 
         std::fs::remove_file(&video_path)
             .wrap_err(format!("删除视频文件`{}`失败", video_path.display()))?;
@@ -156,13 +200,6 @@ impl VideoProcessTask {
             video_path.display()
         ))?;
 
-        if let Some(metadata_path) = metadata_path {
-            std::fs::remove_file(&metadata_path).wrap_err(format!(
-                "删除章节元数据文件`{}`失败",
-                metadata_path.display()
-            ))?;
-        }
-
         download_task.update_progress(|p| p.video_process_task.completed = true);
 
         Ok(())
@@ -190,50 +227,17 @@ impl VideoProcessTask {
 
         let output_path = episode_dir.join(format!("{filename}-merged.mp4"));
 
-        let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
-
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let video_path_clone = video_path.clone();
-        let audio_path_clone = audio_path.clone();
-        let output_path_clone = output_path.clone();
-
-        let current_span = tracing::Span::current();
-        tauri::async_runtime::spawn_blocking(move || {
-            let _enter = current_span.enter();
-
-            let mut command = std::process::Command::new(ffmpeg_program);
-
-            command.arg("-i").arg(video_path_clone);
-            command.arg("-i").arg(audio_path_clone);
-
-            command.arg("-c").arg("copy");
-            command.arg("-map").arg("0:v:0");
-            command.arg("-map").arg("1:a:0");
-
-            command.arg(output_path_clone).arg("-y");
-
-            #[cfg(target_os = "windows")]
-            {
-                // 隐藏窗口
-                use std::os::windows::process::CommandExt;
-                command.creation_flags(0x0800_0000);
-            }
-
-            let output = command.output();
-
-            let _ = tx.send(output);
-        });
-
-        let output = rx.await??;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let err = eyre!(format!("STDOUT: {stdout}"))
-                .wrap_err(format!("STDERR: {stderr}"))
-                .wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试");
-            return Err(err);
-        }
+        let chapter_segments = ChapterSegments {
+            segments: Vec::new(),
+        };
+        remux(
+            progress,
+            vec![video_path.clone(), audio_path.clone()],
+            chapter_segments,
+            CutRanges::default(),
+            &output_path,
+        )
+        .await?;
 
         std::fs::remove_file(&video_path)
             .wrap_err(format!("删除视频文件`{}`失败", video_path.display()))?;
@@ -259,8 +263,6 @@ impl VideoProcessTask {
     ) -> eyre::Result<()> {
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
 
-        let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
-
         let video_path = episode_dir.join(format!("{filename}.mp4"));
         if !video_path.exists() {
             download_task.update_progress(|p| p.video_process_task.completed = true);
@@ -269,69 +271,187 @@ impl VideoProcessTask {
 
         let output_path = episode_dir.join(format!("{filename}-embed.mp4"));
 
-        let metadata_path = self
-            .create_chapter_metadata(&download_task.app, progress, player_info)
+        let chapter_segments = self
+            .create_chapter_segments(&download_task.app, progress, player_info)
             .await
-            .wrap_err("创建章节元数据失败")?;
+            .wrap_err("获取章节失败")?;
 
-        let Some(metadata_path) = metadata_path else {
+        let cut_ranges = self.cut_ranges.clone().unwrap_or_default();
+        if chapter_segments.segments.is_empty() && cut_ranges.is_empty() {
             download_task.update_progress(|p| p.video_process_task.completed = true);
             return Ok(());
-        };
+        }
 
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let video_path_clone = video_path.clone();
-        let metadata_path_clone = metadata_path.clone();
-        let output_path_clone = output_path.clone();
+        remux(
+            progress,
+            vec![video_path.clone()],
+            chapter_segments,
+            cut_ranges.clone(),
+            &output_path,
+        )
+        .await?;
 
-        let current_span = tracing::Span::current();
-        tauri::async_runtime::spawn_blocking(move || {
-            let _enter = current_span.enter();
+        std::fs::remove_file(&video_path)
+            .wrap_err(format!("删除视频文件`{}`失败", video_path.display()))?;
+        std::fs::rename(&output_path, &video_path).wrap_err(format!(
+            "将`{}`重命名为`{}`失败",
+            output_path.display(),
+            video_path.display()
+        ))?;
 
-            let mut command = std::process::Command::new(ffmpeg_program);
+        // 不合并时音频是单独的文件，也要剪掉同样的部分
+        let audio_path = episode_dir.join(format!("{filename}.m4a"));
+        if !cut_ranges.is_empty() && audio_path.exists() {
+            let output_path = episode_dir.join(format!("{filename}-cut.m4a"));
+            let chapter_segments = ChapterSegments {
+                segments: Vec::new(),
+            };
+            remux(
+                progress,
+                vec![audio_path.clone()],
+                chapter_segments,
+                cut_ranges,
+                &output_path,
+            )
+            .await?;
+
+            std::fs::remove_file(&audio_path)
+                .wrap_err(format!("删除音频文件`{}`失败", audio_path.display()))?;
+            std::fs::rename(&output_path, &audio_path).wrap_err(format!(
+                "将`{}`重命名为`{}`失败",
+                output_path.display(),
+                audio_path.display()
+            ))?;
+        }
 
-            command.arg("-i").arg(video_path_clone);
-            command.arg("-i").arg(metadata_path_clone);
+        download_task.update_progress(|p| p.video_process_task.completed = true);
 
-            command.arg("-map_metadata").arg("1");
-            command.arg("-c").arg("copy");
+        Ok(())
+    }
 
-            command.arg(output_path_clone).arg("-y");
+    /// 把视频、音频、字幕、ASS弹幕、章节和封面封装为一个MKV，成功后删除已经封装进去的文件
+    #[instrument(level = "error", skip_all)]
+    #[allow(clippy::too_many_lines)]
+    async fn mux_mkv(
+        &self,
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+        player_info: &mut Option<PlayerInfo>,
+    ) -> eyre::Result<()> {
+        let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
+
+        let video_path = episode_dir.join(format!("{filename}.mp4"));
+        if !video_path.exists() {
+            download_task.update_progress(|p| p.video_process_task.completed = true);
+            return Ok(());
+        }
 
-            #[cfg(target_os = "windows")]
-            {
-                // 隐藏窗口
-                use std::os::windows::process::CommandExt;
-                command.creation_flags(0x0800_0000);
+        let mut media_paths = vec![video_path];
+        // 与MP4一样，没有选自动合并时音频单独保留为m4a，不封装进MKV
+        let audio_path = episode_dir.join(format!("{filename}.m4a"));
+        if self.merge_selected && audio_path.exists() {
+            media_paths.push(audio_path);
+        }
+
+        let embed_selected = self.embed_chapter_selected
+            || self.embed_skip_selected
+            || self.embed_highlight_selected;
+        let chapter_segments = if embed_selected {
+            self.create_chapter_segments(&download_task.app, progress, player_info)
+                .await
+                .wrap_err("获取章节失败")?
+        } else {
+            ChapterSegments {
+                segments: Vec::new(),
             }
+        };
 
-            let output = command.output();
+        let mut subtitles = Vec::new();
+        if progress.subtitle_task.selected {
+            let player_info = player_info
+                .get_or_init(&download_task.app, progress)
+                .await?;
+            let subtitle_task = &progress.subtitle_task;
+            for subtitle_detail in &player_info.subtitle.subtitles {
+                if !subtitle_task.keeps(&subtitle_detail.lan) {
+                    continue;
+                }
+                let lan = utils::filename_filter(&subtitle_detail.lan);
+                let files = find_subtitle_files(episode_dir, &format!("{filename}.{lan}"));
+                push_subtitle_sources(
+                    &mut subtitles,
+                    files,
+                    &subtitle_detail.lan,
+                    &subtitle_detail.lan_doc,
+                );
+            }
 
-            let _ = tx.send(output);
-        });
+            if let Some((primary, secondary)) = subtitle_task.bilingual_lans() {
+                let stem = bilingual_stem(filename, primary, secondary);
+                let lan_doc = |lan: &str| {
+                    player_info
+                        .subtitle
+                        .subtitles
+                        .iter()
+                        .find(|detail| detail.lan == lan)
+                        .map_or_else(|| lan.to_string(), |detail| detail.lan_doc.clone())
+                };
+                let name = format!("{}+{}", lan_doc(primary), lan_doc(secondary));
+                let files = find_subtitle_files(episode_dir, &stem);
+                push_subtitle_sources(&mut subtitles, files, primary, &name);
+            }
+        }
 
-        let output = rx.await??;
+        let danmaku_path = episode_dir.join(format!("{filename}.弹幕.ass"));
+        if danmaku_path.exists() {
+            subtitles.push(SubtitleSource {
+                path: danmaku_path,
+                format: SubtitleFormat::Ass,
+                lan: String::new(),
+                name: "弹幕".to_string(),
+                is_default: false,
+            });
+        }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let err = eyre!(format!("STDOUT: {stdout}"))
-                .wrap_err(format!("STDERR: {stderr}"))
-                .wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试");
-            return Err(err);
+        let cover_path = ["jpg", "png", "webp", "avif"]
+            .iter()
+            .map(|ext| episode_dir.join(format!("{filename}.{ext}")))
+            .find(|path| path.exists());
+
+        let sources = MkvSources {
+            media_paths,
+            subtitles,
+            chapter_segments,
+            cut_ranges: self.cut_ranges.clone().unwrap_or_default(),
+            cover_path,
+            title: progress.episode_title.clone(),
+        };
+        let output_path = episode_dir.join(format!("{filename}.mkv"));
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let output_path_clone = output_path.clone();
+        let current_span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _enter = current_span.enter();
+            let result = mkv_muxer::mux(&sources, &output_path_clone);
+            let _ = tx.send((result, sources));
+        });
+
+        let (result, sources) = rx.await?;
+        if let Err(err) = result {
+            // 删掉写了一半的文件
+            let _ = std::fs::remove_file(&output_path);
+            return Err(err.wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试"));
         }
 
-        std::fs::remove_file(&video_path)
-            .wrap_err(format!("删除视频文件`{}`失败", video_path.display()))?;
-        std::fs::rename(&output_path, &video_path).wrap_err(format!(
-            "将`{}`重命名为`{}`失败",
-            output_path.display(),
-            video_path.display()
-        ))?;
-        std::fs::remove_file(&metadata_path).wrap_err(format!(
-            "删除章节元数据文件`{}`失败",
-            metadata_path.display()
-        ))?;
+        let embedded_paths = sources
+            .media_paths
+            .iter()
+            .chain(sources.subtitles.iter().map(|subtitle| &subtitle.path))
+            .chain(&sources.cover_path);
+        for path in embedded_paths {
+            std::fs::remove_file(path).wrap_err(format!("删除`{}`失败", path.display()))?;
+        }
 
         download_task.update_progress(|p| p.video_process_task.completed = true);
 
@@ -339,12 +459,12 @@ impl VideoProcessTask {
     }
 
     #[instrument(level = "error", skip_all)]
-    async fn create_chapter_metadata(
+    async fn create_chapter_segments(
         &self,
-        app: &AppHandle,
+        app: &AppContext,
         progress: &DownloadProgress,
         player_info: &mut Option<PlayerInfo>,
-    ) -> eyre::Result<Option<PathBuf>> {
+    ) -> eyre::Result<ChapterSegments> {
         let mut chapter_segments = ChapterSegments {
             segments: Vec::new(),
         };
@@ -363,28 +483,240 @@ impl VideoProcessTask {
             chapter_segments = ChapterSegments { segments };
         }
 
+        // 空降助手的片段更准确，高光片段先插入，重叠时被空降助手的片段覆盖
+        if self.embed_highlight_selected {
+            let heatmap = get_danmaku_heatmap(app, progress)
+                .await
+                .wrap_err("统计弹幕热度失败")?;
+            for chapter_segment in heatmap.highlight_chapters() {
+                chapter_segments.insert(chapter_segment);
+            }
+        }
+
         if let (true, Some(bvid)) = (self.embed_skip_selected, &progress.bvid) {
             let bili_client = app.get_bili_client();
             let cid = Some(progress.cid);
 
             let skip_segments = bili_client.get_skip_segments(bvid, cid).await?;
             for segment in skip_segments.0 {
+                // 被剪掉的片段不再作为章节
+                if segment.is_in_categories(&self.cut_skip_categories) {
+                    continue;
+                }
                 if let Some(chapter_segment) = segment.into_chapter_segment() {
                     chapter_segments.insert(chapter_segment);
                 }
             }
         }
 
-        if chapter_segments.segments.is_empty() {
-            return Ok(None);
+        if let Some(cut_ranges) = &self.cut_ranges {
+            chapter_segments = chapter_segments.remap(cut_ranges);
         }
 
+        Ok(chapter_segments)
+    }
+}
+
+/// 找到`{stem}`所有能封装进MKV的字幕文件
+///
+/// ASS、SRT和WebVTT有几个就封装几个，都没有时才退而使用JSON或LRC，JSON的时间轴比LRC准确
+fn find_subtitle_files(episode_dir: &Path, stem: &str) -> Vec<(PathBuf, SubtitleFormat)> {
+    let existing_files = |formats: &[SubtitleFormat]| {
+        formats
+            .iter()
+            .map(|&format| {
+                let path = episode_dir.join(format!("{stem}.{}", format.extension()));
+                (path, format)
+            })
+            .filter(|(path, _)| path.exists())
+            .collect::<Vec<_>>()
+    };
+
+    let files = existing_files(&[
+        SubtitleFormat::Ass,
+        SubtitleFormat::Srt,
+        SubtitleFormat::Vtt,
+    ]);
+    if !files.is_empty() {
+        return files;
+    }
+    existing_files(&[SubtitleFormat::Json, SubtitleFormat::Lrc])
+        .into_iter()
+        .take(1)
+        .collect()
+}
+
+/// 同一个字幕有多种格式时，轨道名后面加上格式以便区分
+fn push_subtitle_sources(
+    subtitles: &mut Vec<SubtitleSource>,
+    files: Vec<(PathBuf, SubtitleFormat)>,
+    lan: &str,
+    name: &str,
+) {
+    let with_format = files.len() > 1;
+    for (path, format) in files {
+        let name = if with_format {
+            format!("{name}({})", format.extension())
+        } else {
+            name.to_string()
+        };
+        subtitles.push(SubtitleSource {
+            path,
+            format,
+            lan: lan.to_string(),
+            name,
+            // 第一个字幕默认显示
+            is_default: subtitles.is_empty(),
+        });
+    }
+}
+
+/// 获取与弹幕任务相同的弹幕(开启了历史弹幕时包括历史弹幕)并统计热度，时间轴是剪切前的
+#[instrument(level = "error", skip_all)]
+async fn get_danmaku_heatmap(
+    app: &AppContext,
+    progress: &DownloadProgress,
+) -> eyre::Result<DanmakuHeatmap> {
+    let replies = get_merged_replies(app, progress).await?;
+    let xml = replies
+        .to_xml(progress.cid)
+        .wrap_err("将弹幕转换为XML失败")?;
+    let analysis_config = app.get_config().read().danmaku_analysis.clone();
+    xml_to_heatmap(&xml, &analysis_config, progress.duration)
+}
+
+/// 把`input_paths`中的轨道合并到`output_path`，`chapter_segments`不为空时同时嵌入章节，
+/// `cut_ranges`不为空时剪掉对应的部分
+///
+/// 优先使用内置的MP4封装器，失败时如果FFmpeg存在且不需要剪切则改用FFmpeg
+#[instrument(level = "error", skip_all)]
+async fn remux(
+    progress: &DownloadProgress,
+    input_paths: Vec<PathBuf>,
+    chapter_segments: ChapterSegments,
+    cut_ranges: CutRanges,
+    output_path: &Path,
+) -> eyre::Result<()> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let output_path_clone = output_path.to_path_buf();
+    let ffmpeg_input_paths = input_paths.clone();
+
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _enter = current_span.enter();
+
+        let input_paths: Vec<&Path> = input_paths.iter().map(PathBuf::as_path).collect();
+        let result = mp4_muxer::mux(
+            &input_paths,
+            &chapter_segments,
+            &cut_ranges,
+            &output_path_clone,
+        );
+
+        let _ = tx.send((result, chapter_segments, cut_ranges));
+    });
+
+    let (result, chapter_segments, cut_ranges) = rx.await?;
+    let Err(err) = result else {
+        return Ok(());
+    };
+    // 删掉写了一半的文件
+    let _ = std::fs::remove_file(output_path);
+
+    let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
+    // FFmpeg只能复制完整的流，剪切只能由内置封装器完成
+    if !ffmpeg_program.exists() || !cut_ranges.is_empty() {
+        return Err(err.wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试"));
+    }
+
+    let err_title = "内置封装器处理失败，改用FFmpeg重试";
+    let message = err.to_message();
+    tracing::warn!(err_title, message);
+
+    let metadata_path = if chapter_segments.segments.is_empty() {
+        None
+    } else {
         let metadata_content = chapter_segments.generate_chapter_metadata(progress.duration);
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
         let metadata_path = episode_dir.join(format!("{filename}.FFMETA.ini"));
         std::fs::write(&metadata_path, metadata_content)
             .wrap_err(format!("保存章节元数据到`{}`失败", metadata_path.display()))?;
+        Some(metadata_path)
+    };
+
+    let result = remux_with_ffmpeg(
+        ffmpeg_program,
+        ffmpeg_input_paths,
+        metadata_path.clone(),
+        output_path.to_path_buf(),
+    )
+    .await;
+
+    if let Some(metadata_path) = metadata_path {
+        std::fs::remove_file(&metadata_path).wrap_err(format!(
+            "删除章节元数据文件`{}`失败",
+            metadata_path.display()
+        ))?;
+    }
+
+    result
+}
+
+/// 第一个输入是视频，第二个输入(如果有)是音频
+async fn remux_with_ffmpeg(
+    ffmpeg_program: PathBuf,
+    input_paths: Vec<PathBuf>,
+    metadata_path: Option<PathBuf>,
+    output_path: PathBuf,
+) -> eyre::Result<()> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _enter = current_span.enter();
+
+        let mut command = std::process::Command::new(ffmpeg_program);
+
+        for input_path in &input_paths {
+            command.arg("-i").arg(input_path);
+        }
+        if let Some(metadata_path) = metadata_path {
+            command.arg("-i").arg(metadata_path);
+            command
+                .arg("-map_metadata")
+                .arg(input_paths.len().to_string());
+        }
 
-        Ok(Some(metadata_path))
+        command.arg("-c").arg("copy");
+        if input_paths.len() > 1 {
+            command.arg("-map").arg("0:v:0");
+            command.arg("-map").arg("1:a:0");
+        }
+
+        command.arg(output_path).arg("-y");
+
+        #[cfg(target_os = "windows")]
+        {
+            // 隐藏窗口
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(0x0800_0000);
+        }
+
+        let output = command.output();
+
+        let _ = tx.send(output);
+    });
+
+    let output = rx.await??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let err = eyre!(format!("STDOUT: {stdout}"))
+            .wrap_err(format!("STDERR: {stderr}"))
+            .wrap_err("原因可能是视频或音频文件损坏，建议[重来]试试");
+        return Err(err);
     }
+
+    Ok(())
 }
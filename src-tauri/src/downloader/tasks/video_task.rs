@@ -1,25 +1,31 @@
 use std::{
     collections::HashMap,
     fs::{File, OpenOptions},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use eyre::{OptionExt, WrapErr, eyre};
 use fs4::fs_std::FileExt;
+use futures_util::future::join_all;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::AppHandle;
 use tokio::task::JoinSet;
 use tracing::{Instrument, instrument};
 
 use crate::{
+    app_context::AppContext,
     config::FileExistAction,
     downloader::{
-        download_chunk_task::DownloadChunkTask, download_progress::DownloadProgress,
-        download_task::DownloadTask, media_chunk::MediaChunk,
+        download_chunk_task::{DownloadChunkResult, DownloadChunkTask, DownloadChunksOutcome},
+        download_progress::{DownloadProgress, MediaUrl},
+        download_task::{DownloadOutcome, DownloadTask},
+        link_refresh::{self, RefreshableMedia},
+        media_chunk::MediaChunk,
+        media_mirror::{MediaMirror, MirrorPool},
     },
-    extensions::{AppHandleExt, EyreReportToMessage},
+    extensions::EyreReportToMessage,
     types::{
         bangumi_media_url::BangumiMediaUrl, cheese_media_url::CheeseMediaUrl,
         codec_type::CodecType, normal_media_url::NormalMediaUrl, video_quality::VideoQuality,
@@ -34,19 +40,52 @@ const CHUNK_SIZE: u64 = 2 * 1024 * 1024; // 2MB
 pub struct VideoTask {
     pub selected: bool,
     pub url: String,
+    pub mirrors: Vec<MediaMirror>,
     pub video_quality: VideoQuality,
     pub codec_type: CodecType,
+    /// 所选视频流的分辨率，分段视频(durl)为0
+    pub width: u32,
+    pub height: u32,
+    /// 分段视频(durl)为所有段的大小之和
     pub content_length: u64,
     pub chunks: Vec<MediaChunk>,
+    /// 旧的FLV/MP4分段视频(durl)的每一段，DASH视频为空
+    pub segments: Vec<VideoSegment>,
     pub completed: bool,
     pub skipped: bool,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct VideoSegment {
+    pub url: String,
+    pub mirrors: Vec<MediaMirror>,
+    pub content_length: u64,
+}
+
+impl VideoSegment {
+    fn new(url_with_content_length: &[(String, u64)]) -> Self {
+        let (url, content_length) = url_with_content_length
+            .iter()
+            .find(|(url, _)| url.starts_with("https://upos-"))
+            .unwrap_or(&url_with_content_length[0])
+            .clone();
+
+        let mirrors = MediaMirror::create_mirrors(url_with_content_length, &url, content_length);
+
+        Self {
+            url,
+            mirrors,
+            content_length,
+        }
+    }
+}
+
 impl VideoTask {
     #[instrument(level = "error", skip_all)]
     pub async fn prepare_normal(
         &mut self,
-        app: &AppHandle,
+        app: &AppContext,
         media_url: &NormalMediaUrl,
     ) -> eyre::Result<()> {
         let mut join_set = JoinSet::new();
@@ -55,6 +94,8 @@ impl VideoTask {
             let app = app.clone();
             let id = media.id;
             let codecid = media.codecid;
+            let width = u32::try_from(media.width).unwrap_or(0);
+            let height = u32::try_from(media.height).unwrap_or(0);
 
             let mut urls = Vec::new();
             urls.extend_from_slice(&media.backup_url);
@@ -65,34 +106,36 @@ impl VideoTask {
                 let url_with_content_length = bili_client.get_url_with_content_length(urls).await;
                 MediaForPrepare {
                     id,
-                    url_with_content_length,
+                    segments: vec![url_with_content_length],
                     codecid,
+                    width,
+                    height,
+                    is_durl: false,
                 }
             };
 
             join_set.spawn(get_url_with_content_length_task.in_current_span());
         }
 
-        for durl in &media_url.durl {
-            let app = app.clone();
-            let id = media_url.quality;
-            let codecid = media_url.video_codecid;
-
-            let mut urls = Vec::new();
-            urls.extend_from_slice(&durl.backup_url);
-            urls.push(durl.url.clone());
-
-            let get_url_with_content_length_task = async move {
-                let bili_client = app.get_bili_client();
-                let url_with_content_length = bili_client.get_url_with_content_length(urls).await;
-                MediaForPrepare {
-                    id,
-                    url_with_content_length,
-                    codecid,
-                }
-            };
-
-            join_set.spawn(get_url_with_content_length_task.in_current_span());
+        if !media_url.durl.is_empty() {
+            let segment_urls = media_url
+                .durl
+                .iter()
+                .map(|durl| {
+                    let mut urls = Vec::new();
+                    urls.extend_from_slice(&durl.backup_url);
+                    urls.push(durl.url.clone());
+                    urls
+                })
+                .collect();
+
+            let get_durl_media_task = get_durl_media(
+                app.clone(),
+                media_url.quality,
+                media_url.video_codecid,
+                segment_urls,
+            );
+            join_set.spawn(get_durl_media_task.in_current_span());
         }
 
         let mut medias: Vec<MediaForPrepare> = Vec::new();
@@ -102,7 +145,7 @@ impl VideoTask {
                 continue;
             };
 
-            if !media.url_with_content_length.is_empty() {
+            if media.is_available() {
                 medias.push(media);
             }
         }
@@ -115,7 +158,7 @@ impl VideoTask {
     #[instrument(level = "error", skip_all)]
     pub async fn prepare_bangumi(
         &mut self,
-        app: &AppHandle,
+        app: &AppContext,
         media_url: &BangumiMediaUrl,
     ) -> eyre::Result<()> {
         let mut medias: Vec<MediaForPrepare> = Vec::new();
@@ -127,6 +170,8 @@ impl VideoTask {
                 let app = app.clone();
                 let id = media.id;
                 let codecid = media.codecid;
+                let width = u32::try_from(media.width).unwrap_or(0);
+                let height = u32::try_from(media.height).unwrap_or(0);
 
                 let mut urls = Vec::new();
                 urls.extend_from_slice(&media.backup_url);
@@ -138,8 +183,11 @@ impl VideoTask {
                         bili_client.get_url_with_content_length(urls).await;
                     MediaForPrepare {
                         id,
-                        url_with_content_length,
+                        segments: vec![url_with_content_length],
                         codecid,
+                        width,
+                        height,
+                        is_durl: false,
                     }
                 };
 
@@ -148,28 +196,28 @@ impl VideoTask {
         }
 
         for durl in &media_url.durls {
-            for media in &durl.durl {
-                let app = app.clone();
-                let id = durl.quality;
-                let codecid = media_url.video_codecid;
-
-                let mut urls = Vec::new();
-                urls.extend_from_slice(&media.backup_url);
-                urls.push(media.url.clone());
-
-                let get_url_with_content_length_task = async move {
-                    let bili_client = app.get_bili_client();
-                    let url_with_content_length =
-                        bili_client.get_url_with_content_length(urls).await;
-                    MediaForPrepare {
-                        id,
-                        url_with_content_length,
-                        codecid,
-                    }
-                };
-
-                join_set.spawn(get_url_with_content_length_task.in_current_span());
+            if durl.durl.is_empty() {
+                continue;
             }
+
+            let segment_urls = durl
+                .durl
+                .iter()
+                .map(|media| {
+                    let mut urls = Vec::new();
+                    urls.extend_from_slice(&media.backup_url);
+                    urls.push(media.url.clone());
+                    urls
+                })
+                .collect();
+
+            let get_durl_media_task = get_durl_media(
+                app.clone(),
+                durl.quality,
+                media_url.video_codecid,
+                segment_urls,
+            );
+            join_set.spawn(get_durl_media_task.in_current_span());
         }
 
         while let Some(join_result) = join_set.join_next().await {
@@ -177,7 +225,7 @@ impl VideoTask {
                 continue;
             };
 
-            if !media.url_with_content_length.is_empty() {
+            if media.is_available() {
                 medias.push(media);
             }
         }
@@ -190,7 +238,7 @@ impl VideoTask {
     #[instrument(level = "error", skip_all)]
     pub async fn prepare_cheese(
         &mut self,
-        app: &AppHandle,
+        app: &AppContext,
         media_url: &CheeseMediaUrl,
     ) -> eyre::Result<()> {
         let mut medias: Vec<MediaForPrepare> = Vec::new();
@@ -202,6 +250,8 @@ impl VideoTask {
                 let app = app.clone();
                 let id = media.id;
                 let codecid = media.codecid;
+                let width = u32::try_from(media.width).unwrap_or(0);
+                let height = u32::try_from(media.height).unwrap_or(0);
 
                 let mut urls = Vec::new();
                 urls.extend_from_slice(&media.backup_url);
@@ -213,8 +263,11 @@ impl VideoTask {
                         bili_client.get_url_with_content_length(urls).await;
                     MediaForPrepare {
                         id,
-                        url_with_content_length,
+                        segments: vec![url_with_content_length],
                         codecid,
+                        width,
+                        height,
+                        is_durl: false,
                     }
                 };
 
@@ -223,28 +276,28 @@ impl VideoTask {
         }
 
         for durl in &media_url.durls {
-            for media in &durl.durl {
-                let app = app.clone();
-                let id = durl.quality;
-                let codecid = media_url.video_codecid;
-
-                let mut urls = Vec::new();
-                urls.extend_from_slice(&media.backup_url);
-                urls.push(media.url.clone());
-
-                let get_url_with_content_length_task = async move {
-                    let bili_client = app.get_bili_client();
-                    let url_with_content_length =
-                        bili_client.get_url_with_content_length(urls).await;
-                    MediaForPrepare {
-                        id,
-                        url_with_content_length,
-                        codecid,
-                    }
-                };
-
-                join_set.spawn(get_url_with_content_length_task.in_current_span());
+            if durl.durl.is_empty() {
+                continue;
             }
+
+            let segment_urls = durl
+                .durl
+                .iter()
+                .map(|media| {
+                    let mut urls = Vec::new();
+                    urls.extend_from_slice(&media.backup_url);
+                    urls.push(media.url.clone());
+                    urls
+                })
+                .collect();
+
+            let get_durl_media_task = get_durl_media(
+                app.clone(),
+                durl.quality,
+                media_url.video_codecid,
+                segment_urls,
+            );
+            join_set.spawn(get_durl_media_task.in_current_span());
         }
 
         while let Some(join_result) = join_set.join_next().await {
@@ -252,7 +305,7 @@ impl VideoTask {
                 continue;
             };
 
-            if !media.url_with_content_length.is_empty() {
+            if media.is_available() {
                 medias.push(media);
             }
         }
@@ -263,7 +316,7 @@ impl VideoTask {
     }
 
     #[instrument(level = "error", skip_all)]
-    fn prepare(&mut self, app: &AppHandle, medias: &[MediaForPrepare]) -> eyre::Result<()> {
+    fn prepare(&mut self, app: &AppContext, medias: &[MediaForPrepare]) -> eyre::Result<()> {
         if medias.is_empty() {
             return Err(eyre!("获取视频地址失败，medias为空"));
         }
@@ -290,38 +343,48 @@ impl VideoTask {
 
         self.video_quality = media.id.into();
         self.codec_type = media.codecid.into();
+        self.width = media.width;
+        self.height = media.height;
 
-        let (url, content_length) = media
-            .url_with_content_length
-            .iter()
-            .find(|(url, _)| url.starts_with("https://upos-"))
-            .unwrap_or(&media.url_with_content_length[0])
-            .clone();
+        let old_segment_lengths = self.segment_lengths();
 
-        self.url = url;
-
-        if self.content_length != content_length {
-            let chunk_count = content_length.div_ceil(CHUNK_SIZE);
-
-            #[allow(clippy::cast_possible_truncation)]
-            let mut chunks = Vec::with_capacity(chunk_count as usize);
-            for i in 0..chunk_count {
-                let start = i * CHUNK_SIZE;
-                let end = std::cmp::min(start + CHUNK_SIZE, content_length) - 1;
-                chunks.push(MediaChunk {
-                    start,
-                    end,
-                    completed: false,
-                });
-            }
+        let mut segments: Vec<VideoSegment> = media
+            .segments
+            .iter()
+            .map(|url_with_content_length| VideoSegment::new(url_with_content_length))
+            .collect();
+        let content_length = segments.iter().map(|s| s.content_length).sum();
+
+        if media.is_durl {
+            self.url.clone_from(&segments[0].url);
+            self.mirrors = Vec::new();
+            self.segments = segments;
+        } else {
+            let segment = segments.remove(0);
+            self.url = segment.url;
+            self.mirrors = segment.mirrors;
+            self.segments = Vec::new();
+        }
+        self.content_length = content_length;
 
-            self.content_length = content_length;
-            self.chunks = chunks;
+        // 分段方式没变时才能继续使用已下载的分片
+        let segment_lengths = self.segment_lengths();
+        if old_segment_lengths != segment_lengths {
+            self.chunks = create_chunks(&segment_lengths);
         }
 
         Ok(())
     }
 
+    /// DASH视频当作只有一段
+    fn segment_lengths(&self) -> Vec<u64> {
+        if self.segments.is_empty() {
+            vec![self.content_length]
+        } else {
+            self.segments.iter().map(|s| s.content_length).collect()
+        }
+    }
+
     pub fn mark_uncompleted(&mut self) {
         self.completed = false;
         self.chunks.iter_mut().for_each(|chunk| {
@@ -334,13 +397,12 @@ impl VideoTask {
         !self.selected || self.completed
     }
 
-    #[allow(clippy::too_many_lines)]
     #[instrument(level = "error", skip_all)]
     pub async fn process(
         &self,
         download_task: &Arc<DownloadTask>,
         progress: &DownloadProgress,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<DownloadOutcome> {
         let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
         let video_task = download_task.progress.read().video_task.clone();
 
@@ -352,124 +414,511 @@ impl VideoTask {
                 p.video_task.skipped = true;
                 p.video_task.completed = true;
             });
-            return Ok(());
+            return Ok(DownloadOutcome::Completed);
         }
 
         let temp_file_path = episode_dir.join(format!(
             "{filename}.mp4.com.lanyeeee.bilibili-video-downloader"
         ));
 
-        let should_reuse_temp_file = temp_file_path
-            .metadata()
-            .map(|m| m.len() == video_task.content_length)
-            .unwrap_or(false);
-
-        let file = if should_reuse_temp_file {
-            // 如果临时文件可以重用，则直接打开它
-            OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&temp_file_path)?
+        let outcome = if video_task.segments.is_empty() {
+            Self::download_dash(download_task, progress, &video_task, &temp_file_path).await?
+        } else {
+            Self::download_durl(download_task, progress, &video_task, &temp_file_path).await?
+        };
+        if outcome == DownloadOutcome::Interrupted {
+            return Ok(outcome);
+        }
+
+        // 重命名临时文件
+        if mp4_path.exists() {
+            std::fs::remove_file(&mp4_path)
+                .wrap_err(format!("删除已存在的视频文件`{}`失败", mp4_path.display()))?;
+        }
+        std::fs::rename(&temp_file_path, &mp4_path).wrap_err(format!(
+            "将临时文件`{}`重命名为`{}`失败",
+            temp_file_path.display(),
+            mp4_path.display()
+        ))?;
+
+        download_task.update_progress(|p| p.video_task.completed = true);
+
+        Ok(DownloadOutcome::Completed)
+    }
+
+    /// 将DASH视频下载到`temp_file_path`
+    async fn download_dash(
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+        video_task: &VideoTask,
+        temp_file_path: &Path,
+    ) -> eyre::Result<DownloadOutcome> {
+        let (file, _) = open_temp_file(temp_file_path, video_task.content_length)?;
+
+        let mirrors = if video_task.mirrors.is_empty() {
+            MediaMirror::create_mirrors(&[], &video_task.url, video_task.content_length)
         } else {
-            // 如果临时文件不能重用，则创建个新的
-            let file = File::create(&temp_file_path)?;
-            file.allocate(video_task.content_length)?;
-            file
+            video_task.mirrors.clone()
         };
-        let file = Arc::new(Mutex::new(file));
+        let mirror_pools = vec![Arc::new(MirrorPool::new(mirrors))];
+
+        let outcome =
+            Self::download_all_chunks(download_task, progress, &[file], mirror_pools).await?;
+        if outcome == DownloadOutcome::Interrupted {
+            return Ok(outcome);
+        }
+        if !is_all_chunks_completed(download_task) {
+            return Err(eyre!(
+                "视频文件`{}`有分片未下载完成，[继续]可以跳过已下载分片断点续传",
+                temp_file_path.display()
+            ));
+        }
+
+        ensure_video_file_complete(download_task, temp_file_path)?;
+
+        Ok(DownloadOutcome::Completed)
+    }
+
+    /// 将分段视频(durl)的每一段分别下载，再用FFmpeg拼接到`temp_file_path`
+    async fn download_durl(
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+        video_task: &VideoTask,
+        temp_file_path: &Path,
+    ) -> eyre::Result<DownloadOutcome> {
+        let (episode_dir, filename) = (&progress.episode_dir, &progress.filename);
+        let segment_paths: Vec<PathBuf> = (1..=video_task.segments.len())
+            .map(|order| {
+                episode_dir.join(format!(
+                    "{filename}.part{order}.com.lanyeeee.bilibili-video-downloader"
+                ))
+            })
+            .collect();
+
+        let mut files = Vec::with_capacity(segment_paths.len());
+        let mut mirror_pools = Vec::with_capacity(segment_paths.len());
+        for (segment_index, (segment, segment_path)) in
+            video_task.segments.iter().zip(&segment_paths).enumerate()
+        {
+            let (file, reused) = open_temp_file(segment_path, segment.content_length)?;
+            if !reused {
+                // 临时文件是新建的，这一段之前下载的分片都作废了
+                download_task.update_progress(|p| {
+                    p.video_task
+                        .chunks
+                        .iter_mut()
+                        .filter(|chunk| chunk.segment_index == segment_index)
+                        .for_each(|chunk| chunk.completed = false);
+                });
+            }
+            files.push(file);
+
+            let mirrors = if segment.mirrors.is_empty() {
+                MediaMirror::create_mirrors(&[], &segment.url, segment.content_length)
+            } else {
+                segment.mirrors.clone()
+            };
+            mirror_pools.push(Arc::new(MirrorPool::new(mirrors)));
+        }
+
+        let outcome =
+            Self::download_all_chunks(download_task, progress, &files, mirror_pools).await?;
+        if outcome == DownloadOutcome::Interrupted {
+            return Ok(outcome);
+        }
+        if !is_all_chunks_completed(download_task) {
+            return Err(eyre!(
+                "视频有分段未下载完成，[继续]可以跳过已下载分片断点续传"
+            ));
+        }
+        // 拼接前关闭文件，避免Windows上的文件占用
+        drop(files);
+
+        let concat_list_path = episode_dir.join(format!(
+            "{filename}.concat.com.lanyeeee.bilibili-video-downloader"
+        ));
+        let concat_list = create_concat_list(&segment_paths);
+        std::fs::write(&concat_list_path, concat_list)
+            .wrap_err(format!("保存拼接列表`{}`失败", concat_list_path.display()))?;
+
+        concat_segments(&concat_list_path, temp_file_path)
+            .await
+            .wrap_err("拼接视频分段失败")?;
+        // 不完整时保留分段的临时文件，重新下载时可以重用
+        ensure_video_file_complete(download_task, temp_file_path)?;
+
+        for path in segment_paths.iter().chain([&concat_list_path]) {
+            std::fs::remove_file(path).wrap_err(format!("删除`{}`失败", path.display()))?;
+        }
+
+        Ok(DownloadOutcome::Completed)
+    }
+
+    /// 下载所有未完成的分片，链接过期时刷新链接后继续
+    ///
+    /// 返回`Completed`时不代表所有分片都已下载
+    async fn download_all_chunks(
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+        files: &[Arc<Mutex<File>>],
+        mirror_pools: Vec<Arc<MirrorPool>>,
+    ) -> eyre::Result<DownloadOutcome> {
+        link_refresh::download_with_refresh(
+            mirror_pools,
+            |mirror_pools| async move {
+                Self::download_chunks(download_task, files, &mirror_pools).await
+            },
+            || async {
+                Self::refresh_url(download_task, progress)
+                    .await
+                    .wrap_err("刷新视频链接失败")
+            },
+        )
+        .await
+    }
 
-        let chunk_count = video_task.chunks.len();
+    /// 下载所有未完成的分片
+    ///
+    /// 分片写入`files[segment_index]`，从`mirror_pools[segment_index]`下载
+    async fn download_chunks(
+        download_task: &Arc<DownloadTask>,
+        files: &[Arc<Mutex<File>>],
+        mirror_pools: &[Arc<MirrorPool>],
+    ) -> eyre::Result<DownloadChunksOutcome> {
+        let chunks = download_task.progress.read().video_task.chunks.clone();
+        let chunk_count = chunks.len();
+        let segment_count = mirror_pools.len();
 
         let mut join_set = JoinSet::new();
-        for (chunk_index, chunk) in video_task.chunks.iter().enumerate() {
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
             if chunk.completed {
                 continue;
             }
 
-            let (start, end) = (chunk.start, chunk.end);
+            let (start, end, segment_index) = (chunk.start, chunk.end, chunk.segment_index);
+            let (Some(file), Some(mirror_pool)) =
+                (files.get(segment_index), mirror_pools.get(segment_index))
+            else {
+                return Err(eyre!(
+                    "分片所属的段`{segment_index}`不存在，视频只有{segment_count}段"
+                ));
+            };
 
             let download_chunk_task = DownloadChunkTask {
                 download_task: download_task.clone(),
                 start,
                 end,
-                url: video_task.url.clone(),
+                mirror_pool: mirror_pool.clone(),
                 file: file.clone(),
                 chunk_index,
             };
 
             let chunk_order = chunk_index + 1;
             let chunk_task = async move {
-                download_chunk_task.process().await.wrap_err(format!(
-                    "分片`{chunk_order}/{chunk_count}`下载失败({start}-{end})"
-                ))
+                match download_chunk_task.process().await {
+                    DownloadChunkResult::Failed(err) => DownloadChunkResult::Failed(err.wrap_err(
+                        format!("分片`{chunk_order}/{chunk_count}`下载失败({start}-{end})"),
+                    )),
+                    result => result,
+                }
             };
             join_set.spawn(chunk_task.in_current_span());
         }
 
+        // DASH视频只有一个镜像池，分段视频(durl)每一段一个
+        let save_mirrors = |p: &mut DownloadProgress| {
+            if p.video_task.segments.is_empty() {
+                p.video_task.mirrors = mirror_pools[0].snapshot();
+            } else {
+                for (segment, mirror_pool) in p.video_task.segments.iter_mut().zip(mirror_pools) {
+                    segment.mirrors = mirror_pool.snapshot();
+                }
+            }
+        };
+
+        let mut interrupted = false;
+        let mut link_expired = false;
         while let Some(join_result) = join_set.join_next().await {
-            let Ok(download_video_result) = join_result else {
+            let Ok(download_chunk_result) = join_result else {
                 continue;
             };
 
-            match download_video_result {
-                Ok(i) => download_task.update_progress(|p| p.video_task.chunks[i].completed = true),
-                Err(err) => {
+            match download_chunk_result {
+                DownloadChunkResult::Downloaded(i) => {
+                    let segment_index = chunks[i].segment_index;
+                    download_task.update_progress(|p| {
+                        p.video_task.chunks[i].completed = true;
+                        save_mirrors(p);
+                    });
+                    if segment_count > 1 && is_segment_completed(download_task, segment_index) {
+                        let segment_order = segment_index + 1;
+                        tracing::info!("视频的第`{segment_order}/{segment_count}`段下载完成");
+                    }
+                }
+                // 被打断的分片可能只写入了一部分，不能标记为已下载
+                DownloadChunkResult::Interrupted => interrupted = true,
+                DownloadChunkResult::Failed(err) => {
+                    download_task.update_progress(save_mirrors);
+                    if link_refresh::is_link_expired(&err) {
+                        link_expired = true;
+                    }
                     let err_title = "视频的一个分片下载失败";
                     let message = err.to_message();
                     tracing::error!(err_title, message);
                 }
             }
         }
-        // 检查视频是否已下载完成
-        let download_completed = download_task
-            .progress
-            .read()
-            .video_task
-            .chunks
-            .iter()
-            .all(|chunk| chunk.completed);
-        if !download_completed {
+
+        let outcome = if interrupted {
+            DownloadChunksOutcome::Interrupted
+        } else if link_expired {
+            DownloadChunksOutcome::LinkExpired
+        } else {
+            DownloadChunksOutcome::Finished
+        };
+
+        Ok(outcome)
+    }
+
+    /// 刷新视频链接，返回每一段的新镜像池
+    async fn refresh_url(
+        download_task: &Arc<DownloadTask>,
+        progress: &DownloadProgress,
+    ) -> eyre::Result<Vec<Arc<MirrorPool>>> {
+        let video_task = download_task.progress.read().video_task.clone();
+        let refreshed =
+            link_refresh::refresh_media(&download_task.app, progress, &video_task).await?;
+
+        let mirror_pools = if refreshed.segments.is_empty() {
+            vec![Arc::new(MirrorPool::new(refreshed.mirrors.clone()))]
+        } else {
+            refreshed
+                .segments
+                .iter()
+                .map(|segment| Arc::new(MirrorPool::new(segment.mirrors.clone())))
+                .collect()
+        };
+        download_task.update_progress(|p| {
+            p.video_task.url = refreshed.url;
+            p.video_task.mirrors = refreshed.mirrors;
+            p.video_task.segments = refreshed.segments;
+        });
+
+        Ok(mirror_pools)
+    }
+}
+
+impl RefreshableMedia for VideoTask {
+    async fn prepare(&mut self, app: &AppContext, media_url: MediaUrl) -> eyre::Result<()> {
+        match media_url {
+            MediaUrl::Normal(media_url) => self.prepare_normal(app, &media_url).await,
+            MediaUrl::Bangumi(media_url) => self.prepare_bangumi(app, &media_url).await,
+            MediaUrl::Cheese(media_url) => self.prepare_cheese(app, &media_url).await,
+        }
+    }
+
+    fn ensure_same_media(&self, previous: &Self) -> eyre::Result<()> {
+        if self.video_quality != previous.video_quality || self.codec_type != previous.codec_type {
+            return Err(eyre!("新的链接中没有与之前相同画质和编码的视频"));
+        }
+
+        if self.segment_lengths() != previous.segment_lengths() {
             return Err(eyre!(
-                "视频文件`{}`有分片未下载完成，[继续]可以跳过已下载分片断点续传",
-                temp_file_path.display()
+                "新的链接中视频大小发生了变化({:?} -> {:?})",
+                previous.segment_lengths(),
+                self.segment_lengths()
             ));
         }
 
-        let is_video_file_complete = utils::is_mp4_complete(&temp_file_path).wrap_err(format!(
-            "检查视频文件`{}`是否完整失败",
+        Ok(())
+    }
+}
+
+/// 检查下载或拼接得到的`temp_file_path`是否完整，不完整时把所有分片标记为未下载
+fn ensure_video_file_complete(
+    download_task: &Arc<DownloadTask>,
+    temp_file_path: &Path,
+) -> eyre::Result<()> {
+    let is_video_file_complete = utils::is_mp4_complete(temp_file_path).wrap_err(format!(
+        "检查视频文件`{}`是否完整失败",
+        temp_file_path.display()
+    ))?;
+
+    if !is_video_file_complete {
+        download_task.update_progress(|p| p.video_task.mark_uncompleted());
+        return Err(eyre!(
+            "视频文件`{}`不完整，[继续]会重新下载所有分片",
             temp_file_path.display()
-        ))?;
+        ));
+    }
 
-        if !is_video_file_complete {
-            download_task.update_progress(|p| p.video_task.mark_uncompleted());
-            return Err(eyre!(
-                "视频文件`{}`不完整，[继续]会重新下载所有分片",
-                temp_file_path.display()
-            ));
+    Ok(())
+}
+
+/// 打开临时文件，大小与`content_length`一致时重用，否则新建
+///
+/// 返回的`bool`表示是否重用了之前的临时文件
+fn open_temp_file(path: &Path, content_length: u64) -> eyre::Result<(Arc<Mutex<File>>, bool)> {
+    let should_reuse_temp_file = path
+        .metadata()
+        .map(|m| m.len() == content_length)
+        .unwrap_or(false);
+
+    let file = if should_reuse_temp_file {
+        // 如果临时文件可以重用，则直接打开它
+        OpenOptions::new().read(true).write(true).open(path)?
+    } else {
+        // 如果临时文件不能重用，则创建个新的
+        let file = File::create(path)?;
+        file.allocate(content_length)?;
+        file
+    };
+
+    Ok((Arc::new(Mutex::new(file)), should_reuse_temp_file))
+}
+
+fn is_all_chunks_completed(download_task: &DownloadTask) -> bool {
+    download_task
+        .progress
+        .read()
+        .video_task
+        .chunks
+        .iter()
+        .all(|chunk| chunk.completed)
+}
+
+fn is_segment_completed(download_task: &DownloadTask, segment_index: usize) -> bool {
+    download_task
+        .progress
+        .read()
+        .video_task
+        .chunks
+        .iter()
+        .filter(|chunk| chunk.segment_index == segment_index)
+        .all(|chunk| chunk.completed)
+}
+
+/// 将每一段按`CHUNK_SIZE`切分为分片，分片的范围是相对于所在段的
+fn create_chunks(segment_lengths: &[u64]) -> Vec<MediaChunk> {
+    let mut chunks = Vec::new();
+    for (segment_index, &content_length) in segment_lengths.iter().enumerate() {
+        let chunk_count = content_length.div_ceil(CHUNK_SIZE);
+        for i in 0..chunk_count {
+            let start = i * CHUNK_SIZE;
+            let end = std::cmp::min(start + CHUNK_SIZE, content_length) - 1;
+            chunks.push(MediaChunk {
+                start,
+                end,
+                completed: false,
+                segment_index,
+            });
         }
+    }
+    chunks
+}
 
-        // 重命名临时文件
-        if mp4_path.exists() {
-            std::fs::remove_file(&mp4_path)
-                .wrap_err(format!("删除已存在的视频文件`{}`失败", mp4_path.display()))?;
+/// 生成`FFmpeg`的`concat`分离器的列表文件内容
+///
+/// 列表文件与分段在同一个目录，只写文件名，避免路径中的特殊字符
+fn create_concat_list(segment_paths: &[PathBuf]) -> String {
+    let mut concat_list = String::new();
+    for name in segment_paths.iter().filter_map(|path| path.file_name()) {
+        let name = name.to_string_lossy().replace('\'', r"'\''");
+        concat_list.push_str(&format!("file '{name}'\n"));
+    }
+    concat_list
+}
+
+/// 用`FFmpeg`的`concat`分离器将分段拼接为MP4，不重新编码
+async fn concat_segments(concat_list_path: &Path, output_path: &Path) -> eyre::Result<()> {
+    let ffmpeg_program = utils::get_ffmpeg_program().wrap_err("获取FFmpeg程序路径失败")?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let concat_list_path = concat_list_path.to_path_buf();
+    let output_path = output_path.to_path_buf();
+
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _enter = current_span.enter();
+
+        let mut command = std::process::Command::new(ffmpeg_program);
+
+        command.arg("-f").arg("concat");
+        command.arg("-safe").arg("0");
+        command.arg("-i").arg(concat_list_path);
+        command.arg("-c").arg("copy");
+        // 临时文件的扩展名不是.mp4，需要指定格式
+        command.arg("-f").arg("mp4");
+
+        command.arg(output_path).arg("-y");
+
+        #[cfg(target_os = "windows")]
+        {
+            // 隐藏窗口
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(0x0800_0000);
         }
-        std::fs::rename(&temp_file_path, &mp4_path).wrap_err(format!(
-            "将临时文件`{}`重命名为`{}`失败",
-            temp_file_path.display(),
-            mp4_path.display()
-        ))?;
 
-        download_task.update_progress(|p| p.video_task.completed = true);
+        let output = command.output();
 
-        Ok(())
+        let _ = tx.send(output);
+    });
+
+    let output = rx.await??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let err = eyre!(format!("STDOUT: {stdout}"))
+            .wrap_err(format!("STDERR: {stderr}"))
+            .wrap_err("原因可能是视频分段损坏，建议[重来]试试");
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 并发获取分段视频(durl)每一段的地址和大小
+async fn get_durl_media(
+    app: AppContext,
+    id: i64,
+    codecid: i64,
+    segment_urls: Vec<Vec<String>>,
+) -> MediaForPrepare {
+    let bili_client = app.get_bili_client();
+    let tasks = segment_urls
+        .into_iter()
+        .map(|urls| bili_client.get_url_with_content_length(urls));
+    let segments = join_all(tasks).await;
+
+    MediaForPrepare {
+        id,
+        segments,
+        codecid,
+        // 分段视频的接口不返回分辨率
+        width: 0,
+        height: 0,
+        is_durl: true,
     }
 }
 
 #[derive(Clone)]
 struct MediaForPrepare {
     pub id: i64,
-    pub url_with_content_length: Vec<(String, u64)>,
+    /// DASH视频只有一段
+    pub segments: Vec<Vec<(String, u64)>>,
     pub codecid: i64,
+    /// 未知时为0
+    pub width: u32,
+    pub height: u32,
+    pub is_durl: bool,
+}
+
+impl MediaForPrepare {
+    /// 每一段都获取到了地址才能下载
+    fn is_available(&self) -> bool {
+        !self.segments.is_empty() && self.segments.iter().all(|s| !s.is_empty())
+    }
 }
 
 fn select_exact_match_media(
@@ -486,11 +935,11 @@ fn select_exact_match_media(
 }
 
 fn select_media_by_priority(
-    app: &AppHandle,
+    app: &AppContext,
     medias: &[MediaForPrepare],
 ) -> Option<MediaForPrepare> {
     let (video_quality_priority, codec_type_priority) = {
-        let config = app.get_config().inner().read();
+        let config = app.get_config().read();
         (
             config.video_quality_priority.clone(),
             config.codec_type_priority.clone(),
@@ -523,3 +972,36 @@ fn select_media_by_priority(
 
     media.cloned()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_chunks_per_segment() {
+        let chunks = create_chunks(&[CHUNK_SIZE + 1, 10]);
+
+        let ranges: Vec<(u64, u64, usize)> = chunks
+            .iter()
+            .map(|chunk| (chunk.start, chunk.end, chunk.segment_index))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, CHUNK_SIZE - 1, 0),
+                (CHUNK_SIZE, CHUNK_SIZE, 0),
+                (0, 9, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn create_concat_list_escapes_quotes() {
+        let dir = PathBuf::from("episode");
+        let segment_paths = vec![dir.join("a.part1"), dir.join("it's.part2")];
+
+        let concat_list = create_concat_list(&segment_paths);
+
+        assert_eq!(concat_list, "file 'a.part1'\nfile 'it'\\''s.part2'\n");
+    }
+}
@@ -6,6 +6,7 @@ use specta::Type;
 use tracing::instrument;
 use tracing_error::SpanTrace;
 
+#[cfg(feature = "gui")]
 pub type CommandResult<T> = Result<T, CommandError>;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
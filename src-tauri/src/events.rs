@@ -1,19 +1,21 @@
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri_specta::Event;
 
 use crate::downloader::{
     download_progress::DownloadProgress, download_task_state::DownloadTaskState,
 };
+#[cfg(feature = "gui")]
 use crate::types::plugin_info::PluginInfo;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[cfg_attr(feature = "gui", derive(tauri_specta::Event))]
 #[serde(rename_all = "camelCase")]
 pub struct LogEvent {
     pub json_raw: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[cfg_attr(feature = "gui", derive(tauri_specta::Event))]
 #[serde(tag = "event", content = "data")]
 pub enum DownloadEvent {
     Speed {
@@ -48,7 +50,8 @@ pub enum DownloadEvent {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Serialize, Deserialize, Type, tauri_specta::Event)]
 #[serde(tag = "event", content = "data")]
 pub enum PluginEvent {
     Loaded { plugin_info: PluginInfo },
@@ -1,14 +1,25 @@
 use eyre::WrapErr;
+#[cfg(feature = "gui")]
 use parking_lot::RwLock;
+#[cfg(feature = "gui")]
 use tauri::{AppHandle, Manager, State};
 use tracing::instrument;
 
 use crate::{
+    app_context::AppContext, downloader::download_progress::DownloadProgress,
+    types::player_info::PlayerInfo,
+};
+#[cfg(feature = "gui")]
+use crate::{
+    app_context::EventEmitter,
     bili_client::BiliClient,
     config::Config,
-    downloader::{download_manager::DownloadManager, download_progress::DownloadProgress},
+    downloader::download_manager::DownloadManager,
+    events::{DownloadEvent, LogEvent, PluginEvent},
+    live_recorder::live_recorder_manager::LiveRecorderManager,
+    mirror_sync::mirror_sync_manager::MirrorSyncManager,
     plugin::plugin_manager::PluginManager,
-    types::player_info::PlayerInfo,
+    subscription::subscription_manager::SubscriptionManager,
 };
 
 pub trait EyreReportToMessage {
@@ -21,32 +32,63 @@ impl EyreReportToMessage for eyre::Report {
     }
 }
 
+#[cfg(feature = "gui")]
 pub trait AppHandleExt {
-    fn get_config(&self) -> State<'_, RwLock<Config>>;
-    fn get_bili_client(&self) -> State<'_, BiliClient>;
-    fn get_download_manager(&self) -> State<'_, DownloadManager>;
-    fn get_plugin_manager(&self) -> State<'_, PluginManager>;
+    fn get_context(&self) -> State<'_, AppContext>;
+    fn get_config(&self) -> &RwLock<Config>;
+    fn get_bili_client(&self) -> &BiliClient;
+    fn get_download_manager(&self) -> &DownloadManager;
+    fn get_plugin_manager(&self) -> &PluginManager;
+    fn get_subscription_manager(&self) -> &SubscriptionManager;
+    fn get_mirror_sync_manager(&self) -> &MirrorSyncManager;
+    fn get_live_recorder_manager(&self) -> &LiveRecorderManager;
 }
 
+#[cfg(feature = "gui")]
 impl AppHandleExt for AppHandle {
-    fn get_config(&self) -> State<'_, RwLock<Config>> {
-        self.state::<RwLock<Config>>()
+    fn get_context(&self) -> State<'_, AppContext> {
+        self.state::<AppContext>()
+    }
+    fn get_config(&self) -> &RwLock<Config> {
+        self.get_context().inner().get_config()
+    }
+    fn get_bili_client(&self) -> &BiliClient {
+        self.get_context().inner().get_bili_client()
+    }
+    fn get_download_manager(&self) -> &DownloadManager {
+        self.get_context().inner().get_download_manager()
     }
-    fn get_bili_client(&self) -> State<'_, BiliClient> {
-        self.state::<BiliClient>()
+    fn get_plugin_manager(&self) -> &PluginManager {
+        self.get_context().inner().get_plugin_manager()
+    }
+    fn get_subscription_manager(&self) -> &SubscriptionManager {
+        self.get_context().inner().get_subscription_manager()
+    }
+    fn get_mirror_sync_manager(&self) -> &MirrorSyncManager {
+        self.get_context().inner().get_mirror_sync_manager()
+    }
+    fn get_live_recorder_manager(&self) -> &LiveRecorderManager {
+        self.get_context().inner().get_live_recorder_manager()
+    }
+}
+
+#[cfg(feature = "gui")]
+impl EventEmitter for AppHandle {
+    fn emit_download_event(&self, event: DownloadEvent) {
+        let _ = tauri_specta::Event::emit(&event, self);
     }
-    fn get_download_manager(&self) -> State<'_, DownloadManager> {
-        self.state::<DownloadManager>()
+    fn emit_plugin_event(&self, event: PluginEvent) {
+        let _ = tauri_specta::Event::emit(&event, self);
     }
-    fn get_plugin_manager(&self) -> State<'_, PluginManager> {
-        self.state::<PluginManager>()
+    fn emit_log_event(&self, event: LogEvent) {
+        let _ = tauri_specta::Event::emit(&event, self);
     }
 }
 
 pub trait GetOrInitPlayerInfo {
     async fn get_or_init<'a>(
         &'a mut self,
-        app: &AppHandle,
+        app: &AppContext,
         progress: &DownloadProgress,
     ) -> eyre::Result<&'a mut PlayerInfo>;
 }
@@ -55,7 +97,7 @@ impl GetOrInitPlayerInfo for Option<PlayerInfo> {
     #[instrument(level = "error", skip_all)]
     async fn get_or_init<'a>(
         &'a mut self,
-        app: &AppHandle,
+        app: &AppContext,
         progress: &DownloadProgress,
     ) -> eyre::Result<&'a mut PlayerInfo> {
         if let Some(info) = self {
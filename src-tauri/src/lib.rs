@@ -1,4 +1,9 @@
+mod app_context;
+mod audio_tagger;
 mod bili_client;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "gui")]
 mod commands;
 mod config;
 mod danmaku_xml_to_ass;
@@ -6,8 +11,18 @@ mod downloader;
 mod errors;
 mod events;
 mod extensions;
+mod link_parser;
+mod live_recorder;
 mod logger;
+mod mirror_sync;
+mod mkv_muxer;
+mod mp4_muxer;
 mod plugin;
+mod remote_api;
+mod subscription;
+mod subtitle_converter;
+#[cfg(test)]
+mod test_support;
 mod types;
 mod utils;
 mod wbi;
@@ -16,34 +31,49 @@ mod protobuf {
     include!("./bilibili.community.service.dm.v1.rs");
 }
 
+#[cfg(feature = "gui")]
 use commands::{
-    add_plugin, create_download_tasks, delete_download_tasks, generate_qrcode,
-    get_available_media_formats, get_bangumi_follow_info, get_bangumi_info, get_config,
-    get_fav_folders, get_fav_info, get_history_info, get_logs_dir_size, get_normal_info,
-    get_plugin_infos, get_qrcode_status, get_skip_segments, get_user_info, get_user_video_info,
-    get_watch_later_info, pause_download_tasks, restart_download_task, restart_download_tasks,
-    restore_download_tasks, resume_download_tasks, save_config, search, set_plugin_enabled,
-    set_plugin_priority, show_path_in_file_manager, uninstall_plugin,
+    add_mirror_sync, add_plugin, add_subscription, check_subscriptions, create_download_tasks,
+    delete_download_tasks, generate_qrcode, get_archived_items, get_available_media_formats,
+    get_bangumi_follow_info, get_bangumi_info, get_config, get_danmaku_history_dates,
+    get_fav_folders, get_fav_info, get_history_info, get_live_recordings, get_logs_dir_size,
+    get_mirror_syncs, get_normal_info, get_plugin_infos, get_qrcode_status, get_skip_segments,
+    get_subscriptions, get_user_info, get_user_video_info, get_watch_later_info, parse_links,
+    pause_download_tasks, query_download_history, remove_mirror_sync, remove_subscription,
+    restart_download_task, restart_download_tasks, restore_download_tasks, resume_download_tasks,
+    save_config, search, set_download_tasks_speed_limit, set_plugin_enabled, set_plugin_priority,
+    show_path_in_file_manager, start_live_recording, stop_live_recording, sync_mirrors,
+    uninstall_plugin, update_mirror_sync, update_subscription,
 };
+#[cfg(feature = "gui")]
 use eyre::WrapErr;
-use parking_lot::RwLock;
+#[cfg(feature = "gui")]
 use tauri::{Manager, Wry};
 
+#[cfg(feature = "gui")]
 use crate::{
+    app_context::AppContext,
     bili_client::BiliClient,
     commands::open_log_file,
     config::Config,
     downloader::download_manager::DownloadManager,
     errors::install_custom_eyre_handler,
     events::{DownloadEvent, LogEvent, PluginEvent},
+    extensions::EyreReportToMessage,
+    live_recorder::live_recorder_manager::LiveRecorderManager,
+    mirror_sync::mirror_sync_manager::MirrorSyncManager,
     plugin::plugin_manager::PluginManager,
+    remote_api::RemoteApiServer,
+    subscription::subscription_manager::SubscriptionManager,
 };
 
+#[cfg(feature = "gui")]
 fn generate_context() -> tauri::Context<Wry> {
     tauri::generate_context!()
 }
 
-#[allow(clippy::missing_panics_doc)]
+#[cfg(feature = "gui")]
+#[allow(clippy::missing_panics_doc, clippy::too_many_lines)]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     install_custom_eyre_handler().unwrap();
@@ -68,19 +98,37 @@ pub fn run() {
             pause_download_tasks,
             resume_download_tasks,
             delete_download_tasks,
+            set_download_tasks_speed_limit,
             restart_download_tasks,
             restart_download_task,
             restore_download_tasks,
             search,
+            parse_links,
             get_logs_dir_size,
             show_path_in_file_manager,
             get_skip_segments,
+            get_danmaku_history_dates,
             get_available_media_formats,
             open_log_file,
             add_plugin,
             uninstall_plugin,
             set_plugin_enabled,
             set_plugin_priority,
+            get_subscriptions,
+            add_subscription,
+            update_subscription,
+            remove_subscription,
+            check_subscriptions,
+            get_mirror_syncs,
+            get_archived_items,
+            add_mirror_sync,
+            update_mirror_sync,
+            remove_mirror_sync,
+            sync_mirrors,
+            query_download_history,
+            get_live_recordings,
+            start_live_recording,
+            stop_live_recording,
         ])
         .events(tauri_specta::collect_events![
             LogEvent,
@@ -123,19 +171,45 @@ pub fn run() {
                 app_data_dir.display()
             ))?;
 
-            let config = RwLock::new(Config::new(app.handle())?);
-            app.manage(config);
+            // 下载核心直接使用tokio::spawn，需要进入Tauri的tokio运行时
+            let runtime_handle = tauri::async_runtime::handle();
+            let _runtime_guard = runtime_handle.inner().enter();
 
-            let bili_client = BiliClient::new(app.handle().clone());
-            app.manage(bili_client);
+            let app_version = app.package_info().version.to_string();
+            let context = AppContext::new(app_data_dir, app_version, app.handle().clone());
+            app.manage(context.clone());
 
-            let download_manager = DownloadManager::new(app.handle().clone());
-            app.manage(download_manager);
+            let config = Config::new(&context)?;
+            context.manage_config(config);
 
-            logger::init(app.handle())?;
+            let bili_client = BiliClient::new(context.clone());
+            context.manage_bili_client(bili_client);
 
-            let plugin_manager = PluginManager::new(app.handle())?;
-            app.manage(plugin_manager);
+            let download_manager = DownloadManager::new(context.clone());
+            context.manage_download_manager(download_manager);
+
+            let subscription_manager = SubscriptionManager::new(context.clone())?;
+            context.manage_subscription_manager(subscription_manager);
+
+            let mirror_sync_manager = MirrorSyncManager::new(context.clone())?;
+            context.manage_mirror_sync_manager(mirror_sync_manager);
+
+            let live_recorder_manager = LiveRecorderManager::new(context.clone());
+            context.manage_live_recorder_manager(live_recorder_manager);
+
+            logger::init(&context, true)?;
+
+            let plugin_manager = PluginManager::new(&context)?;
+            context.manage_plugin_manager(plugin_manager);
+
+            let remote_api_server = RemoteApiServer::new(context.clone());
+            // 远程API启动失败不影响GUI的使用
+            if let Err(err) = remote_api_server.reload() {
+                let err_title = "启动远程API失败";
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+            }
+            context.manage_remote_api_server(remote_api_server);
 
             Ok(())
         })
@@ -0,0 +1,623 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use reqwest::Url;
+
+use crate::{
+    extensions::EyreReportToMessage,
+    types::{
+        get_bangumi_info_params::GetBangumiInfoParams, get_cheese_info_params::GetCheeseInfoParams,
+        get_fav_info_params::GetFavInfoParams, get_normal_info_params::GetNormalInfoParams,
+        get_user_video_info_params::GetUserVideoInfoParams, parsed_target::ParsedTarget,
+        search_params::SearchParams,
+    },
+};
+
+/// 匹配哔哩哔哩的链接，协议可以省略，遇到空白或中文等非ASCII字符时结束
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:https?://)?(?:[a-z0-9-]+\.)*(?:bilibili\.com|b23\.tv|bili2233\.cn)(?:/[!-~]*)?",
+    )
+    .unwrap()
+});
+
+/// 链接末尾的这些字符通常是句子里的标点，不属于链接
+const TRAILING_PUNCTUATION: &[char] =
+    &['.', ',', ';', ':', '!', '?', ')', ']', '}', '>', '"', '\''];
+
+/// 嵌在其他文字中或在分享文案标题中的`av` `ep` `ss`等ID至少要有这么多位数字，
+/// 避免把`【EP1】`这样的标题文字当成ID
+const MIN_EMBEDDED_ID_DIGITS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Link {
+    Target(ParsedTarget),
+    /// `b23.tv`短链接，需要请求一次才知道指向哪里
+    ShortLink(String),
+}
+
+/// 从文本中按出现顺序提取所有链接和ID，不发送请求
+///
+/// 支持分享文案、多行文本，以及不带链接的`BV` `av` `ep` `ss` `uid` `fid`号
+pub fn extract_links(text: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut last_end = 0;
+
+    for url_match in URL_REGEX.find_iter(text) {
+        links.extend(extract_ids(&text[last_end..url_match.start()]));
+        last_end = url_match.end();
+
+        // 分享文案里链接前面经常紧跟中文，所以不能用`\b`，只排除`notbilibili.com`这样的域名
+        let is_part_of_domain = text[..url_match.start()]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+        if is_part_of_domain {
+            continue;
+        }
+
+        let url = url_match.as_str().trim_end_matches(TRAILING_PUNCTUATION);
+        if let Some(link) = parse_url(url) {
+            links.push(link);
+        }
+    }
+    links.extend(extract_ids(&text[last_end..]));
+
+    links
+}
+
+/// 解析文本中的所有搜索目标，短链接会通过`resolve_short_link`获取真实链接
+///
+/// 重复的目标只保留第一个，无法解析的短链接会记录日志后跳过
+pub async fn parse_text<F, Fut>(text: &str, resolve_short_link: F) -> Vec<ParsedTarget>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = eyre::Result<String>>,
+{
+    let mut targets: Vec<ParsedTarget> = Vec::new();
+
+    for link in extract_links(text) {
+        let target = match link {
+            Link::Target(target) => target,
+            Link::ShortLink(short_link) => {
+                let resolved = match resolve_short_link(short_link.clone()).await {
+                    Ok(resolved) => resolved,
+                    Err(err) => {
+                        let err_title = format!("解析短链接`{short_link}`失败");
+                        let message = err.to_message();
+                        tracing::error!(err_title, message);
+                        continue;
+                    }
+                };
+                // 重定向后仍然是短链接说明短链接已经失效，不再继续解析，避免循环
+                let Some(Link::Target(target)) = parse_url(&resolved) else {
+                    let err_title = format!("解析短链接`{short_link}`失败");
+                    let message = format!("无法识别短链接指向的`{resolved}`");
+                    tracing::error!(err_title, message);
+                    continue;
+                };
+                target
+            }
+        };
+
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+
+    targets
+}
+
+/// 解析单个链接，不是视频、番剧、课程、UP主空间或收藏夹的链接返回`None`
+pub fn parse_url(url: &str) -> Option<Link> {
+    let lowercase_url = url.to_ascii_lowercase();
+    let has_scheme = lowercase_url.starts_with("http://") || lowercase_url.starts_with("https://");
+    let url = if has_scheme {
+        Url::parse(url).ok()?
+    } else {
+        Url::parse(&format!("https://{url}")).ok()?
+    };
+
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    if is_short_link_host(host) {
+        return (!segments.is_empty()).then(|| Link::ShortLink(url.to_string()));
+    }
+    if host != "bilibili.com" && !host.ends_with(".bilibili.com") {
+        return None;
+    }
+
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+
+    let target = if host == "space.bilibili.com" {
+        parse_space_url(&segments, query("fid"))?
+    } else {
+        parse_main_site_url(&segments, query("bvid").as_deref(), query("p").as_deref())?
+    };
+
+    Some(Link::Target(target))
+}
+
+fn is_short_link_host(host: &str) -> bool {
+    ["b23.tv", "bili2233.cn"]
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+}
+
+/// `space.bilibili.com/{mid}`和`space.bilibili.com/{mid}/favlist?fid={id}`
+fn parse_space_url(segments: &[&str], fid: Option<String>) -> Option<ParsedTarget> {
+    let mid: i64 = segments.first()?.parse().ok()?;
+
+    let params = match segments.get(1).copied() {
+        Some("favlist" | "fav") => {
+            // 没有fid时是默认收藏夹，链接里没有它的ID
+            let media_list_id = fid?.parse().ok()?;
+            SearchParams::Fav(GetFavInfoParams {
+                media_list_id,
+                pn: 1,
+            })
+        }
+        // 合集、频道等不是投稿列表
+        Some("lists" | "channel" | "collectiondetail" | "seriesdetail") => return None,
+        _ => SearchParams::UserVideo(GetUserVideoInfoParams { mid, pn: 1 }),
+    };
+
+    Some(ParsedTarget { params, page: None })
+}
+
+/// `www.bilibili.com` `m.bilibili.com`上的视频、番剧、课程和收藏夹链接
+fn parse_main_site_url(
+    segments: &[&str],
+    bvid: Option<&str>,
+    page: Option<&str>,
+) -> Option<ParsedTarget> {
+    let page = page
+        .and_then(|page| page.parse().ok())
+        .filter(|page: &i64| *page > 0);
+
+    // 稍后再看、活动页等链接会把视频放在`bvid`参数里
+    let from_bvid_query = || {
+        let params = parse_video_id(bvid?)?;
+        Some(ParsedTarget { params, page })
+    };
+
+    let params = match segments {
+        ["video", id, ..] => {
+            let params = parse_video_id(id)?;
+            return Some(ParsedTarget { params, page });
+        }
+        ["bangumi", "play", id, ..] => match parse_prefixed_id(id)? {
+            ("ep", id) => SearchParams::Bangumi(GetBangumiInfoParams::EpId(id)),
+            ("ss", id) => SearchParams::Bangumi(GetBangumiInfoParams::SeasonId(id)),
+            _ => return None,
+        },
+        ["cheese", "play", id, ..] => match parse_prefixed_id(id)? {
+            ("ep", id) => SearchParams::Cheese(GetCheeseInfoParams::EpId(id)),
+            ("ss", id) => SearchParams::Cheese(GetCheeseInfoParams::SeasonId(id)),
+            _ => return None,
+        },
+        ["medialist", "detail", id, ..] | ["list", id, ..] => match parse_prefixed_id(id) {
+            Some(("ml", media_list_id)) => SearchParams::Fav(GetFavInfoParams {
+                media_list_id,
+                pn: 1,
+            }),
+            _ => return from_bvid_query(),
+        },
+        _ => return from_bvid_query(),
+    };
+
+    Some(ParsedTarget { params, page: None })
+}
+
+/// 从不在链接里的文本中提取ID，例如`BV1GJ411x7h7` `av170001` `ep123` `uid456`
+///
+/// 用空白分隔、且不在分享文案标题`【】`中的ID可以是任意长度，
+/// 其他ID只有完整的BV号，或者数字至少有`MIN_EMBEDDED_ID_DIGITS`位时才算
+fn extract_ids(text: &str) -> Vec<Link> {
+    let mut params = Vec::new();
+    let mut title_depth = 0_usize;
+    for word in text.split_whitespace() {
+        let in_title = title_depth > 0;
+        for c in word.chars() {
+            match c {
+                '【' => title_depth += 1,
+                '】' => title_depth = title_depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let standalone = word
+            .trim_end_matches(|c: char| c.is_ascii_punctuation() || "，。、；：！？".contains(c));
+        if !in_title && let Some(standalone_params) = parse_id(standalone) {
+            params.push(standalone_params);
+            continue;
+        }
+
+        let embedded_params = word
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|token| {
+                let digits = token.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+                digits.len() >= MIN_EMBEDDED_ID_DIGITS
+            })
+            .filter_map(parse_id);
+        params.extend(embedded_params);
+    }
+
+    params
+        .into_iter()
+        .map(|params| Link::Target(ParsedTarget { params, page: None }))
+        .collect()
+}
+
+/// 解析不带链接的`BV` `av` `ep` `ss` `uid` `fid`号
+fn parse_id(id: &str) -> Option<SearchParams> {
+    if let Some(params) = parse_video_id(id) {
+        return Some(params);
+    }
+
+    let params = match parse_prefixed_id(id)? {
+        ("ep", id) => SearchParams::Bangumi(GetBangumiInfoParams::EpId(id)),
+        ("ss", id) => SearchParams::Bangumi(GetBangumiInfoParams::SeasonId(id)),
+        ("uid", mid) => SearchParams::UserVideo(GetUserVideoInfoParams { mid, pn: 1 }),
+        ("fid", media_list_id) => SearchParams::Fav(GetFavInfoParams {
+            media_list_id,
+            pn: 1,
+        }),
+        _ => return None,
+    };
+    Some(params)
+}
+
+/// 解析`BV1GJ411x7h7`或`av170001`
+fn parse_video_id(id: &str) -> Option<SearchParams> {
+    if id.len() == 12 && id.is_char_boundary(2) && id[..2].eq_ignore_ascii_case("bv") {
+        let tail = &id[2..];
+        if !tail.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        // BV号除了前缀以外区分大小写
+        return Some(SearchParams::Normal(GetNormalInfoParams::Bvid(format!(
+            "BV{tail}"
+        ))));
+    }
+
+    match parse_prefixed_id(id)? {
+        ("av", aid) => Some(SearchParams::Normal(GetNormalInfoParams::Aid(aid))),
+        _ => None,
+    }
+}
+
+/// 把`ep123`拆成小写的前缀和数字，前缀只能是字母，数字必须为正数
+fn parse_prefixed_id(id: &str) -> Option<(&'static str, i64)> {
+    const PREFIXES: [&str; 6] = ["av", "ep", "ss", "ml", "uid", "fid"];
+
+    let digits_start = id.find(|c: char| c.is_ascii_digit())?;
+    let (prefix, digits) = id.split_at(digits_start);
+    let prefix = PREFIXES
+        .into_iter()
+        .find(|p| p.eq_ignore_ascii_case(prefix))?;
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let id: i64 = digits.parse().ok()?;
+
+    (id > 0).then_some((prefix, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bvid(bvid: &str) -> SearchParams {
+        SearchParams::Normal(GetNormalInfoParams::Bvid(bvid.to_string()))
+    }
+
+    fn target(params: SearchParams) -> Link {
+        Link::Target(ParsedTarget { params, page: None })
+    }
+
+    fn target_with_page(params: SearchParams, page: i64) -> Link {
+        Link::Target(ParsedTarget {
+            params,
+            page: Some(page),
+        })
+    }
+
+    fn parse_one(url: &str) -> Option<Link> {
+        let links = extract_links(url);
+        assert!(links.len() <= 1, "`{url}`解析出了多个结果: {links:?}");
+        links.into_iter().next()
+    }
+
+    #[test]
+    fn video_urls() {
+        let cases = [
+            ("https://www.bilibili.com/video/BV1GJ411x7h7", None),
+            ("https://www.bilibili.com/video/BV1GJ411x7h7/", None),
+            ("http://www.bilibili.com/video/BV1GJ411x7h7?p=3", Some(3)),
+            (
+                "www.bilibili.com/video/BV1GJ411x7h7/?spm_id_from=333&p=12",
+                Some(12),
+            ),
+            ("bilibili.com/video/BV1GJ411x7h7?p=0", None),
+            ("https://m.bilibili.com/video/BV1GJ411x7h7?p=abc", None),
+            ("HTTPS://WWW.BILIBILI.COM/video/bv1GJ411x7h7", None),
+        ];
+        for (url, page) in cases {
+            let expected = match page {
+                Some(page) => target_with_page(bvid("BV1GJ411x7h7"), page),
+                None => target(bvid("BV1GJ411x7h7")),
+            };
+            assert_eq!(parse_one(url), Some(expected), "{url}");
+        }
+
+        assert_eq!(
+            parse_one("https://www.bilibili.com/video/av170001?p=2"),
+            Some(target_with_page(
+                SearchParams::Normal(GetNormalInfoParams::Aid(170_001)),
+                2
+            ))
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/list/watchlater?bvid=BV1GJ411x7h7&oid=1"),
+            Some(target(bvid("BV1GJ411x7h7")))
+        );
+        assert_eq!(parse_one("https://www.bilibili.com/video/BV1GJ4"), None);
+        assert_eq!(parse_one("https://www.bilibili.com/video/"), None);
+    }
+
+    #[test]
+    fn bangumi_and_cheese_urls() {
+        assert_eq!(
+            parse_one("https://www.bilibili.com/bangumi/play/ep508404?from_spmid=666.25"),
+            Some(target(SearchParams::Bangumi(GetBangumiInfoParams::EpId(
+                508_404
+            ))))
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/bangumi/play/ss39462/"),
+            Some(target(SearchParams::Bangumi(
+                GetBangumiInfoParams::SeasonId(39_462)
+            )))
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/cheese/play/ep1234"),
+            Some(target(SearchParams::Cheese(GetCheeseInfoParams::EpId(
+                1234
+            ))))
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/cheese/play/ss567?query_from=0"),
+            Some(target(SearchParams::Cheese(GetCheeseInfoParams::SeasonId(
+                567
+            ))))
+        );
+        // 番剧的`md`号和`p`参数都不支持
+        assert_eq!(
+            parse_one("https://www.bilibili.com/bangumi/media/md28229233"),
+            None
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/bangumi/play/ep1?p=2"),
+            Some(target(SearchParams::Bangumi(GetBangumiInfoParams::EpId(1))))
+        );
+    }
+
+    #[test]
+    fn space_and_favlist_urls() {
+        let user_video = target(SearchParams::UserVideo(GetUserVideoInfoParams {
+            mid: 8_047_632,
+            pn: 1,
+        }));
+        assert_eq!(
+            parse_one("https://space.bilibili.com/8047632"),
+            Some(user_video.clone())
+        );
+        assert_eq!(
+            parse_one("https://space.bilibili.com/8047632/video?tid=0"),
+            Some(user_video.clone())
+        );
+        assert_eq!(
+            parse_one("space.bilibili.com/8047632/upload/video"),
+            Some(user_video)
+        );
+
+        let fav = target(SearchParams::Fav(GetFavInfoParams {
+            media_list_id: 1_052_622_027,
+            pn: 1,
+        }));
+        assert_eq!(
+            parse_one("https://space.bilibili.com/8047632/favlist?fid=1052622027&ftype=create"),
+            Some(fav.clone())
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/medialist/detail/ml1052622027"),
+            Some(fav.clone())
+        );
+        assert_eq!(
+            parse_one("https://www.bilibili.com/list/ml1052622027?oid=1"),
+            Some(fav)
+        );
+
+        assert_eq!(
+            parse_one("https://space.bilibili.com/8047632/favlist"),
+            None
+        );
+        assert_eq!(
+            parse_one("https://space.bilibili.com/8047632/lists/123?type=season"),
+            None
+        );
+        assert_eq!(parse_one("https://space.bilibili.com/abc"), None);
+    }
+
+    #[test]
+    fn short_links_and_unrelated_urls() {
+        assert_eq!(
+            parse_one("https://b23.tv/xYz123A"),
+            Some(Link::ShortLink("https://b23.tv/xYz123A".to_string()))
+        );
+        assert_eq!(
+            parse_one("b23.tv/xYz123A"),
+            Some(Link::ShortLink("https://b23.tv/xYz123A".to_string()))
+        );
+        assert_eq!(
+            parse_one("https://bili2233.cn/abc"),
+            Some(Link::ShortLink("https://bili2233.cn/abc".to_string()))
+        );
+        assert_eq!(parse_one("https://b23.tv/"), None);
+
+        assert_eq!(parse_one("https://live.bilibili.com/21452505"), None);
+        assert_eq!(
+            parse_one("https://notbilibili.com/video/BV1GJ411x7h7"),
+            None
+        );
+        assert_eq!(parse_one("https://www.bilibili.com"), None);
+    }
+
+    #[test]
+    fn share_messages() {
+        let text =
+            "【【官方 MV】Never Gonna Give You Up - Rick Astley-哔哩哔哩】 https://b23.tv/aBc1234";
+        assert_eq!(
+            extract_links(text),
+            vec![Link::ShortLink("https://b23.tv/aBc1234".to_string())]
+        );
+
+        // 链接后面紧跟中文和标点
+        let text = "快看这个https://www.bilibili.com/video/BV1GJ411x7h7?p=2，太好笑了。";
+        assert_eq!(
+            extract_links(text),
+            vec![target_with_page(bvid("BV1GJ411x7h7"), 2)]
+        );
+
+        let text = "(see https://www.bilibili.com/bangumi/play/ss39462).";
+        assert_eq!(
+            extract_links(text),
+            vec![target(SearchParams::Bangumi(
+                GetBangumiInfoParams::SeasonId(39_462)
+            ))]
+        );
+    }
+
+    #[test]
+    fn bare_ids() {
+        let text =
+            "BV1GJ411x7h7 av170001\nep508404、ss39462 uid8047632 fid1052622027 视频bv1xx411c7mD";
+        assert_eq!(
+            extract_links(text),
+            vec![
+                target(bvid("BV1GJ411x7h7")),
+                target(SearchParams::Normal(GetNormalInfoParams::Aid(170_001))),
+                target(SearchParams::Bangumi(GetBangumiInfoParams::EpId(508_404))),
+                target(SearchParams::Bangumi(GetBangumiInfoParams::SeasonId(39462))),
+                target(SearchParams::UserVideo(GetUserVideoInfoParams {
+                    mid: 8_047_632,
+                    pn: 1,
+                })),
+                target(SearchParams::Fav(GetFavInfoParams {
+                    media_list_id: 1_052_622_027,
+                    pn: 1,
+                })),
+                target(bvid("BV1xx411c7mD")),
+            ]
+        );
+
+        // 普通单词、长度不对的BV号、0和不是纯数字的ID都不算
+        let text = "class1 pass2 BV1GJ411x7h av0 ep12a ssabc md28229233 ml123";
+        assert_eq!(extract_links(text), vec![]);
+    }
+
+    #[test]
+    fn share_message_titles_are_not_ids() {
+        let text = "【【EP1】开箱 av1 对比ss2 第2期-哔哩哔哩】 https://b23.tv/aBc1234";
+        assert_eq!(
+            extract_links(text),
+            vec![Link::ShortLink("https://b23.tv/aBc1234".to_string())]
+        );
+
+        // 标题里完整的BV号和足够长的ID仍然会被提取，标题外单独的短ID也会
+        let text = "【BV1GJ411x7h7合集 EP12-哔哩哔哩】 https://b23.tv/aBc1234\nep1";
+        assert_eq!(
+            extract_links(text),
+            vec![
+                target(bvid("BV1GJ411x7h7")),
+                Link::ShortLink("https://b23.tv/aBc1234".to_string()),
+                target(SearchParams::Bangumi(GetBangumiInfoParams::EpId(1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_lines_keep_order() {
+        let text = "
+            ep1
+            https://www.bilibili.com/video/BV1GJ411x7h7?p=3 BV1xx411c7mD
+            https://b23.tv/short
+            https://space.bilibili.com/2/favlist?fid=3
+        ";
+        assert_eq!(
+            extract_links(text),
+            vec![
+                target(SearchParams::Bangumi(GetBangumiInfoParams::EpId(1))),
+                target_with_page(bvid("BV1GJ411x7h7"), 3),
+                target(bvid("BV1xx411c7mD")),
+                Link::ShortLink("https://b23.tv/short".to_string()),
+                target(SearchParams::Fav(GetFavInfoParams {
+                    media_list_id: 3,
+                    pn: 1,
+                })),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn parse_text_resolves_short_links_and_deduplicates() {
+        let text = "
+            https://b23.tv/ok
+            BV1GJ411x7h7
+            https://b23.tv/dup
+            https://b23.tv/failed
+            https://b23.tv/expired
+            https://www.bilibili.com/video/BV1GJ411x7h7?p=2
+        ";
+        let targets = parse_text(text, |short_link| async move {
+            match short_link.as_str() {
+                "https://b23.tv/ok" => {
+                    Ok("https://www.bilibili.com/bangumi/play/ep508404?share_source=copy".into())
+                }
+                "https://b23.tv/dup" => Ok("https://m.bilibili.com/video/BV1GJ411x7h7".into()),
+                // 失效的短链接会重定向到自己
+                "https://b23.tv/expired" => Ok("https://b23.tv/expired".into()),
+                _ => Err(eyre::eyre!("请求失败")),
+            }
+        })
+        .await;
+
+        assert_eq!(
+            targets,
+            vec![
+                ParsedTarget {
+                    params: SearchParams::Bangumi(GetBangumiInfoParams::EpId(508_404)),
+                    page: None,
+                },
+                ParsedTarget {
+                    params: bvid("BV1GJ411x7h7"),
+                    page: None,
+                },
+                // 分P不同，不算重复
+                ParsedTarget {
+                    params: bvid("BV1GJ411x7h7"),
+                    page: Some(2),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod flv;
+pub mod hls;
+pub mod live_danmaku;
+pub mod live_recorder_manager;
+pub mod live_recording_task;
+pub mod live_source;
@@ -0,0 +1,302 @@
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{Buf, Bytes, BytesMut};
+use eyre::eyre;
+
+const FLV_SIGNATURE: &[u8] = b"FLV";
+/// FLV头的最小长度，后面还跟着4字节的`PreviousTagSize0`
+const FLV_HEADER_LEN: usize = 9;
+const TAG_HEADER_LEN: usize = 11;
+const PREVIOUS_TAG_SIZE_LEN: usize = 4;
+
+pub const TAG_TYPE_AUDIO: u8 = 8;
+pub const TAG_TYPE_VIDEO: u8 = 9;
+pub const TAG_TYPE_SCRIPT: u8 = 18;
+
+/// 扩展格式中表示序列头的`PacketType`
+const PACKET_TYPE_SEQUENCE_START: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlvTag {
+    pub tag_type: u8,
+    pub timestamp: u32,
+    pub data: Bytes,
+}
+
+impl FlvTag {
+    pub fn is_video_keyframe(&self) -> bool {
+        // 高4位是帧类型，扩展格式(Enhanced RTMP)时最高位是扩展标志
+        self.tag_type == TAG_TYPE_VIDEO
+            && self
+                .data
+                .first()
+                .is_some_and(|&byte| (byte >> 4) & 0x07 == 1)
+    }
+
+    /// 是否是解码必需的序列头，例如AVC/HEVC的配置或AAC的音频配置
+    pub fn is_sequence_header(&self) -> bool {
+        let Some(&first) = self.data.first() else {
+            return false;
+        };
+
+        match self.tag_type {
+            TAG_TYPE_VIDEO if first & 0x80 != 0 => first & 0x0F == PACKET_TYPE_SEQUENCE_START,
+            TAG_TYPE_VIDEO => matches!(first & 0x0F, 7 | 12) && self.data.get(1) == Some(&0),
+            TAG_TYPE_AUDIO => first >> 4 == 10 && self.data.get(1) == Some(&0),
+            _ => false,
+        }
+    }
+
+    /// 以`timestamp`作为时间戳编码为标签，包括后面的`PreviousTagSize`
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn encode(&self, timestamp: u32, buf: &mut Vec<u8>) {
+        let data_size = u32::try_from(self.data.len()).unwrap_or(u32::MAX) & 0x00FF_FFFF;
+
+        let mut header = [0u8; TAG_HEADER_LEN];
+        header[0] = self.tag_type;
+        BigEndian::write_u24(&mut header[1..4], data_size);
+        BigEndian::write_u24(&mut header[4..7], timestamp & 0x00FF_FFFF);
+        header[7] = (timestamp >> 24) as u8;
+        // StreamID始终为0
+
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&self.data);
+        let mut previous_tag_size = [0u8; PREVIOUS_TAG_SIZE_LEN];
+        BigEndian::write_u32(&mut previous_tag_size, TAG_HEADER_LEN as u32 + data_size);
+        buf.extend_from_slice(&previous_tag_size);
+    }
+}
+
+/// 从分块到达的字节流中解析出FLV标签
+#[derive(Default)]
+pub struct FlvDemuxer {
+    buf: BytesMut,
+    header_skipped: bool,
+}
+
+impl FlvDemuxer {
+    pub fn push(&mut self, data: &[u8]) -> eyre::Result<Vec<FlvTag>> {
+        self.buf.extend_from_slice(data);
+
+        if !self.header_skipped {
+            if self.buf.len() < FLV_HEADER_LEN {
+                return Ok(Vec::new());
+            }
+            if !self.buf.starts_with(FLV_SIGNATURE) {
+                return Err(eyre!("直播流不是FLV格式"));
+            }
+            let header_len = BigEndian::read_u32(&self.buf[5..9]) as usize;
+            if self.buf.len() < header_len + PREVIOUS_TAG_SIZE_LEN {
+                return Ok(Vec::new());
+            }
+            self.buf.advance(header_len + PREVIOUS_TAG_SIZE_LEN);
+            self.header_skipped = true;
+        }
+
+        let mut tags = Vec::new();
+        while self.buf.len() >= TAG_HEADER_LEN {
+            let data_size = BigEndian::read_u24(&self.buf[1..4]) as usize;
+            let tag_len = TAG_HEADER_LEN + data_size + PREVIOUS_TAG_SIZE_LEN;
+            if self.buf.len() < tag_len {
+                break;
+            }
+
+            let mut tag_bytes = self.buf.split_to(tag_len).freeze();
+            let tag_type = tag_bytes[0] & 0x1F;
+            let timestamp = BigEndian::read_u24(&tag_bytes[4..7]) | (u32::from(tag_bytes[7]) << 24);
+            tag_bytes.advance(TAG_HEADER_LEN);
+            let data = tag_bytes.split_to(data_size);
+
+            tags.push(FlvTag {
+                tag_type,
+                timestamp,
+                data,
+            });
+        }
+
+        Ok(tags)
+    }
+}
+
+/// 写入文件的FLV数据
+pub struct FlvOutput {
+    /// 为`true`时需要先换一个新文件，再写入`bytes`
+    pub new_file: bool,
+    pub bytes: Vec<u8>,
+}
+
+/// 决定每个标签写到哪个文件
+///
+/// 只在视频关键帧处分段，每个文件开头都会重新写入FLV头、`onMetaData`和序列头，
+/// 时间戳从0开始，这样每个文件都能单独播放
+#[derive(Default)]
+pub struct FlvSegmenter {
+    script_tag: Option<FlvTag>,
+    video_header: Option<FlvTag>,
+    audio_header: Option<FlvTag>,
+    /// 当前文件第一个标签的原始时间戳，还没开始写文件时为`None`
+    base_timestamp: Option<u32>,
+}
+
+impl FlvSegmenter {
+    /// `split`为`true`时会在下一个可以分段的位置换新文件
+    ///
+    /// 返回`None`表示这个标签不需要写入
+    pub fn process(&mut self, tag: &FlvTag, split: bool) -> Option<FlvOutput> {
+        if tag.tag_type == TAG_TYPE_SCRIPT || tag.is_sequence_header() {
+            let slot = match tag.tag_type {
+                TAG_TYPE_SCRIPT => &mut self.script_tag,
+                TAG_TYPE_VIDEO => &mut self.video_header,
+                _ => &mut self.audio_header,
+            };
+            *slot = Some(tag.clone());
+            // 还没开始写文件时，会在文件开头统一写入
+            let base_timestamp = self.base_timestamp?;
+            return Some(FlvOutput {
+                new_file: false,
+                bytes: Self::encode_tag(tag, base_timestamp),
+            });
+        }
+
+        // 有视频时只能从关键帧开始，否则开头的画面无法解码
+        let can_start_file = self.video_header.is_none() || tag.is_video_keyframe();
+
+        match self.base_timestamp {
+            Some(base_timestamp) if !(split && can_start_file) => Some(FlvOutput {
+                new_file: false,
+                bytes: Self::encode_tag(tag, base_timestamp),
+            }),
+            _ if can_start_file => Some(FlvOutput {
+                new_file: true,
+                bytes: self.start_file(tag),
+            }),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn start_file(&mut self, first_tag: &FlvTag) -> Vec<u8> {
+        self.base_timestamp = Some(first_tag.timestamp);
+
+        let mut flags = 0;
+        if self.audio_header.is_some() || first_tag.tag_type == TAG_TYPE_AUDIO {
+            flags |= 0x04;
+        }
+        if self.video_header.is_some() || first_tag.tag_type == TAG_TYPE_VIDEO {
+            flags |= 0x01;
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(FLV_SIGNATURE);
+        bytes.extend_from_slice(&[1, flags, 0, 0, 0, FLV_HEADER_LEN as u8]);
+        bytes.extend_from_slice(&[0; PREVIOUS_TAG_SIZE_LEN]);
+
+        let header_tags = [&self.script_tag, &self.video_header, &self.audio_header];
+        for header_tag in header_tags.into_iter().flatten() {
+            header_tag.encode(0, &mut bytes);
+        }
+        first_tag.encode(0, &mut bytes);
+
+        bytes
+    }
+
+    fn encode_tag(tag: &FlvTag, base_timestamp: u32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TAG_HEADER_LEN + tag.data.len() + 4);
+        // 时间戳回退时不能变成负数
+        tag.encode(tag.timestamp.saturating_sub(base_timestamp), &mut bytes);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video_tag(timestamp: u32, keyframe: bool) -> FlvTag {
+        let frame_type = if keyframe { 0x10 } else { 0x20 };
+        FlvTag {
+            tag_type: TAG_TYPE_VIDEO,
+            timestamp,
+            data: Bytes::from(vec![frame_type | 7, 1, 0, 0, 0, 0xAB]),
+        }
+    }
+
+    fn header_tags() -> Vec<FlvTag> {
+        vec![
+            FlvTag {
+                tag_type: TAG_TYPE_SCRIPT,
+                timestamp: 0,
+                data: Bytes::from_static(b"onMetaData"),
+            },
+            FlvTag {
+                tag_type: TAG_TYPE_VIDEO,
+                timestamp: 0,
+                data: Bytes::from_static(&[0x17, 0, 0, 0, 0, 1]),
+            },
+            FlvTag {
+                tag_type: TAG_TYPE_AUDIO,
+                timestamp: 0,
+                data: Bytes::from_static(&[0xAF, 0, 0x12, 0x10]),
+            },
+        ]
+    }
+
+    fn encode_stream(tags: &[FlvTag]) -> Vec<u8> {
+        let mut bytes = b"FLV\x01\x05\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        for tag in tags {
+            tag.encode(tag.timestamp, &mut bytes);
+        }
+        bytes
+    }
+
+    #[test]
+    fn demuxer_handles_tags_split_across_chunks() {
+        let mut tags = header_tags();
+        tags.push(video_tag(0x0100_0010, true));
+        tags.push(video_tag(0x0100_0050, false));
+        let stream = encode_stream(&tags);
+
+        let mut demuxer = FlvDemuxer::default();
+        let mut parsed = Vec::new();
+        for chunk in stream.chunks(7) {
+            parsed.extend(demuxer.push(chunk).unwrap());
+        }
+
+        assert_eq!(parsed, tags);
+    }
+
+    #[test]
+    fn demuxer_rejects_non_flv() {
+        let mut demuxer = FlvDemuxer::default();
+        assert!(demuxer.push(b"#EXTM3U\n#EXT-X-VERSION:3").is_err());
+    }
+
+    #[test]
+    fn segmenter_splits_at_keyframe_with_headers_and_rebased_timestamps() {
+        let mut segmenter = FlvSegmenter::default();
+        for tag in header_tags() {
+            assert!(segmenter.process(&tag, false).is_none());
+        }
+        // 第一个关键帧之前的帧无法解码，直接丢弃
+        assert!(segmenter.process(&video_tag(900, false), false).is_none());
+
+        let first = segmenter.process(&video_tag(1000, true), false).unwrap();
+        assert!(first.new_file);
+
+        // 要求分段后，非关键帧仍然写入当前文件
+        let inter = segmenter.process(&video_tag(1040, false), true).unwrap();
+        assert!(!inter.new_file);
+
+        let second = segmenter.process(&video_tag(5000, true), true).unwrap();
+        assert!(second.new_file);
+
+        let mut demuxer = FlvDemuxer::default();
+        let tags = demuxer.push(&second.bytes).unwrap();
+        let mut expected = header_tags();
+        expected.push(video_tag(0, true));
+        assert_eq!(tags, expected);
+
+        let next = segmenter.process(&video_tag(5040, false), false).unwrap();
+        let tags = demuxer.push(&next.bytes).unwrap();
+        assert_eq!(tags, vec![video_tag(40, false)]);
+    }
+}
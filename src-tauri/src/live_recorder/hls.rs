@@ -0,0 +1,138 @@
+use eyre::{WrapErr, eyre};
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct HlsPlaylist {
+    /// 主播放列表中第一个子播放列表的地址，媒体播放列表为`None`
+    pub variant_uri: Option<String>,
+    pub target_duration: f64,
+    /// fMP4的初始化分片
+    pub map_uri: Option<String>,
+    pub segments: Vec<HlsSegment>,
+    /// 有`#EXT-X-ENDLIST`，说明直播已经结束
+    pub ended: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HlsSegment {
+    pub sequence: u64,
+    pub uri: String,
+    pub duration: f64,
+}
+
+impl HlsPlaylist {
+    /// 只解析录制需要的标签，其余的忽略
+    pub fn parse(text: &str) -> eyre::Result<HlsPlaylist> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("#EXTM3U") {
+            return Err(eyre!("不是m3u8播放列表: {text}"));
+        }
+
+        let mut playlist = HlsPlaylist::default();
+        let mut sequence = 0;
+        let mut pending_duration = None;
+        let mut is_variant = false;
+
+        for line in lines {
+            if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+                sequence = value
+                    .parse()
+                    .wrap_err(format!("解析`{line}`中的序号失败"))?;
+            } else if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                playlist.target_duration = value
+                    .parse()
+                    .wrap_err(format!("解析`{line}`中的时长失败"))?;
+            } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+                playlist.map_uri = get_attribute(value, "URI");
+            } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+                let duration = value.split(',').next().unwrap_or_default();
+                pending_duration = Some(duration.parse().unwrap_or_default());
+            } else if line.starts_with("#EXT-X-STREAM-INF:") {
+                is_variant = true;
+            } else if line == "#EXT-X-ENDLIST" {
+                playlist.ended = true;
+            } else if !line.starts_with('#') {
+                if is_variant {
+                    playlist.variant_uri = Some(line.to_string());
+                    break;
+                }
+                if let Some(duration) = pending_duration.take() {
+                    playlist.segments.push(HlsSegment {
+                        sequence,
+                        uri: line.to_string(),
+                        duration,
+                    });
+                    sequence += 1;
+                }
+            }
+        }
+
+        Ok(playlist)
+    }
+}
+
+/// 从`URI="h1.m4s",BYTERANGE="..."`这样的属性列表中取出属性值
+fn get_attribute(attributes: &str, name: &str) -> Option<String> {
+    let start = attributes.find(&format!("{name}="))? + name.len() + 1;
+    let rest = &attributes[start..];
+    let value = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => rest.split(',').next()?,
+    };
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn parse_media_playlist() {
+        let text = "#EXTM3U
+#EXT-X-VERSION:7
+#EXT-X-TARGETDURATION:1
+#EXT-X-MEDIA-SEQUENCE:4120
+#EXT-X-MAP:URI=\"h1700000000.m4s\"
+#EXTINF:1.00,a|1
+4120.m4s
+#EXTINF:0.98,a|1
+4121.m4s?trid=x
+";
+        let playlist = HlsPlaylist::parse(text).unwrap();
+
+        assert_eq!(playlist.target_duration, 1.0);
+        assert_eq!(playlist.map_uri.as_deref(), Some("h1700000000.m4s"));
+        assert_eq!(
+            playlist.segments,
+            vec![
+                HlsSegment {
+                    sequence: 4120,
+                    uri: "4120.m4s".to_string(),
+                    duration: 1.0,
+                },
+                HlsSegment {
+                    sequence: 4121,
+                    uri: "4121.m4s?trid=x".to_string(),
+                    duration: 0.98,
+                },
+            ]
+        );
+        assert!(!playlist.ended);
+        assert!(playlist.variant_uri.is_none());
+    }
+
+    #[test]
+    fn parse_master_playlist_and_endlist() {
+        let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\nsub/index.m3u8\n";
+        let playlist = HlsPlaylist::parse(master).unwrap();
+        assert_eq!(playlist.variant_uri.as_deref(), Some("sub/index.m3u8"));
+
+        let ended = "#EXTM3U\n#EXTINF:2,\n0.ts\n#EXT-X-ENDLIST\n";
+        let playlist = HlsPlaylist::parse(ended).unwrap();
+        assert!(playlist.ended);
+        assert_eq!(playlist.segments.len(), 1);
+        assert!(playlist.map_uri.is_none());
+
+        assert!(HlsPlaylist::parse("<html>").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{io::Read, time::Duration};
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{Buf, Bytes, BytesMut};
+use eyre::{WrapErr, eyre};
+use flate2::read::ZlibDecoder;
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::watch,
+};
+
+use crate::live_recorder::live_source::DanmuServer;
+
+const HEADER_LEN: usize = 16;
+/// 数据包最大长度，超过说明数据流已经错乱
+const MAX_PACKET_LEN: usize = 16 * 1024 * 1024;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+const PROTOVER_JSON: u16 = 0;
+const PROTOVER_INT: u16 = 1;
+const PROTOVER_ZLIB: u16 = 2;
+
+const OP_HEARTBEAT: u32 = 2;
+const OP_COMMAND: u32 = 5;
+const OP_AUTH: u32 = 7;
+const OP_AUTH_REPLY: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub protover: u16,
+    pub op: u32,
+    pub body: Bytes,
+}
+
+/// 直播弹幕，字段与视频弹幕一致，方便转换为XML和ASS
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveDanmaku {
+    pub mode: i32,
+    pub fontsize: i32,
+    pub color: u32,
+    pub mid_hash: String,
+    pub content: String,
+    /// 发送时间(秒)
+    pub ctime: i64,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn encode_packet(protover: u16, op: u32, body: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; HEADER_LEN];
+    BigEndian::write_u32(&mut header[0..4], (HEADER_LEN + body.len()) as u32);
+    BigEndian::write_u16(&mut header[4..6], HEADER_LEN as u16);
+    BigEndian::write_u16(&mut header[6..8], protover);
+    BigEndian::write_u32(&mut header[8..12], op);
+    BigEndian::write_u32(&mut header[12..16], 1);
+
+    let mut packet = header.to_vec();
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// 从`buf`中取出所有完整的数据包，压缩过的数据包会被解压展开
+pub fn decode_packets(buf: &mut BytesMut) -> eyre::Result<Vec<Packet>> {
+    let mut packets = Vec::new();
+
+    while buf.len() >= HEADER_LEN {
+        let packet_len = BigEndian::read_u32(&buf[0..4]) as usize;
+        let header_len = BigEndian::read_u16(&buf[4..6]) as usize;
+        if packet_len < header_len || header_len < HEADER_LEN || packet_len > MAX_PACKET_LEN {
+            return Err(eyre!(
+                "数据包的长度不正确，packet_len: {packet_len}, header_len: {header_len}"
+            ));
+        }
+        if buf.len() < packet_len {
+            break;
+        }
+
+        let mut packet_bytes = buf.split_to(packet_len).freeze();
+        let protover = BigEndian::read_u16(&packet_bytes[6..8]);
+        let op = BigEndian::read_u32(&packet_bytes[8..12]);
+        packet_bytes.advance(header_len);
+
+        if protover == PROTOVER_ZLIB {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(packet_bytes.as_ref())
+                .read_to_end(&mut decompressed)
+                .wrap_err("解压zlib数据包失败")?;
+            let mut inner_buf = BytesMut::from(decompressed.as_slice());
+            packets.extend(decode_packets(&mut inner_buf)?);
+        } else {
+            packets.push(Packet {
+                protover,
+                op,
+                body: packet_bytes,
+            });
+        }
+    }
+
+    Ok(packets)
+}
+
+/// 解析`DANMU_MSG`命令，其他命令返回`None`
+pub fn parse_danmu_msg(body: &[u8]) -> Option<LiveDanmaku> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    // 命令可能带有后缀，例如`DANMU_MSG:4:0:2:2:2:0`
+    if !value["cmd"].as_str()?.starts_with("DANMU_MSG") {
+        return None;
+    }
+
+    let info = &value["info"];
+    let meta = &info[0];
+    let as_i32 = |value: &serde_json::Value| value.as_i64().and_then(|n| i32::try_from(n).ok());
+
+    Some(LiveDanmaku {
+        mode: as_i32(&meta[1]).unwrap_or(1),
+        fontsize: as_i32(&meta[2]).unwrap_or(25),
+        color: meta[3]
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .unwrap_or(0x00FF_FFFF),
+        mid_hash: meta[7].as_str().unwrap_or_default().to_string(),
+        content: info[1].as_str()?.to_string(),
+        ctime: meta[4].as_i64().unwrap_or_default() / 1000,
+    })
+}
+
+/// 连接弹幕服务器并持续接收弹幕，直到连接断开或收到停止信号
+///
+/// 收到停止信号时返回`Ok`，连接断开时返回`Err`
+pub async fn receive_danmaku(
+    server: &DanmuServer,
+    room_id: i64,
+    stop_receiver: &mut watch::Receiver<bool>,
+    mut on_danmaku: impl FnMut(LiveDanmaku),
+) -> eyre::Result<()> {
+    let mut stream = TcpStream::connect((server.host.as_str(), server.port))
+        .await
+        .wrap_err(format!(
+            "连接弹幕服务器`{}:{}`失败",
+            server.host, server.port
+        ))?;
+
+    let auth_body = json!({
+        "uid": server.uid,
+        "roomid": room_id,
+        "protover": PROTOVER_ZLIB,
+        "platform": "web",
+        "type": 2,
+        "key": server.token,
+    })
+    .to_string();
+    stream
+        .write_all(&encode_packet(PROTOVER_INT, OP_AUTH, auth_body.as_bytes()))
+        .await
+        .wrap_err("发送认证包失败")?;
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut buf = BytesMut::with_capacity(4096);
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                stream
+                    .write_all(&encode_packet(PROTOVER_INT, OP_HEARTBEAT, &[]))
+                    .await
+                    .wrap_err("发送心跳包失败")?;
+            }
+            read_result = stream.read_buf(&mut buf) => {
+                let read_len = read_result.wrap_err("读取弹幕服务器的数据失败")?;
+                if read_len == 0 {
+                    return Err(eyre!("弹幕服务器断开了连接"));
+                }
+
+                for packet in decode_packets(&mut buf)? {
+                    match packet.op {
+                        OP_AUTH_REPLY => check_auth_reply(&packet.body)?,
+                        OP_COMMAND if packet.protover == PROTOVER_JSON => {
+                            if let Some(danmaku) = parse_danmu_msg(&packet.body) {
+                                on_danmaku(danmaku);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // 发送端被丢弃也当作停止
+            _ = stop_receiver.changed() => return Ok(()),
+        }
+    }
+}
+
+fn check_auth_reply(body: &[u8]) -> eyre::Result<()> {
+    let value: serde_json::Value = serde_json::from_slice(body).wrap_err(format!(
+        "解析认证回复失败: {}",
+        String::from_utf8_lossy(body)
+    ))?;
+    if value["code"].as_i64() != Some(0) {
+        return Err(eyre!("弹幕服务器认证失败: {value}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::ZlibEncoder};
+
+    use super::*;
+
+    pub fn danmu_msg(content: &str) -> String {
+        json!({
+            "cmd": "DANMU_MSG:4:0:2:2:2:0",
+            "info": [
+                [0, 1, 25, 16_777_215, 1_700_000_000_123_i64, 0, 0, "5a8c3e1b", 0],
+                content,
+                [123, "user"],
+            ],
+        })
+        .to_string()
+    }
+
+    /// 与服务器一样，把多个JSON数据包压缩进一个数据包
+    pub fn zlib_packet(bodies: &[String]) -> Vec<u8> {
+        let mut inner = Vec::new();
+        for body in bodies {
+            inner.extend(encode_packet(PROTOVER_JSON, OP_COMMAND, body.as_bytes()));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&inner).unwrap();
+        encode_packet(PROTOVER_ZLIB, OP_COMMAND, &encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn decode_nested_zlib_packets_split_across_reads() {
+        let bodies = vec![
+            danmu_msg("第一条"),
+            r#"{"cmd":"INTERACT_WORD"}"#.to_string(),
+        ];
+        let mut stream = encode_packet(PROTOVER_INT, OP_AUTH_REPLY, br#"{"code":0}"#);
+        stream.extend(zlib_packet(&bodies));
+
+        let mut buf = BytesMut::new();
+        let mut packets = Vec::new();
+        for chunk in stream.chunks(10) {
+            buf.extend_from_slice(chunk);
+            packets.extend(decode_packets(&mut buf).unwrap());
+        }
+
+        assert!(buf.is_empty());
+        let ops: Vec<u32> = packets.iter().map(|packet| packet.op).collect();
+        assert_eq!(ops, vec![OP_AUTH_REPLY, OP_COMMAND, OP_COMMAND]);
+        assert_eq!(packets[1].body, Bytes::from(bodies[0].clone()));
+    }
+
+    #[test]
+    fn decode_rejects_broken_header() {
+        let mut buf = BytesMut::from(&[0u8; HEADER_LEN][..]);
+        assert!(decode_packets(&mut buf).is_err());
+    }
+
+    #[test]
+    fn parse_danmu_msg_only_accepts_danmaku() {
+        let danmaku = parse_danmu_msg(danmu_msg("你好").as_bytes()).unwrap();
+        assert_eq!(
+            danmaku,
+            LiveDanmaku {
+                mode: 1,
+                fontsize: 25,
+                color: 16_777_215,
+                mid_hash: "5a8c3e1b".to_string(),
+                content: "你好".to_string(),
+                ctime: 1_700_000_000,
+            }
+        );
+
+        assert!(parse_danmu_msg(br#"{"cmd":"SEND_GIFT","data":{}}"#).is_none());
+        assert!(parse_danmu_msg(b"not json").is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, sync::Arc};
+
+use eyre::{OptionExt, WrapErr, eyre};
+use parking_lot::Mutex;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{Instrument, instrument};
+
+use crate::{
+    app_context::AppContext,
+    live_recorder::{
+        live_recording_task::{LiveRecordOptions, LiveRecordingTask},
+        live_source::{BiliLiveSource, LiveSource},
+    },
+    types::live_recording::{LiveRecording, LiveRecordingState},
+};
+
+struct RecordingHandle {
+    status: Arc<Mutex<LiveRecording>>,
+    stop_sender: watch::Sender<bool>,
+    join_handle: JoinHandle<()>,
+}
+
+/// 管理正在录制的直播间，录制状态只保存在内存中
+pub struct LiveRecorderManager {
+    app: AppContext,
+    /// 键是真实房间号
+    recordings: Mutex<HashMap<i64, RecordingHandle>>,
+}
+
+impl LiveRecorderManager {
+    pub fn new(app: AppContext) -> Self {
+        Self {
+            app,
+            recordings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn get_live_recordings(&self) -> Vec<LiveRecording> {
+        let mut recordings: Vec<LiveRecording> = self
+            .recordings
+            .lock()
+            .values()
+            .map(|handle| handle.status.lock().clone())
+            .collect();
+        recordings.sort_by_key(|recording| recording.start_ts);
+        recordings
+    }
+
+    /// `room`可以是房间号、短号或直播间链接
+    #[instrument(level = "error", skip_all, fields(room = room))]
+    pub async fn start_live_recording(&self, room: &str) -> eyre::Result<LiveRecording> {
+        let room_id = parse_live_room_id(room)?;
+
+        let source = Arc::new(BiliLiveSource::new(self.app.clone()));
+        let live_room = source
+            .get_room(room_id)
+            .await
+            .wrap_err("获取直播间信息失败")?;
+        let room_id = live_room.room_id;
+        let options = LiveRecordOptions::from_config(&self.app.get_config().read(), room_id);
+
+        let mut recordings = self.recordings.lock();
+        let is_recording = recordings
+            .get(&room_id)
+            .is_some_and(|handle| handle.status.lock().state != LiveRecordingState::Stopped);
+        if is_recording {
+            return Err(eyre!("直播间`{room_id}`已经在录制中"));
+        }
+
+        let (stop_sender, stop_receiver) = watch::channel(false);
+        let task = LiveRecordingTask::new(source, live_room, options, stop_receiver);
+        let status = task.status();
+        let join_handle = tokio::spawn(task.run().in_current_span());
+
+        let recording = status.lock().clone();
+        recordings.insert(
+            room_id,
+            RecordingHandle {
+                status,
+                stop_sender,
+                join_handle,
+            },
+        );
+
+        Ok(recording)
+    }
+
+    /// 停止录制，并等待已录制的文件和弹幕保存完毕
+    #[instrument(level = "error", skip_all, fields(room_id = room_id))]
+    pub async fn stop_live_recording(&self, room_id: i64) -> eyre::Result<()> {
+        let handle = self
+            .recordings
+            .lock()
+            .remove(&room_id)
+            .ok_or_eyre(format!("没有在录制直播间`{room_id}`"))?;
+        // 录制已经结束时没有接收端，发送失败也没关系
+        let _ = handle.stop_sender.send(true);
+        handle
+            .join_handle
+            .await
+            .wrap_err(format!("等待直播间`{room_id}`的录制结束失败"))?;
+
+        Ok(())
+    }
+}
+
+/// 从房间号或`https://live.bilibili.com/21452505?...`这样的链接中解析出房间号
+pub fn parse_live_room_id(input: &str) -> eyre::Result<i64> {
+    let input = input.trim();
+    if let Ok(room_id) = input.parse() {
+        return Ok(room_id);
+    }
+
+    let path = input
+        .split_once("live.bilibili.com/")
+        .map(|(_, path)| path)
+        .ok_or_eyre(format!("`{input}`不是直播间号或直播间链接"))?;
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    // 兼容`/h5/21452505`、`/blanc/21452505`等路径
+    path.split('/')
+        .filter_map(|segment| segment.parse().ok())
+        .next_back()
+        .ok_or_eyre(format!("无法从`{input}`中解析出直播间号"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_live_room_id_accepts_ids_and_urls() {
+        assert_eq!(parse_live_room_id(" 21452505 ").unwrap(), 21_452_505);
+        assert_eq!(
+            parse_live_room_id("https://live.bilibili.com/21452505?spm_id_from=333").unwrap(),
+            21_452_505
+        );
+        assert_eq!(
+            parse_live_room_id("live.bilibili.com/h5/6#anchor").unwrap(),
+            6
+        );
+        assert_eq!(
+            parse_live_room_id("https://live.bilibili.com/blanc/6/").unwrap(),
+            6
+        );
+
+        assert!(parse_live_room_id("https://www.bilibili.com/video/BV1xx").is_err());
+        assert!(parse_live_room_id("https://live.bilibili.com/p/eden").is_err());
+    }
+}
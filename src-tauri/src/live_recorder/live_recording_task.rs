@@ -0,0 +1,908 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use eyre::{WrapErr, eyre};
+use parking_lot::Mutex;
+use reqwest::{StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::sync::watch;
+use tracing::instrument;
+
+use crate::{
+    config::{Config, LiveStreamFormat},
+    danmaku_xml_to_ass::{canvas::CanvasConfig, filter::DanmakuFilter, xml_to_ass},
+    downloader::fmt_params::ts_to_string,
+    extensions::EyreReportToMessage,
+    live_recorder::{
+        flv::{FlvDemuxer, FlvSegmenter},
+        hls::HlsPlaylist,
+        live_danmaku::{LiveDanmaku, receive_danmaku},
+        live_source::{LiveRoom, LiveSource, LiveStream},
+    },
+    protobuf::{DanmakuElem, DmSegMobileReply},
+    types::live_recording::{LiveRecording, LiveRecordingState},
+    utils::{self, ToXml},
+};
+
+/// 未开播时多久再检查一次
+const OFFLINE_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// 超过这个时间没有收到直播流的数据就当作断线
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 一次录制的设置，开始录制时从配置中读取
+#[derive(Debug, Clone)]
+pub struct LiveRecordOptions {
+    pub output_dir: PathBuf,
+    pub format: LiveStreamFormat,
+    /// 单位为字节，为0时不按大小分段
+    pub split_size: u64,
+    /// 为`None`时不按时长分段
+    pub split_duration: Option<Duration>,
+    pub xml_danmaku: bool,
+    pub ass_danmaku: bool,
+    pub danmaku_config: CanvasConfig,
+    pub danmaku_filter: DanmakuFilter,
+    pub time_fmt: String,
+    /// 断线后等多久再重连
+    pub reconnect_interval: Duration,
+}
+
+impl LiveRecordOptions {
+    pub fn from_config(config: &Config, room_id: i64) -> Self {
+        let split_duration = match config.live_split_duration_min {
+            0 => None,
+            min => Some(Duration::from_mins(min)),
+        };
+
+        Self {
+            output_dir: config
+                .download_dir
+                .join("直播录制")
+                .join(room_id.to_string()),
+            format: config.live_stream_format,
+            split_size: config.live_split_size_mb.saturating_mul(1024 * 1024),
+            split_duration,
+            xml_danmaku: config.download_xml_danmaku,
+            ass_danmaku: config.download_ass_danmaku,
+            danmaku_config: config.danmaku_config.clone(),
+            danmaku_filter: config.danmaku_filter.clone(),
+            time_fmt: config.time_fmt.clone(),
+            reconnect_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// 录制一个直播间，断线后会自动重连，直到收到停止信号
+pub struct LiveRecordingTask<S: LiveSource> {
+    source: Arc<S>,
+    room: LiveRoom,
+    options: LiveRecordOptions,
+    status: Arc<Mutex<LiveRecording>>,
+    danmaku_collector: Arc<Mutex<DanmakuCollector>>,
+    stop_receiver: watch::Receiver<bool>,
+}
+
+/// 正在写入的文件
+struct RecordingFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+    start: Instant,
+}
+
+impl<S: LiveSource> LiveRecordingTask<S> {
+    pub fn new(
+        source: Arc<S>,
+        room: LiveRoom,
+        options: LiveRecordOptions,
+        stop_receiver: watch::Receiver<bool>,
+    ) -> Self {
+        let status = LiveRecording {
+            room_id: room.room_id,
+            uid: room.uid,
+            title: room.title.clone(),
+            output_dir: options.output_dir.clone(),
+            start_ts: chrono::Local::now().timestamp(),
+            ..Default::default()
+        };
+
+        Self {
+            source,
+            room,
+            options,
+            status: Arc::new(Mutex::new(status)),
+            danmaku_collector: Arc::new(Mutex::new(DanmakuCollector::default())),
+            stop_receiver,
+        }
+    }
+
+    pub fn status(&self) -> Arc<Mutex<LiveRecording>> {
+        self.status.clone()
+    }
+
+    #[instrument(level = "error", skip_all, fields(room_id = self.room.room_id))]
+    pub async fn run(self) {
+        let room_id = self.room.room_id;
+
+        let danmaku_handle = (self.options.xml_danmaku || self.options.ass_danmaku).then(|| {
+            tokio::spawn(danmaku_loop(
+                self.source.clone(),
+                room_id,
+                self.danmaku_collector.clone(),
+                self.status.clone(),
+                self.stop_receiver.clone(),
+                self.options.reconnect_interval,
+            ))
+        });
+
+        let mut stop_receiver = self.stop_receiver.clone();
+        while !is_stopped(&stop_receiver) {
+            self.set_state(LiveRecordingState::Connecting);
+
+            let result = match self.source.get_stream(room_id, self.options.format).await {
+                Ok(Some(stream)) => self.record_stream(&stream).await,
+                Ok(None) => {
+                    tracing::debug!("直播间未开播，稍后再检查");
+                    self.set_state(LiveRecordingState::Waiting);
+                    wait_or_stop(&mut stop_receiver, OFFLINE_RECHECK_INTERVAL).await;
+                    continue;
+                }
+                Err(err) => Err(err.wrap_err("获取直播流失败")),
+            };
+
+            if let Err(err) = result {
+                let err_title = format!("直播间`{room_id}`的录制中断，稍后重连");
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+                self.status.lock().last_error = Some(message);
+            }
+
+            if is_stopped(&stop_receiver) {
+                break;
+            }
+            self.set_state(LiveRecordingState::Waiting);
+            wait_or_stop(&mut stop_receiver, self.options.reconnect_interval).await;
+        }
+
+        if let Some(danmaku_handle) = danmaku_handle {
+            let _ = danmaku_handle.await;
+        }
+        self.set_state(LiveRecordingState::Stopped);
+        tracing::debug!("直播录制已停止");
+    }
+
+    async fn record_stream(&self, stream: &LiveStream) -> eyre::Result<()> {
+        match stream.format {
+            LiveStreamFormat::Flv => self.record_flv(stream).await,
+            LiveStreamFormat::Hls => self.record_hls(stream).await,
+        }
+    }
+
+    async fn record_flv(&self, stream: &LiveStream) -> eyre::Result<()> {
+        let mut http_resp = self
+            .source
+            .media_client()
+            .get(&stream.url)
+            .send()
+            .await
+            .wrap_err("请求FLV直播流失败")?;
+        let status = http_resp.status();
+        if status != StatusCode::OK {
+            let body = http_resp.text().await?;
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+
+        let mut stop_receiver = self.stop_receiver.clone();
+        let mut demuxer = FlvDemuxer::default();
+        let mut segmenter = FlvSegmenter::default();
+        let mut current_file: Option<RecordingFile> = None;
+
+        let result = async {
+            loop {
+                let chunk = tokio::select! {
+                    chunk = tokio::time::timeout(STREAM_TIMEOUT, http_resp.chunk()) => chunk
+                        .map_err(|_| eyre!("直播流超过{}秒没有数据", STREAM_TIMEOUT.as_secs()))?
+                        .wrap_err("读取直播流失败")?,
+                    _ = stop_receiver.changed() => return Ok(()),
+                };
+                let Some(chunk) = chunk else {
+                    tracing::debug!("直播流已结束");
+                    return Ok(());
+                };
+
+                for tag in demuxer.push(&chunk)? {
+                    let split = current_file.as_ref().is_some_and(|f| self.should_split(f));
+                    let Some(output) = segmenter.process(&tag, split) else {
+                        continue;
+                    };
+                    if output.new_file {
+                        if let Some(file) = current_file.take() {
+                            self.finish_file(file)?;
+                        }
+                        current_file = Some(self.create_file("flv")?);
+                    }
+                    if let Some(file) = current_file.as_mut() {
+                        self.write(file, &output.bytes)?;
+                    }
+                }
+            }
+        }
+        .await;
+
+        // 出错时也要把已经录制的部分保存好
+        if let Some(file) = current_file.take() {
+            self.finish_file(file)?;
+        }
+
+        result
+    }
+
+    async fn record_hls(&self, stream: &LiveStream) -> eyre::Result<()> {
+        let client = self.source.media_client();
+        let mut playlist_url =
+            Url::parse(&stream.url).wrap_err(format!("解析直播流地址`{}`失败", stream.url))?;
+
+        let mut stop_receiver = self.stop_receiver.clone();
+        let mut last_sequence: Option<u64> = None;
+        // fMP4的初始化分片，每个文件开头都要有
+        let mut init_segment: Option<(String, Bytes)> = None;
+        let mut last_segment_time = Instant::now();
+        let mut current_file: Option<RecordingFile> = None;
+
+        let result = async {
+            while !is_stopped(&stop_receiver) {
+                let playlist_bytes = fetch(&client, &playlist_url)
+                    .await
+                    .wrap_err("获取m3u8播放列表失败")?;
+                let playlist = HlsPlaylist::parse(&String::from_utf8_lossy(&playlist_bytes))?;
+
+                if let Some(variant_uri) = &playlist.variant_uri {
+                    playlist_url = playlist_url.join(variant_uri)?;
+                    continue;
+                }
+
+                // 初始化分片变了，之后的分片不能再接在当前文件后面
+                let mut init_changed = false;
+                if let Some(map_uri) = &playlist.map_uri
+                    && init_segment.as_ref().is_none_or(|(uri, _)| uri != map_uri)
+                {
+                    let data = fetch(&client, &playlist_url.join(map_uri)?)
+                        .await
+                        .wrap_err("下载初始化分片失败")?;
+                    init_segment = Some((map_uri.clone(), data));
+                    init_changed = true;
+                }
+                let extension = if init_segment.is_some() { "mp4" } else { "ts" };
+
+                for segment in &playlist.segments {
+                    if last_sequence.is_some_and(|last| segment.sequence <= last) {
+                        continue;
+                    }
+
+                    let data = fetch(&client, &playlist_url.join(&segment.uri)?)
+                        .await
+                        .wrap_err(format!("下载分片`{}`失败", segment.uri))?;
+
+                    let need_new_file =
+                        init_changed || current_file.as_ref().is_none_or(|f| self.should_split(f));
+                    if need_new_file {
+                        if let Some(file) = current_file.take() {
+                            self.finish_file(file)?;
+                        }
+                        let mut file = self.create_file(extension)?;
+                        if let Some((_, init_data)) = &init_segment {
+                            self.write(&mut file, init_data)?;
+                        }
+                        current_file = Some(file);
+                        init_changed = false;
+                    }
+                    if let Some(file) = current_file.as_mut() {
+                        self.write(file, &data)?;
+                    }
+
+                    last_sequence = Some(segment.sequence);
+                    last_segment_time = Instant::now();
+                }
+
+                if playlist.ended {
+                    tracing::debug!("直播流已结束");
+                    return Ok(());
+                }
+                if last_segment_time.elapsed() > STREAM_TIMEOUT {
+                    return Err(eyre!(
+                        "直播流超过{}秒没有新的分片",
+                        STREAM_TIMEOUT.as_secs()
+                    ));
+                }
+
+                let poll_interval =
+                    Duration::from_secs_f64((playlist.target_duration / 2.0).max(0.5));
+                wait_or_stop(&mut stop_receiver, poll_interval).await;
+            }
+
+            Ok(())
+        }
+        .await;
+
+        if let Some(file) = current_file.take() {
+            self.finish_file(file)?;
+        }
+
+        result
+    }
+
+    fn should_split(&self, file: &RecordingFile) -> bool {
+        let split_by_size = self.options.split_size != 0 && file.size >= self.options.split_size;
+        let split_by_duration = self
+            .options
+            .split_duration
+            .is_some_and(|duration| file.start.elapsed() >= duration);
+        split_by_size || split_by_duration
+    }
+
+    fn create_file(&self, extension: &str) -> eyre::Result<RecordingFile> {
+        let output_dir = &self.options.output_dir;
+        std::fs::create_dir_all(output_dir)
+            .wrap_err(format!("创建目录`{}`失败", output_dir.display()))?;
+
+        let now = chrono::Local::now().timestamp();
+        let time = ts_to_string(now, &self.options.time_fmt).unwrap_or_else(|| now.to_string());
+        let stem = utils::filename_filter(&format!("{time}-{}", self.room.title));
+
+        // 同一秒内分段时文件名会重复
+        let mut path = output_dir.join(format!("{stem}.{extension}"));
+        let mut index = 1;
+        while path.exists() {
+            index += 1;
+            path = output_dir.join(format!("{stem}-{index}.{extension}"));
+        }
+
+        let file = File::create(&path).wrap_err(format!("创建文件`{}`失败", path.display()))?;
+        tracing::debug!("开始录制到`{}`", path.display());
+
+        self.danmaku_collector.lock().start_file();
+        {
+            let mut status = self.status.lock();
+            status.state = LiveRecordingState::Recording;
+            status.current_file = Some(path.clone());
+        }
+
+        Ok(RecordingFile {
+            path,
+            writer: BufWriter::new(file),
+            size: 0,
+            start: Instant::now(),
+        })
+    }
+
+    fn write(&self, file: &mut RecordingFile, bytes: &[u8]) -> eyre::Result<()> {
+        file.writer
+            .write_all(bytes)
+            .wrap_err(format!("写入文件`{}`失败", file.path.display()))?;
+        file.size += bytes.len() as u64;
+        self.status.lock().recorded_bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// 写完文件，并保存这段时间内的弹幕
+    fn finish_file(&self, mut file: RecordingFile) -> eyre::Result<()> {
+        file.writer
+            .flush()
+            .wrap_err(format!("写入文件`{}`失败", file.path.display()))?;
+        {
+            let mut status = self.status.lock();
+            status.file_count += 1;
+            status.current_file = None;
+        }
+
+        let elems = self.danmaku_collector.lock().finish_file();
+        if self.options.xml_danmaku || self.options.ass_danmaku {
+            self.save_danmaku(&file.path, elems)
+                .wrap_err(format!("保存`{}`的弹幕失败", file.path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// 与下载视频时一样，保存为`{文件名}.弹幕.xml`和`{文件名}.弹幕.ass`
+    fn save_danmaku(&self, path: &std::path::Path, elems: Vec<DanmakuElem>) -> eyre::Result<()> {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let output_dir = &self.options.output_dir;
+
+        let replies = vec![DmSegMobileReply {
+            elems,
+            ..Default::default()
+        }];
+        let xml = replies
+            .to_xml(self.room.room_id)
+            .wrap_err("将弹幕转换为XML失败")?;
+
+        if self.options.xml_danmaku {
+            let xml_path = output_dir.join(format!("{stem}.弹幕.xml"));
+            std::fs::write(&xml_path, &xml)
+                .wrap_err(format!("保存弹幕XML到`{}`失败", xml_path.display()))?;
+        }
+
+        if self.options.ass_danmaku {
+            let ass_path = output_dir.join(format!("{stem}.弹幕.ass"));
+            let ass_file = File::create(&ass_path)
+                .wrap_err(format!("创建弹幕ASS文件`{}`失败", ass_path.display()))?;
+            let config = self.options.danmaku_config.clone();
+            xml_to_ass(&xml, ass_file, stem, config, &self.options.danmaku_filter)
+                .wrap_err("将弹幕XML转换为ASS失败")?;
+        }
+
+        Ok(())
+    }
+
+    fn set_state(&self, state: LiveRecordingState) {
+        let mut status = self.status.lock();
+        status.state = state;
+        if state != LiveRecordingState::Recording {
+            status.current_file = None;
+        }
+    }
+}
+
+/// 把弹幕分配给正在录制的文件，弹幕时间从文件开始录制时算起
+#[derive(Default)]
+struct DanmakuCollector {
+    current: Option<(Instant, Vec<DanmakuElem>)>,
+    next_id: i64,
+}
+
+impl DanmakuCollector {
+    fn start_file(&mut self) {
+        self.current = Some((Instant::now(), Vec::new()));
+    }
+
+    /// 没有在录制时丢弃弹幕，返回是否收下了这条弹幕
+    fn push(&mut self, danmaku: LiveDanmaku) -> bool {
+        let Some((start, elems)) = &mut self.current else {
+            return false;
+        };
+
+        self.next_id += 1;
+        elems.push(DanmakuElem {
+            id: self.next_id,
+            id_str: self.next_id.to_string(),
+            progress: i32::try_from(start.elapsed().as_millis()).unwrap_or(i32::MAX),
+            mode: danmaku.mode,
+            fontsize: danmaku.fontsize,
+            color: danmaku.color,
+            mid_hash: danmaku.mid_hash,
+            content: danmaku.content,
+            ctime: danmaku.ctime,
+            ..Default::default()
+        });
+        true
+    }
+
+    fn finish_file(&mut self) -> Vec<DanmakuElem> {
+        self.current
+            .take()
+            .map(|(_, elems)| elems)
+            .unwrap_or_default()
+    }
+}
+
+/// 持续接收弹幕，断线后自动重连，直到收到停止信号
+async fn danmaku_loop<S: LiveSource>(
+    source: Arc<S>,
+    room_id: i64,
+    danmaku_collector: Arc<Mutex<DanmakuCollector>>,
+    status: Arc<Mutex<LiveRecording>>,
+    mut stop_receiver: watch::Receiver<bool>,
+    reconnect_interval: Duration,
+) {
+    while !is_stopped(&stop_receiver) {
+        let result = match source.get_danmu_server(room_id).await {
+            Ok(server) => {
+                receive_danmaku(&server, room_id, &mut stop_receiver, |danmaku| {
+                    if danmaku_collector.lock().push(danmaku) {
+                        status.lock().danmaku_count += 1;
+                    }
+                })
+                .await
+            }
+            Err(err) => Err(err.wrap_err("获取弹幕服务器失败")),
+        };
+
+        if let Err(err) = result {
+            let err_title = format!("直播间`{room_id}`的弹幕连接中断，稍后重连");
+            let message = err.to_message();
+            tracing::error!(err_title, message);
+        }
+
+        if is_stopped(&stop_receiver) {
+            break;
+        }
+        wait_or_stop(&mut stop_receiver, reconnect_interval).await;
+    }
+}
+
+async fn fetch(client: &ClientWithMiddleware, url: &Url) -> eyre::Result<Bytes> {
+    let request = async {
+        let http_resp = client.get(url.as_str()).send().await?;
+        let status = http_resp.status();
+        if status != StatusCode::OK {
+            let body = http_resp.text().await?;
+            return Err(eyre!("预料之外的状态码({status}): {body}"));
+        }
+        Ok(http_resp.bytes().await?)
+    };
+
+    tokio::time::timeout(STREAM_TIMEOUT, request)
+        .await
+        .map_err(|_| eyre!("请求`{url}`超时"))?
+}
+
+/// 发送端被丢弃也当作停止，避免没有人能停止录制时空转
+fn is_stopped(stop_receiver: &watch::Receiver<bool>) -> bool {
+    *stop_receiver.borrow() || stop_receiver.has_changed().is_err()
+}
+
+async fn wait_or_stop(stop_receiver: &mut watch::Receiver<bool>, duration: Duration) {
+    tokio::select! {
+        () = tokio::time::sleep(duration) => {}
+        _ = stop_receiver.changed() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::live_recorder::{
+        flv::{FlvTag, TAG_TYPE_AUDIO, TAG_TYPE_SCRIPT, TAG_TYPE_VIDEO},
+        live_danmaku::{
+            encode_packet,
+            tests::{danmu_msg, zlib_packet},
+        },
+        live_source::DanmuServer,
+    };
+
+    use super::*;
+
+    const INIT_SEGMENT: &[u8] = b"init-segment";
+
+    /// 用本地服务器代替哔哩哔哩，每次请求直播流都只推送一小段就断开
+    struct TestSource {
+        http_addr: SocketAddr,
+        danmu_addr: Option<SocketAddr>,
+        format: LiveStreamFormat,
+        stream_requests: AtomicUsize,
+    }
+
+    impl LiveSource for TestSource {
+        async fn get_room(&self, room_id: i64) -> eyre::Result<LiveRoom> {
+            Ok(test_room(room_id))
+        }
+
+        async fn get_stream(
+            &self,
+            _room_id: i64,
+            _format: LiveStreamFormat,
+        ) -> eyre::Result<Option<LiveStream>> {
+            self.stream_requests.fetch_add(1, Ordering::SeqCst);
+            let path = match self.format {
+                LiveStreamFormat::Flv => "live.flv",
+                LiveStreamFormat::Hls => "master.m3u8",
+            };
+            Ok(Some(LiveStream {
+                url: format!("http://{}/{path}", self.http_addr),
+                format: self.format,
+            }))
+        }
+
+        async fn get_danmu_server(&self, _room_id: i64) -> eyre::Result<DanmuServer> {
+            let addr = self.danmu_addr.ok_or_else(|| eyre!("没有弹幕服务器"))?;
+            Ok(DanmuServer {
+                host: addr.ip().to_string(),
+                port: addr.port(),
+                token: "token".to_string(),
+                uid: 0,
+            })
+        }
+
+        fn media_client(&self) -> ClientWithMiddleware {
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build()
+        }
+    }
+
+    fn test_room(room_id: i64) -> LiveRoom {
+        LiveRoom {
+            room_id,
+            uid: 1,
+            title: "测试直播".to_string(),
+        }
+    }
+
+    fn test_options(
+        output_dir: PathBuf,
+        format: LiveStreamFormat,
+        danmaku: bool,
+    ) -> LiveRecordOptions {
+        LiveRecordOptions {
+            output_dir,
+            format,
+            // 每个关键帧或分片都换一个新文件
+            split_size: 1,
+            split_duration: None,
+            xml_danmaku: danmaku,
+            ass_danmaku: danmaku,
+            danmaku_config: CanvasConfig::default(),
+            danmaku_filter: DanmakuFilter::default(),
+            time_fmt: "%Y-%m-%d_%H-%M-%S".to_string(),
+            reconnect_interval: Duration::from_millis(50),
+        }
+    }
+
+    fn flv_stream_parts() -> Vec<Vec<u8>> {
+        let mut header = b"FLV\x01\x05\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        let header_tags = [
+            (TAG_TYPE_SCRIPT, b"onMetaData".to_vec()),
+            (TAG_TYPE_VIDEO, vec![0x17, 0, 0, 0, 0, 1]),
+            (TAG_TYPE_AUDIO, vec![0xAF, 0, 0x12, 0x10]),
+        ];
+        for (tag_type, data) in header_tags {
+            let tag = FlvTag {
+                tag_type,
+                timestamp: 0,
+                data: Bytes::from(data),
+            };
+            tag.encode(0, &mut header);
+        }
+
+        let mut parts = vec![header];
+        for i in 0..3 {
+            let mut part = Vec::new();
+            let keyframe = FlvTag {
+                tag_type: TAG_TYPE_VIDEO,
+                timestamp: 1000 + i * 40,
+                data: Bytes::from_static(&[0x17, 1, 0, 0, 0, 0xAB]),
+            };
+            keyframe.encode(keyframe.timestamp, &mut part);
+            parts.push(part);
+        }
+        parts
+    }
+
+    async fn serve_http(listener: TcpListener) {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(handle_http(stream));
+        }
+    }
+
+    async fn handle_http(mut stream: TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        let request = String::from_utf8_lossy(&request);
+        let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+
+        let parts: Vec<Vec<u8>> = match path.as_str() {
+            "/live.flv" => flv_stream_parts(),
+            "/master.m3u8" => {
+                vec![b"#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\nsub/index.m3u8\n".to_vec()]
+            }
+            "/sub/index.m3u8" => vec![
+                b"#EXTM3U
+#EXT-X-TARGETDURATION:1
+#EXT-X-MEDIA-SEQUENCE:7
+#EXT-X-MAP:URI=\"init.m4s\"
+#EXTINF:1.00,
+7.m4s
+#EXTINF:1.00,
+8.m4s
+#EXT-X-ENDLIST
+"
+                .to_vec(),
+            ],
+            "/sub/init.m4s" => vec![INIT_SEGMENT.to_vec()],
+            "/sub/7.m4s" => vec![b"segment-7".to_vec()],
+            "/sub/8.m4s" => vec![b"segment-8".to_vec()],
+            _ => {
+                let _ = stream
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+                return;
+            }
+        };
+
+        // 没有Content-Length，关闭连接就是直播流断开
+        let _ = stream
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+            .await;
+        for part in parts {
+            if stream.write_all(&part).await.is_err() {
+                return;
+            }
+            let _ = stream.flush().await;
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+    }
+
+    async fn serve_danmaku(listener: TcpListener) {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                if stream.read(&mut buf).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                let auth_reply = encode_packet(1, 8, br#"{"code":0}"#);
+                if stream.write_all(&auth_reply).await.is_err() {
+                    return;
+                }
+                for i in 0.. {
+                    let packet = zlib_packet(&[danmu_msg(&format!("弹幕{i}"))]);
+                    if stream.write_all(&packet).await.is_err() {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            });
+        }
+    }
+
+    async fn local_listener() -> TcpListener {
+        TcpListener::bind("127.0.0.1:0").await.unwrap()
+    }
+
+    /// 等到`condition`成立后停止录制，返回最终状态
+    async fn record_until(
+        source: Arc<TestSource>,
+        options: LiveRecordOptions,
+        condition: impl Fn(&LiveRecording) -> bool,
+    ) -> LiveRecording {
+        let (stop_sender, stop_receiver) = watch::channel(false);
+        let task = LiveRecordingTask::new(source, test_room(1000), options, stop_receiver);
+        let status = task.status();
+        let join_handle = tokio::spawn(task.run());
+
+        tokio::time::timeout(Duration::from_secs(20), async {
+            while !condition(&status.lock()) {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("录制没有在限定时间内达到预期");
+
+        stop_sender.send(true).unwrap();
+        join_handle.await.unwrap();
+        status.lock().clone()
+    }
+
+    fn files_with_suffix(dir: &std::path::Path, suffix: &str) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(suffix))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[tokio::test]
+    async fn record_flv_splits_files_records_danmaku_and_reconnects() {
+        let http_listener = local_listener().await;
+        let danmu_listener = local_listener().await;
+        let source = Arc::new(TestSource {
+            http_addr: http_listener.local_addr().unwrap(),
+            danmu_addr: Some(danmu_listener.local_addr().unwrap()),
+            format: LiveStreamFormat::Flv,
+            stream_requests: AtomicUsize::new(0),
+        });
+        tokio::spawn(serve_http(http_listener));
+        tokio::spawn(serve_danmaku(danmu_listener));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let options = test_options(temp_dir.path().to_path_buf(), LiveStreamFormat::Flv, true);
+        let recording = record_until(source.clone(), options, |recording| {
+            recording.file_count >= 4 && recording.danmaku_count > 0
+        })
+        .await;
+
+        assert_eq!(recording.state, LiveRecordingState::Stopped);
+        assert!(recording.last_error.is_none());
+        // 每次连接只推送3个关键帧，要录满4个文件必须重连过
+        assert!(source.stream_requests.load(Ordering::SeqCst) >= 2);
+
+        let flv_paths = files_with_suffix(temp_dir.path(), ".flv");
+        assert!(flv_paths.len() >= 4);
+        let mut demuxed_keyframes = 0;
+        for path in &flv_paths {
+            let data = std::fs::read(path).unwrap();
+            assert!(data.starts_with(b"FLV"));
+            let tags = FlvDemuxer::default().push(&data).unwrap();
+            // 每个文件都能单独播放：开头是脚本标签和序列头，第一帧是关键帧
+            assert_eq!(tags[0].tag_type, TAG_TYPE_SCRIPT);
+            assert!(tags[1].is_sequence_header() && tags[2].is_sequence_header());
+            assert!(tags[3].is_video_keyframe());
+            assert_eq!(tags[3].timestamp, 0);
+            demuxed_keyframes += tags
+                .iter()
+                .filter(|tag| tag.is_video_keyframe() && !tag.is_sequence_header())
+                .count();
+        }
+        assert_eq!(demuxed_keyframes, flv_paths.len());
+
+        // 每个视频文件都有对应的弹幕文件，弹幕总数与状态一致
+        let xml_paths = files_with_suffix(temp_dir.path(), ".弹幕.xml");
+        let ass_paths = files_with_suffix(temp_dir.path(), ".弹幕.ass");
+        assert_eq!(xml_paths.len(), flv_paths.len());
+        assert_eq!(ass_paths.len(), flv_paths.len());
+        let xml_danmaku_count: usize = xml_paths
+            .iter()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .unwrap()
+                    .matches("<d p=")
+                    .count()
+            })
+            .sum();
+        assert_eq!(
+            u64::try_from(xml_danmaku_count).unwrap(),
+            recording.danmaku_count
+        );
+    }
+
+    #[tokio::test]
+    async fn record_hls_follows_variant_and_writes_init_segment_to_every_file() {
+        let http_listener = local_listener().await;
+        let source = Arc::new(TestSource {
+            http_addr: http_listener.local_addr().unwrap(),
+            danmu_addr: None,
+            format: LiveStreamFormat::Hls,
+            stream_requests: AtomicUsize::new(0),
+        });
+        tokio::spawn(serve_http(http_listener));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let options = test_options(temp_dir.path().to_path_buf(), LiveStreamFormat::Hls, false);
+        let recording = record_until(source, options, |recording| recording.file_count >= 2).await;
+
+        assert_eq!(recording.state, LiveRecordingState::Stopped);
+        let mp4_paths = files_with_suffix(temp_dir.path(), ".mp4");
+        assert!(mp4_paths.len() >= 2);
+        let expected = [
+            [INIT_SEGMENT, b"segment-7"].concat(),
+            [INIT_SEGMENT, b"segment-8"].concat(),
+        ];
+        let contents: Vec<Vec<u8>> = mp4_paths
+            .iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect();
+        assert!(contents.iter().all(|content| expected.contains(content)));
+        assert!(expected.iter().all(|content| contents.contains(content)));
+        assert!(files_with_suffix(temp_dir.path(), ".弹幕.xml").is_empty());
+    }
+}
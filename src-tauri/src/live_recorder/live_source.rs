@@ -0,0 +1,155 @@
+use eyre::{OptionExt, WrapErr};
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    app_context::AppContext,
+    config::LiveStreamFormat,
+    types::live_play_info::{CodecInLive, LivePlayInfo},
+};
+
+/// 直播间的基本信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveRoom {
+    /// 真实房间号，不是短号
+    pub room_id: i64,
+    pub uid: i64,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveStream {
+    pub url: String,
+    pub format: LiveStreamFormat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DanmuServer {
+    pub host: String,
+    pub port: u16,
+    pub token: String,
+    pub uid: i64,
+}
+
+/// 直播流和弹幕服务器的来源
+///
+/// 录制只通过它访问网络，测试时可以换成本地的服务器
+pub trait LiveSource: Send + Sync + 'static {
+    /// `room_id`可以是短号
+    fn get_room(&self, room_id: i64) -> impl Future<Output = eyre::Result<LiveRoom>> + Send;
+
+    /// 未开播时返回`None`，没有`format`格式的直播流时会返回另一种格式
+    fn get_stream(
+        &self,
+        room_id: i64,
+        format: LiveStreamFormat,
+    ) -> impl Future<Output = eyre::Result<Option<LiveStream>>> + Send;
+
+    fn get_danmu_server(
+        &self,
+        room_id: i64,
+    ) -> impl Future<Output = eyre::Result<DanmuServer>> + Send;
+
+    /// 用来下载直播流的客户端
+    fn media_client(&self) -> ClientWithMiddleware;
+}
+
+/// 从哔哩哔哩获取直播流
+pub struct BiliLiveSource {
+    app: AppContext,
+}
+
+impl BiliLiveSource {
+    pub fn new(app: AppContext) -> Self {
+        Self { app }
+    }
+}
+
+impl LiveSource for BiliLiveSource {
+    async fn get_room(&self, room_id: i64) -> eyre::Result<LiveRoom> {
+        let bili_client = self.app.get_bili_client();
+        let room_info = bili_client.get_live_room_info(room_id).await?;
+        Ok(LiveRoom {
+            room_id: room_info.room_id,
+            uid: room_info.uid,
+            title: room_info.title,
+        })
+    }
+
+    async fn get_stream(
+        &self,
+        room_id: i64,
+        format: LiveStreamFormat,
+    ) -> eyre::Result<Option<LiveStream>> {
+        let bili_client = self.app.get_bili_client();
+        let play_info = bili_client.get_live_play_info(room_id).await?;
+        if play_info.live_status != 1 {
+            return Ok(None);
+        }
+
+        let fallback_format = match format {
+            LiveStreamFormat::Flv => LiveStreamFormat::Hls,
+            LiveStreamFormat::Hls => LiveStreamFormat::Flv,
+        };
+        let stream = [format, fallback_format]
+            .into_iter()
+            .find_map(|format| select_stream(&play_info, format));
+
+        Ok(stream)
+    }
+
+    async fn get_danmu_server(&self, room_id: i64) -> eyre::Result<DanmuServer> {
+        let bili_client = self.app.get_bili_client();
+        let danmu_info = bili_client.get_live_danmu_info(room_id).await?;
+        let host = danmu_info
+            .host_list
+            .into_iter()
+            .next()
+            .ok_or_eyre("弹幕服务器列表为空")
+            .wrap_err("获取弹幕服务器失败")?;
+
+        Ok(DanmuServer {
+            host: host.host,
+            port: host.port,
+            token: danmu_info.token,
+            // 不登录时用户信息会被隐藏，不影响弹幕内容
+            uid: 0,
+        })
+    }
+
+    fn media_client(&self) -> ClientWithMiddleware {
+        self.app.get_bili_client().media_client.read().clone()
+    }
+}
+
+/// 选出`format`格式的直播流，优先AVC编码，兼容性更好
+fn select_stream(play_info: &LivePlayInfo, format: LiveStreamFormat) -> Option<LiveStream> {
+    let (protocol_name, format_names): (&str, &[&str]) = match format {
+        LiveStreamFormat::Flv => ("http_stream", &["flv"]),
+        LiveStreamFormat::Hls => ("http_hls", &["fmp4", "ts"]),
+    };
+
+    let stream = play_info
+        .playurl_info
+        .as_ref()?
+        .playurl
+        .stream
+        .iter()
+        .find(|stream| stream.protocol_name == protocol_name)?;
+
+    let codecs = format_names.iter().flat_map(|format_name| {
+        stream
+            .format
+            .iter()
+            .filter(move |format| format.format_name == *format_name)
+            .flat_map(|format| &format.codec)
+    });
+    let codec: &CodecInLive = codecs
+        .clone()
+        .find(|codec| codec.codec_name == "avc")
+        .or_else(|| codecs.clone().next())?;
+
+    let url_info = codec.url_info.first()?;
+    let url = format!("{}{}{}", url_info.host, codec.base_url, url_info.extra);
+
+    Some(LiveStream { url, format })
+}
@@ -1,14 +1,9 @@
 use std::{io::Write, sync::OnceLock};
 
-use crate::{
-    events::LogEvent,
-    extensions::{AppHandleExt, EyreReportToMessage},
-};
+use crate::{app_context::AppContext, events::LogEvent, extensions::EyreReportToMessage};
 use eyre::{OptionExt, WrapErr};
 use notify::{RecommendedWatcher, Watcher};
-use tauri::{AppHandle, Manager};
-use tauri_specta::Event;
-use tracing::{Instrument, Level, Subscriber, instrument};
+use tracing::{Level, Subscriber, instrument};
 use tracing_appender::{
     non_blocking::WorkerGuard,
     rolling::{RollingFileAppender, Rotation},
@@ -24,13 +19,13 @@ use tracing_subscriber::{
 };
 
 struct LogEventWriter {
-    app: AppHandle,
+    app: AppContext,
 }
 
 impl Write for LogEventWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let json_raw = String::from_utf8_lossy(buf).to_string();
-        let _ = LogEvent { json_raw }.emit(&self.app);
+        self.app.emit_log_event(LogEvent { json_raw });
         Ok(buf.len())
     }
 
@@ -40,7 +35,7 @@ impl Write for LogEventWriter {
 }
 
 struct LogEventWriterFactory {
-    app: AppHandle,
+    app: AppContext,
 }
 
 impl MakeWriter<'_> for LogEventWriterFactory {
@@ -56,8 +51,9 @@ impl MakeWriter<'_> for LogEventWriterFactory {
 static RELOAD_FN: OnceLock<Box<dyn Fn() -> eyre::Result<()> + Send + Sync>> = OnceLock::new();
 static GUARD: OnceLock<parking_lot::Mutex<Option<WorkerGuard>>> = OnceLock::new();
 
+/// `log_to_console`为`false`时不输出到控制台，避免日志淹没命令行的输出
 #[instrument(level = "error", skip_all)]
-pub fn init(app: &AppHandle) -> eyre::Result<()> {
+pub fn init(app: &AppContext, log_to_console: bool) -> eyre::Result<()> {
     let lib_module_path = module_path!();
     let lib_target = lib_module_path.split("::").next().ok_or_eyre(format!(
         "解析lib_target失败: lib_module_path={lib_module_path}"
@@ -68,12 +64,14 @@ pub fn init(app: &AppHandle) -> eyre::Result<()> {
     let (file_layer, guard) = create_file_layer(app)?;
     let (reloadable_file_layer, reload_handle) = tracing_subscriber::reload::Layer::new(file_layer);
     // 输出到控制台
-    let console_layer = layer()
-        .with_writer(std::io::stdout)
-        .with_timer(LocalTime::rfc_3339())
-        .with_file(true)
-        .with_line_number(true)
-        .pretty();
+    let console_layer = log_to_console.then(|| {
+        layer()
+            .with_writer(std::io::stdout)
+            .with_timer(LocalTime::rfc_3339())
+            .with_file(true)
+            .with_line_number(true)
+            .pretty()
+    });
     // 发送到前端
     let log_event_factory = LogEventWriterFactory { app: app.clone() };
     let log_event_layer = layer()
@@ -105,7 +103,7 @@ pub fn init(app: &AppHandle) -> eyre::Result<()> {
             Ok(())
         })
     });
-    tauri::async_runtime::spawn(file_log_watcher(app.clone()));
+    tokio::spawn(file_log_watcher(app.clone()));
 
     Ok(())
 }
@@ -125,7 +123,7 @@ pub fn disable_file_logger() -> eyre::Result<()> {
 
 #[instrument(level = "error", skip_all)]
 fn create_file_layer<S>(
-    app: &AppHandle,
+    app: &AppContext,
 ) -> eyre::Result<(Box<dyn Layer<S> + Send + Sync>, Option<WorkerGuard>)>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
@@ -142,7 +140,7 @@ where
             .json();
         return Ok((Box::new(sink_layer), None));
     }
-    let logs_dir = logs_dir(app).wrap_err("获取日志目录失败")?;
+    let logs_dir = logs_dir(app);
     let file_appender = RollingFileAppender::builder()
         .filename_prefix("bilibili-video-downloader")
         .filename_suffix("log")
@@ -161,20 +159,18 @@ where
 }
 
 #[instrument(level = "error", skip_all)]
-async fn file_log_watcher(app: AppHandle) {
+async fn file_log_watcher(app: AppContext) {
     let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
     let event_handler_span = tracing::error_span!("file_log_watcher_event_handler");
 
     let event_handler = move |res| {
-        let send_event_task = async {
-            if let Err(err) = sender.send(res).await.map_err(eyre::Report::from) {
-                let err_title = "发送日志文件watcher事件失败";
-                let message = err.to_message();
-                tracing::error!(err_title, message);
-            }
-        };
-
-        tauri::async_runtime::block_on(send_event_task.instrument(event_handler_span.clone()));
+        let _enter = event_handler_span.enter();
+        // notify在自己的线程中调用event_handler，可以直接阻塞
+        if let Err(err) = sender.blocking_send(res).map_err(eyre::Report::from) {
+            let err_title = "发送日志文件watcher事件失败";
+            let message = err.to_message();
+            tracing::error!(err_title, message);
+        }
     };
 
     let mut watcher = match RecommendedWatcher::new(event_handler, notify::Config::default())
@@ -189,15 +185,7 @@ async fn file_log_watcher(app: AppHandle) {
         }
     };
 
-    let logs_dir = match logs_dir(&app) {
-        Ok(logs_dir) => logs_dir,
-        Err(err) => {
-            let err_title = "日志文件watcher获取日志目录失败";
-            let message = err.to_message();
-            tracing::error!(err_title, message);
-            return;
-        }
-    };
+    let logs_dir = logs_dir(&app);
 
     if let Err(err) = watcher
         .watch(&logs_dir, notify::RecursiveMode::NonRecursive)
@@ -230,10 +218,6 @@ async fn file_log_watcher(app: AppHandle) {
 }
 
 #[instrument(level = "error", skip_all)]
-pub fn logs_dir(app: &AppHandle) -> eyre::Result<std::path::PathBuf> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .wrap_err("获取app_data_dir目录失败")?;
-    Ok(app_data_dir.join("日志"))
+pub fn logs_dir(app: &AppContext) -> std::path::PathBuf {
+    app.app_data_dir().join("日志")
 }
@@ -0,0 +1,633 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{WrapErr, eyre};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::instrument;
+
+#[cfg(feature = "gui")]
+use crate::types::mirror_sync::{AddMirrorSyncParams, UpdateMirrorSyncParams};
+use crate::{
+    app_context::AppContext,
+    downloader::download_progress::DownloadProgress,
+    extensions::EyreReportToMessage,
+    types::{
+        create_download_task_params::{CreateDownloadTaskParams, CreateNormalDownloadTaskParams},
+        get_fav_info_params::GetFavInfoParams,
+        get_normal_info_params::GetNormalInfoParams,
+        mirror_sync::{ArchivedItem, MirrorSource, MirrorSync, RemovedItemAction},
+    },
+};
+
+/// 同步关闭时，多久再看一次配置
+const DISABLED_RECHECK_INTERVAL: Duration = Duration::from_mins(1);
+
+/// 持久化到`mirror_sync.json`的数据
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct MirrorSyncStore {
+    mirror_syncs: Vec<MirrorSync>,
+    /// 键为`MirrorSource::key`
+    archived_items: HashMap<String, Vec<ArchivedItem>>,
+}
+
+/// 收藏夹或稍后再看中的一个视频
+#[derive(Debug, Clone, PartialEq)]
+struct FolderItem {
+    bvid: String,
+    title: String,
+    /// 已失效的视频仍然在收藏夹中，但无法下载
+    is_valid: bool,
+}
+
+pub struct MirrorSyncManager {
+    app: AppContext,
+    store: Mutex<MirrorSyncStore>,
+    /// 避免定时同步和手动同步同时进行
+    sync_lock: tokio::sync::Mutex<()>,
+}
+
+impl MirrorSyncManager {
+    #[instrument(level = "error", skip_all)]
+    pub fn new(app: AppContext) -> eyre::Result<Self> {
+        let store_path = get_store_path(&app);
+        let store = if store_path.exists() {
+            let json_string = std::fs::read_to_string(&store_path)
+                .wrap_err(format!("读取`{}`失败", store_path.display()))?;
+            serde_json::from_str(&json_string)
+                .wrap_err(format!("将`{}`解析为同步数据失败", store_path.display()))?
+        } else {
+            MirrorSyncStore::default()
+        };
+
+        tokio::spawn(Self::sync_loop(app.clone()));
+
+        Ok(Self {
+            app,
+            store: Mutex::new(store),
+            sync_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn get_mirror_syncs(&self) -> Vec<MirrorSync> {
+        self.store.lock().mirror_syncs.clone()
+    }
+
+    pub fn get_archived_items(&self, source: &MirrorSource) -> Vec<ArchivedItem> {
+        self.store
+            .lock()
+            .archived_items
+            .get(&source.key())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(source = ?params.source))]
+    pub fn add_mirror_sync(&self, params: AddMirrorSyncParams) -> eyre::Result<()> {
+        let mut store = self.store.lock();
+        if store.mirror_syncs.iter().any(|m| m.source == params.source) {
+            return Err(eyre!("已经添加过`{:?}`的同步", params.source));
+        }
+
+        store.mirror_syncs.push(MirrorSync {
+            source: params.source,
+            title: String::new(),
+            enabled: true,
+            removed_item_action: params.removed_item_action,
+            create_ts: now_ts(),
+            last_sync_ts: None,
+        });
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(source = ?params.source))]
+    pub fn update_mirror_sync(&self, params: &UpdateMirrorSyncParams) -> eyre::Result<()> {
+        let mut store = self.store.lock();
+        let Some(mirror_sync) = store
+            .mirror_syncs
+            .iter_mut()
+            .find(|m| m.source == params.source)
+        else {
+            return Err(eyre!("没有添加`{:?}`的同步", params.source));
+        };
+        mirror_sync.enabled = params.enabled;
+        mirror_sync.removed_item_action = params.removed_item_action;
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    /// 只删除同步配置和已归档记录，不会删除本地文件
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(source = ?source))]
+    pub fn remove_mirror_sync(&self, source: &MirrorSource) -> eyre::Result<()> {
+        let mut store = self.store.lock();
+        let len_before = store.mirror_syncs.len();
+        store.mirror_syncs.retain(|m| &m.source != source);
+        if store.mirror_syncs.len() == len_before {
+            return Err(eyre!("没有添加`{source:?}`的同步"));
+        }
+        store.archived_items.remove(&source.key());
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    /// 同步所有启用的收藏夹和稍后再看
+    ///
+    /// 单个同步失败不影响其他同步
+    #[instrument(level = "error", skip_all)]
+    pub async fn sync_all(&self) {
+        let _sync_guard = self.sync_lock.lock().await;
+
+        let mirror_syncs: Vec<MirrorSync> = self
+            .get_mirror_syncs()
+            .into_iter()
+            .filter(|m| m.enabled)
+            .collect();
+
+        for mirror_sync in mirror_syncs {
+            let source = mirror_sync.source.clone();
+            if let Err(err) = self.sync(mirror_sync).await {
+                let err_title = format!("同步`{source:?}`失败");
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+            }
+        }
+    }
+
+    #[instrument(level = "error", skip_all, fields(source = ?mirror_sync.source))]
+    async fn sync(&self, mirror_sync: MirrorSync) -> eyre::Result<()> {
+        let source = &mirror_sync.source;
+        let key = source.key();
+        // 必须拿到完整的列表，否则会把没拿到的视频当成已移除
+        let (title, items) = self.get_folder_items(source).await?;
+
+        let archived_items = self.get_archived_items(source);
+        let new_items = find_new_items(&items, &archived_items);
+        let mut created_count = 0;
+        for item in &new_items {
+            let new_archived_items = match self.create_download_tasks(item).await {
+                Ok(new_archived_items) => new_archived_items,
+                Err(err) => {
+                    // 不记为已归档，下次同步时重试
+                    let err_title = format!("为`{}`创建下载任务失败", item.bvid);
+                    let message = err.to_message();
+                    tracing::error!(err_title, message);
+                    continue;
+                }
+            };
+            created_count += new_archived_items.len();
+            // 每创建一次就保存，避免重启后重复创建下载任务
+            let mut store = self.store.lock();
+            store
+                .archived_items
+                .entry(key.clone())
+                .or_default()
+                .extend(new_archived_items);
+            self.save(&store)?;
+        }
+
+        let removed_count = self.handle_removed_items(&mirror_sync, &items)?;
+
+        let mut store = self.store.lock();
+        // 同步期间可能已被删除
+        let Some(stored_mirror_sync) = store.mirror_syncs.iter_mut().find(|m| &m.source == source)
+        else {
+            return Ok(());
+        };
+        stored_mirror_sync.title = title;
+        stored_mirror_sync.last_sync_ts = Some(now_ts());
+        self.save(&store)?;
+
+        tracing::info!(
+            item_count = items.len(),
+            created_count,
+            removed_count,
+            "同步完成"
+        );
+
+        Ok(())
+    }
+
+    /// 翻完所有页，返回标题和所有视频
+    async fn get_folder_items(
+        &self,
+        source: &MirrorSource,
+    ) -> eyre::Result<(String, Vec<FolderItem>)> {
+        let bili_client = self.app.get_bili_client();
+
+        let mut items = Vec::new();
+        match source {
+            MirrorSource::Fav { media_list_id } => {
+                let mut title = String::new();
+                for pn in 1.. {
+                    let params = GetFavInfoParams {
+                        media_list_id: *media_list_id,
+                        pn,
+                    };
+                    let fav_info = bili_client
+                        .get_fav_info(params)
+                        .await
+                        .wrap_err(format!("获取收藏夹第{pn}页失败"))?;
+                    title = fav_info.info.title;
+                    // 只有type为2的是视频，attr最低位为1表示视频已失效
+                    let medias = fav_info.medias.unwrap_or_default();
+                    items.extend(medias.into_iter().filter(|m| m.type_field == 2).map(|m| {
+                        FolderItem {
+                            bvid: m.bvid,
+                            title: m.title,
+                            is_valid: m.attr & 1 == 0,
+                        }
+                    }));
+                    if !fav_info.has_more {
+                        break;
+                    }
+                }
+                Ok((title, items))
+            }
+            MirrorSource::WatchLater => {
+                for page in 1.. {
+                    let watch_later_info = bili_client
+                        .get_watch_later_info(page)
+                        .await
+                        .wrap_err(format!("获取稍后再看第{page}页失败"))?;
+                    if watch_later_info.list.is_empty() {
+                        break;
+                    }
+                    // state小于0表示视频已失效
+                    items.extend(watch_later_info.list.into_iter().map(|m| FolderItem {
+                        bvid: m.bvid,
+                        title: m.title,
+                        is_valid: m.state >= 0,
+                    }));
+                    if items.len() >= usize::try_from(watch_later_info.count).unwrap_or_default() {
+                        break;
+                    }
+                }
+                Ok(("稍后再看".to_string(), items))
+            }
+        }
+    }
+
+    async fn create_download_tasks(&self, item: &FolderItem) -> eyre::Result<Vec<ArchivedItem>> {
+        let params = GetNormalInfoParams::Bvid(item.bvid.clone());
+        let info = self
+            .app
+            .get_bili_client()
+            .get_normal_info(params)
+            .await
+            .wrap_err("获取视频信息失败")?;
+
+        let params = CreateDownloadTaskParams::Normal(CreateNormalDownloadTaskParams {
+            aid_cid_pairs: vec![(info.aid, None)],
+            info,
+        });
+        let created = self
+            .app
+            .get_download_manager()
+            .create_download_tasks(&params);
+        if created.is_empty() {
+            return Err(eyre!("没有创建任何下载任务"));
+        }
+
+        let archive_ts = now_ts();
+        let archived_item = |task_id: Option<&str>, progress: &DownloadProgress| ArchivedItem {
+            bvid: item.bvid.clone(),
+            cid: progress.cid,
+            title: item.title.clone(),
+            task_id: task_id.map(ToString::to_string),
+            episode_dir: progress.episode_dir.clone(),
+            filename: progress.filename.clone(),
+            archive_ts,
+            removed_ts: None,
+        };
+        // 因为重复而跳过的下载也算归档，但本地文件不是这个同步下载的，不记录任务id，移除时不会删除
+        let archived_items = created
+            .tasks
+            .iter()
+            .map(|task| archived_item(Some(&task.task_id), &task.progress.read()))
+            .chain(
+                created
+                    .skipped_duplicates
+                    .iter()
+                    .map(|progress| archived_item(None, progress)),
+            )
+            .collect();
+
+        Ok(archived_items)
+    }
+
+    /// 按`removed_item_action`处理已经不在列表中的已归档视频，返回被处理的数量
+    fn handle_removed_items(
+        &self,
+        mirror_sync: &MirrorSync,
+        items: &[FolderItem],
+    ) -> eyre::Result<usize> {
+        let key = mirror_sync.source.key();
+        let mut store = self.store.lock();
+        let Some(archived_items) = store.archived_items.get_mut(&key) else {
+            return Ok(0);
+        };
+
+        let current_bvids: HashSet<&str> = items.iter().map(|i| i.bvid.as_str()).collect();
+        // 用`Record`记录过移除时间的视频重新加入后，清除移除时间
+        for archived_item in archived_items.iter_mut() {
+            if current_bvids.contains(archived_item.bvid.as_str()) {
+                archived_item.removed_ts = None;
+            }
+        }
+
+        let removed_indexes = find_removed_items(items, archived_items);
+        let removed_count = removed_indexes.len();
+        match mirror_sync.removed_item_action {
+            RemovedItemAction::Keep => {}
+            RemovedItemAction::Record => {
+                let removed_ts = now_ts();
+                for i in removed_indexes {
+                    archived_items[i].removed_ts = Some(removed_ts);
+                }
+            }
+            RemovedItemAction::Delete => {
+                let download_manager = self.app.get_download_manager();
+                let removed_indexes: HashSet<usize> = removed_indexes.into_iter().collect();
+                let mut index = 0;
+                archived_items.retain(|archived_item| {
+                    let is_removed = removed_indexes.contains(&index);
+                    index += 1;
+                    if !is_removed {
+                        return true;
+                    }
+
+                    // 只删除这个同步创建的任务的输出文件
+                    let Some(task_id) = archived_item.task_id.clone() else {
+                        return false;
+                    };
+                    // 任务还在时从下载进度中找输出文件，否则用下载完成时记录在下载历史中的输出文件
+                    let task = download_manager
+                        .download_tasks
+                        .read()
+                        .get(&task_id)
+                        .cloned();
+                    let output_paths = if let Some(task) = task {
+                        let output_paths = task.progress.read().output_paths();
+                        download_manager.delete_download_tasks(&vec![task_id]);
+                        output_paths
+                    } else {
+                        download_manager
+                            .download_history
+                            .find_by_task_id(&task_id)
+                            .map(|record| record.output_paths)
+                            .unwrap_or_default()
+                    };
+                    if let Err(err) = delete_local_files(&archived_item.episode_dir, &output_paths)
+                    {
+                        let err_title = format!("删除`{}`的本地文件失败", archived_item.bvid);
+                        let message = err.to_message();
+                        tracing::error!(err_title, message);
+                        // 保留记录，下次同步时重试
+                        return true;
+                    }
+                    false
+                });
+            }
+        }
+        self.save(&store)?;
+
+        Ok(removed_count)
+    }
+
+    fn save(&self, store: &MirrorSyncStore) -> eyre::Result<()> {
+        let store_path = get_store_path(&self.app);
+        let json_string = serde_json::to_string_pretty(store)?;
+        std::fs::write(&store_path, json_string)
+            .wrap_err(format!("保存`{}`失败", store_path.display()))?;
+        Ok(())
+    }
+
+    /// 按配置的间隔定时同步，间隔为0时不同步
+    async fn sync_loop(app: AppContext) {
+        loop {
+            let interval_min = app.get_config().read().mirror_sync_interval_min;
+            if interval_min == 0 {
+                sleep(DISABLED_RECHECK_INTERVAL).await;
+                continue;
+            }
+
+            sleep(Duration::from_secs(interval_min.saturating_mul(60))).await;
+            app.get_mirror_sync_manager().sync_all().await;
+        }
+    }
+}
+
+/// 返回还没归档过的有效视频
+fn find_new_items<'a>(
+    items: &'a [FolderItem],
+    archived_items: &[ArchivedItem],
+) -> Vec<&'a FolderItem> {
+    let archived_bvids: HashSet<&str> = archived_items.iter().map(|a| a.bvid.as_str()).collect();
+    let mut seen_bvids = HashSet::new();
+    items
+        .iter()
+        .filter(|item| item.is_valid)
+        .filter(|item| !archived_bvids.contains(item.bvid.as_str()))
+        // 稍后再看翻页时列表可能变动，导致同一个视频出现两次
+        .filter(|item| seen_bvids.insert(item.bvid.as_str()))
+        .collect()
+}
+
+/// 返回已经不在列表中、且还没记录过移除时间的已归档视频的下标
+fn find_removed_items(items: &[FolderItem], archived_items: &[ArchivedItem]) -> Vec<usize> {
+    let current_bvids: HashSet<&str> = items.iter().map(|i| i.bvid.as_str()).collect();
+    archived_items
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| a.removed_ts.is_none() && !current_bvids.contains(a.bvid.as_str()))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// 删除`output_paths`中的文件，`episode_dir`为空时一并删除
+///
+/// 只删除记录过的文件，同一目录下文件名以同样前缀开头的其他视频的文件不受影响
+fn delete_local_files(episode_dir: &Path, output_paths: &[PathBuf]) -> eyre::Result<()> {
+    for path in output_paths {
+        if !path.is_file() {
+            continue;
+        }
+        std::fs::remove_file(path).wrap_err(format!("删除`{}`失败", path.display()))?;
+    }
+    // 目录中还有其他文件时会失败，忽略即可
+    let _ = std::fs::remove_dir(episode_dir);
+
+    Ok(())
+}
+
+fn get_store_path(app: &AppContext) -> PathBuf {
+    app.app_data_dir().join("mirror_sync.json")
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs().cast_signed())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        downloader::download_history::DownloadHistoryRecord, test_support::create_test_app,
+    };
+
+    use super::*;
+
+    fn item(bvid: &str, is_valid: bool) -> FolderItem {
+        FolderItem {
+            bvid: bvid.to_string(),
+            title: String::new(),
+            is_valid,
+        }
+    }
+
+    fn archived(bvid: &str, removed_ts: Option<i64>) -> ArchivedItem {
+        ArchivedItem {
+            bvid: bvid.to_string(),
+            removed_ts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_items_skip_archived_invalid_and_duplicated() {
+        let items = [
+            item("BV1", true),
+            item("BV2", true),
+            item("BV3", false),
+            item("BV4", true),
+            item("BV4", true),
+        ];
+        let archived_items = [archived("BV2", None), archived("BV5", Some(1))];
+
+        let new_bvids: Vec<&str> = find_new_items(&items, &archived_items)
+            .iter()
+            .map(|i| i.bvid.as_str())
+            .collect();
+        assert_eq!(new_bvids, ["BV1", "BV4"]);
+    }
+
+    #[test]
+    fn removed_items_ignore_invalid_and_recorded() {
+        // 失效的视频仍在列表中，不算移除
+        let items = [item("BV1", true), item("BV2", false)];
+        let archived_items = [
+            archived("BV1", None),
+            archived("BV2", None),
+            archived("BV3", None),
+            archived("BV4", Some(1)),
+            archived("BV3", None),
+        ];
+
+        assert_eq!(find_removed_items(&items, &archived_items), [2, 4]);
+    }
+
+    #[test]
+    fn delete_local_files_only_deletes_recorded_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let episode_dir = temp_dir.path().join("合集");
+        std::fs::create_dir(&episode_dir).unwrap();
+        // 除了P1的输出，其他都是同一目录下其他视频的文件
+        for name in [
+            "P1.mp4",
+            "P1.弹幕.ass",
+            "P1-元数据.json",
+            "P1-xxx.mp4",
+            "P1.5.mp4",
+            "P10.mp4",
+        ] {
+            std::fs::write(episode_dir.join(name), "").unwrap();
+        }
+        let output_paths = |names: &[&str]| -> Vec<PathBuf> {
+            names.iter().map(|name| episode_dir.join(name)).collect()
+        };
+
+        let p1_paths = output_paths(&["P1.mp4", "P1.弹幕.ass", "P1-元数据.json", "P1.nfo"]);
+        delete_local_files(&episode_dir, &p1_paths).unwrap();
+
+        let mut remaining: Vec<String> = std::fs::read_dir(&episode_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, ["P1-xxx.mp4", "P1.5.mp4", "P10.mp4"]);
+
+        let other_paths = output_paths(&["P1-xxx.mp4", "P1.5.mp4", "P10.mp4"]);
+        delete_local_files(&episode_dir, &other_paths).unwrap();
+        assert!(!episode_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn removing_duplicate_skipped_item_keeps_other_task_files() {
+        let (app, _data_dir) = create_test_app(|_| {});
+        let episode_dir = app.app_data_dir().join("下载");
+        std::fs::create_dir(&episode_dir).unwrap();
+        let owned_path = episode_dir.join("BV1.mp4");
+        let other_path = episode_dir.join("BV2.mp4");
+        std::fs::write(&owned_path, "").unwrap();
+        std::fs::write(&other_path, "").unwrap();
+
+        // BV2是其他任务下载的，这个同步因为重复跳过了它
+        let download_history = &app.get_download_manager().download_history;
+        for (task_id, path) in [("owned", &owned_path), ("other", &other_path)] {
+            let record = DownloadHistoryRecord {
+                task_id: task_id.to_string(),
+                output_paths: vec![path.clone()],
+                ..Default::default()
+            };
+            download_history.add_record(record).unwrap();
+        }
+
+        let manager = MirrorSyncManager::new(app.clone()).unwrap();
+        let mirror_sync = MirrorSync {
+            source: MirrorSource::WatchLater,
+            title: String::new(),
+            enabled: true,
+            removed_item_action: RemovedItemAction::Delete,
+            create_ts: 0,
+            last_sync_ts: None,
+        };
+        let archived_items = vec![
+            ArchivedItem {
+                task_id: Some("owned".to_string()),
+                episode_dir: episode_dir.clone(),
+                ..archived("BV1", None)
+            },
+            ArchivedItem {
+                task_id: None,
+                episode_dir: episode_dir.clone(),
+                ..archived("BV2", None)
+            },
+        ];
+        manager
+            .store
+            .lock()
+            .archived_items
+            .insert(mirror_sync.source.key(), archived_items);
+
+        let removed_count = manager.handle_removed_items(&mirror_sync, &[]).unwrap();
+
+        assert_eq!(removed_count, 2);
+        assert!(!owned_path.exists());
+        assert!(other_path.exists());
+        assert!(manager.get_archived_items(&mirror_sync.source).is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+pub mod codec;
+pub mod ebml;
+pub mod subtitle;
+pub mod writer;
+
+use std::path::{Path, PathBuf};
+
+use eyre::{WrapErr, eyre};
+use tracing::instrument;
+
+use crate::{
+    downloader::{chapter_segments::ChapterSegments, cut_ranges::CutRanges},
+    mp4_muxer::{
+        self, cut,
+        track::{self, Track, TrackSource},
+    },
+    subtitle_converter,
+    types::subtitle::Subtitle,
+};
+
+use writer::{Attachment, Frame, FrameData, MkvFile, MkvTrack, TrackType};
+
+/// B站语言代码的主标签与ISO 639-2(B)代码的对应关系
+const ISO_639_2_CODES: [(&str, &str); 15] = [
+    ("zh", "chi"),
+    ("en", "eng"),
+    ("ja", "jpn"),
+    ("ko", "kor"),
+    ("es", "spa"),
+    ("fr", "fre"),
+    ("de", "ger"),
+    ("ru", "rus"),
+    ("pt", "por"),
+    ("ar", "ara"),
+    ("th", "tha"),
+    ("vi", "vie"),
+    ("id", "ind"),
+    ("ms", "may"),
+    ("it", "ita"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleFormat {
+    Srt,
+    Ass,
+    Vtt,
+    /// 只有开始时间，每句持续到下一句开始
+    Lrc,
+    /// B站的JSON字幕，转换为SRT后封装
+    Json,
+}
+
+impl SubtitleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Lrc => "lrc",
+            SubtitleFormat::Json => "json",
+        }
+    }
+}
+
+/// 要封装进MKV的一个字幕文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleSource {
+    pub path: PathBuf,
+    pub format: SubtitleFormat,
+    /// B站的语言代码，如`zh-CN`、`ai-zh`，为空表示未知语言
+    pub lan: String,
+    /// 轨道名
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// 封装MKV所需的全部输入
+pub struct MkvSources {
+    /// 视频和音频文件，可以是DASH的fMP4，也可以是普通MP4
+    pub media_paths: Vec<PathBuf>,
+    pub subtitles: Vec<SubtitleSource>,
+    /// 时间是剪切后的时间
+    pub chapter_segments: ChapterSegments,
+    /// 从视频和音频中剪掉的部分，字幕应该已经按剪切后的时间轴调整过
+    pub cut_ranges: CutRanges,
+    /// 作为附件嵌入的封面
+    pub cover_path: Option<PathBuf>,
+    pub title: String,
+}
+
+/// 把视频、音频、字幕、章节和封面无损封装为一个MKV，不依赖FFmpeg
+#[instrument(level = "error", skip_all, fields(output_path = ?output_path))]
+pub fn mux(sources: &MkvSources, output_path: &Path) -> eyre::Result<()> {
+    let mut mp4_tracks = Vec::new();
+    for path in &sources.media_paths {
+        let input_tracks =
+            track::read_tracks(path).wrap_err(format!("解析`{}`失败", path.display()))?;
+        mp4_tracks.extend(input_tracks);
+    }
+    if mp4_tracks.is_empty() {
+        return Err(eyre!("输入文件中没有视频或音频轨道"));
+    }
+    if !sources.cut_ranges.is_empty() {
+        mp4_tracks = mp4_tracks
+            .iter()
+            .map(|track| cut::cut_track(track, &sources.cut_ranges))
+            .collect();
+    }
+
+    let chapter_duration = mp4_muxer::chapter_duration(&mp4_tracks);
+    let chapters = mp4_muxer::create_chapters(&sources.chapter_segments, chapter_duration);
+    let duration = mp4_tracks
+        .iter()
+        .map(|track| to_ms(i128::from(track.presentation_duration()), track.timescale))
+        .max()
+        .unwrap_or(0);
+
+    let mut tracks = Vec::new();
+    for track in &mp4_tracks {
+        let TrackSource::File(path) = &track.source else {
+            return Err(eyre!("输入轨道不是来自文件"));
+        };
+        let codec_info =
+            codec::codec_info(track).wrap_err(format!("获取`{}`的编码信息失败", path.display()))?;
+        let frames = create_frames(track);
+        tracks.push(MkvTrack::from_codec_info(codec_info, path.clone(), frames));
+    }
+    for subtitle in &sources.subtitles {
+        let subtitle_track = create_subtitle_track(subtitle)
+            .wrap_err(format!("解析字幕`{}`失败", subtitle.path.display()))?;
+        tracks.push(subtitle_track);
+    }
+    shift_to_non_negative(&mut tracks);
+
+    let mut attachments = Vec::new();
+    if let Some(cover_path) = &sources.cover_path {
+        let attachment = create_cover_attachment(cover_path)
+            .wrap_err(format!("读取封面`{}`失败", cover_path.display()))?;
+        attachments.push(attachment);
+    }
+
+    let file = MkvFile {
+        title: sources.title.clone(),
+        duration: u64::try_from(duration).unwrap_or(0),
+        tracks,
+        chapters,
+        attachments,
+    };
+    writer::write_mkv(&file, output_path).wrap_err(format!("写入`{}`失败", output_path.display()))
+}
+
+/// 把以`timescale`为单位的时间向下取整为毫秒
+fn to_ms(time: i128, timescale: u32) -> i64 {
+    let ms = (time * 1000).div_euclid(i128::from(timescale));
+    i64::try_from(ms).unwrap_or(i64::MAX)
+}
+
+/// 每个样本是一帧，展示时间要减去编辑列表跳过的部分
+fn create_frames(track: &Track) -> Vec<Frame> {
+    let media_time = i128::from(track.media_time);
+
+    let mut frames = Vec::with_capacity(track.samples.len());
+    for chunk in &track.chunks {
+        let mut offset = chunk.offset;
+        let mut decode_time = i128::from(chunk.decode_time);
+        let samples = &track.samples[chunk.first_sample..chunk.first_sample + chunk.sample_count];
+        for sample in samples {
+            let presentation_time = decode_time + i128::from(sample.composition_offset);
+            frames.push(Frame {
+                timestamp: to_ms(presentation_time - media_time, track.timescale),
+                decode_time: to_ms(decode_time - media_time, track.timescale),
+                duration: None,
+                is_keyframe: sample.is_sync,
+                data: FrameData::File {
+                    offset,
+                    size: u64::from(sample.size),
+                },
+            });
+            offset += u64::from(sample.size);
+            decode_time += i128::from(sample.duration);
+        }
+    }
+
+    frames
+}
+
+fn create_subtitle_track(subtitle: &SubtitleSource) -> eyre::Result<MkvTrack> {
+    let content = std::fs::read_to_string(&subtitle.path)?;
+    let (codec_id, codec_private, events) = match subtitle.format {
+        SubtitleFormat::Srt => ("S_TEXT/UTF8", None, subtitle::parse_srt(&content)?),
+        SubtitleFormat::Ass => {
+            let (header, events) = subtitle::parse_ass(&content)?;
+            ("S_TEXT/ASS", Some(header.into_bytes()), events)
+        }
+        SubtitleFormat::Vtt => {
+            let (header, events) = subtitle::parse_vtt(&content)?;
+            ("S_TEXT/WEBVTT", Some(header.into_bytes()), events)
+        }
+        SubtitleFormat::Lrc => ("S_TEXT/UTF8", None, subtitle::parse_lrc(&content)),
+        SubtitleFormat::Json => {
+            let bili_subtitle: Subtitle =
+                serde_json::from_str(&content).wrap_err("将内容解析为Subtitle失败")?;
+            let srt = subtitle_converter::to_srt(&bili_subtitle);
+            ("S_TEXT/UTF8", None, subtitle::parse_srt(&srt)?)
+        }
+    };
+
+    let frames = events
+        .into_iter()
+        .map(|event| {
+            let start = i64::try_from(event.start).unwrap_or(i64::MAX);
+            Frame {
+                timestamp: start,
+                decode_time: start,
+                duration: Some(event.end - event.start),
+                is_keyframe: true,
+                data: FrameData::Memory(event.data),
+            }
+        })
+        .collect();
+    let (language, language_bcp47) = language_tags(&subtitle.lan);
+
+    Ok(MkvTrack {
+        track_type: TrackType::Subtitle,
+        codec_id,
+        codec_private,
+        codec_kind: None,
+        name: Some(subtitle.name.clone()),
+        language,
+        language_bcp47,
+        is_default: subtitle.is_default,
+        source: None,
+        frames,
+    })
+}
+
+/// 把B站的语言代码转为(ISO 639-2代码, BCP 47标签)，不认识的语言都是`und`
+fn language_tags(lan: &str) -> (String, String) {
+    // AI生成的字幕的语言代码带有`ai-`前缀，如`ai-zh`
+    let lan = lan.strip_prefix("ai-").unwrap_or(lan);
+    let primary = lan
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let language = ISO_639_2_CODES
+        .iter()
+        .find(|(code, _)| *code == primary)
+        .map_or("und", |(_, iso_639_2)| iso_639_2);
+    let is_valid_bcp47 =
+        !primary.is_empty() && lan.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    let language_bcp47 = if is_valid_bcp47 { lan } else { "und" };
+
+    (language.to_string(), language_bcp47.to_string())
+}
+
+/// MKV的时间戳不能为负数，编辑列表跳过音频的编码器延迟后，开头几帧的展示时间可能为负，
+/// 这时把所有轨道一起往后推
+fn shift_to_non_negative(tracks: &mut [MkvTrack]) {
+    let min_timestamp = tracks
+        .iter()
+        .flat_map(|track| &track.frames)
+        .map(|frame| frame.timestamp)
+        .min()
+        .unwrap_or(0);
+    if min_timestamp >= 0 {
+        return;
+    }
+
+    for frame in tracks.iter_mut().flat_map(|track| &mut track.frames) {
+        frame.timestamp -= min_timestamp;
+        frame.decode_time -= min_timestamp;
+    }
+}
+
+fn create_cover_attachment(cover_path: &Path) -> eyre::Result<Attachment> {
+    let ext = cover_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let media_type = match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        _ => return Err(eyre!("不支持的封面格式`{ext}`")),
+    };
+    let data = std::fs::read(cover_path)?;
+
+    // 播放器会把名为cover的图片附件当作封面
+    Ok(Attachment {
+        file_name: format!("cover.{ext}"),
+        media_type,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        downloader::chapter_segments::ChapterSegment,
+        test_support::{assert_golden, fixture_path},
+    };
+
+    use super::*;
+
+    #[test]
+    fn convert_bilibili_language_codes() {
+        let tags = |lan| language_tags(lan);
+        assert_eq!(tags("zh-CN"), ("chi".to_string(), "zh-CN".to_string()));
+        assert_eq!(tags("ai-en"), ("eng".to_string(), "en".to_string()));
+        assert_eq!(tags("zh-Hant"), ("chi".to_string(), "zh-Hant".to_string()));
+        assert_eq!(tags("nl"), ("und".to_string(), "nl".to_string()));
+        assert_eq!(tags(""), ("und".to_string(), "und".to_string()));
+    }
+
+    #[test]
+    fn mux_dash_with_subtitles_danmaku_chapters_and_cover() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("merged.mkv");
+        let mut chapter_segments = ChapterSegments {
+            segments: Vec::new(),
+        };
+        chapter_segments.insert(ChapterSegment {
+            title: "正片".to_string(),
+            start: 1,
+            end: 2,
+        });
+        let sources = MkvSources {
+            media_paths: vec![
+                fixture_path("mp4_muxer/video.m4s"),
+                fixture_path("mp4_muxer/audio.m4s"),
+            ],
+            subtitles: vec![
+                SubtitleSource {
+                    path: fixture_path("mkv_muxer/subtitle.zh-CN.srt"),
+                    format: SubtitleFormat::Srt,
+                    lan: "zh-CN".to_string(),
+                    name: "中文（中国）".to_string(),
+                    is_default: true,
+                },
+                SubtitleSource {
+                    path: fixture_path("mkv_muxer/danmaku.ass"),
+                    format: SubtitleFormat::Ass,
+                    lan: String::new(),
+                    name: "弹幕".to_string(),
+                    is_default: false,
+                },
+            ],
+            chapter_segments,
+            cut_ranges: CutRanges::default(),
+            cover_path: Some(fixture_path("mkv_muxer/cover.png")),
+            title: "测试视频".to_string(),
+        };
+
+        mux(&sources, &output_path).unwrap();
+
+        assert_golden(&output_path, "mkv_muxer/merged.mkv");
+    }
+}
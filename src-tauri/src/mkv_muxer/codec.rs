@@ -0,0 +1,226 @@
+use byteorder::{BigEndian, ByteOrder};
+use eyre::{OptionExt, eyre};
+
+use crate::mp4_muxer::{
+    bmff::{self, find_box, read_full_box_header},
+    track::{HANDLER_AUDIO, HANDLER_VIDEO, Track},
+};
+
+/// 视频样本描述中子Box之前的固定部分
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+/// 音频样本描述(version 0)中子Box之前的固定部分
+const AUDIO_SAMPLE_ENTRY_SIZE: usize = 28;
+
+/// MKV轨道的编码信息，从MP4的`stsd`转换而来
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecInfo {
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    pub kind: CodecKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodecKind {
+    Video {
+        width: u32,
+        height: u32,
+    },
+    Audio {
+        sampling_frequency: f64,
+        channels: u32,
+        bit_depth: Option<u32>,
+    },
+}
+
+/// 根据`track`的第一个样本描述确定MKV的`CodecID`和`CodecPrivate`
+pub fn codec_info(track: &Track) -> eyre::Result<CodecInfo> {
+    let entry = track.sample_entry()?;
+
+    match track.handler_type {
+        HANDLER_VIDEO => video_codec_info(entry.box_type, entry.payload),
+        HANDLER_AUDIO => audio_codec_info(entry.box_type, entry.payload, track.timescale),
+        handler_type => Err(eyre!(
+            "不支持封装为MKV的轨道类型`{}`",
+            bmff::fourcc(handler_type)
+        )),
+    }
+}
+
+fn video_codec_info(entry_type: [u8; 4], entry: &[u8]) -> eyre::Result<CodecInfo> {
+    if entry.len() < VISUAL_SAMPLE_ENTRY_SIZE {
+        return Err(eyre!("视频样本描述被截断"));
+    }
+    let kind = CodecKind::Video {
+        width: u32::from(BigEndian::read_u16(&entry[24..26])),
+        height: u32::from(BigEndian::read_u16(&entry[26..28])),
+    };
+    let children = &entry[VISUAL_SAMPLE_ENTRY_SIZE..];
+
+    let (codec_id, config_type) = match &entry_type {
+        b"avc1" | b"avc3" => ("V_MPEG4/ISO/AVC", *b"avcC"),
+        // 杜比视界的基础层也是HEVC
+        b"hvc1" | b"hev1" | b"dvh1" | b"dvhe" => ("V_MPEGH/ISO/HEVC", *b"hvcC"),
+        b"av01" => ("V_AV1", *b"av1C"),
+        _ => {
+            return Err(eyre!(
+                "不支持封装为MKV的视频编码`{}`",
+                bmff::fourcc(entry_type)
+            ));
+        }
+    };
+    let config = find_box(children, config_type)?
+        .ok_or_eyre(format!("找不到{} Box", bmff::fourcc(config_type)))?;
+
+    Ok(CodecInfo {
+        codec_id,
+        codec_private: Some(config.to_vec()),
+        kind,
+    })
+}
+
+fn audio_codec_info(entry_type: [u8; 4], entry: &[u8], timescale: u32) -> eyre::Result<CodecInfo> {
+    if entry.len() < AUDIO_SAMPLE_ENTRY_SIZE {
+        return Err(eyre!("音频样本描述被截断"));
+    }
+    // QuickTime的version 1和version 2在固定部分后面还有额外的字段
+    let children_offset = match BigEndian::read_u16(&entry[8..10]) {
+        1 => AUDIO_SAMPLE_ENTRY_SIZE + 16,
+        2 => AUDIO_SAMPLE_ENTRY_SIZE + 36,
+        _ => AUDIO_SAMPLE_ENTRY_SIZE,
+    };
+    let children = entry.get(children_offset..).unwrap_or_default();
+
+    let sample_rate = BigEndian::read_u32(&entry[24..28]) >> 16;
+    let mut kind = CodecKind::Audio {
+        sampling_frequency: f64::from(if sample_rate == 0 {
+            timescale
+        } else {
+            sample_rate
+        }),
+        channels: u32::from(BigEndian::read_u16(&entry[16..18])),
+        bit_depth: None,
+    };
+
+    let (codec_id, codec_private) = match &entry_type {
+        b"mp4a" => {
+            let esds = find_box(children, *b"esds")?.ok_or_eyre("找不到esds Box")?;
+            parse_esds(esds)?
+        }
+        b"fLaC" => {
+            let dfla = find_box(children, *b"dfLa")?.ok_or_eyre("找不到dfLa Box")?;
+            let (_, _, cursor) = read_full_box_header(dfla)?;
+            let metadata_blocks = &dfla[usize::try_from(cursor.position())?..];
+            // 96kHz以上的采样率无法用16.16定点数表示，以STREAMINFO为准
+            if let Some(stream_info) = parse_flac_stream_info(metadata_blocks) {
+                kind = stream_info;
+            }
+            let mut codec_private = b"fLaC".to_vec();
+            codec_private.extend_from_slice(metadata_blocks);
+            ("A_FLAC", Some(codec_private))
+        }
+        b"ec-3" => ("A_EAC3", None),
+        b"ac-3" => ("A_AC3", None),
+        _ => {
+            return Err(eyre!(
+                "不支持封装为MKV的音频编码`{}`",
+                bmff::fourcc(entry_type)
+            ));
+        }
+    };
+
+    Ok(CodecInfo {
+        codec_id,
+        codec_private,
+        kind,
+    })
+}
+
+/// 从`esds`中取出编码类型和`DecoderSpecificInfo`(AAC的`AudioSpecificConfig`)
+fn parse_esds(esds: &[u8]) -> eyre::Result<(&'static str, Option<Vec<u8>>)> {
+    const ES_DESCRIPTOR_TAG: u8 = 0x03;
+    const DECODER_CONFIG_DESCRIPTOR_TAG: u8 = 0x04;
+    const DECODER_SPECIFIC_INFO_TAG: u8 = 0x05;
+
+    let (_, _, cursor) = read_full_box_header(esds)?;
+    let data = &esds[usize::try_from(cursor.position())?..];
+
+    let es = find_descriptor(data, ES_DESCRIPTOR_TAG)?.ok_or_eyre("找不到ES_Descriptor")?;
+    let flags = *es.get(2).ok_or_eyre("ES_Descriptor被截断")?;
+    let mut offset = 3;
+    if flags & 0x80 != 0 {
+        // dependsOn_ES_ID
+        offset += 2;
+    }
+    if flags & 0x40 != 0 {
+        let url_len = *es.get(offset).ok_or_eyre("ES_Descriptor被截断")?;
+        offset += 1 + usize::from(url_len);
+    }
+    if flags & 0x20 != 0 {
+        // OCR_ES_Id
+        offset += 2;
+    }
+    let es_children = es.get(offset..).ok_or_eyre("ES_Descriptor被截断")?;
+
+    let decoder_config = find_descriptor(es_children, DECODER_CONFIG_DESCRIPTOR_TAG)?
+        .ok_or_eyre("找不到DecoderConfigDescriptor")?;
+    let object_type = *decoder_config
+        .first()
+        .ok_or_eyre("DecoderConfigDescriptor被截断")?;
+    // objectTypeIndication、streamType、bufferSizeDB、maxBitrate、avgBitrate
+    let decoder_specific_info = match decoder_config.get(13..) {
+        Some(children) => find_descriptor(children, DECODER_SPECIFIC_INFO_TAG)?,
+        None => None,
+    };
+
+    match object_type {
+        0x40 | 0x66 | 0x67 | 0x68 => {
+            let audio_specific_config =
+                decoder_specific_info.ok_or_eyre("AAC轨道缺少AudioSpecificConfig")?;
+            Ok(("A_AAC", Some(audio_specific_config.to_vec())))
+        }
+        0x69 | 0x6B => Ok(("A_MPEG/L3", None)),
+        _ => Err(eyre!(
+            "不支持封装为MKV的MPEG-4音频类型`0x{object_type:02X}`"
+        )),
+    }
+}
+
+/// 在一串MPEG-4描述符中找到标签为`tag`的那个，返回它的内容
+fn find_descriptor(mut data: &[u8], tag: u8) -> eyre::Result<Option<&[u8]>> {
+    while let Some((&current_tag, rest)) = data.split_first() {
+        // 长度每字节7位，最高位表示后面还有
+        let mut size = 0usize;
+        let mut header_len = 0;
+        loop {
+            let byte = *rest.get(header_len).ok_or_eyre("描述符长度被截断")?;
+            size = (size << 7) | usize::from(byte & 0x7F);
+            header_len += 1;
+            if byte & 0x80 == 0 || header_len == 4 {
+                break;
+            }
+        }
+        let content = rest
+            .get(header_len..header_len + size)
+            .ok_or_eyre("描述符被截断")?;
+        if current_tag == tag {
+            return Ok(Some(content));
+        }
+        data = &rest[header_len + size..];
+    }
+    Ok(None)
+}
+
+/// 解析FLAC的`STREAMINFO`，它总是第一个元数据块
+fn parse_flac_stream_info(metadata_blocks: &[u8]) -> Option<CodecKind> {
+    // 4字节块头，然后是最小/最大块大小(各2字节)、最小/最大帧大小(各3字节)
+    let info = metadata_blocks.get(4 + 10..4 + 14)?;
+    let sample_rate =
+        (u32::from(info[0]) << 12) | (u32::from(info[1]) << 4) | (u32::from(info[2]) >> 4);
+    let channels = u32::from((info[2] >> 1) & 0x07) + 1;
+    let bit_depth = ((u32::from(info[2]) & 0x01) << 4 | (u32::from(info[3]) >> 4)) + 1;
+    Some(CodecKind::Audio {
+        sampling_frequency: f64::from(sample_rate),
+        channels,
+        bit_depth: Some(bit_depth),
+    })
+}
@@ -0,0 +1,175 @@
+// EBML头部
+pub const EBML: u32 = 0x1A45_DFA3;
+pub const EBML_VERSION: u32 = 0x4286;
+pub const EBML_READ_VERSION: u32 = 0x42F7;
+pub const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+pub const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+pub const DOC_TYPE: u32 = 0x4282;
+pub const DOC_TYPE_VERSION: u32 = 0x4287;
+pub const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+
+pub const SEGMENT: u32 = 0x1853_8067;
+
+pub const SEEK_HEAD: u32 = 0x114D_9B74;
+pub const SEEK: u32 = 0x4DBB;
+pub const SEEK_ID: u32 = 0x53AB;
+pub const SEEK_POSITION: u32 = 0x53AC;
+
+pub const INFO: u32 = 0x1549_A966;
+pub const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+pub const DURATION: u32 = 0x4489;
+pub const TITLE: u32 = 0x7BA9;
+pub const MUXING_APP: u32 = 0x4D80;
+pub const WRITING_APP: u32 = 0x5741;
+
+pub const TRACKS: u32 = 0x1654_AE6B;
+pub const TRACK_ENTRY: u32 = 0xAE;
+pub const TRACK_NUMBER: u32 = 0xD7;
+pub const TRACK_UID: u32 = 0x73C5;
+pub const TRACK_TYPE: u32 = 0x83;
+pub const FLAG_DEFAULT: u32 = 0x88;
+pub const FLAG_LACING: u32 = 0x9C;
+pub const NAME: u32 = 0x536E;
+pub const LANGUAGE: u32 = 0x22_B59C;
+pub const LANGUAGE_BCP47: u32 = 0x22_B59D;
+pub const CODEC_ID: u32 = 0x86;
+pub const CODEC_PRIVATE: u32 = 0x63A2;
+pub const VIDEO: u32 = 0xE0;
+pub const PIXEL_WIDTH: u32 = 0xB0;
+pub const PIXEL_HEIGHT: u32 = 0xBA;
+pub const AUDIO: u32 = 0xE1;
+pub const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub const CHANNELS: u32 = 0x9F;
+pub const BIT_DEPTH: u32 = 0x6264;
+
+pub const CLUSTER: u32 = 0x1F43_B675;
+pub const TIMESTAMP: u32 = 0xE7;
+pub const SIMPLE_BLOCK: u32 = 0xA3;
+pub const BLOCK_GROUP: u32 = 0xA0;
+pub const BLOCK: u32 = 0xA1;
+pub const BLOCK_DURATION: u32 = 0x9B;
+
+pub const CUES: u32 = 0x1C53_BB6B;
+pub const CUE_POINT: u32 = 0xBB;
+pub const CUE_TIME: u32 = 0xB3;
+pub const CUE_TRACK_POSITIONS: u32 = 0xB7;
+pub const CUE_TRACK: u32 = 0xF7;
+pub const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+pub const CHAPTERS: u32 = 0x1043_A770;
+pub const EDITION_ENTRY: u32 = 0x45B9;
+pub const EDITION_UID: u32 = 0x45BC;
+pub const CHAPTER_ATOM: u32 = 0xB6;
+pub const CHAPTER_UID: u32 = 0x73C4;
+pub const CHAPTER_TIME_START: u32 = 0x91;
+pub const CHAPTER_TIME_END: u32 = 0x92;
+pub const CHAPTER_DISPLAY: u32 = 0x80;
+pub const CHAP_STRING: u32 = 0x85;
+pub const CHAP_LANGUAGE: u32 = 0x437C;
+
+pub const ATTACHMENTS: u32 = 0x1941_A469;
+pub const ATTACHED_FILE: u32 = 0x61A7;
+pub const FILE_NAME: u32 = 0x466E;
+pub const FILE_MEDIA_TYPE: u32 = 0x4660;
+pub const FILE_DATA: u32 = 0x465C;
+pub const FILE_UID: u32 = 0x46AE;
+
+/// 元素ID本身已经带有长度标记，去掉前导的0字节后原样写入
+pub fn write_id(buf: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let leading_zeros = (id.leading_zeros() / 8) as usize;
+    buf.extend_from_slice(&bytes[leading_zeros.min(3)..]);
+}
+
+/// 写入变长整数，使用能容纳`value`的最短长度
+///
+/// 每种长度下全为1的值表示未知尺寸，所以不能使用
+pub fn write_vint(buf: &mut Vec<u8>, value: u64) {
+    let mut len = 1;
+    while len < 8 && value >= (1 << (7 * len)) - 1 {
+        len += 1;
+    }
+    write_vint_with_len(buf, value, len);
+}
+
+/// 用固定的`len`字节写入变长整数，用于需要预留位置的场合
+pub fn write_vint_with_len(buf: &mut Vec<u8>, value: u64, len: usize) {
+    let marked = value | (1 << (7 * len));
+    buf.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+/// 在`buf`末尾写入一个主元素，`write_children`负责写入它的子元素
+pub fn write_master(buf: &mut Vec<u8>, id: u32, write_children: impl FnOnce(&mut Vec<u8>)) {
+    let mut children = Vec::new();
+    write_children(&mut children);
+    write_binary(buf, id, &children);
+}
+
+/// 只写入元素的ID和尺寸，内容由调用者随后写入
+pub fn write_header(buf: &mut Vec<u8>, id: u32, size: u64) {
+    write_id(buf, id);
+    write_vint(buf, size);
+}
+
+pub fn write_uint(buf: &mut Vec<u8>, id: u32, value: u64) {
+    let len = (8 - value.leading_zeros() as usize / 8).max(1);
+    write_header(buf, id, len as u64);
+    buf.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+}
+
+/// 总是用8字节写入无符号整数，这样元素的尺寸与值无关
+pub fn write_uint_fixed(buf: &mut Vec<u8>, id: u32, value: u64) {
+    write_header(buf, id, 8);
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn write_float(buf: &mut Vec<u8>, id: u32, value: f64) {
+    write_header(buf, id, 8);
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn write_string(buf: &mut Vec<u8>, id: u32, value: &str) {
+    write_binary(buf, id, value.as_bytes());
+}
+
+pub fn write_binary(buf: &mut Vec<u8>, id: u32, value: &[u8]) {
+    write_header(buf, id, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vint(value: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_vint(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn encode_vint_with_shortest_length() {
+        assert_eq!(vint(0), [0x80]);
+        assert_eq!(vint(126), [0xFE]);
+        // 0x7F在1字节下全为1，表示未知尺寸
+        assert_eq!(vint(127), [0x40, 0x7F]);
+        assert_eq!(vint(16382), [0x7F, 0xFE]);
+        assert_eq!(vint(16383), [0x20, 0x3F, 0xFF]);
+    }
+
+    #[test]
+    fn encode_elements() {
+        let mut buf = Vec::new();
+        write_uint(&mut buf, TRACK_NUMBER, 1);
+        write_uint(&mut buf, TIMESTAMP_SCALE, 1_000_000);
+        write_string(&mut buf, CODEC_ID, "A_AAC");
+        assert_eq!(
+            buf,
+            [
+                0xD7, 0x81, 0x01, // TrackNumber
+                0x2A, 0xD7, 0xB1, 0x83, 0x0F, 0x42, 0x40, // TimestampScale
+                0x86, 0x85, b'A', b'_', b'A', b'A', b'C', // CodecID
+            ]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use eyre::{OptionExt, WrapErr, eyre};
+
+/// LRC最后一句没有结束标记时持续的时间
+const LRC_LAST_LINE_MS: u64 = 5000;
+
+/// 一条字幕，时间的单位是毫秒
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleEvent {
+    pub start: u64,
+    pub end: u64,
+    /// 写入MKV块中的内容
+    pub data: Vec<u8>,
+}
+
+/// 解析SRT，块内容是纯文本(`S_TEXT/UTF8`)
+pub fn parse_srt(content: &str) -> eyre::Result<Vec<SubtitleEvent>> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+
+    let mut events = Vec::new();
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(time_line) = lines.next() else {
+            continue;
+        };
+        let (start, end) = time_line
+            .split_once("-->")
+            .ok_or_eyre("SRT时间行格式错误")?;
+        let start =
+            parse_srt_time(start.trim()).wrap_err(format!("解析SRT时间`{}`失败", start.trim()))?;
+        let end =
+            parse_srt_time(end.trim()).wrap_err(format!("解析SRT时间`{}`失败", end.trim()))?;
+        let text = lines.collect::<Vec<_>>().join("\n");
+
+        events.push(SubtitleEvent {
+            start,
+            end: end.max(start),
+            data: text.into_bytes(),
+        });
+    }
+
+    Ok(events)
+}
+
+/// 解析ASS，返回作为`CodecPrivate`的头部(到`[Events]`的`Format`行为止)和所有`Dialogue`
+///
+/// 按Matroska的规定，块内容是`ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`，
+/// 这里假设`[Events]`的字段顺序是标准的`Layer, Start, End, Style, ...`
+pub fn parse_ass(content: &str) -> eyre::Result<(String, Vec<SubtitleEvent>)> {
+    let content = content.trim_start_matches('\u{feff}');
+
+    let mut header = String::new();
+    let mut in_events = false;
+    let mut header_finished = false;
+    let mut events = Vec::new();
+    for line in content.lines() {
+        if !header_finished {
+            header.push_str(line);
+            header.push('\n');
+            if line.trim() == "[Events]" {
+                in_events = true;
+            } else if in_events && line.starts_with("Format:") {
+                header_finished = true;
+            }
+            continue;
+        }
+
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        let fields: Vec<&str> = dialogue.trim_start().splitn(10, ',').collect();
+        let [
+            layer,
+            start,
+            end,
+            style,
+            name,
+            margin_l,
+            margin_r,
+            margin_v,
+            effect,
+            text,
+        ] = fields[..]
+        else {
+            return Err(eyre!("ASS的Dialogue行字段不足: {line}"));
+        };
+        let start = parse_ass_time(start.trim()).wrap_err(format!("解析ASS时间`{start}`失败"))?;
+        let end = parse_ass_time(end.trim()).wrap_err(format!("解析ASS时间`{end}`失败"))?;
+        let read_order = events.len();
+
+        events.push(SubtitleEvent {
+            start,
+            end: end.max(start),
+            data: format!(
+                "{read_order},{layer},{style},{name},{margin_l},{margin_r},{margin_v},{effect},{text}"
+            )
+            .into_bytes(),
+        });
+    }
+
+    if !header_finished {
+        return Err(eyre!("ASS中找不到`[Events]`的Format行"));
+    }
+
+    Ok((header, events))
+}
+
+/// 解析WebVTT，返回作为`CodecPrivate`的头部(第一条cue之前的所有块)和所有cue
+///
+/// 块内容是cue的文本，cue的标识和设置会被丢弃
+pub fn parse_vtt(content: &str) -> eyre::Result<(String, Vec<SubtitleEvent>)> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    if !content.starts_with("WEBVTT") {
+        return Err(eyre!("WebVTT不是以`WEBVTT`开头"));
+    }
+
+    let mut header_blocks = Vec::new();
+    let mut events = Vec::new();
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(time_line) = lines.next() else {
+            if events.is_empty() && !block.trim().is_empty() {
+                header_blocks.push(block.trim());
+            }
+            continue;
+        };
+        let (start, rest) = time_line
+            .split_once("-->")
+            .ok_or_eyre("WebVTT时间行格式错误")?;
+        // 结束时间后面可能跟着cue设置
+        let end = rest
+            .split_whitespace()
+            .next()
+            .ok_or_eyre("WebVTT时间行缺少结束时间")?;
+        let start = parse_vtt_time(start.trim())
+            .wrap_err(format!("解析WebVTT时间`{}`失败", start.trim()))?;
+        let end = parse_vtt_time(end).wrap_err(format!("解析WebVTT时间`{end}`失败"))?;
+        let text = lines.collect::<Vec<_>>().join("\n");
+
+        events.push(SubtitleEvent {
+            start,
+            end: end.max(start),
+            data: text.into_bytes(),
+        });
+    }
+
+    Ok((header_blocks.join("\n\n"), events))
+}
+
+/// 解析LRC，块内容是纯文本(`S_TEXT/UTF8`)
+///
+/// 每句持续到下一行开始，空歌词只用来标记上一句的结束，`[ti:标题]`之类的标签会被忽略
+pub fn parse_lrc(content: &str) -> Vec<SubtitleEvent> {
+    let content = content.trim_start_matches('\u{feff}');
+
+    let mut lines: Vec<(u64, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let (tag, text) = line.strip_prefix('[')?.split_once(']')?;
+            let start = parse_lrc_time(tag).ok()?;
+            Some((start, text.trim()))
+        })
+        .collect();
+    lines.sort_by_key(|&(start, _)| start);
+
+    let mut events = Vec::new();
+    for (i, &(start, text)) in lines.iter().enumerate() {
+        if text.is_empty() {
+            continue;
+        }
+        let end = lines
+            .get(i + 1)
+            .map_or(start + LRC_LAST_LINE_MS, |&(next_start, _)| next_start);
+        events.push(SubtitleEvent {
+            start,
+            end: end.max(start),
+            data: text.as_bytes().to_vec(),
+        });
+    }
+
+    events
+}
+
+/// `HH:MM:SS,mmm`
+fn parse_srt_time(time: &str) -> eyre::Result<u64> {
+    let (hms, ms) = time.split_once(',').ok_or_eyre("缺少毫秒")?;
+    let seconds = parse_hms(hms)?;
+    let ms: u64 = ms.parse()?;
+    Ok(seconds * 1000 + ms)
+}
+
+/// `H:MM:SS.cc`，秒的小数部分是百分之一秒
+fn parse_ass_time(time: &str) -> eyre::Result<u64> {
+    let (hms, centiseconds) = time.split_once('.').ok_or_eyre("缺少小数部分")?;
+    let seconds = parse_hms(hms)?;
+    let centiseconds: u64 = centiseconds.parse()?;
+    Ok(seconds * 1000 + centiseconds * 10)
+}
+
+/// `HH:MM:SS.mmm`，小时可以省略
+fn parse_vtt_time(time: &str) -> eyre::Result<u64> {
+    let (hms, ms) = time.split_once('.').ok_or_eyre("缺少毫秒")?;
+    let seconds = if hms.matches(':').count() == 1 {
+        parse_hms(&format!("0:{hms}"))?
+    } else {
+        parse_hms(hms)?
+    };
+    let ms: u64 = ms.parse()?;
+    Ok(seconds * 1000 + ms)
+}
+
+/// `mm:ss.xx`，分钟可以超过59，小数部分可以是一到三位
+fn parse_lrc_time(time: &str) -> eyre::Result<u64> {
+    let (minutes, seconds) = time.split_once(':').ok_or_eyre("缺少冒号")?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let (minutes, seconds, fraction_value): (u64, u64, u64) =
+        (minutes.parse()?, seconds.parse()?, fraction.parse()?);
+    let fraction_ms = match fraction.len() {
+        1 => fraction_value * 100,
+        2 => fraction_value * 10,
+        3 => fraction_value,
+        _ => return Err(eyre!("小数部分的位数不对")),
+    };
+    Ok((minutes * 60 + seconds) * 1000 + fraction_ms)
+}
+
+fn parse_hms(hms: &str) -> eyre::Result<u64> {
+    let mut parts = hms.split(':');
+    let (Some(h), Some(m), Some(s), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(eyre!("时间不是`时:分:秒`格式"));
+    };
+    let (h, m, s): (u64, u64, u64) = (h.parse()?, m.parse()?, s.parse()?);
+    Ok(h * 3600 + m * 60 + s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_srt_events() {
+        let srt = "1\r\n00:00:00,500 --> 00:00:01,250\r\n第一行\r\n第二行\r\n\r\n\
+                   2\r\n01:02:03,004 --> 01:02:04,000\r\nhello\r\n";
+
+        let events = parse_srt(srt).unwrap();
+
+        assert_eq!(
+            events,
+            [
+                SubtitleEvent {
+                    start: 500,
+                    end: 1250,
+                    data: "第一行\n第二行".as_bytes().to_vec(),
+                },
+                SubtitleEvent {
+                    start: 3_723_004,
+                    end: 3_724_000,
+                    data: b"hello".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_vtt_header_and_cues() {
+        let vtt = "WEBVTT\n\nNOTE 由B站字幕转换\n\n\
+                   00:00.500 --> 00:01.250 line:90%\n第一行\n第二行\n\n\
+                   cue-2\n01:02:03.004 --> 01:02:04.000\nhello &amp; bye\n";
+
+        let (header, events) = parse_vtt(vtt).unwrap();
+
+        assert_eq!(header, "WEBVTT\n\nNOTE 由B站字幕转换");
+        assert_eq!(
+            events,
+            [
+                SubtitleEvent {
+                    start: 500,
+                    end: 1250,
+                    data: "第一行\n第二行".as_bytes().to_vec(),
+                },
+                SubtitleEvent {
+                    start: 3_723_004,
+                    end: 3_724_000,
+                    data: b"hello &amp; bye".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lrc_lines_until_next_line() {
+        let lrc = "[ti:测试]\n[00:01.50]第一句\n[00:03.00]第二句\n[00:04.25]\n[01:00.00]最后一句\n";
+
+        let events = parse_lrc(lrc);
+
+        assert_eq!(
+            events,
+            [
+                SubtitleEvent {
+                    start: 1500,
+                    end: 3000,
+                    data: "第一句".as_bytes().to_vec(),
+                },
+                SubtitleEvent {
+                    start: 3000,
+                    end: 4250,
+                    data: "第二句".as_bytes().to_vec(),
+                },
+                SubtitleEvent {
+                    start: 60_000,
+                    end: 60_000 + LRC_LAST_LINE_MS,
+                    data: "最后一句".as_bytes().to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ass_header_and_dialogues() {
+        let ass = "[Script Info]\nTitle: 测试\n\n[V4+ Styles]\nStyle: Float,黑体\n\n\
+                   [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 2,0:00:01.50,0:00:09.50,Float,,0,0,0,,{\\move(1, 2, 3, 4)}弹幕,带逗号\n";
+
+        let (header, events) = parse_ass(ass).unwrap();
+
+        assert!(header.starts_with("[Script Info]\n"));
+        assert!(header.ends_with("Effect, Text\n"));
+        assert_eq!(
+            events,
+            [SubtitleEvent {
+                start: 1500,
+                end: 9500,
+                data: "0,2,Float,,0,0,0,,{\\move(1, 2, 3, 4)}弹幕,带逗号"
+                    .as_bytes()
+                    .to_vec(),
+            }]
+        );
+    }
+}
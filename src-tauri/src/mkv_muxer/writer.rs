@@ -0,0 +1,559 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::{WrapErr, eyre};
+
+use crate::mp4_muxer::Chapter;
+
+use super::{
+    codec::{CodecInfo, CodecKind},
+    ebml::{self, write_binary, write_float, write_master, write_string, write_uint},
+};
+
+const APP_NAME: &str = "bilibili-video-downloader";
+/// 时间戳的单位是1毫秒
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// 主轨道遇到关键帧时，如果当前Cluster已经这么长(毫秒)就开始新的Cluster
+const CLUSTER_DURATION_MS: i64 = 1000;
+/// `SimpleBlock`的`Flags`中的关键帧标记
+const FLAG_KEYFRAME: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackType {
+    Video,
+    Audio,
+    Subtitle,
+}
+
+impl TrackType {
+    fn value(self) -> u64 {
+        match self {
+            TrackType::Video => 1,
+            TrackType::Audio => 2,
+            TrackType::Subtitle => 0x11,
+        }
+    }
+}
+
+pub struct MkvTrack {
+    pub track_type: TrackType,
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    /// 视频或音频的参数，字幕轨道为`None`
+    pub codec_kind: Option<CodecKind>,
+    pub name: Option<String>,
+    /// ISO 639-2语言代码
+    pub language: String,
+    /// BCP 47语言标签
+    pub language_bcp47: String,
+    pub is_default: bool,
+    /// `FrameData::File`的数据来源
+    pub source: Option<PathBuf>,
+    pub frames: Vec<Frame>,
+}
+
+impl MkvTrack {
+    pub fn from_codec_info(codec_info: CodecInfo, source: PathBuf, frames: Vec<Frame>) -> Self {
+        let track_type = match codec_info.kind {
+            CodecKind::Video { .. } => TrackType::Video,
+            CodecKind::Audio { .. } => TrackType::Audio,
+        };
+        MkvTrack {
+            track_type,
+            codec_id: codec_info.codec_id,
+            codec_private: codec_info.codec_private,
+            codec_kind: Some(codec_info.kind),
+            name: None,
+            language: "und".to_string(),
+            language_bcp47: "und".to_string(),
+            is_default: true,
+            source: Some(source),
+            frames,
+        }
+    }
+}
+
+/// 一帧视频或音频，或者一条字幕，时间的单位是毫秒
+pub struct Frame {
+    /// 展示时间
+    pub timestamp: i64,
+    /// 解码时间，用于在轨道之间交错排列
+    pub decode_time: i64,
+    /// 只有字幕需要写入时长
+    pub duration: Option<u64>,
+    pub is_keyframe: bool,
+    pub data: FrameData,
+}
+
+pub enum FrameData {
+    File { offset: u64, size: u64 },
+    Memory(Vec<u8>),
+}
+
+impl FrameData {
+    fn len(&self) -> u64 {
+        match self {
+            FrameData::File { size, .. } => *size,
+            FrameData::Memory(data) => data.len() as u64,
+        }
+    }
+}
+
+pub struct Attachment {
+    pub file_name: String,
+    pub media_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+pub struct MkvFile {
+    pub title: String,
+    /// 毫秒
+    pub duration: u64,
+    pub tracks: Vec<MkvTrack>,
+    pub chapters: Vec<Chapter>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// 规划好的一个Cluster
+struct Cluster {
+    timestamp: u64,
+    blocks: Vec<BlockLayout>,
+    /// 不包括Cluster自身头部的尺寸
+    size: u64,
+    /// 以主轨道的关键帧开头，可以作为Cue
+    is_cue: bool,
+}
+
+/// 一个块在帧数据前后需要写入的内容
+struct BlockLayout {
+    track_index: usize,
+    frame_index: usize,
+    header: Vec<u8>,
+    /// `BlockGroup`中跟在`Block`后面的`BlockDuration`
+    trailer: Vec<u8>,
+}
+
+/// 把`file`写成MKV，`Segment`中依次是`SeekHead`、`Info`、`Tracks`、`Chapters`、`Attachments`、
+/// 所有的`Cluster`和`Cues`
+///
+/// 所有元素的尺寸都预先算好，只需要顺序写一遍
+pub fn write_mkv(file: &MkvFile, output_path: &Path) -> eyre::Result<()> {
+    let cue_track = file
+        .tracks
+        .iter()
+        .position(|track| track.track_type == TrackType::Video)
+        .unwrap_or(0);
+    let clusters = plan_clusters(file, cue_track)?;
+
+    let info = create_info(file);
+    let tracks = create_tracks(&file.tracks);
+    let chapters = create_chapters(&file.chapters, file.duration);
+    let attachments = create_attachments(&file.attachments);
+
+    // SeekPosition是定长的，先用0占位算出SeekHead的长度
+    let seek_head_len = create_seek_head(&[(ebml::INFO, 0); 5]).len() as u64;
+    let mut position = seek_head_len;
+    let mut seek_entries = Vec::new();
+    for (id, element) in [
+        (ebml::INFO, &info),
+        (ebml::TRACKS, &tracks),
+        (ebml::CHAPTERS, &chapters),
+        (ebml::ATTACHMENTS, &attachments),
+    ] {
+        if !element.is_empty() {
+            seek_entries.push((id, position));
+            position += element.len() as u64;
+        }
+    }
+
+    let mut cluster_positions = Vec::with_capacity(clusters.len());
+    for cluster in &clusters {
+        cluster_positions.push(position);
+        position += element_len(ebml::CLUSTER, cluster.size);
+    }
+    let cues = create_cues(&clusters, &cluster_positions, cue_track);
+    seek_entries.push((ebml::CUES, position));
+    position += cues.len() as u64;
+
+    // 没有Chapters或Attachments时用Void补齐，保证SeekHead的长度不变
+    let mut seek_head = create_seek_head(&seek_entries);
+    let padding = seek_head_len - seek_head.len() as u64;
+    if padding > 0 {
+        write_void(&mut seek_head, padding);
+    }
+
+    let output_file =
+        File::create(output_path).wrap_err(format!("创建文件`{}`失败", output_path.display()))?;
+    let mut writer = BufWriter::new(output_file);
+
+    let mut header = create_ebml_header();
+    ebml::write_header(&mut header, ebml::SEGMENT, position);
+    writer.write_all(&header)?;
+    for element in [&seek_head, &info, &tracks, &chapters, &attachments] {
+        writer.write_all(element)?;
+    }
+    write_clusters(file, &clusters, &mut writer)?;
+    writer.write_all(&cues)?;
+    writer
+        .flush()
+        .wrap_err(format!("写入文件`{}`失败", output_path.display()))?;
+
+    Ok(())
+}
+
+/// 元素头部加上内容的总长度
+fn element_len(id: u32, size: u64) -> u64 {
+    let mut header = Vec::new();
+    ebml::write_header(&mut header, id, size);
+    header.len() as u64 + size
+}
+
+/// 按解码时间交错排列所有帧并划分Cluster
+fn plan_clusters(file: &MkvFile, cue_track: usize) -> eyre::Result<Vec<Cluster>> {
+    let mut order: Vec<(i64, usize, usize)> = file
+        .tracks
+        .iter()
+        .enumerate()
+        .flat_map(|(track_index, track)| {
+            track
+                .frames
+                .iter()
+                .enumerate()
+                .map(move |(frame_index, frame)| (frame.decode_time, track_index, frame_index))
+        })
+        .collect();
+    order.sort_unstable();
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for (_, track_index, frame_index) in order {
+        let frame = &file.tracks[track_index].frames[frame_index];
+        let timestamp = u64::try_from(frame.timestamp)
+            .map_err(|_| eyre!("帧的时间戳`{}`为负数", frame.timestamp))?;
+        let is_cue = track_index == cue_track && frame.is_keyframe;
+
+        let start_new_cluster = match clusters.last() {
+            None => true,
+            Some(cluster) => {
+                let relative = i128::from(timestamp) - i128::from(cluster.timestamp);
+                i16::try_from(relative).is_err()
+                    || (is_cue && relative >= i128::from(CLUSTER_DURATION_MS))
+            }
+        };
+        if start_new_cluster {
+            let mut timestamp_element = Vec::new();
+            write_uint(&mut timestamp_element, ebml::TIMESTAMP, timestamp);
+            clusters.push(Cluster {
+                timestamp,
+                blocks: Vec::new(),
+                size: timestamp_element.len() as u64,
+                is_cue,
+            });
+        }
+
+        let Some(cluster) = clusters.last_mut() else {
+            unreachable!()
+        };
+        // 上面已经保证了相对时间戳在i16的范围内
+        #[allow(clippy::cast_possible_truncation)]
+        let relative = (i128::from(timestamp) - i128::from(cluster.timestamp)) as i16;
+        let (header, trailer) = create_block_layout(track_index + 1, relative, frame);
+        cluster.size += (header.len() + trailer.len()) as u64 + frame.data.len();
+        cluster.blocks.push(BlockLayout {
+            track_index,
+            frame_index,
+            header,
+            trailer,
+        });
+    }
+
+    Ok(clusters)
+}
+
+/// 返回帧数据前后需要写入的内容，有时长的帧(字幕)写成`BlockGroup`，其余写成`SimpleBlock`
+fn create_block_layout(
+    track_number: usize,
+    relative_timestamp: i16,
+    frame: &Frame,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut block_header = Vec::new();
+    ebml::write_vint(&mut block_header, track_number as u64);
+    block_header.extend_from_slice(&relative_timestamp.to_be_bytes());
+    let block_size = block_header.len() as u64 + 1 + frame.data.len();
+
+    let mut header = Vec::new();
+    let mut trailer = Vec::new();
+    if let Some(duration) = frame.duration {
+        // Block没有关键帧标记
+        block_header.push(0);
+        write_uint(&mut trailer, ebml::BLOCK_DURATION, duration);
+        let group_size = element_len(ebml::BLOCK, block_size) + trailer.len() as u64;
+        ebml::write_header(&mut header, ebml::BLOCK_GROUP, group_size);
+        ebml::write_header(&mut header, ebml::BLOCK, block_size);
+    } else {
+        block_header.push(if frame.is_keyframe { FLAG_KEYFRAME } else { 0 });
+        ebml::write_header(&mut header, ebml::SIMPLE_BLOCK, block_size);
+    }
+    header.extend_from_slice(&block_header);
+
+    (header, trailer)
+}
+
+/// 记录读取位置，顺序读取时不需要真的`seek`
+struct SourceReader {
+    reader: BufReader<File>,
+    position: u64,
+}
+
+fn write_clusters(
+    file: &MkvFile,
+    clusters: &[Cluster],
+    writer: &mut BufWriter<File>,
+) -> eyre::Result<()> {
+    let mut readers: HashMap<&Path, SourceReader> = HashMap::new();
+    for cluster in clusters {
+        let mut header = Vec::new();
+        ebml::write_header(&mut header, ebml::CLUSTER, cluster.size);
+        write_uint(&mut header, ebml::TIMESTAMP, cluster.timestamp);
+        writer.write_all(&header)?;
+
+        for block in &cluster.blocks {
+            let track = &file.tracks[block.track_index];
+            let frame = &track.frames[block.frame_index];
+
+            writer.write_all(&block.header)?;
+            match &frame.data {
+                FrameData::Memory(data) => writer.write_all(data)?,
+                FrameData::File { offset, size } => {
+                    let Some(path) = &track.source else {
+                        return Err(eyre!("轨道缺少数据来源"));
+                    };
+                    if !readers.contains_key(path.as_path()) {
+                        let file = File::open(path)
+                            .wrap_err(format!("打开文件`{}`失败", path.display()))?;
+                        let reader = SourceReader {
+                            reader: BufReader::with_capacity(1 << 20, file),
+                            position: 0,
+                        };
+                        readers.insert(path, reader);
+                    }
+                    let Some(reader) = readers.get_mut(path.as_path()) else {
+                        unreachable!()
+                    };
+                    copy_frame(reader, *offset, *size, writer)
+                        .wrap_err(format!("从`{}`复制样本数据失败", path.display()))?;
+                }
+            }
+            writer.write_all(&block.trailer)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_frame(
+    source: &mut SourceReader,
+    offset: u64,
+    size: u64,
+    writer: &mut BufWriter<File>,
+) -> eyre::Result<()> {
+    if offset != source.position {
+        let distance = i128::from(offset) - i128::from(source.position);
+        match i64::try_from(distance) {
+            // 在缓冲区范围内时不会丢弃缓冲区
+            Ok(distance) => source.reader.seek_relative(distance)?,
+            Err(_) => {
+                source.reader.seek(SeekFrom::Start(offset))?;
+            }
+        }
+    }
+
+    let copied = io::copy(&mut (&mut source.reader).take(size), writer)?;
+    source.position = offset + copied;
+    if copied != size {
+        return Err(eyre!("样本数据不完整，需要{size}字节，只读到{copied}字节"));
+    }
+    Ok(())
+}
+
+fn create_ebml_header() -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::EBML, |buf| {
+        write_uint(buf, ebml::EBML_VERSION, 1);
+        write_uint(buf, ebml::EBML_READ_VERSION, 1);
+        write_uint(buf, ebml::EBML_MAX_ID_LENGTH, 4);
+        write_uint(buf, ebml::EBML_MAX_SIZE_LENGTH, 8);
+        write_string(buf, ebml::DOC_TYPE, "matroska");
+        // LanguageBCP47需要version 4
+        write_uint(buf, ebml::DOC_TYPE_VERSION, 4);
+        write_uint(buf, ebml::DOC_TYPE_READ_VERSION, 2);
+    });
+    buf
+}
+
+fn create_seek_head(entries: &[(u32, u64)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::SEEK_HEAD, |buf| {
+        for &(id, position) in entries {
+            write_master(buf, ebml::SEEK, |buf| {
+                let mut id_bytes = Vec::new();
+                ebml::write_id(&mut id_bytes, id);
+                write_binary(buf, ebml::SEEK_ID, &id_bytes);
+                ebml::write_uint_fixed(buf, ebml::SEEK_POSITION, position);
+            });
+        }
+    });
+    buf
+}
+
+/// 写入一个总长度为`len`的`Void`元素，`len`至少为2
+fn write_void(buf: &mut Vec<u8>, len: u64) {
+    const VOID: u32 = 0xEC;
+
+    let mut size_len = 1;
+    while size_len < 8 && len - 1 - size_len >= (1 << (7 * size_len)) - 1 {
+        size_len += 1;
+    }
+    let size = len - 1 - size_len;
+    ebml::write_id(buf, VOID);
+    #[allow(clippy::cast_possible_truncation)]
+    ebml::write_vint_with_len(buf, size, size_len as usize);
+    #[allow(clippy::cast_possible_truncation)]
+    buf.resize(buf.len() + size as usize, 0);
+}
+
+fn create_info(file: &MkvFile) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::INFO, |buf| {
+        write_uint(buf, ebml::TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+        write_string(buf, ebml::MUXING_APP, APP_NAME);
+        write_string(buf, ebml::WRITING_APP, APP_NAME);
+        #[allow(clippy::cast_precision_loss)]
+        write_float(buf, ebml::DURATION, file.duration as f64);
+        if !file.title.is_empty() {
+            write_string(buf, ebml::TITLE, &file.title);
+        }
+    });
+    buf
+}
+
+fn create_tracks(tracks: &[MkvTrack]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::TRACKS, |buf| {
+        for (i, track) in tracks.iter().enumerate() {
+            write_master(buf, ebml::TRACK_ENTRY, |buf| {
+                write_track_entry(buf, i + 1, track);
+            });
+        }
+    });
+    buf
+}
+
+fn write_track_entry(buf: &mut Vec<u8>, track_number: usize, track: &MkvTrack) {
+    write_uint(buf, ebml::TRACK_NUMBER, track_number as u64);
+    write_uint(buf, ebml::TRACK_UID, track_number as u64);
+    write_uint(buf, ebml::TRACK_TYPE, track.track_type.value());
+    write_uint(buf, ebml::FLAG_DEFAULT, u64::from(track.is_default));
+    write_uint(buf, ebml::FLAG_LACING, 0);
+    if let Some(name) = &track.name {
+        write_string(buf, ebml::NAME, name);
+    }
+    write_string(buf, ebml::LANGUAGE, &track.language);
+    write_string(buf, ebml::LANGUAGE_BCP47, &track.language_bcp47);
+    write_string(buf, ebml::CODEC_ID, track.codec_id);
+    if let Some(codec_private) = &track.codec_private {
+        write_binary(buf, ebml::CODEC_PRIVATE, codec_private);
+    }
+
+    match track.codec_kind {
+        Some(CodecKind::Video { width, height }) => {
+            write_master(buf, ebml::VIDEO, |buf| {
+                write_uint(buf, ebml::PIXEL_WIDTH, u64::from(width));
+                write_uint(buf, ebml::PIXEL_HEIGHT, u64::from(height));
+            });
+        }
+        Some(CodecKind::Audio {
+            sampling_frequency,
+            channels,
+            bit_depth,
+        }) => {
+            write_master(buf, ebml::AUDIO, |buf| {
+                write_float(buf, ebml::SAMPLING_FREQUENCY, sampling_frequency);
+                write_uint(buf, ebml::CHANNELS, u64::from(channels));
+                if let Some(bit_depth) = bit_depth {
+                    write_uint(buf, ebml::BIT_DEPTH, u64::from(bit_depth));
+                }
+            });
+        }
+        None => {}
+    }
+}
+
+/// 没有章节时返回空的`Vec`
+fn create_chapters(chapters: &[Chapter], duration: u64) -> Vec<u8> {
+    if chapters.is_empty() {
+        return Vec::new();
+    }
+
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::CHAPTERS, |buf| {
+        write_master(buf, ebml::EDITION_ENTRY, |buf| {
+            write_uint(buf, ebml::EDITION_UID, 1);
+            for (i, chapter) in chapters.iter().enumerate() {
+                let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+                write_master(buf, ebml::CHAPTER_ATOM, |buf| {
+                    write_uint(buf, ebml::CHAPTER_UID, i as u64 + 1);
+                    // 章节的时间总是以纳秒为单位
+                    write_uint(buf, ebml::CHAPTER_TIME_START, chapter.start * 1_000_000);
+                    write_uint(buf, ebml::CHAPTER_TIME_END, end * 1_000_000);
+                    write_master(buf, ebml::CHAPTER_DISPLAY, |buf| {
+                        write_string(buf, ebml::CHAP_STRING, &chapter.title);
+                        write_string(buf, ebml::CHAP_LANGUAGE, "und");
+                    });
+                });
+            }
+        });
+    });
+    buf
+}
+
+/// 没有附件时返回空的`Vec`
+fn create_attachments(attachments: &[Attachment]) -> Vec<u8> {
+    if attachments.is_empty() {
+        return Vec::new();
+    }
+
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::ATTACHMENTS, |buf| {
+        for (i, attachment) in attachments.iter().enumerate() {
+            write_master(buf, ebml::ATTACHED_FILE, |buf| {
+                write_string(buf, ebml::FILE_NAME, &attachment.file_name);
+                write_string(buf, ebml::FILE_MEDIA_TYPE, attachment.media_type);
+                write_binary(buf, ebml::FILE_DATA, &attachment.data);
+                write_uint(buf, ebml::FILE_UID, i as u64 + 1);
+            });
+        }
+    });
+    buf
+}
+
+fn create_cues(clusters: &[Cluster], cluster_positions: &[u64], cue_track: usize) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_master(&mut buf, ebml::CUES, |buf| {
+        for (cluster, &position) in clusters.iter().zip(cluster_positions) {
+            if !cluster.is_cue {
+                continue;
+            }
+            write_master(buf, ebml::CUE_POINT, |buf| {
+                write_uint(buf, ebml::CUE_TIME, cluster.timestamp);
+                write_master(buf, ebml::CUE_TRACK_POSITIONS, |buf| {
+                    write_uint(buf, ebml::CUE_TRACK, cue_track as u64 + 1);
+                    write_uint(buf, ebml::CUE_CLUSTER_POSITION, position);
+                });
+            });
+        }
+    });
+    buf
+}
@@ -0,0 +1,284 @@
+pub mod bmff;
+pub mod cut;
+pub mod track;
+pub mod writer;
+
+use std::path::Path;
+
+use eyre::{WrapErr, eyre};
+use tracing::instrument;
+
+use crate::downloader::{chapter_segments::ChapterSegments, cut_ranges::CutRanges};
+
+use track::{Chunk, HANDLER_TEXT, HANDLER_VIDEO, Sample, Track, TrackSource};
+
+/// `und`，未指定语言
+const LANGUAGE_UNDETERMINED: u16 = 0x55C4;
+
+/// 写入MP4的章节，时间的单位是毫秒
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: u64,
+}
+
+/// 把`input_paths`中所有的视频和音频轨道无损合并为一个普通MP4，不依赖FFmpeg
+///
+/// 输入可以是DASH的fMP4，也可以是普通MP4，输入中已有的章节轨道会被丢弃，
+/// `chapter_segments`不为空时重新写入章节，它的时间应该是剪切后的时间。
+/// `cut_ranges`覆盖的部分会从所有轨道中剪掉
+#[instrument(level = "error", skip_all, fields(output_path = ?output_path))]
+pub fn mux(
+    input_paths: &[&Path],
+    chapter_segments: &ChapterSegments,
+    cut_ranges: &CutRanges,
+    output_path: &Path,
+) -> eyre::Result<()> {
+    let mut tracks = Vec::new();
+    for path in input_paths {
+        let input_tracks =
+            track::read_tracks(path).wrap_err(format!("解析`{}`失败", path.display()))?;
+        tracks.extend(input_tracks);
+    }
+    if !cut_ranges.is_empty() {
+        tracks = tracks
+            .iter()
+            .map(|track| cut::cut_track(track, cut_ranges))
+            .collect();
+    }
+    if tracks.is_empty() {
+        return Err(eyre!("输入文件中没有视频或音频轨道"));
+    }
+
+    let duration = chapter_duration(&tracks);
+    let chapters = create_chapters(chapter_segments, duration);
+    if !chapters.is_empty() {
+        tracks.push(create_chapter_track(&chapters, duration));
+    }
+
+    writer::write_mp4(&tracks, &chapters, None, output_path)
+        .wrap_err(format!("写入`{}`失败", output_path.display()))
+}
+
+/// 章节覆盖的时长(毫秒)，以视频的时长为准，音频通常会比视频长一点点
+pub fn chapter_duration(tracks: &[Track]) -> u64 {
+    let Some(duration_track) = tracks
+        .iter()
+        .find(|track| track.handler_type == HANDLER_VIDEO)
+        .or(tracks.first())
+    else {
+        return 0;
+    };
+    let duration = u128::from(duration_track.presentation_duration()) * 1000
+        / u128::from(duration_track.timescale);
+    u64::try_from(duration).unwrap_or(u64::MAX)
+}
+
+/// 把以秒为单位的`chapter_segments`转为覆盖`0..duration`毫秒的章节
+pub fn create_chapters(chapter_segments: &ChapterSegments, duration: u64) -> Vec<Chapter> {
+    if chapter_segments.segments.is_empty() {
+        return Vec::new();
+    }
+
+    let mut chapters: Vec<Chapter> = chapter_segments
+        .fill_gaps(duration.div_ceil(1000))
+        .into_iter()
+        .map(|segment| Chapter {
+            title: segment.title,
+            start: u64::try_from(segment.start)
+                .unwrap_or(0)
+                .saturating_mul(1000),
+        })
+        .filter(|chapter| chapter.start < duration)
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters.dedup_by_key(|chapter| chapter.start);
+
+    chapters
+}
+
+/// 每个章节是一个样本，时长持续到下一个章节开始
+fn create_chapter_track(chapters: &[Chapter], duration: u64) -> Track {
+    // 文字样本的格式是2字节长度+UTF-8标题，后面跟着声明编码为UTF-8的encd Box
+    const ENCD_BOX: [u8; 12] = [0, 0, 0, 12, b'e', b'n', b'c', b'd', 0, 0, 1, 0];
+
+    let mut data = Vec::new();
+    let mut samples = Vec::with_capacity(chapters.len());
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+        let title = chapter.title.as_bytes();
+        let title = &title[..title.len().min(usize::from(u16::MAX))];
+
+        let sample_start = data.len();
+        #[allow(clippy::cast_possible_truncation)]
+        data.extend_from_slice(&(title.len() as u16).to_be_bytes());
+        data.extend_from_slice(title);
+        data.extend_from_slice(&ENCD_BOX);
+
+        #[allow(clippy::cast_possible_truncation)]
+        samples.push(Sample {
+            size: (data.len() - sample_start) as u32,
+            duration: u32::try_from(end - chapter.start).unwrap_or(u32::MAX),
+            composition_offset: 0,
+            is_sync: true,
+        });
+    }
+
+    let chunk = Chunk {
+        offset: 0,
+        size: data.len() as u64,
+        first_sample: 0,
+        sample_count: samples.len(),
+        decode_time: 0,
+    };
+
+    Track {
+        handler_type: HANDLER_TEXT,
+        timescale: 1000,
+        language: LANGUAGE_UNDETERMINED,
+        width: 0,
+        height: 0,
+        stsd: create_tx3g_stsd(),
+        media_time: 0,
+        samples,
+        chunks: vec![chunk],
+        source: TrackSource::Memory(data),
+    }
+}
+
+/// 只包含一个默认样式的`tx3g`(3GPP Timed Text)样本描述
+fn create_tx3g_stsd() -> Vec<u8> {
+    let mut buf = Vec::new();
+    bmff::write_full_box(&mut buf, *b"stsd", 0, 0, |buf| {
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        bmff::write_box(buf, *b"tx3g", |buf| {
+            // reserved和data_reference_index
+            buf.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            // displayFlags、对齐方式、背景色
+            buf.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+            // BoxRecord
+            buf.extend_from_slice(&[0; 8]);
+            // StyleRecord
+            buf.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+            bmff::write_box(buf, *b"ftab", |buf| {
+                // 一个ID为1、名字为空的字体
+                buf.extend_from_slice(&[0, 1, 0, 1, 0]);
+            });
+        });
+    });
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        downloader::chapter_segments::ChapterSegment,
+        test_support::{assert_golden, fixture_path},
+    };
+
+    use super::*;
+
+    fn chapter_segments() -> ChapterSegments {
+        let mut chapter_segments = ChapterSegments {
+            segments: Vec::new(),
+        };
+        chapter_segments.insert(ChapterSegment {
+            title: "开头".to_string(),
+            start: 0,
+            end: 1,
+        });
+        chapter_segments.insert(ChapterSegment {
+            title: "正片".to_string(),
+            start: 1,
+            end: 2,
+        });
+        chapter_segments
+    }
+
+    fn no_chapters() -> ChapterSegments {
+        ChapterSegments {
+            segments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn merge_dash_video_and_audio() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("merged.mp4");
+        let inputs = [
+            &*fixture_path("mp4_muxer/video.m4s"),
+            &*fixture_path("mp4_muxer/audio.m4s"),
+        ];
+
+        mux(&inputs, &no_chapters(), &CutRanges::default(), &output_path).unwrap();
+
+        assert_golden(&output_path, "mp4_muxer/merged.mp4");
+    }
+
+    #[test]
+    fn merge_dash_video_and_audio_with_chapters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("merged_with_chapters.mp4");
+        let inputs = [
+            &*fixture_path("mp4_muxer/video.m4s"),
+            &*fixture_path("mp4_muxer/audio.m4s"),
+        ];
+
+        mux(
+            &inputs,
+            &chapter_segments(),
+            &CutRanges::default(),
+            &output_path,
+        )
+        .unwrap();
+
+        assert_golden(&output_path, "mp4_muxer/merged_with_chapters.mp4");
+    }
+
+    #[test]
+    fn embed_chapters_into_merged_mp4() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("embedded.mp4");
+        let inputs = [&*fixture_path("mp4_muxer/merged.mp4")];
+
+        mux(
+            &inputs,
+            &chapter_segments(),
+            &CutRanges::default(),
+            &output_path,
+        )
+        .unwrap();
+
+        // 普通MP4的Chunk划分和交错顺序不变，结果应该与直接合并时一致
+        assert_golden(&output_path, "mp4_muxer/merged_with_chapters.mp4");
+    }
+
+    #[test]
+    fn replace_existing_chapters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("replaced.mp4");
+        let inputs = [&*fixture_path("mp4_muxer/merged_with_chapters.mp4")];
+
+        mux(&inputs, &no_chapters(), &CutRanges::default(), &output_path).unwrap();
+
+        assert_golden(&output_path, "mp4_muxer/merged.mp4");
+    }
+
+    #[test]
+    fn reject_truncated_input() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let video = std::fs::read(fixture_path("mp4_muxer/video.m4s")).unwrap();
+        let truncated_path = temp_dir.path().join("truncated.m4s");
+        std::fs::write(&truncated_path, &video[..video.len() - 10]).unwrap();
+        let output_path = temp_dir.path().join("output.mp4");
+
+        let result = mux(
+            &[&truncated_path],
+            &no_chapters(),
+            &CutRanges::default(),
+            &output_path,
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use eyre::eyre;
+
+/// 内存中的一个Box，`payload`不包括头部
+pub struct Mp4Box<'a> {
+    pub box_type: [u8; 4],
+    pub payload: &'a [u8],
+}
+
+/// 依次遍历一段内存中的Box
+pub struct BoxIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for BoxIter<'a> {
+    type Item = eyre::Result<Mp4Box<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let result = self.next_box();
+        if result.is_err() {
+            // 出错后不再继续遍历
+            self.data = &[];
+        }
+        Some(result)
+    }
+}
+
+impl<'a> BoxIter<'a> {
+    fn next_box(&mut self) -> eyre::Result<Mp4Box<'a>> {
+        if self.data.len() < 8 {
+            return Err(eyre!("Box头部被截断，剩余{}字节", self.data.len()));
+        }
+
+        let size_field = BigEndian::read_u32(&self.data[0..4]);
+        let mut box_type = [0u8; 4];
+        box_type.copy_from_slice(&self.data[4..8]);
+
+        let (header_size, box_size) = match size_field {
+            // Box延伸到末尾
+            0 => (8, self.data.len() as u64),
+            1 => {
+                if self.data.len() < 16 {
+                    return Err(eyre!("Box`{}`的64位尺寸被截断", fourcc(box_type)));
+                }
+                (16, BigEndian::read_u64(&self.data[8..16]))
+            }
+            _ => (8, u64::from(size_field)),
+        };
+
+        let box_size = usize::try_from(box_size).unwrap_or(usize::MAX);
+        if box_size < header_size || box_size > self.data.len() {
+            return Err(eyre!(
+                "Box`{}`的尺寸{box_size}不合法，剩余{}字节",
+                fourcc(box_type),
+                self.data.len()
+            ));
+        }
+
+        let payload = &self.data[header_size..box_size];
+        self.data = &self.data[box_size..];
+
+        Ok(Mp4Box { box_type, payload })
+    }
+}
+
+pub fn iter_boxes(data: &[u8]) -> BoxIter<'_> {
+    BoxIter { data }
+}
+
+/// 找到`data`中第一个类型为`box_type`的Box，返回它的`payload`
+pub fn find_box(data: &[u8], box_type: [u8; 4]) -> eyre::Result<Option<&[u8]>> {
+    for mp4_box in iter_boxes(data) {
+        let mp4_box = mp4_box?;
+        if mp4_box.box_type == box_type {
+            return Ok(Some(mp4_box.payload));
+        }
+    }
+    Ok(None)
+}
+
+/// 沿着`path`逐层查找Box，返回最后一层的`payload`
+pub fn find_box_path<'a>(data: &'a [u8], path: &[[u8; 4]]) -> eyre::Result<Option<&'a [u8]>> {
+    let mut current = data;
+    for &box_type in path {
+        match find_box(current, box_type)? {
+            Some(payload) => current = payload,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// 找到`data`中所有类型为`box_type`的Box，返回它们的`payload`
+pub fn find_boxes(data: &[u8], box_type: [u8; 4]) -> eyre::Result<Vec<&[u8]>> {
+    let mut payloads = Vec::new();
+    for mp4_box in iter_boxes(data) {
+        let mp4_box = mp4_box?;
+        if mp4_box.box_type == box_type {
+            payloads.push(mp4_box.payload);
+        }
+    }
+    Ok(payloads)
+}
+
+/// 读取`FullBox`的`version`和`flags`，返回的`Cursor`位于它们之后
+pub fn read_full_box_header(payload: &[u8]) -> eyre::Result<(u8, u32, Cursor<&[u8]>)> {
+    let mut cursor = Cursor::new(payload);
+    let version_and_flags = cursor.read_u32::<BigEndian>()?;
+    #[allow(clippy::cast_possible_truncation)]
+    let version = (version_and_flags >> 24) as u8;
+    let flags = version_and_flags & 0x00FF_FFFF;
+    Ok((version, flags, cursor))
+}
+
+/// 读取表的条目数，每个条目占`entry_size`字节
+///
+/// 条目数来自文件，条目放不进Box剩余的部分时返回错误，避免按损坏的条目数分配内存
+pub fn read_entry_count(cursor: &mut Cursor<&[u8]>, entry_size: usize) -> eyre::Result<usize> {
+    let count = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+    let position = usize::try_from(cursor.position())?;
+    let remaining = cursor.get_ref().len().saturating_sub(position);
+    if count
+        .checked_mul(entry_size)
+        .is_none_or(|size| size > remaining)
+    {
+        return Err(eyre!(
+            "条目数{count}(每个{entry_size}字节)超出了Box剩余的{remaining}字节"
+        ));
+    }
+    Ok(count)
+}
+
+pub fn fourcc(box_type: [u8; 4]) -> String {
+    String::from_utf8_lossy(&box_type).to_string()
+}
+
+/// 在`buf`末尾写入一个Box，`write_payload`负责写入`payload`
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_box(buf: &mut Vec<u8>, box_type: [u8; 4], write_payload: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&box_type);
+    write_payload(buf);
+    // moov等Box的尺寸远小于4GB
+    let size = (buf.len() - start) as u32;
+    BigEndian::write_u32(&mut buf[start..start + 4], size);
+}
+
+/// 在`buf`末尾写入一个`FullBox`，`write_payload`负责写入`version`和`flags`之后的部分
+pub fn write_full_box(
+    buf: &mut Vec<u8>,
+    box_type: [u8; 4],
+    version: u8,
+    flags: u32,
+    write_payload: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(buf, box_type, |buf| {
+        buf.extend_from_slice(&((u32::from(version) << 24) | (flags & 0x00FF_FFFF)).to_be_bytes());
+        write_payload(buf);
+    });
+}
@@ -0,0 +1,116 @@
+use crate::downloader::cut_ranges::CutRanges;
+
+use super::track::{Chunk, Track};
+
+/// 可以作为剪切点的时间(毫秒)：每个关键帧的展示时间，以及轨道的结尾，按升序排列
+pub fn cut_points(track: &Track) -> Vec<u64> {
+    let mut points: Vec<u64> = sample_times(track)
+        .zip(&track.samples)
+        .filter(|(_, sample)| sample.is_sync)
+        .filter_map(|(time, _)| u64::try_from(time).ok())
+        .collect();
+    let end = u128::from(track.presentation_duration()) * 1000 / u128::from(track.timescale);
+    points.push(u64::try_from(end).unwrap_or(u64::MAX));
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+/// 删掉展示时间落在`cut_ranges`中的样本，后面的样本往前补上
+///
+/// 剪切范围的两端应该是视频的关键帧，这样剪切后的视频不需要重新编码也能正常解码
+pub fn cut_track(track: &Track, cut_ranges: &CutRanges) -> Track {
+    let is_kept: Vec<bool> = sample_times(track)
+        .map(|time| u64::try_from(time).map_or(true, |time| !cut_ranges.contains(time)))
+        .collect();
+
+    let mut samples = Vec::with_capacity(track.samples.len());
+    let mut chunks = Vec::with_capacity(track.chunks.len());
+    let mut decode_time = track.chunks.first().map_or(0, |chunk| chunk.decode_time);
+    for chunk in &track.chunks {
+        let mut offset = chunk.offset;
+        // 连续保留的样本组成一个新的Chunk
+        let mut current: Option<Chunk> = None;
+        let range = chunk.first_sample..chunk.first_sample + chunk.sample_count;
+        for (&sample, &kept) in track.samples[range.clone()].iter().zip(&is_kept[range]) {
+            if kept {
+                let current = current.get_or_insert(Chunk {
+                    offset,
+                    size: 0,
+                    first_sample: samples.len(),
+                    sample_count: 0,
+                    decode_time,
+                });
+                current.size += u64::from(sample.size);
+                current.sample_count += 1;
+                samples.push(sample);
+                decode_time += u64::from(sample.duration);
+            } else if let Some(current) = current.take() {
+                chunks.push(current);
+            }
+            offset += u64::from(sample.size);
+        }
+        chunks.extend(current);
+    }
+
+    Track {
+        samples,
+        chunks,
+        ..track.clone()
+    }
+}
+
+/// 按顺序返回每个样本减去编辑列表跳过的部分后的展示时间(毫秒)
+fn sample_times(track: &Track) -> impl Iterator<Item = i64> + '_ {
+    let media_time = i128::from(track.media_time);
+    let timescale = i128::from(track.timescale);
+    track.chunks.iter().flat_map(move |chunk| {
+        let samples = &track.samples[chunk.first_sample..chunk.first_sample + chunk.sample_count];
+        let mut decode_time = i128::from(chunk.decode_time);
+        samples.iter().map(move |sample| {
+            let presentation_time = decode_time + i128::from(sample.composition_offset);
+            decode_time += i128::from(sample.duration);
+            let ms = ((presentation_time - media_time) * 1000).div_euclid(timescale);
+            i64::try_from(ms).unwrap_or(i64::MAX)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        downloader::cut_ranges::CutRange,
+        mp4_muxer::track::{HANDLER_VIDEO, read_tracks},
+        test_support::fixture_path,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cut_video_at_keyframes() {
+        let video_path = fixture_path("mp4_muxer/video.m4s");
+        let track = read_tracks(&video_path)
+            .unwrap()
+            .into_iter()
+            .find(|track| track.handler_type == HANDLER_VIDEO)
+            .unwrap();
+        let cut_points = cut_points(&track);
+        let keyframe_count = track.samples.iter().filter(|s| s.is_sync).count();
+        assert_eq!(cut_points.len(), keyframe_count + 1);
+        let (start, end) = (cut_points[0], cut_points[1]);
+
+        let cut = cut_track(&track, &CutRanges(vec![CutRange { start, end }]));
+
+        // 剪掉第一个GOP后，第一个样本就是原来的第二个关键帧
+        let removed = track.samples.len() - cut.samples.len();
+        assert!(removed > 0);
+        assert_eq!(cut.samples[..], track.samples[removed..]);
+        assert!(cut.samples[0].is_sync);
+        let sample_count: usize = cut.chunks.iter().map(|chunk| chunk.sample_count).sum();
+        assert_eq!(sample_count, cut.samples.len());
+        assert_eq!(
+            cut.presentation_duration() * 1000 / u64::from(track.timescale),
+            (track.presentation_duration() * 1000 / u64::from(track.timescale)) - (end - start)
+        );
+    }
+}
@@ -0,0 +1,658 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use eyre::{OptionExt, WrapErr, eyre};
+
+use super::bmff::{
+    self, find_box, find_box_path, find_boxes, read_entry_count, read_full_box_header,
+};
+
+pub const HANDLER_VIDEO: [u8; 4] = *b"vide";
+pub const HANDLER_AUDIO: [u8; 4] = *b"soun";
+pub const HANDLER_TEXT: [u8; 4] = *b"text";
+
+/// `trun`和`tfhd`中表示非关键帧的`sample_is_non_sync_sample`
+const SAMPLE_FLAG_NON_SYNC: u32 = 0x0001_0000;
+
+/// 一条可以无损复制到新文件的轨道
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub handler_type: [u8; 4],
+    pub timescale: u32,
+    /// ISO-639-2/T语言代码，按`mdhd`的格式压缩为15位
+    pub language: u16,
+    /// 16.16定点数，音频轨道为0
+    pub width: u32,
+    pub height: u32,
+    /// 完整的`stsd` Box，包括头部，里面是编码器配置
+    pub stsd: Vec<u8>,
+    /// 编辑列表中媒体的起始时间，单位是`timescale`，用于跳过B帧带来的延迟或音频的编码器延迟
+    pub media_time: i64,
+    pub samples: Vec<Sample>,
+    pub chunks: Vec<Chunk>,
+    pub source: TrackSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub size: u32,
+    pub duration: u32,
+    pub composition_offset: i32,
+    pub is_sync: bool,
+}
+
+/// 源文件中连续存放的一组样本，复制时整块复制
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk {
+    /// 在源中的偏移
+    pub offset: u64,
+    pub size: u64,
+    pub first_sample: usize,
+    pub sample_count: usize,
+    pub decode_time: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackSource {
+    File(PathBuf),
+    /// 章节轨道这种自己生成的轨道，数据直接放在内存里
+    Memory(Vec<u8>),
+}
+
+impl Track {
+    /// 所有样本的总时长，单位是`timescale`
+    pub fn media_duration(&self) -> u64 {
+        self.samples.iter().map(|s| u64::from(s.duration)).sum()
+    }
+
+    /// 去掉编辑列表跳过的部分后的时长，单位是`timescale`
+    pub fn presentation_duration(&self) -> u64 {
+        let media_time = u64::try_from(self.media_time).unwrap_or(0);
+        self.media_duration().saturating_sub(media_time)
+    }
+
+    /// `stsd`中的第一个样本描述，它的类型就是编码格式，如`avc1`、`mp4a`、`fLaC`
+    pub fn sample_entry(&self) -> eyre::Result<bmff::Mp4Box<'_>> {
+        let stsd = bmff::iter_boxes(&self.stsd)
+            .next()
+            .ok_or_eyre("stsd为空")??;
+        let (_, _, cursor) = read_full_box_header(stsd.payload)?;
+        // 跳过entry_count
+        let entries = stsd
+            .payload
+            .get(usize::try_from(cursor.position())? + 4..)
+            .ok_or_eyre("stsd被截断")?;
+        bmff::iter_boxes(entries)
+            .next()
+            .ok_or_eyre("stsd中没有样本描述")?
+    }
+}
+
+/// 文件顶层的Box，只记录位置
+struct TopLevelBox {
+    box_type: [u8; 4],
+    offset: u64,
+    size: u64,
+}
+
+/// `trex`或`tfhd`中的样本默认值
+#[derive(Default, Clone, Copy)]
+struct SampleDefaults {
+    duration: u32,
+    size: u32,
+    flags: u32,
+}
+
+/// 读取`path`中所有的视频和音频轨道，同时支持普通MP4和DASH使用的分片MP4(fMP4)
+#[allow(clippy::similar_names)]
+pub fn read_tracks(path: &Path) -> eyre::Result<Vec<Track>> {
+    let file = File::open(path).wrap_err(format!("打开文件`{}`失败", path.display()))?;
+    let file_size = file
+        .metadata()
+        .wrap_err(format!("获取文件`{}`元数据失败", path.display()))?
+        .len();
+    let mut reader = BufReader::new(file);
+
+    let top_level_boxes = scan_top_level_boxes(&mut reader, file_size)?;
+
+    let moov_box = top_level_boxes
+        .iter()
+        .find(|b| &b.box_type == b"moov")
+        .ok_or_eyre("找不到moov Box")?;
+    let moov = read_box_payload(&mut reader, moov_box)?;
+
+    let mut trex_defaults = HashMap::new();
+    if let Some(mvex) = find_box(&moov, *b"mvex")? {
+        for trex in find_boxes(mvex, *b"trex")? {
+            let (_, _, mut cursor) = read_full_box_header(trex)?;
+            let track_id = cursor.read_u32::<BigEndian>()?;
+            let _sample_description_index = cursor.read_u32::<BigEndian>()?;
+            let defaults = SampleDefaults {
+                duration: cursor.read_u32::<BigEndian>()?,
+                size: cursor.read_u32::<BigEndian>()?,
+                flags: cursor.read_u32::<BigEndian>()?,
+            };
+            trex_defaults.insert(track_id, defaults);
+        }
+    }
+
+    // (track_id, 下一个样本的解码时间, 轨道)
+    let mut tracks = Vec::new();
+    for trak in find_boxes(&moov, *b"trak")? {
+        if let Some((track_id, track)) = parse_trak(trak, path, file_size)? {
+            tracks.push((track_id, track.media_duration(), track));
+        }
+    }
+
+    for moof_box in top_level_boxes.iter().filter(|b| &b.box_type == b"moof") {
+        let moof = read_box_payload(&mut reader, moof_box)?;
+        for traf in find_boxes(&moof, *b"traf")? {
+            for (track_id, next_decode_time, track) in &mut tracks {
+                let defaults = trex_defaults.get(track_id).copied().unwrap_or_default();
+                let fragment = TrackFragment {
+                    track_id: *track_id,
+                    moof_offset: moof_box.offset,
+                    trex_defaults: defaults,
+                };
+                parse_traf(traf, &fragment, next_decode_time, track)?;
+            }
+        }
+    }
+
+    let tracks: Vec<Track> = tracks.into_iter().map(|(_, _, track)| track).collect();
+    for chunk in tracks.iter().flat_map(|track| &track.chunks) {
+        if chunk.offset + chunk.size > file_size {
+            return Err(eyre!(
+                "样本数据超出了文件末尾(偏移{}，长度{})，文件可能不完整",
+                chunk.offset,
+                chunk.size
+            ));
+        }
+    }
+
+    Ok(tracks)
+}
+
+fn scan_top_level_boxes(
+    reader: &mut BufReader<File>,
+    file_size: u64,
+) -> eyre::Result<Vec<TopLevelBox>> {
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+
+    while offset < file_size {
+        reader.seek(SeekFrom::Start(offset))?;
+        let size_field = reader.read_u32::<BigEndian>()?;
+        let mut box_type = [0u8; 4];
+        reader.read_exact(&mut box_type)?;
+
+        let size = match size_field {
+            0 => file_size - offset,
+            1 => reader.read_u64::<BigEndian>()?,
+            _ => u64::from(size_field),
+        };
+        if size < 8 || offset + size > file_size {
+            return Err(eyre!(
+                "Box`{}`的尺寸{size}不合法，文件可能不完整",
+                bmff::fourcc(box_type)
+            ));
+        }
+
+        boxes.push(TopLevelBox {
+            box_type,
+            offset,
+            size,
+        });
+        offset += size;
+    }
+
+    Ok(boxes)
+}
+
+/// 读取整个Box(包括头部)，再返回它的`payload`
+fn read_box_payload(
+    reader: &mut BufReader<File>,
+    top_level_box: &TopLevelBox,
+) -> eyre::Result<Vec<u8>> {
+    let size = usize::try_from(top_level_box.size)?;
+    let mut data = vec![0u8; size];
+    reader.seek(SeekFrom::Start(top_level_box.offset))?;
+    reader.read_exact(&mut data)?;
+
+    let mp4_box = bmff::iter_boxes(&data).next().ok_or_eyre("Box为空")??;
+    Ok(mp4_box.payload.to_vec())
+}
+
+/// 解析`trak`，不是视频或音频轨道时返回`None`
+#[allow(clippy::similar_names)]
+fn parse_trak(trak: &[u8], path: &Path, file_size: u64) -> eyre::Result<Option<(u32, Track)>> {
+    let hdlr = find_box_path(trak, &[*b"mdia", *b"hdlr"])?.ok_or_eyre("找不到hdlr Box")?;
+    let (_, _, mut cursor) = read_full_box_header(hdlr)?;
+    let _pre_defined = cursor.read_u32::<BigEndian>()?;
+    let mut handler_type = [0u8; 4];
+    cursor.read_exact(&mut handler_type)?;
+    if handler_type != HANDLER_VIDEO && handler_type != HANDLER_AUDIO {
+        return Ok(None);
+    }
+
+    let tkhd = find_box(trak, *b"tkhd")?.ok_or_eyre("找不到tkhd Box")?;
+    let (version, _, mut cursor) = read_full_box_header(tkhd)?;
+    // 跳过创建时间和修改时间
+    cursor.set_position(cursor.position() + if version == 1 { 16 } else { 8 });
+    let track_id = cursor.read_u32::<BigEndian>()?;
+    // 跳过reserved、duration、reserved、layer、alternate_group、volume、reserved和matrix
+    let duration_size = if version == 1 { 8 } else { 4 };
+    cursor.set_position(cursor.position() + 4 + duration_size + 8 + 2 + 2 + 2 + 2 + 36);
+    let width = cursor.read_u32::<BigEndian>()?;
+    let height = cursor.read_u32::<BigEndian>()?;
+
+    let mdhd = find_box_path(trak, &[*b"mdia", *b"mdhd"])?.ok_or_eyre("找不到mdhd Box")?;
+    let (version, _, mut cursor) = read_full_box_header(mdhd)?;
+    cursor.set_position(cursor.position() + if version == 1 { 16 } else { 8 });
+    let timescale = cursor.read_u32::<BigEndian>()?;
+    if timescale == 0 {
+        return Err(eyre!("mdhd中的timescale为0"));
+    }
+    cursor.set_position(cursor.position() + duration_size);
+    let language = cursor.read_u16::<BigEndian>()?;
+
+    let media_time = match find_box_path(trak, &[*b"edts", *b"elst"])? {
+        Some(elst) => parse_elst(elst)?,
+        None => 0,
+    };
+
+    let stbl =
+        find_box_path(trak, &[*b"mdia", *b"minf", *b"stbl"])?.ok_or_eyre("找不到stbl Box")?;
+    let stsd_payload = find_box(stbl, *b"stsd")?.ok_or_eyre("找不到stsd Box")?;
+    let mut stsd = Vec::new();
+    bmff::write_box(&mut stsd, *b"stsd", |buf| {
+        buf.extend_from_slice(stsd_payload);
+    });
+
+    let mut track = Track {
+        handler_type,
+        timescale,
+        language,
+        width,
+        height,
+        stsd,
+        media_time,
+        samples: Vec::new(),
+        chunks: Vec::new(),
+        source: TrackSource::File(path.to_path_buf()),
+    };
+    parse_stbl(stbl, file_size, &mut track).wrap_err("解析stbl失败")?;
+
+    Ok(Some((track_id, track)))
+}
+
+/// 返回第一个非空编辑的媒体起始时间
+fn parse_elst(elst: &[u8]) -> eyre::Result<i64> {
+    let (version, _, mut cursor) = read_full_box_header(elst)?;
+    let entry_count = cursor.read_u32::<BigEndian>()?;
+    for _ in 0..entry_count {
+        let media_time = if version == 1 {
+            let _segment_duration = cursor.read_u64::<BigEndian>()?;
+            cursor.read_i64::<BigEndian>()?
+        } else {
+            let _segment_duration = cursor.read_u32::<BigEndian>()?;
+            i64::from(cursor.read_i32::<BigEndian>()?)
+        };
+        let _media_rate = cursor.read_u32::<BigEndian>()?;
+        // -1表示空编辑，也就是一段空白
+        if media_time != -1 {
+            return Ok(media_time);
+        }
+    }
+    Ok(0)
+}
+
+/// 解析普通MP4的样本表，fMP4的样本表是空的
+#[allow(clippy::similar_names)]
+///
+/// 表中的条目数都来自文件，会先检查它们与Box的长度是否相符，文件损坏时返回错误而不是分配大量内存
+fn parse_stbl(stbl: &[u8], file_size: u64, track: &mut Track) -> eyre::Result<()> {
+    let Some(stsz) = find_box(stbl, *b"stsz")? else {
+        return Ok(());
+    };
+    let (_, _, mut cursor) = read_full_box_header(stsz)?;
+    let sample_size = cursor.read_u32::<BigEndian>()?;
+    let sample_count = if sample_size == 0 {
+        read_entry_count(&mut cursor, 4)?
+    } else {
+        // 样本尺寸固定时没有尺寸表，样本总长度不可能超过文件长度
+        let sample_count = cursor.read_u32::<BigEndian>()?;
+        if u64::from(sample_count) * u64::from(sample_size) > file_size {
+            return Err(eyre!(
+                "stsz中的样本数{sample_count}乘以样本尺寸{sample_size}超过了文件长度{file_size}"
+            ));
+        }
+        usize::try_from(sample_count)?
+    };
+    if sample_count == 0 {
+        return Ok(());
+    }
+    let mut sizes = Vec::with_capacity(sample_count);
+    for _ in 0..sample_count {
+        let size = if sample_size == 0 {
+            cursor.read_u32::<BigEndian>()?
+        } else {
+            sample_size
+        };
+        sizes.push(size);
+    }
+
+    let stts = find_box(stbl, *b"stts")?.ok_or_eyre("找不到stts Box")?;
+    let (_, _, mut cursor) = read_full_box_header(stts)?;
+    let mut durations = Vec::with_capacity(sample_count);
+    for _ in 0..read_entry_count(&mut cursor, 8)? {
+        let count = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+        let delta = cursor.read_u32::<BigEndian>()?;
+        if count > sample_count - durations.len() {
+            return Err(eyre!("stts中的样本数超过了stsz中的样本数{sample_count}"));
+        }
+        durations.extend(std::iter::repeat_n(delta, count));
+    }
+
+    let mut composition_offsets = Vec::new();
+    if let Some(ctts) = find_box(stbl, *b"ctts")? {
+        let (_, _, mut cursor) = read_full_box_header(ctts)?;
+        for _ in 0..read_entry_count(&mut cursor, 8)? {
+            let count = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+            // version 0是无符号数，但实际不会超过i32的范围，和version 1一样按有符号数读取
+            let offset = cursor.read_i32::<BigEndian>()?;
+            if count > sample_count - composition_offsets.len() {
+                return Err(eyre!("ctts中的样本数超过了stsz中的样本数{sample_count}"));
+            }
+            composition_offsets.extend(std::iter::repeat_n(offset, count));
+        }
+    }
+
+    // 没有stss说明所有样本都是关键帧
+    let sync_samples = match find_box(stbl, *b"stss")? {
+        Some(stss) => {
+            let (_, _, mut cursor) = read_full_box_header(stss)?;
+            let mut sync_samples = vec![false; sample_count];
+            for _ in 0..read_entry_count(&mut cursor, 4)? {
+                let number = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+                if let Some(is_sync) = number.checked_sub(1).and_then(|i| sync_samples.get_mut(i)) {
+                    *is_sync = true;
+                }
+            }
+            sync_samples
+        }
+        None => vec![true; sample_count],
+    };
+
+    track.samples = (0..sample_count)
+        .map(|i| Sample {
+            size: sizes[i],
+            duration: durations.get(i).copied().unwrap_or_default(),
+            composition_offset: composition_offsets.get(i).copied().unwrap_or_default(),
+            is_sync: sync_samples[i],
+        })
+        .collect();
+
+    parse_chunks(stbl, track)
+}
+
+/// 根据`stsc`和`stco`/`co64`把样本划分为`Chunk`
+fn parse_chunks(stbl: &[u8], track: &mut Track) -> eyre::Result<()> {
+    let sample_count = track.samples.len();
+
+    let chunk_offsets: Vec<u64> = if let Some(stco) = find_box(stbl, *b"stco")? {
+        let (_, _, mut cursor) = read_full_box_header(stco)?;
+        (0..read_entry_count(&mut cursor, 4)?)
+            .map(|_| cursor.read_u32::<BigEndian>().map(u64::from))
+            .collect::<Result<_, _>>()?
+    } else if let Some(co64) = find_box(stbl, *b"co64")? {
+        let (_, _, mut cursor) = read_full_box_header(co64)?;
+        (0..read_entry_count(&mut cursor, 8)?)
+            .map(|_| cursor.read_u64::<BigEndian>())
+            .collect::<Result<_, _>>()?
+    } else {
+        return Err(eyre!("找不到stco或co64 Box"));
+    };
+
+    // stsc中的(first_chunk, samples_per_chunk)，first_chunk从1开始
+    let stsc = find_box(stbl, *b"stsc")?.ok_or_eyre("找不到stsc Box")?;
+    let (_, _, mut cursor) = read_full_box_header(stsc)?;
+    let mut stsc_entries = Vec::new();
+    for _ in 0..read_entry_count(&mut cursor, 12)? {
+        let first_chunk = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+        let samples_per_chunk = usize::try_from(cursor.read_u32::<BigEndian>()?)?;
+        let _sample_description_index = cursor.read_u32::<BigEndian>()?;
+        stsc_entries.push((first_chunk, samples_per_chunk));
+    }
+
+    let mut first_sample = 0;
+    let mut decode_time = 0;
+    for (chunk_index, &offset) in chunk_offsets.iter().enumerate() {
+        let samples_per_chunk = stsc_entries
+            .iter()
+            .rev()
+            .find(|(first_chunk, _)| *first_chunk <= chunk_index + 1)
+            .map_or(0, |(_, samples_per_chunk)| *samples_per_chunk);
+        let sample_count = samples_per_chunk.min(sample_count - first_sample);
+        let samples = &track.samples[first_sample..first_sample + sample_count];
+
+        track.chunks.push(Chunk {
+            offset,
+            size: samples.iter().map(|s| u64::from(s.size)).sum(),
+            first_sample,
+            sample_count,
+            decode_time,
+        });
+
+        first_sample += sample_count;
+        decode_time += samples.iter().map(|s| u64::from(s.duration)).sum::<u64>();
+    }
+
+    if first_sample != sample_count {
+        return Err(eyre!(
+            "stsc中的样本数{first_sample}与stsz中的样本数{sample_count}不一致"
+        ));
+    }
+
+    Ok(())
+}
+
+/// 解析`traf`需要的上下文
+struct TrackFragment {
+    track_id: u32,
+    moof_offset: u64,
+    trex_defaults: SampleDefaults,
+}
+
+/// 解析`moof`中属于`fragment.track_id`的`traf`，每个`trun`作为一个`Chunk`
+fn parse_traf(
+    traf: &[u8],
+    fragment: &TrackFragment,
+    next_decode_time: &mut u64,
+    track: &mut Track,
+) -> eyre::Result<()> {
+    let tfhd = find_box(traf, *b"tfhd")?.ok_or_eyre("找不到tfhd Box")?;
+    let (_, tfhd_flags, mut cursor) = read_full_box_header(tfhd)?;
+    if cursor.read_u32::<BigEndian>()? != fragment.track_id {
+        return Ok(());
+    }
+
+    // 没有base_data_offset时，以moof的起始位置为基准
+    let base_data_offset = if tfhd_flags & 0x01 != 0 {
+        cursor.read_u64::<BigEndian>()?
+    } else {
+        fragment.moof_offset
+    };
+    if tfhd_flags & 0x02 != 0 {
+        let _sample_description_index = cursor.read_u32::<BigEndian>()?;
+    }
+    let mut defaults = fragment.trex_defaults;
+    if tfhd_flags & 0x08 != 0 {
+        defaults.duration = cursor.read_u32::<BigEndian>()?;
+    }
+    if tfhd_flags & 0x10 != 0 {
+        defaults.size = cursor.read_u32::<BigEndian>()?;
+    }
+    if tfhd_flags & 0x20 != 0 {
+        defaults.flags = cursor.read_u32::<BigEndian>()?;
+    }
+
+    if let Some(tfdt) = find_box(traf, *b"tfdt")? {
+        let (version, _, mut cursor) = read_full_box_header(tfdt)?;
+        *next_decode_time = if version == 1 {
+            cursor.read_u64::<BigEndian>()?
+        } else {
+            u64::from(cursor.read_u32::<BigEndian>()?)
+        };
+    }
+
+    let mut data_offset = base_data_offset;
+    for trun in find_boxes(traf, *b"trun")? {
+        let (_, trun_flags, mut cursor) = read_full_box_header(trun)?;
+        let sample_count = cursor.read_u32::<BigEndian>()?;
+        // 没有data_offset时，紧接着上一个trun的数据
+        if trun_flags & 0x01 != 0 {
+            let offset = i64::from(cursor.read_i32::<BigEndian>()?);
+            data_offset = base_data_offset
+                .checked_add_signed(offset)
+                .ok_or_eyre("trun中的data_offset不合法")?;
+        }
+        let first_sample_flags = if trun_flags & 0x04 != 0 {
+            Some(cursor.read_u32::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        let first_sample = track.samples.len();
+        let mut chunk_size = 0;
+        let decode_time = *next_decode_time;
+        for i in 0..sample_count {
+            let duration = if trun_flags & 0x100 != 0 {
+                cursor.read_u32::<BigEndian>()?
+            } else {
+                defaults.duration
+            };
+            let size = if trun_flags & 0x200 != 0 {
+                cursor.read_u32::<BigEndian>()?
+            } else {
+                defaults.size
+            };
+            let flags = if trun_flags & 0x400 != 0 {
+                cursor.read_u32::<BigEndian>()?
+            } else if let (0, Some(flags)) = (i, first_sample_flags) {
+                flags
+            } else {
+                defaults.flags
+            };
+            let composition_offset = if trun_flags & 0x800 != 0 {
+                cursor.read_i32::<BigEndian>()?
+            } else {
+                0
+            };
+
+            track.samples.push(Sample {
+                size,
+                duration,
+                composition_offset,
+                is_sync: flags & SAMPLE_FLAG_NON_SYNC == 0,
+            });
+            chunk_size += u64::from(size);
+            *next_decode_time += u64::from(duration);
+        }
+
+        if sample_count > 0 {
+            track.chunks.push(Chunk {
+                offset: data_offset,
+                size: chunk_size,
+                first_sample,
+                sample_count: track.samples.len() - first_sample,
+                decode_time,
+            });
+        }
+        data_offset += chunk_size;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_track() -> Track {
+        Track {
+            handler_type: HANDLER_VIDEO,
+            timescale: 1000,
+            language: 0,
+            width: 0,
+            height: 0,
+            stsd: Vec::new(),
+            media_time: 0,
+            samples: Vec::new(),
+            chunks: Vec::new(),
+            source: TrackSource::File(PathBuf::new()),
+        }
+    }
+
+    /// 两个样本的stbl，`stts_entry_count`和`stco_entry_count`是表中声明的条目数
+    fn stbl(stsz_sample_count: u32, stts_entry_count: u32, stco_entry_count: u32) -> Vec<u8> {
+        let mut stbl = Vec::new();
+        bmff::write_full_box(&mut stbl, *b"stsz", 0, 0, |buf| {
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            buf.extend_from_slice(&stsz_sample_count.to_be_bytes());
+            for size in [10u32, 20] {
+                buf.extend_from_slice(&size.to_be_bytes());
+            }
+        });
+        bmff::write_full_box(&mut stbl, *b"stts", 0, 0, |buf| {
+            buf.extend_from_slice(&stts_entry_count.to_be_bytes());
+            buf.extend_from_slice(&2u32.to_be_bytes());
+            buf.extend_from_slice(&40u32.to_be_bytes());
+        });
+        bmff::write_full_box(&mut stbl, *b"stsc", 0, 0, |buf| {
+            for value in [1u32, 1, 2, 1] {
+                buf.extend_from_slice(&value.to_be_bytes());
+            }
+        });
+        bmff::write_full_box(&mut stbl, *b"stco", 0, 0, |buf| {
+            buf.extend_from_slice(&stco_entry_count.to_be_bytes());
+            buf.extend_from_slice(&100u32.to_be_bytes());
+        });
+        stbl
+    }
+
+    #[test]
+    fn parse_sample_table() {
+        let mut track = empty_track();
+        parse_stbl(&stbl(2, 1, 1), 1000, &mut track).unwrap();
+
+        assert_eq!(track.samples.len(), 2);
+        assert_eq!(track.samples[1].size, 20);
+        assert_eq!(track.samples[1].duration, 40);
+        assert_eq!(track.chunks[0].size, 30);
+    }
+
+    #[test]
+    fn reject_counts_that_do_not_fit_in_box() {
+        for stbl in [
+            stbl(u32::MAX, 1, 1),
+            stbl(2, u32::MAX, 1),
+            stbl(2, 1, u32::MAX),
+        ] {
+            assert!(parse_stbl(&stbl, 1000, &mut empty_track()).is_err());
+        }
+
+        // 样本尺寸固定时没有尺寸表，样本数由文件长度限制
+        let mut stbl = Vec::new();
+        bmff::write_full_box(&mut stbl, *b"stsz", 0, 0, |buf| {
+            buf.extend_from_slice(&1u32.to_be_bytes());
+            buf.extend_from_slice(&u32::MAX.to_be_bytes());
+        });
+        assert!(parse_stbl(&stbl, 1000, &mut empty_track()).is_err());
+    }
+}
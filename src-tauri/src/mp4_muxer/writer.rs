@@ -0,0 +1,503 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::{WrapErr, eyre};
+
+use super::{
+    Chapter,
+    bmff::{write_box, write_full_box},
+    track::{HANDLER_AUDIO, HANDLER_TEXT, HANDLER_VIDEO, Track, TrackSource},
+};
+
+/// `mvhd`和`tkhd`使用的时间单位，毫秒
+const MOVIE_TIMESCALE: u32 = 1000;
+/// 单位矩阵，16.16和2.30定点数
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+/// 把`tracks`写成一个普通MP4：`ftyp`、按解码时间交错排列的`mdat`、最后是`moov`
+///
+/// 有文字轨道时，它会被第一个视频轨道的`tref/chap`引用作为章节轨道，同时也写入Nero格式的`chpl`。
+/// `meta`是完整的`meta` Box(比如iTunes元数据)，会写在`moov/udta`里
+pub fn write_mp4(
+    tracks: &[Track],
+    chapters: &[Chapter],
+    meta: Option<&[u8]>,
+    output_path: &Path,
+) -> eyre::Result<()> {
+    let ftyp = create_ftyp();
+
+    // 按解码时间交错排列所有Chunk，播放器顺序读取时不需要来回跳
+    let mut chunk_order: Vec<(usize, usize)> = tracks
+        .iter()
+        .enumerate()
+        .flat_map(|(track_index, track)| {
+            (0..track.chunks.len()).map(move |chunk_index| (track_index, chunk_index))
+        })
+        .collect();
+    chunk_order.sort_by(|&(track_a, chunk_a), &(track_b, chunk_b)| {
+        compare_decode_time(&tracks[track_a], chunk_a, &tracks[track_b], chunk_b)
+    });
+
+    let mdat_payload_size: u64 = tracks
+        .iter()
+        .flat_map(|track| &track.chunks)
+        .map(|chunk| chunk.size)
+        .sum();
+    let use_large_size = mdat_payload_size + 8 > u64::from(u32::MAX);
+    let mdat_header_size = if use_large_size { 16 } else { 8 };
+
+    let mut chunk_offsets: Vec<Vec<u64>> = tracks
+        .iter()
+        .map(|track| vec![0; track.chunks.len()])
+        .collect();
+    let mut offset = ftyp.len() as u64 + mdat_header_size;
+    for &(track_index, chunk_index) in &chunk_order {
+        chunk_offsets[track_index][chunk_index] = offset;
+        offset += tracks[track_index].chunks[chunk_index].size;
+    }
+
+    let moov = create_moov(tracks, &chunk_offsets, chapters, meta);
+
+    let output_file =
+        File::create(output_path).wrap_err(format!("创建文件`{}`失败", output_path.display()))?;
+    let mut writer = BufWriter::new(output_file);
+
+    writer.write_all(&ftyp)?;
+    if use_large_size {
+        writer.write_all(&1u32.to_be_bytes())?;
+        writer.write_all(b"mdat")?;
+        writer.write_all(&(mdat_payload_size + 16).to_be_bytes())?;
+    } else {
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_all(&((mdat_payload_size + 8) as u32).to_be_bytes())?;
+        writer.write_all(b"mdat")?;
+    }
+
+    let mut readers: HashMap<PathBuf, BufReader<File>> = HashMap::new();
+    for &(track_index, chunk_index) in &chunk_order {
+        let track = &tracks[track_index];
+        let chunk = &track.chunks[chunk_index];
+        match &track.source {
+            TrackSource::File(path) => {
+                if !readers.contains_key(path) {
+                    let file =
+                        File::open(path).wrap_err(format!("打开文件`{}`失败", path.display()))?;
+                    readers.insert(path.clone(), BufReader::new(file));
+                }
+                let Some(reader) = readers.get_mut(path) else {
+                    unreachable!()
+                };
+                reader.seek(SeekFrom::Start(chunk.offset))?;
+                let copied = io::copy(&mut reader.take(chunk.size), &mut writer)
+                    .wrap_err(format!("从`{}`复制样本数据失败", path.display()))?;
+                if copied != chunk.size {
+                    return Err(eyre!(
+                        "`{}`中的样本数据不完整，需要{}字节，只读到{copied}字节",
+                        path.display(),
+                        chunk.size
+                    ));
+                }
+            }
+            TrackSource::Memory(data) => {
+                let start = usize::try_from(chunk.offset)?;
+                let end = start + usize::try_from(chunk.size)?;
+                writer.write_all(&data[start..end])?;
+            }
+        }
+    }
+
+    writer.write_all(&moov)?;
+    writer
+        .flush()
+        .wrap_err(format!("写入文件`{}`失败", output_path.display()))?;
+
+    Ok(())
+}
+
+fn compare_decode_time(
+    track_a: &Track,
+    chunk_a: usize,
+    track_b: &Track,
+    chunk_b: usize,
+) -> Ordering {
+    // 交叉相乘，避免换算时间单位时的误差
+    let time_a = u128::from(track_a.chunks[chunk_a].decode_time) * u128::from(track_b.timescale);
+    let time_b = u128::from(track_b.chunks[chunk_b].decode_time) * u128::from(track_a.timescale);
+    time_a.cmp(&time_b)
+}
+
+fn create_ftyp() -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_box(&mut buf, *b"ftyp", |buf| {
+        buf.extend_from_slice(b"isom");
+        buf.extend_from_slice(&0x200u32.to_be_bytes());
+        for brand in [b"isom", b"iso2", b"mp41"] {
+            buf.extend_from_slice(brand);
+        }
+    });
+    buf
+}
+
+/// 把`timescale`为单位的时长换算为`MOVIE_TIMESCALE`，四舍五入
+#[allow(clippy::cast_possible_truncation)]
+fn to_movie_timescale(duration: u64, timescale: u32) -> u64 {
+    let timescale = u128::from(timescale);
+    ((u128::from(duration) * u128::from(MOVIE_TIMESCALE) + timescale / 2) / timescale) as u64
+}
+
+fn fits_u32(value: u64) -> bool {
+    u32::try_from(value).is_ok()
+}
+
+/// 写入`version`对应长度的时长，0是32位，1是64位
+#[allow(clippy::cast_possible_truncation)]
+fn write_duration(buf: &mut Vec<u8>, version: u8, duration: u64) {
+    if version == 1 {
+        buf.extend_from_slice(&duration.to_be_bytes());
+    } else {
+        buf.extend_from_slice(&(duration as u32).to_be_bytes());
+    }
+}
+
+/// 写入`version`对应长度的创建时间和修改时间，都写0以保证输出是确定的
+fn write_creation_and_modification_time(buf: &mut Vec<u8>, version: u8) {
+    let size = if version == 1 { 16 } else { 8 };
+    buf.extend(std::iter::repeat_n(0, size));
+}
+
+fn write_matrix(buf: &mut Vec<u8>) {
+    for value in UNITY_MATRIX {
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn create_moov(
+    tracks: &[Track],
+    chunk_offsets: &[Vec<u64>],
+    chapters: &[Chapter],
+    meta: Option<&[u8]>,
+) -> Vec<u8> {
+    let movie_duration = tracks
+        .iter()
+        .map(|track| to_movie_timescale(track.presentation_duration(), track.timescale))
+        .max()
+        .unwrap_or(0);
+
+    // track_id从1开始，与下标一一对应
+    #[allow(clippy::cast_possible_truncation)]
+    let chapter_track_id = tracks
+        .iter()
+        .position(|track| track.handler_type == HANDLER_TEXT)
+        .map(|index| index as u32 + 1);
+    let first_video_index = tracks
+        .iter()
+        .position(|track| track.handler_type == HANDLER_VIDEO);
+
+    let mut buf = Vec::new();
+    write_box(&mut buf, *b"moov", |buf| {
+        let version = u8::from(!fits_u32(movie_duration));
+        write_full_box(buf, *b"mvhd", version, 0, |buf| {
+            write_creation_and_modification_time(buf, version);
+            buf.extend_from_slice(&MOVIE_TIMESCALE.to_be_bytes());
+            write_duration(buf, version, movie_duration);
+            // rate 1.0，volume 1.0，reserved
+            buf.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+            buf.extend_from_slice(&0x0100u16.to_be_bytes());
+            buf.extend_from_slice(&[0; 10]);
+            write_matrix(buf);
+            // pre_defined
+            buf.extend_from_slice(&[0; 24]);
+            #[allow(clippy::cast_possible_truncation)]
+            let next_track_id = tracks.len() as u32 + 1;
+            buf.extend_from_slice(&next_track_id.to_be_bytes());
+        });
+
+        for (index, track) in tracks.iter().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let track_id = index as u32 + 1;
+            let chapter_track_id = chapter_track_id.filter(|_| first_video_index == Some(index));
+            write_trak(
+                buf,
+                track,
+                track_id,
+                chapter_track_id,
+                &chunk_offsets[index],
+            );
+        }
+
+        if !chapters.is_empty() || meta.is_some() {
+            write_box(buf, *b"udta", |buf| {
+                if !chapters.is_empty() {
+                    write_chpl(buf, chapters);
+                }
+                if let Some(meta) = meta {
+                    buf.extend_from_slice(meta);
+                }
+            });
+        }
+    });
+    buf
+}
+
+fn write_trak(
+    buf: &mut Vec<u8>,
+    track: &Track,
+    track_id: u32,
+    chapter_track_id: Option<u32>,
+    chunk_offsets: &[u64],
+) {
+    let media_duration = track.media_duration();
+    let track_duration = to_movie_timescale(track.presentation_duration(), track.timescale);
+
+    write_box(buf, *b"trak", |buf| {
+        // 章节轨道不需要播放器直接显示，不设置track_enabled
+        let flags = if track.handler_type == HANDLER_TEXT {
+            0x02
+        } else {
+            0x03
+        };
+        let version = u8::from(!fits_u32(track_duration));
+        write_full_box(buf, *b"tkhd", version, flags, |buf| {
+            write_creation_and_modification_time(buf, version);
+            buf.extend_from_slice(&track_id.to_be_bytes());
+            buf.extend_from_slice(&[0; 4]);
+            write_duration(buf, version, track_duration);
+            // reserved、layer、alternate_group
+            buf.extend_from_slice(&[0; 12]);
+            let volume: u16 = if track.handler_type == HANDLER_AUDIO {
+                0x0100
+            } else {
+                0
+            };
+            buf.extend_from_slice(&volume.to_be_bytes());
+            buf.extend_from_slice(&[0; 2]);
+            write_matrix(buf);
+            buf.extend_from_slice(&track.width.to_be_bytes());
+            buf.extend_from_slice(&track.height.to_be_bytes());
+        });
+
+        if track.media_time != 0 {
+            write_box(buf, *b"edts", |buf| {
+                let version =
+                    u8::from(!fits_u32(track_duration) || i32::try_from(track.media_time).is_err());
+                write_full_box(buf, *b"elst", version, 0, |buf| {
+                    buf.extend_from_slice(&1u32.to_be_bytes());
+                    write_duration(buf, version, track_duration);
+                    if version == 1 {
+                        buf.extend_from_slice(&track.media_time.to_be_bytes());
+                    } else {
+                        #[allow(clippy::cast_possible_truncation)]
+                        buf.extend_from_slice(&(track.media_time as i32).to_be_bytes());
+                    }
+                    // media_rate 1.0
+                    buf.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+                });
+            });
+        }
+
+        if let Some(chapter_track_id) = chapter_track_id {
+            write_box(buf, *b"tref", |buf| {
+                write_box(buf, *b"chap", |buf| {
+                    buf.extend_from_slice(&chapter_track_id.to_be_bytes());
+                });
+            });
+        }
+
+        write_box(buf, *b"mdia", |buf| {
+            let version = u8::from(!fits_u32(media_duration));
+            write_full_box(buf, *b"mdhd", version, 0, |buf| {
+                write_creation_and_modification_time(buf, version);
+                buf.extend_from_slice(&track.timescale.to_be_bytes());
+                write_duration(buf, version, media_duration);
+                buf.extend_from_slice(&track.language.to_be_bytes());
+                buf.extend_from_slice(&[0; 2]);
+            });
+
+            let handler_name: &[u8] = match track.handler_type {
+                HANDLER_VIDEO => b"VideoHandler\0",
+                HANDLER_AUDIO => b"SoundHandler\0",
+                _ => b"ChapterHandler\0",
+            };
+            write_full_box(buf, *b"hdlr", 0, 0, |buf| {
+                buf.extend_from_slice(&[0; 4]);
+                buf.extend_from_slice(&track.handler_type);
+                buf.extend_from_slice(&[0; 12]);
+                buf.extend_from_slice(handler_name);
+            });
+
+            write_box(buf, *b"minf", |buf| {
+                match track.handler_type {
+                    HANDLER_VIDEO => write_full_box(buf, *b"vmhd", 0, 1, |buf| {
+                        // graphicsmode和opcolor
+                        buf.extend_from_slice(&[0; 8]);
+                    }),
+                    HANDLER_AUDIO => write_full_box(buf, *b"smhd", 0, 0, |buf| {
+                        // balance和reserved
+                        buf.extend_from_slice(&[0; 4]);
+                    }),
+                    _ => write_full_box(buf, *b"nmhd", 0, 0, |_| {}),
+                }
+
+                write_box(buf, *b"dinf", |buf| {
+                    write_full_box(buf, *b"dref", 0, 0, |buf| {
+                        buf.extend_from_slice(&1u32.to_be_bytes());
+                        // flags为1表示数据就在本文件中
+                        write_full_box(buf, *b"url ", 0, 1, |_| {});
+                    });
+                });
+
+                write_box(buf, *b"stbl", |buf| write_stbl(buf, track, chunk_offsets));
+            });
+        });
+    });
+}
+
+fn write_stbl(buf: &mut Vec<u8>, track: &Track, chunk_offsets: &[u64]) {
+    buf.extend_from_slice(&track.stsd);
+
+    let durations: Vec<u32> = track.samples.iter().map(|s| s.duration).collect();
+    write_full_box(buf, *b"stts", 0, 0, |buf| {
+        write_run_length_entries(buf, &durations);
+    });
+
+    let composition_offsets: Vec<i32> =
+        track.samples.iter().map(|s| s.composition_offset).collect();
+    if composition_offsets.iter().any(|&offset| offset != 0) {
+        // 有负数时只能用version 1
+        let version = u8::from(composition_offsets.iter().any(|&offset| offset < 0));
+        #[allow(clippy::cast_sign_loss)]
+        let offsets: Vec<u32> = composition_offsets.iter().map(|&o| o as u32).collect();
+        write_full_box(buf, *b"ctts", version, 0, |buf| {
+            write_run_length_entries(buf, &offsets);
+        });
+    }
+
+    // 全都是关键帧时不需要stss
+    if track.samples.iter().any(|s| !s.is_sync) {
+        let sync_sample_numbers: Vec<u32> = (1..)
+            .zip(&track.samples)
+            .filter(|(_, sample)| sample.is_sync)
+            .map(|(number, _)| number)
+            .collect();
+        write_full_box(buf, *b"stss", 0, 0, |buf| {
+            write_u32_table(buf, &sync_sample_numbers);
+        });
+    }
+
+    write_full_box(buf, *b"stsc", 0, 0, |buf| {
+        // (first_chunk, samples_per_chunk)，只在每个Chunk的样本数变化时写一条
+        let mut entries: Vec<(u32, u32)> = Vec::new();
+        for (chunk_number, chunk) in (1..).zip(&track.chunks) {
+            #[allow(clippy::cast_possible_truncation)]
+            let sample_count = chunk.sample_count as u32;
+            if entries
+                .last()
+                .is_none_or(|&(_, count)| count != sample_count)
+            {
+                entries.push((chunk_number, sample_count));
+            }
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        buf.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for (first_chunk, samples_per_chunk) in entries {
+            buf.extend_from_slice(&first_chunk.to_be_bytes());
+            buf.extend_from_slice(&samples_per_chunk.to_be_bytes());
+            // sample_description_index
+            buf.extend_from_slice(&1u32.to_be_bytes());
+        }
+    });
+
+    let sizes: Vec<u32> = track.samples.iter().map(|s| s.size).collect();
+    write_full_box(buf, *b"stsz", 0, 0, |buf| {
+        #[allow(clippy::cast_possible_truncation)]
+        let sample_count = sizes.len() as u32;
+        match sizes.first() {
+            // 所有样本一样大时只写一个sample_size
+            Some(&size) if sizes.iter().all(|&s| s == size) => {
+                buf.extend_from_slice(&size.to_be_bytes());
+                buf.extend_from_slice(&sample_count.to_be_bytes());
+            }
+            _ => {
+                buf.extend_from_slice(&0u32.to_be_bytes());
+                write_u32_table(buf, &sizes);
+            }
+        }
+    });
+
+    if chunk_offsets.iter().all(|&offset| fits_u32(offset)) {
+        #[allow(clippy::cast_possible_truncation)]
+        let offsets: Vec<u32> = chunk_offsets.iter().map(|&o| o as u32).collect();
+        write_full_box(buf, *b"stco", 0, 0, |buf| write_u32_table(buf, &offsets));
+    } else {
+        write_full_box(buf, *b"co64", 0, 0, |buf| {
+            #[allow(clippy::cast_possible_truncation)]
+            buf.extend_from_slice(&(chunk_offsets.len() as u32).to_be_bytes());
+            for offset in chunk_offsets {
+                buf.extend_from_slice(&offset.to_be_bytes());
+            }
+        });
+    }
+}
+
+/// 写入`entry_count`和`(sample_count, value)`，连续相同的值合并为一条
+fn write_run_length_entries(buf: &mut Vec<u8>, values: &[u32]) {
+    let mut entries: Vec<(u32, u32)> = Vec::new();
+    for &value in values {
+        match entries.last_mut() {
+            Some((count, last_value)) if *last_value == value => *count += 1,
+            _ => entries.push((1, value)),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    buf.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for (count, value) in entries {
+        buf.extend_from_slice(&count.to_be_bytes());
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// 写入`entry_count`和每个值
+fn write_u32_table(buf: &mut Vec<u8>, values: &[u32]) {
+    #[allow(clippy::cast_possible_truncation)]
+    buf.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    for value in values {
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Nero格式的章节，`QuickTime`不认，但不少Windows上的播放器只认它
+fn write_chpl(buf: &mut Vec<u8>, chapters: &[Chapter]) {
+    write_full_box(buf, *b"chpl", 1, 0, |buf| {
+        buf.extend_from_slice(&[0; 4]);
+        // 章节数只有1字节
+        let chapters = &chapters[..chapters.len().min(usize::from(u8::MAX))];
+        #[allow(clippy::cast_possible_truncation)]
+        buf.push(chapters.len() as u8);
+        for chapter in chapters {
+            // 单位是100纳秒
+            buf.extend_from_slice(&(chapter.start * 10_000).to_be_bytes());
+            let title = truncate_utf8(&chapter.title, usize::from(u8::MAX));
+            #[allow(clippy::cast_possible_truncation)]
+            buf.push(title.len() as u8);
+            buf.extend_from_slice(title.as_bytes());
+        }
+    });
+}
+
+/// 在字符边界处截断，保证结果不超过`max_len`字节
+fn truncate_utf8(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
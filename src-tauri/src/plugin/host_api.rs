@@ -2,14 +2,13 @@ use std::sync::OnceLock;
 
 use bilibili_video_downloader_plugin_api::v1::{HostApiV1, HostConfigV1};
 use eyre::WrapErr;
-use tauri::AppHandle;
 
-use crate::{config::Config, extensions::AppHandleExt};
+use crate::{app_context::AppContext, config::Config};
 
-static HOST_APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+static HOST_APP_CONTEXT: OnceLock<AppContext> = OnceLock::new();
 
-pub fn init(app: &AppHandle) {
-    HOST_APP_HANDLE.get_or_init(|| app.clone());
+pub fn init(app: &AppContext) {
+    HOST_APP_CONTEXT.get_or_init(|| app.clone());
 }
 
 pub fn build_host_api_v1() -> HostApiV1 {
@@ -24,7 +23,7 @@ unsafe extern "C" fn host_get_config_json_v1(out_ptr: *mut *mut u8, out_len: *mu
         return 1;
     }
 
-    let Some(app) = HOST_APP_HANDLE.get() else {
+    let Some(app) = HOST_APP_CONTEXT.get() else {
         return 2;
     };
 
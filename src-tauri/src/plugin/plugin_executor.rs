@@ -16,7 +16,7 @@ pub async fn execute_hook(
     let api = plugin.api.clone();
 
     let (tx, rx) = tokio::sync::oneshot::channel::<eyre::Result<Vec<u8>>>();
-    tauri::async_runtime::spawn_blocking(move || {
+    tokio::task::spawn_blocking(move || {
         let result = call_on_hook_blocking(api, &input_bytes);
         let _ = tx.send(result);
     });
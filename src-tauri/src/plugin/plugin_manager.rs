@@ -1,16 +1,16 @@
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
+use std::collections::HashMap;
+#[cfg(feature = "gui")]
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "gui")]
 use eyre::eyre;
 use parking_lot::RwLock;
-use tauri::{AppHandle, Manager};
-use tauri_specta::Event;
 use tracing::instrument;
 
+#[cfg(feature = "gui")]
+use crate::events::PluginEvent;
 use crate::{
-    events::PluginEvent,
+    app_context::AppContext,
     extensions::EyreReportToMessage,
     types::plugin_info::{PluginDescriptorInfo, PluginInfo, PluginMetadata, PluginRuntimeStatus},
 };
@@ -21,17 +21,17 @@ use super::{
 };
 
 pub struct PluginManager {
-    app: AppHandle,
+    app: AppContext,
     infos: RwLock<HashMap<String, PluginInfo>>,
     runtimes: RwLock<Vec<PluginRuntime>>,
 }
 
 impl PluginManager {
     #[instrument(level = "error", skip_all)]
-    pub fn new(app: &AppHandle) -> eyre::Result<PluginManager> {
+    pub fn new(app: &AppContext) -> eyre::Result<PluginManager> {
         host_api::init(app);
 
-        let app_data_dir = app.path().app_data_dir()?;
+        let app_data_dir = app.app_data_dir();
         let plugin_json_path = app_data_dir.join("plugin.json");
 
         let mut infos = HashMap::new();
@@ -89,6 +89,7 @@ impl PluginManager {
         Ok(plugin_manager)
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all, fields(plugin_path = plugin_path))]
     pub fn add_plugin(&self, plugin_path: &str) -> eyre::Result<()> {
         let runtime = plugin_loader::load_plugin_from_path(&PathBuf::from(plugin_path), 0, true)?;
@@ -111,11 +112,13 @@ impl PluginManager {
         }
 
         self.save_metadata()?;
-        let _ = PluginEvent::Loaded { plugin_info }.emit(&self.app);
+        self.app
+            .emit_plugin_event(PluginEvent::Loaded { plugin_info });
 
         Ok(())
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all, fields(plugin_path = plugin_path))]
     pub fn uninstall_plugin(&self, plugin_path: &str) -> eyre::Result<()> {
         {
@@ -131,16 +134,16 @@ impl PluginManager {
             remove_runtime_by_path(&mut runtimes, Path::new(plugin_path));
         }
 
-        let _ = PluginEvent::Uninstall {
+        self.app.emit_plugin_event(PluginEvent::Uninstall {
             plugin_path: plugin_path.to_string(),
-        }
-        .emit(&self.app);
+        });
 
         self.save_metadata()?;
 
         Ok(())
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(level = "error", skip_all, fields(plugin_path = plugin_path, enabled = enabled))]
     pub fn set_plugin_enabled(&self, plugin_path: &str, enabled: bool) -> eyre::Result<()> {
         if !enabled {
@@ -162,7 +165,8 @@ impl PluginManager {
                 remove_runtime_by_path(&mut runtimes, Path::new(plugin_path));
             }
 
-            let _ = PluginEvent::Update { plugin_info }.emit(&self.app);
+            self.app
+                .emit_plugin_event(PluginEvent::Update { plugin_info });
 
             self.save_metadata()?;
 
@@ -217,13 +221,15 @@ impl PluginManager {
                 }
             };
 
-        let _ = PluginEvent::Update { plugin_info }.emit(&self.app);
+        self.app
+            .emit_plugin_event(PluginEvent::Update { plugin_info });
 
         self.save_metadata()?;
 
         Ok(())
     }
 
+    #[cfg(feature = "gui")]
     #[instrument(
         level = "error",
         skip_all,
@@ -251,13 +257,15 @@ impl PluginManager {
             }
         }
 
-        let _ = PluginEvent::Update { plugin_info }.emit(&self.app);
+        self.app
+            .emit_plugin_event(PluginEvent::Update { plugin_info });
 
         self.save_metadata()?;
 
         Ok(())
     }
 
+    #[cfg(feature = "gui")]
     pub fn get_plugin_infos(&self) -> Vec<PluginInfo> {
         self.infos.read().values().cloned().collect()
     }
@@ -270,14 +278,14 @@ impl PluginManager {
             return Ok(());
         }
 
-        let app_version = self.app.package_info().version.to_string();
+        let app_version = self.app.app_version();
 
         for runtime in &runtimes {
             if !runtime.enabled || !runtime.should_run_hook(hook_point) {
                 continue;
             }
 
-            let input = context.to_input(&app_version)?;
+            let input = context.to_input(app_version)?;
             let output = match plugin_executor::execute_hook(runtime, &input).await {
                 Ok(output) => output,
                 Err(err) => match runtime.descriptor.failure_policy {
@@ -314,7 +322,7 @@ impl PluginManager {
 
     #[instrument(level = "error", skip_all)]
     fn save_metadata(&self) -> eyre::Result<()> {
-        let app_data_dir = self.app.path().app_data_dir()?;
+        let app_data_dir = self.app.app_data_dir();
         let plugin_json_path = app_data_dir.join("plugin.json");
 
         let metadata_by_path: HashMap<String, PluginMetadata> = self
@@ -340,6 +348,7 @@ fn insert_runtime_by_priority(runtimes: &mut Vec<PluginRuntime>, runtime: Plugin
     runtimes.insert(insert_idx, runtime);
 }
 
+#[cfg(feature = "gui")]
 fn remove_runtime_by_path(
     runtimes: &mut Vec<PluginRuntime>,
     plugin_path: &Path,
@@ -0,0 +1,565 @@
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use eyre::{WrapErr, eyre};
+use futures_util::stream;
+use http_body_util::{BodyExt, Full, StreamBody, combinators::UnsyncBoxBody};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Frame, Incoming},
+    header::{self, HeaderValue},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::error::RecvError, watch},
+};
+use tracing::instrument;
+
+use crate::{
+    app_context::AppContext,
+    config::Config,
+    errors::CommandError,
+    extensions::EyreReportToMessage,
+    link_parser,
+    types::{
+        create_download_task_params::CreateDownloadTaskParams,
+        query_download_history_params::QueryDownloadHistoryParams, search_params::SearchParams,
+    },
+};
+
+type ApiBody = UnsyncBoxBody<Bytes, Infallible>;
+type ApiResult = Result<Response<ApiBody>, ApiError>;
+
+/// SSE连接空闲时发送心跳的间隔，避免连接被代理断开
+const SSE_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// 内嵌的HTTP服务器，用于在浏览器脚本或局域网中的其他机器上远程控制下载
+///
+/// 接口与`commands.rs`中的命令一一对应，请求和响应都是JSON，
+/// 失败时返回`CommandError`，下载事件通过SSE推送
+pub struct RemoteApiServer {
+    app: AppContext,
+    /// 正在运行的服务器的停止信号，drop时服务器和所有连接都会停止
+    shutdown_sender: Mutex<Option<watch::Sender<()>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TaskIdsParams {
+    task_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ParseLinksParams {
+    text: String,
+}
+
+impl RemoteApiServer {
+    pub fn new(app: AppContext) -> Self {
+        Self {
+            app,
+            shutdown_sender: Mutex::new(None),
+        }
+    }
+
+    /// 按当前配置启动或停止服务器，已经在运行的服务器会先被停止
+    ///
+    /// 启动成功时返回实际监听的地址
+    #[instrument(level = "error", skip_all)]
+    pub fn reload(&self) -> eyre::Result<Option<SocketAddr>> {
+        let mut shutdown_sender = self.shutdown_sender.lock();
+        if shutdown_sender.take().is_some() {
+            tracing::info!("远程API已停止");
+        }
+
+        let (enabled, bind_address, token_is_empty) = {
+            let config = self.app.get_config().read();
+            (
+                config.remote_api_enabled,
+                config.remote_api_bind_address.clone(),
+                config.remote_api_token.trim().is_empty(),
+            )
+        };
+        if !enabled {
+            return Ok(None);
+        }
+        if token_is_empty {
+            return Err(eyre!("远程API的token不能为空"));
+        }
+
+        let listener = std::net::TcpListener::bind(&bind_address)
+            .wrap_err(format!("监听地址`{bind_address}`失败"))?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let local_addr = listener.local_addr()?;
+
+        let (sender, receiver) = watch::channel(());
+        tokio::spawn(serve(self.app.clone(), listener, receiver));
+        *shutdown_sender = Some(sender);
+        tracing::info!(%local_addr, "远程API已启动");
+
+        Ok(Some(local_addr))
+    }
+}
+
+async fn serve(app: AppContext, listener: TcpListener, mut shutdown_receiver: watch::Receiver<()>) {
+    loop {
+        let stream = tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    let err_title = "远程API接受连接失败";
+                    let message = eyre::Report::from(err).to_message();
+                    tracing::error!(err_title, message);
+                    continue;
+                }
+            },
+            // 发送端被drop时也会返回
+            _ = shutdown_receiver.changed() => return,
+        };
+
+        let app = app.clone();
+        let mut shutdown_receiver = shutdown_receiver.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle_request(app.clone(), request));
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            tokio::select! {
+                result = connection => {
+                    if let Err(err) = result {
+                        // 客户端断开连接是正常情况，不需要报错
+                        let message = eyre::Report::from(err).to_message();
+                        tracing::debug!(message, "远程API的连接已断开");
+                    }
+                }
+                _ = shutdown_receiver.changed() => {}
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    app: AppContext,
+    request: Request<Incoming>,
+) -> Result<Response<ApiBody>, Infallible> {
+    let allowed_origin = allowed_origin(&app, &request);
+    let mut response = route(&app, request)
+        .await
+        .unwrap_or_else(ApiError::into_response);
+
+    // 只有配置中允许的网页可以跨域访问，其他网页的脚本即使猜中了token也读不到响应
+    let headers = response.headers_mut();
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    if let Some(origin) = allowed_origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    Ok(response)
+}
+
+async fn route(app: &AppContext, request: Request<Incoming>) -> ApiResult {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    if method == Method::OPTIONS {
+        // 浏览器的CORS预检请求不携带token
+        return Ok(preflight_response());
+    }
+
+    check_token(app, &request)?;
+
+    match (&method, path.as_str()) {
+        (&Method::GET, "/api/get_config") => {
+            let config = app.get_config().read().clone();
+            json_response(&config)
+        }
+        (&Method::POST, "/api/save_config") => {
+            let config: Config = read_json(request).await?;
+            app.update_config(config)
+                .map_err(|err| ApiError::internal("保存配置失败", err))?;
+            json_response(&())
+        }
+        (&Method::POST, "/api/search") => {
+            let params: SearchParams = read_json(request).await?;
+            let search_result = app
+                .get_bili_client()
+                .search(params)
+                .await
+                .map_err(|err| ApiError::internal("搜索失败", err))?;
+            json_response(&search_result)
+        }
+        (&Method::POST, "/api/parse_links") => {
+            let params: ParseLinksParams = read_json(request).await?;
+            let bili_client = app.get_bili_client();
+            let targets = link_parser::parse_text(&params.text, |short_link| async move {
+                bili_client.resolve_short_link(&short_link).await
+            })
+            .await;
+            json_response(&targets)
+        }
+        (&Method::POST, "/api/create_download_tasks") => {
+            let params: CreateDownloadTaskParams = read_json(request).await?;
+            app.get_download_manager().create_download_tasks(&params);
+            json_response(&())
+        }
+        (&Method::POST, "/api/query_download_history") => {
+            let params: QueryDownloadHistoryParams = read_json(request).await?;
+            let download_manager = app.get_download_manager();
+            json_response(&download_manager.download_history.query(&params))
+        }
+        (&Method::POST, "/api/pause_download_tasks") => {
+            let params: TaskIdsParams = read_json(request).await?;
+            app.get_download_manager()
+                .pause_download_tasks(&params.task_ids);
+            json_response(&())
+        }
+        (&Method::POST, "/api/resume_download_tasks") => {
+            let params: TaskIdsParams = read_json(request).await?;
+            app.get_download_manager()
+                .resume_download_tasks(&params.task_ids);
+            json_response(&())
+        }
+        (&Method::POST, "/api/delete_download_tasks") => {
+            let params: TaskIdsParams = read_json(request).await?;
+            app.get_download_manager()
+                .delete_download_tasks(&params.task_ids);
+            json_response(&())
+        }
+        (&Method::GET, "/api/download_events") => Ok(download_events_response(app)),
+        _ => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "远程API请求失败",
+            eyre!("接口`{method} {path}`不存在"),
+        )),
+    }
+}
+
+/// 请求的`Origin`在`remote_api_allowed_origins`中时返回它，作为`Access-Control-Allow-Origin`
+fn allowed_origin(app: &AppContext, request: &Request<Incoming>) -> Option<HeaderValue> {
+    let origin = request.headers().get(header::ORIGIN)?;
+    let origin_str = origin.to_str().ok()?;
+    let is_allowed = app
+        .get_config()
+        .read()
+        .remote_api_allowed_origins
+        .iter()
+        .any(|allowed| allowed.trim().trim_end_matches('/') == origin_str);
+    is_allowed.then(|| origin.clone())
+}
+
+/// token可以放在`Authorization: Bearer <token>`中，
+/// 也可以放在查询参数`token`中，因为浏览器的`EventSource`无法设置请求头
+fn check_token(app: &AppContext, request: &Request<Incoming>) -> Result<(), ApiError> {
+    let token = app.get_config().read().remote_api_token.clone();
+
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // 查询参数中的token可能经过了百分号编码
+    let query_token = request.uri().query().and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.into_owned())
+    });
+
+    let is_valid = |candidate: &str| constant_time_eq(candidate.as_bytes(), token.as_bytes());
+    let header_valid = header_token.is_some_and(is_valid);
+    let query_valid = query_token.as_deref().is_some_and(is_valid);
+    if !token.is_empty() && (header_valid || query_valid) {
+        return Ok(());
+    }
+
+    Err(ApiError::new(
+        StatusCode::UNAUTHORIZED,
+        "远程API认证失败",
+        eyre!("token不正确"),
+    ))
+}
+
+/// 比较所用的时间只与长度有关，避免通过响应时间逐个字节猜出token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn read_json<T: DeserializeOwned>(request: Request<Incoming>) -> Result<T, ApiError> {
+    let body = request
+        .into_body()
+        .collect()
+        .await
+        .map_err(|err| ApiError::bad_request("读取请求体失败", err))?
+        .to_bytes();
+    let value = serde_json::from_slice(&body).map_err(|err| {
+        let body = String::from_utf8_lossy(&body);
+        let err = eyre::Report::from(err).wrap_err(format!("解析请求体失败: {body}"));
+        ApiError::bad_request("远程API请求失败", err)
+    })?;
+    Ok(value)
+}
+
+fn json_response<T: Serialize>(value: &T) -> ApiResult {
+    let json =
+        serde_json::to_vec(value).map_err(|err| ApiError::internal("序列化响应失败", err))?;
+    let body = Full::new(Bytes::from(json)).boxed_unsync();
+    Ok(response(StatusCode::OK, "application/json", body))
+}
+
+fn download_events_response(app: &AppContext) -> Response<ApiBody> {
+    let receiver = app.subscribe_download_events();
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let recv_result = tokio::time::timeout(SSE_KEEP_ALIVE_INTERVAL, receiver.recv()).await;
+        let data = match recv_result {
+            Ok(Ok(event)) => match serde_json::to_string(&event) {
+                Ok(json) => format!("data: {json}\n\n"),
+                Err(err) => {
+                    let err_title = "序列化下载事件失败";
+                    let message = eyre::Report::from(err).to_message();
+                    tracing::error!(err_title, message);
+                    return None;
+                }
+            },
+            // 以`:`开头的行是SSE的注释，客户端会忽略
+            Ok(Err(RecvError::Lagged(skipped_count))) => {
+                format!(": 处理太慢，跳过了{skipped_count}个事件\n\n")
+            }
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => ": keep-alive\n\n".to_string(),
+        };
+        let frame = Frame::data(Bytes::from(data));
+        Some((Ok::<_, Infallible>(frame), receiver))
+    });
+
+    let body = StreamBody::new(events).boxed_unsync();
+    let mut response = response(StatusCode::OK, "text/event-stream", body);
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+fn preflight_response() -> Response<ApiBody> {
+    let body = Full::new(Bytes::new()).boxed_unsync();
+    let mut response = response(StatusCode::NO_CONTENT, "text/plain", body);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, OPTIONS"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("Authorization, Content-Type"),
+    );
+    response
+}
+
+fn response(status: StatusCode, content_type: &'static str, body: ApiBody) -> Response<ApiBody> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+struct ApiError {
+    status: StatusCode,
+    error: CommandError,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, err_title: &str, err: impl Into<eyre::Report>) -> Self {
+        Self {
+            status,
+            error: CommandError::from(err_title, err),
+        }
+    }
+
+    fn bad_request(err_title: &str, err: impl Into<eyre::Report>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, err_title, err)
+    }
+
+    fn internal(err_title: &str, err: impl Into<eyre::Report>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, err_title, err)
+    }
+
+    fn into_response(self) -> Response<ApiBody> {
+        let json = serde_json::to_vec(&self.error).unwrap_or_default();
+        let body = Full::new(Bytes::from(json)).boxed_unsync();
+        response(self.status, "application/json", body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use crate::{events::DownloadEvent, test_support::create_test_app};
+
+    use super::*;
+
+    const TOKEN: &str = "test-token";
+
+    /// 启动一个监听随机端口的服务器，返回的`TempDir`被drop时数据目录会被删除
+    fn start_server() -> (AppContext, String, tempfile::TempDir) {
+        let (app, data_dir) = create_test_app(|config| {
+            config.remote_api_enabled = true;
+            config.remote_api_bind_address = "127.0.0.1:0".to_string();
+            config.remote_api_token = TOKEN.to_string();
+        });
+
+        let remote_api_server = RemoteApiServer::new(app.clone());
+        let local_addr = remote_api_server.reload().unwrap().unwrap();
+        let base_url = format!("http://{local_addr}");
+        app.manage_remote_api_server(remote_api_server);
+
+        (app, base_url, data_dir)
+    }
+
+    #[tokio::test]
+    async fn requests_without_valid_token_are_rejected() {
+        let (_app, base_url, _data_dir) = start_server();
+        let client = Client::new();
+        let url = format!("{base_url}/api/get_config");
+
+        let status = client.get(&url).send().await.unwrap().status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let status = client
+            .get(&url)
+            .bearer_auth("wrong-token")
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let resp = client.get(&url).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let config: serde_json::Value =
+            serde_json::from_slice(&resp.bytes().await.unwrap()).unwrap();
+        assert_eq!(config["remote_api_token"], TOKEN);
+    }
+
+    #[tokio::test]
+    async fn percent_encoded_query_token_is_accepted() {
+        let (_app, base_url, _data_dir) = start_server();
+
+        let status = Client::new()
+            .get(format!("{base_url}/api/get_config?token=test%2Dtoken"))
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn only_allowed_origins_can_access_cross_origin() {
+        let (_app, base_url, _data_dir) = start_server();
+        let client = Client::new();
+        let url = format!("{base_url}/api/get_config");
+
+        let resp = client
+            .get(&url)
+            .bearer_auth(TOKEN)
+            .header(header::ORIGIN, "https://www.bilibili.com")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://www.bilibili.com"
+        );
+
+        let resp = client
+            .request(Method::OPTIONS, &url)
+            .header(header::ORIGIN, "https://evil.example.com")
+            .send()
+            .await
+            .unwrap();
+        assert!(
+            !resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_are_returned_as_command_error() {
+        let (_app, base_url, _data_dir) = start_server();
+        let client = Client::new();
+
+        let resp = client
+            .post(format!("{base_url}/api/pause_download_tasks"))
+            .bearer_auth(TOKEN)
+            .body("not json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: CommandError = serde_json::from_slice(&resp.bytes().await.unwrap()).unwrap();
+        assert_eq!(err.err_title, "远程API请求失败");
+
+        let resp = client
+            .get(format!("{base_url}/api/not_exist"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn save_config_takes_effect() {
+        let (app, base_url, _data_dir) = start_server();
+        let client = Client::new();
+
+        let mut config = app.get_config().read().clone();
+        config.speed_limit_kb_per_sec = 512;
+        let resp = client
+            .post(format!("{base_url}/api/save_config"))
+            .bearer_auth(TOKEN)
+            .body(serde_json::to_string(&config).unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(app.get_config().read().speed_limit_kb_per_sec, 512);
+    }
+
+    #[tokio::test]
+    async fn download_events_are_streamed() {
+        let (app, base_url, _data_dir) = start_server();
+
+        // `EventSource`只能通过查询参数携带token
+        let mut resp = Client::new()
+            .get(format!("{base_url}/api/download_events?token={TOKEN}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        app.emit_download_event(DownloadEvent::TaskDelete {
+            task_id: "task-1".to_string(),
+        });
+
+        // 下载管理器会定时发送`Speed`事件，跳过它们
+        let chunk = loop {
+            let chunk = resp.chunk().await.unwrap().unwrap();
+            let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+            if !chunk.contains("\"Speed\"") {
+                break chunk;
+            }
+        };
+        assert_eq!(
+            chunk,
+            "data: {\"event\":\"TaskDelete\",\"data\":{\"task_id\":\"task-1\"}}\n\n"
+        );
+    }
+}
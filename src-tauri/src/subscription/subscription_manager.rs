@@ -0,0 +1,452 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{WrapErr, eyre};
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::instrument;
+
+#[cfg(feature = "gui")]
+use crate::types::subscription::{AddSubscriptionParams, UpdateSubscriptionParams};
+use crate::{
+    app_context::AppContext,
+    downloader::download_task::CreatedDownloadTasks,
+    extensions::EyreReportToMessage,
+    types::{
+        create_download_task_params::{CreateDownloadTaskParams, CreateNormalDownloadTaskParams},
+        get_normal_info_params::GetNormalInfoParams,
+        get_user_video_info_params::GetUserVideoInfoParams,
+        subscription::Subscription,
+        user_video_info::EpInUserVideo,
+    },
+};
+
+/// 每次检查最多翻多少页投稿，避免第一次检查时请求过多
+const MAX_PAGES_PER_CHECK: i64 = 5;
+/// 订阅检查关闭时，多久再看一次配置
+const DISABLED_RECHECK_INTERVAL: Duration = Duration::from_mins(1);
+/// 为一个投稿创建下载任务最多尝试多少次，超过后不再重试
+const MAX_CREATE_ATTEMPTS: u32 = 5;
+
+/// 持久化到`subscription.json`的数据
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct SubscriptionStore {
+    subscriptions: Vec<Subscription>,
+    /// 每个UP主已经检查过的投稿的`bvid`
+    ///
+    /// 被过滤掉的投稿也会记为已见，修改过滤条件不会让旧投稿重新被下载
+    seen_bvids: HashMap<i64, HashSet<String>>,
+    /// 每个UP主创建下载任务失败的投稿，按发布时间从早到晚排列
+    ///
+    /// 这些投稿已经记为已见，翻页时不会再找到，所以单独记录下来，每次检查时重试，
+    /// 失败`MAX_CREATE_ATTEMPTS`次后放弃
+    failed_bvids: HashMap<i64, Vec<FailedVideo>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FailedVideo {
+    bvid: String,
+    /// 已经失败的次数
+    attempts: u32,
+}
+
+impl SubscriptionStore {
+    /// 按发布时间从早到晚返回要创建下载任务的投稿，之前失败的投稿排在最前面
+    fn bvids_to_create(
+        &self,
+        subscription: &Subscription,
+        title_regex: Option<&Regex>,
+        new_videos: &[EpInUserVideo],
+    ) -> Vec<String> {
+        let mut bvids: Vec<String> = self
+            .failed_bvids
+            .get(&subscription.mid)
+            .into_iter()
+            .flatten()
+            .map(|failed| failed.bvid.clone())
+            .collect();
+        if subscription.skip_existing {
+            return bvids;
+        }
+        for video in new_videos.iter().rev() {
+            if subscription.filter.matches(video, title_regex) && !bvids.contains(&video.bvid) {
+                bvids.push(video.bvid.clone());
+            }
+        }
+        bvids
+    }
+
+    /// 把这次检查到的投稿都记为已见，`failed_bvids`留到下次检查时重试
+    ///
+    /// 累计失败`MAX_CREATE_ATTEMPTS`次的投稿不再重试
+    fn record_checked(
+        &mut self,
+        mid: i64,
+        new_videos: &[EpInUserVideo],
+        failed_bvids: Vec<String>,
+    ) {
+        self.seen_bvids
+            .entry(mid)
+            .or_default()
+            .extend(new_videos.iter().map(|video| video.bvid.clone()));
+
+        let previous_failed = self.failed_bvids.remove(&mid).unwrap_or_default();
+        let failed_bvids: Vec<FailedVideo> = failed_bvids
+            .into_iter()
+            .filter_map(|bvid| {
+                let previous_attempts = previous_failed
+                    .iter()
+                    .find(|failed| failed.bvid == bvid)
+                    .map_or(0, |failed| failed.attempts);
+                let attempts = previous_attempts + 1;
+                if attempts >= MAX_CREATE_ATTEMPTS {
+                    tracing::warn!("为投稿`{bvid}`创建下载任务已失败{attempts}次，不再重试");
+                    return None;
+                }
+                Some(FailedVideo { bvid, attempts })
+            })
+            .collect();
+        if !failed_bvids.is_empty() {
+            self.failed_bvids.insert(mid, failed_bvids);
+        }
+    }
+}
+
+pub struct SubscriptionManager {
+    app: AppContext,
+    store: Mutex<SubscriptionStore>,
+    /// 避免定时检查和手动检查同时进行
+    check_lock: tokio::sync::Mutex<()>,
+}
+
+impl SubscriptionManager {
+    #[instrument(level = "error", skip_all)]
+    pub fn new(app: AppContext) -> eyre::Result<Self> {
+        let store_path = get_store_path(&app);
+        let store = if store_path.exists() {
+            let json_string = std::fs::read_to_string(&store_path)
+                .wrap_err(format!("读取`{}`失败", store_path.display()))?;
+            serde_json::from_str(&json_string)
+                .wrap_err(format!("将`{}`解析为订阅数据失败", store_path.display()))?
+        } else {
+            SubscriptionStore::default()
+        };
+
+        tokio::spawn(Self::check_loop(app.clone()));
+
+        Ok(Self {
+            app,
+            store: Mutex::new(store),
+            check_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn get_subscriptions(&self) -> Vec<Subscription> {
+        self.store.lock().subscriptions.clone()
+    }
+
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(mid = params.mid))]
+    pub fn add_subscription(&self, params: AddSubscriptionParams) -> eyre::Result<()> {
+        params.filter.compile_title_regex()?;
+
+        let mut store = self.store.lock();
+        if store.subscriptions.iter().any(|s| s.mid == params.mid) {
+            return Err(eyre!("已经订阅过UP主`{}`", params.mid));
+        }
+
+        store.subscriptions.push(Subscription {
+            mid: params.mid,
+            enabled: true,
+            filter: params.filter,
+            skip_existing: params.skip_existing,
+            create_ts: now_ts(),
+            ..Default::default()
+        });
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(mid = params.mid))]
+    pub fn update_subscription(&self, params: UpdateSubscriptionParams) -> eyre::Result<()> {
+        params.filter.compile_title_regex()?;
+
+        let mut store = self.store.lock();
+        let Some(subscription) = store.subscriptions.iter_mut().find(|s| s.mid == params.mid)
+        else {
+            return Err(eyre!("没有订阅UP主`{}`", params.mid));
+        };
+        subscription.enabled = params.enabled;
+        subscription.filter = params.filter;
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gui")]
+    #[instrument(level = "error", skip_all, fields(mid = mid))]
+    pub fn remove_subscription(&self, mid: i64) -> eyre::Result<()> {
+        let mut store = self.store.lock();
+        let len_before = store.subscriptions.len();
+        store.subscriptions.retain(|s| s.mid != mid);
+        if store.subscriptions.len() == len_before {
+            return Err(eyre!("没有订阅UP主`{mid}`"));
+        }
+        store.seen_bvids.remove(&mid);
+        store.failed_bvids.remove(&mid);
+        self.save(&store)?;
+
+        Ok(())
+    }
+
+    /// 检查所有启用的订阅，为新投稿创建下载任务
+    ///
+    /// 单个订阅检查失败不影响其他订阅
+    #[instrument(level = "error", skip_all)]
+    pub async fn check_subscriptions(&self) {
+        let _check_guard = self.check_lock.lock().await;
+
+        let subscriptions: Vec<Subscription> = self
+            .get_subscriptions()
+            .into_iter()
+            .filter(|s| s.enabled)
+            .collect();
+
+        for subscription in subscriptions {
+            let mid = subscription.mid;
+            if let Err(err) = self.check_subscription(subscription).await {
+                let err_title = format!("检查UP主`{mid}`的订阅失败");
+                let message = err.to_message();
+                tracing::error!(err_title, message);
+            }
+        }
+    }
+
+    #[instrument(level = "error", skip_all, fields(mid = subscription.mid))]
+    async fn check_subscription(&self, subscription: Subscription) -> eyre::Result<()> {
+        let mid = subscription.mid;
+        let title_regex = subscription.filter.compile_title_regex()?;
+
+        let seen_bvids = self
+            .store
+            .lock()
+            .seen_bvids
+            .get(&mid)
+            .cloned()
+            .unwrap_or_default();
+        let new_videos = self.get_new_videos(&subscription, &seen_bvids).await?;
+        let bvids_to_create =
+            self.store
+                .lock()
+                .bvids_to_create(&subscription, title_regex.as_ref(), &new_videos);
+
+        let mut failed_bvids = Vec::new();
+        let mut created_count = 0;
+        let mut skipped_count = 0;
+        for bvid in bvids_to_create {
+            match self.create_download_tasks(&bvid).await {
+                Ok(created) => {
+                    created_count += created.tasks.len();
+                    skipped_count += created.skipped_duplicates.len();
+                }
+                Err(err) => {
+                    let err_title = format!("为投稿`{bvid}`创建下载任务失败，下次检查时重试");
+                    let message = err.to_message();
+                    tracing::error!(err_title, message);
+                    failed_bvids.push(bvid);
+                }
+            }
+        }
+
+        let mut store = self.store.lock();
+        // 检查期间订阅可能已被删除
+        let Some(stored_subscription) = store.subscriptions.iter_mut().find(|s| s.mid == mid)
+        else {
+            return Ok(());
+        };
+        if let Some(video) = new_videos.first() {
+            stored_subscription.up_name.clone_from(&video.author);
+        }
+        stored_subscription.skip_existing = false;
+        stored_subscription.last_check_ts = Some(now_ts());
+        store.record_checked(mid, &new_videos, failed_bvids);
+        self.save(&store)?;
+
+        tracing::info!(
+            new_video_count = new_videos.len(),
+            created_count,
+            skipped_count,
+            "UP主的订阅检查完成"
+        );
+
+        Ok(())
+    }
+
+    /// 从新到旧获取还没见过的投稿
+    async fn get_new_videos(
+        &self,
+        subscription: &Subscription,
+        seen_bvids: &HashSet<String>,
+    ) -> eyre::Result<Vec<EpInUserVideo>> {
+        let bili_client = self.app.get_bili_client();
+        let min_pub_ts = subscription.filter.min_pub_ts;
+
+        let mut new_videos = Vec::new();
+        'pages: for pn in 1..=MAX_PAGES_PER_CHECK {
+            let params = GetUserVideoInfoParams {
+                pn,
+                mid: subscription.mid,
+            };
+            let user_video_info = bili_client
+                .get_user_video_info(params)
+                .await
+                .wrap_err(format!("获取第{pn}页投稿失败"))?;
+
+            let videos = user_video_info.list.vlist;
+            if videos.is_empty() {
+                break;
+            }
+            for video in videos {
+                // 投稿按发布时间从新到旧排列，之后的投稿都已经检查过或太早了
+                if seen_bvids.contains(&video.bvid)
+                    || min_pub_ts.is_some_and(|min_pub_ts| video.created < min_pub_ts)
+                {
+                    break 'pages;
+                }
+                new_videos.push(video);
+            }
+        }
+
+        Ok(new_videos)
+    }
+
+    /// 因为重复而跳过的下载也算成功，只有什么都没创建时才返回错误
+    async fn create_download_tasks(&self, bvid: &str) -> eyre::Result<CreatedDownloadTasks> {
+        let params = GetNormalInfoParams::Bvid(bvid.to_string());
+        let info = self
+            .app
+            .get_bili_client()
+            .get_normal_info(params)
+            .await
+            .wrap_err("获取视频信息失败")?;
+
+        let params = CreateDownloadTaskParams::Normal(CreateNormalDownloadTaskParams {
+            aid_cid_pairs: vec![(info.aid, None)],
+            info,
+        });
+        let created = self
+            .app
+            .get_download_manager()
+            .create_download_tasks(&params);
+        if created.is_empty() {
+            return Err(eyre!("没有创建任何下载任务"));
+        }
+
+        Ok(created)
+    }
+
+    fn save(&self, store: &SubscriptionStore) -> eyre::Result<()> {
+        let store_path = get_store_path(&self.app);
+        let json_string = serde_json::to_string_pretty(store)?;
+        std::fs::write(&store_path, json_string)
+            .wrap_err(format!("保存`{}`失败", store_path.display()))?;
+        Ok(())
+    }
+
+    /// 按配置的间隔定时检查订阅，间隔为0时不检查
+    async fn check_loop(app: AppContext) {
+        loop {
+            let interval_min = app.get_config().read().subscription_check_interval_min;
+            if interval_min == 0 {
+                sleep(DISABLED_RECHECK_INTERVAL).await;
+                continue;
+            }
+
+            sleep(Duration::from_secs(interval_min.saturating_mul(60))).await;
+            app.get_subscription_manager().check_subscriptions().await;
+        }
+    }
+}
+
+fn get_store_path(app: &AppContext) -> PathBuf {
+    app.app_data_dir().join("subscription.json")
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs().cast_signed())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(bvid: &str) -> EpInUserVideo {
+        EpInUserVideo {
+            bvid: bvid.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failed_video_is_retried_after_newer_videos_are_seen() {
+        let mut store = SubscriptionStore::default();
+        let subscription = Subscription {
+            mid: 1,
+            ..Default::default()
+        };
+
+        // 投稿从新到旧排列，BV1创建失败，BV2成功
+        let new_videos = [video("BV2"), video("BV1")];
+        let bvids = store.bvids_to_create(&subscription, None, &new_videos);
+        assert_eq!(bvids, ["BV1", "BV2"]);
+        store.record_checked(1, &new_videos, vec!["BV1".to_string()]);
+
+        // 两个投稿都已见，翻页时不会再找到，但BV1仍会重试，排在新投稿前面
+        let new_videos = [video("BV3")];
+        let bvids = store.bvids_to_create(&subscription, None, &new_videos);
+        assert_eq!(bvids, ["BV1", "BV3"]);
+        store.record_checked(1, &new_videos, Vec::new());
+
+        assert!(store.bvids_to_create(&subscription, None, &[]).is_empty());
+        let seen_bvids = &store.seen_bvids[&1];
+        assert!(
+            ["BV1", "BV2", "BV3"]
+                .iter()
+                .all(|bvid| seen_bvids.contains(*bvid))
+        );
+    }
+
+    #[test]
+    fn stop_retrying_after_max_attempts() {
+        let mut store = SubscriptionStore::default();
+        let subscription = Subscription {
+            mid: 1,
+            ..Default::default()
+        };
+
+        store.record_checked(1, &[video("BV1")], vec!["BV1".to_string()]);
+        for _ in 1..MAX_CREATE_ATTEMPTS - 1 {
+            let bvids = store.bvids_to_create(&subscription, None, &[]);
+            assert_eq!(bvids, ["BV1"]);
+            store.record_checked(1, &[], bvids);
+        }
+        assert_eq!(store.failed_bvids[&1][0].attempts, MAX_CREATE_ATTEMPTS - 1);
+
+        // 最后一次也失败后不再重试
+        let bvids = store.bvids_to_create(&subscription, None, &[]);
+        store.record_checked(1, &[], bvids);
+        assert!(store.bvids_to_create(&subscription, None, &[]).is_empty());
+        assert!(!store.failed_bvids.contains_key(&1));
+    }
+}
@@ -0,0 +1,246 @@
+//! 把B站的JSON字幕转换为SRT、ASS、WebVTT和LRC，以及把两种语言的字幕合并为双语字幕
+
+use std::fmt::Write;
+
+use crate::{
+    types::subtitle::{Body, Subtitle},
+    utils,
+};
+
+/// ASS字幕的画布大小
+const ASS_PLAY_RES: (u32, u32) = (1920, 1080);
+/// B站字幕的`font_size`是相对大小，默认为0.4，乘上这个数换算成1080P下的像素
+const ASS_FONT_SIZE_SCALE: f64 = 135.0;
+/// B站字幕默认显示在底部居中，与ASS的`Alignment`取值相同
+const DEFAULT_LOCATION: i64 = 2;
+
+pub fn to_srt(subtitle: &Subtitle) -> String {
+    let mut srt = String::new();
+    for (i, body) in subtitle.body.iter().enumerate() {
+        let index = i + 1;
+        let _ = writeln!(
+            srt,
+            "{index}\n{} --> {}\n{}\n",
+            utils::seconds_to_srt_time(body.from),
+            utils::seconds_to_srt_time(body.to),
+            body.content,
+        );
+    }
+    srt
+}
+
+pub fn to_vtt(subtitle: &Subtitle) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for body in &subtitle.body {
+        let content = body
+            .content
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        let _ = writeln!(
+            vtt,
+            "{} --> {}\n{content}\n",
+            utils::seconds_to_vtt_time(body.from),
+            utils::seconds_to_vtt_time(body.to),
+        );
+    }
+    vtt
+}
+
+/// LRC只有开始时间，字幕之间有空隙时在上一句结束的时间插入一行空歌词
+pub fn to_lrc(subtitle: &Subtitle, title: &str) -> String {
+    let mut lrc = format!("[ti:{title}]\n");
+    for (i, body) in subtitle.body.iter().enumerate() {
+        let content = body.content.lines().collect::<Vec<_>>().join(" ");
+        let _ = writeln!(lrc, "[{}]{content}", lrc_time(body.from));
+
+        let next_from = subtitle.body.get(i + 1).map(|next| next.from);
+        if next_from.is_none_or(|from| from > body.to) {
+            let _ = writeln!(lrc, "[{}]", lrc_time(body.to));
+        }
+    }
+    lrc
+}
+
+/// 按字幕自带的字号、颜色和背景生成样式，`location`不是底部居中的字幕单独指定位置
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+pub fn to_ass(subtitle: &Subtitle, title: &str) -> String {
+    let (width, height) = ASS_PLAY_RES;
+    let font_size = if subtitle.font_size > 0.0 {
+        subtitle.font_size
+    } else {
+        0.4
+    };
+    let font_size = (font_size * ASS_FONT_SIZE_SCALE).round() as u32;
+    let primary_colour = ass_colour(&subtitle.font_color, 0, "FFFFFF");
+    // 有背景时用不透明背景框，没有背景时用黑色描边
+    let background_alpha = subtitle.background_alpha.clamp(0.0, 1.0);
+    let (border_style, outline, outline_colour) = if background_alpha > 0.0 {
+        let alpha = ((1.0 - background_alpha) * 255.0).round() as u8;
+        (
+            3,
+            8,
+            ass_colour(&subtitle.background_color, alpha, "000000"),
+        )
+    } else {
+        (1, 2, ass_colour("", 0, "000000"))
+    };
+
+    let mut ass = format!(
+        "\
+        [Script Info]\n\
+        ; Script generated by bilibili-video-downloader (https://github.com/lanyeeee/bilibili-video-downloader)\n\
+        Title: {title}\n\
+        ScriptType: v4.00+\n\
+        PlayResX: {width}\n\
+        PlayResY: {height}\n\
+        WrapStyle: 0\n\
+        ScaledBorderAndShadow: yes\n\
+        \n\
+        [V4+ Styles]\n\
+        Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, \
+                Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, \
+                Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+        Style: Default,黑体,{font_size},{primary_colour},&H00FFFFFF,{outline_colour},{outline_colour},\
+        0, 0, 0, 0, 100, 100, 0.00, 0.00, {border_style}, \
+        {outline}, 0, {DEFAULT_LOCATION}, 40, 40, 40, 1\n\
+        \n\
+        [Events]\n\
+        Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+        "
+    );
+    for body in &subtitle.body {
+        let alignment = if (1..=9).contains(&body.location) && body.location != DEFAULT_LOCATION {
+            format!("{{\\an{}}}", body.location)
+        } else {
+            String::new()
+        };
+        let text = body.content.lines().collect::<Vec<_>>().join("\\N");
+        let _ = writeln!(
+            ass,
+            "Dialogue: 0,{},{},Default,,0,0,0,,{alignment}{text}",
+            ass_time(body.from),
+            ass_time(body.to),
+        );
+    }
+    ass
+}
+
+/// 把`primary`和`secondary`合并为一个字幕，同一时刻显示的字幕`primary`在上、`secondary`在下
+///
+/// 两种语言的断句通常不一样，所以按所有字幕的起止时间把时间轴切成小段，
+/// 每一段显示当时两种语言的所有字幕，内容相同的相邻小段再合并回去
+pub fn merge_bilingual(primary: &Subtitle, secondary: &Subtitle) -> Subtitle {
+    let mut boundaries: Vec<f64> = primary
+        .body
+        .iter()
+        .chain(&secondary.body)
+        .flat_map(|body| [body.from, body.to])
+        .collect();
+    boundaries.sort_by(f64::total_cmp);
+    boundaries.dedup();
+
+    let mut merged: Vec<Body> = Vec::new();
+    for window in boundaries.windows(2) {
+        let (from, to) = (window[0], window[1]);
+        let active: Vec<&Body> = primary
+            .body
+            .iter()
+            .chain(&secondary.body)
+            .filter(|body| body.from <= from && to <= body.to)
+            .collect();
+        let Some(first) = active.first() else {
+            continue;
+        };
+        let content = active
+            .iter()
+            .map(|body| body.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if let Some(last) = merged.last_mut()
+            && (last.to - from).abs() < f64::EPSILON
+            && last.content == content
+        {
+            last.to = to;
+            continue;
+        }
+        merged.push(Body {
+            from,
+            to,
+            location: first.location,
+            content,
+        });
+    }
+
+    Subtitle {
+        body: merged,
+        ..primary.clone()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn lrc_time(seconds: f64) -> String {
+    let total_cs = (seconds.max(0.0) * 100.0).round() as u64;
+    let (m, s, cs) = (total_cs / 6000, total_cs / 100 % 60, total_cs % 100);
+    format!("{m:02}:{s:02}.{cs:02}")
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn ass_time(seconds: f64) -> String {
+    let total_cs = (seconds.max(0.0) * 100.0).round() as u64;
+    let (h, m, s, cs) = (
+        total_cs / 360_000,
+        total_cs / 6000 % 60,
+        total_cs / 100 % 60,
+        total_cs % 100,
+    );
+    format!("{h}:{m:02}:{s:02}.{cs:02}")
+}
+
+/// 把`#RRGGBB`转换为ASS的`&HAABBGGRR`，解析失败时使用`default_rgb`
+fn ass_colour(rgb: &str, alpha: u8, default_rgb: &str) -> String {
+    let rgb = rgb.trim().trim_start_matches('#');
+    let rgb = if rgb.len() == 6 && rgb.chars().all(|c| c.is_ascii_hexdigit()) {
+        rgb
+    } else {
+        default_rgb
+    };
+    let (r, g, b) = (&rgb[0..2], &rgb[2..4], &rgb[4..6]);
+    format!("&H{alpha:02X}{b}{g}{r}").to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support;
+
+    use super::*;
+
+    fn read_fixture(name: &str) -> String {
+        test_support::read_fixture(&format!("subtitle_converter/{name}"))
+    }
+
+    fn read_subtitle(lan: &str) -> Subtitle {
+        serde_json::from_str(&read_fixture(&format!("{lan}.json"))).unwrap()
+    }
+
+    #[test]
+    fn convert_to_each_format() {
+        let subtitle = read_subtitle("zh-CN");
+
+        assert_eq!(to_srt(&subtitle), read_fixture("zh-CN.srt"));
+        assert_eq!(to_vtt(&subtitle), read_fixture("zh-CN.vtt"));
+        assert_eq!(to_lrc(&subtitle, "测试视频"), read_fixture("zh-CN.lrc"));
+        assert_eq!(to_ass(&subtitle, "测试视频"), read_fixture("zh-CN.ass"));
+    }
+
+    #[test]
+    fn merge_two_languages() {
+        let merged = merge_bilingual(&read_subtitle("zh-CN"), &read_subtitle("en-US"));
+
+        assert_eq!(to_srt(&merged), read_fixture("bilingual.srt"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    app_context::{AppContext, EventEmitter},
+    bili_client::BiliClient,
+    config::Config,
+    danmaku_xml_to_ass::canvas::CanvasConfig,
+    downloader::download_manager::DownloadManager,
+    events::{DownloadEvent, LogEvent},
+};
+
+struct NoopEventEmitter;
+
+impl EventEmitter for NoopEventEmitter {
+    fn emit_download_event(&self, _event: DownloadEvent) {}
+    #[cfg(feature = "gui")]
+    fn emit_plugin_event(&self, _event: crate::events::PluginEvent) {}
+    fn emit_log_event(&self, _event: LogEvent) {}
+}
+
+/// 创建一个数据目录在临时目录中的`AppContext`，已经管理了配置、`BiliClient`和`DownloadManager`
+///
+/// 返回的`TempDir`被drop时数据目录会被删除
+pub fn create_test_app(update_config: impl FnOnce(&mut Config)) -> (AppContext, tempfile::TempDir) {
+    let data_dir = tempfile::tempdir().unwrap();
+    let app = AppContext::new(
+        data_dir.path().to_path_buf(),
+        "test".to_string(),
+        NoopEventEmitter,
+    );
+
+    let mut config = Config::new(&app).unwrap();
+    update_config(&mut config);
+    app.manage_config(config);
+    app.manage_bili_client(BiliClient::new(app.clone()));
+    app.manage_download_manager(DownloadManager::new(app.clone()));
+
+    (app, data_dir)
+}
+
+/// `tests/fixtures`中的测试数据
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+pub fn read_fixture(name: &str) -> String {
+    std::fs::read_to_string(fixture_path(name)).unwrap()
+}
+
+/// 每种弹幕都只有两个槽位，方便测试排布、延迟和丢弃
+pub fn small_canvas_config() -> CanvasConfig {
+    CanvasConfig {
+        float_percentage: 0.1,
+        top_percentage: 0.1,
+        bottom_percentage: 0.1,
+        ..Default::default()
+    }
+}
+
+/// 检查输出与`tests/fixtures`中的golden文件是否完全一致
+///
+/// 设置环境变量`UPDATE_GOLDEN=1`时用输出覆盖golden文件
+pub fn assert_golden(output_path: &Path, golden_name: &str) {
+    let output = std::fs::read(output_path).unwrap();
+    let golden_path = fixture_path(golden_name);
+    if std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1") {
+        std::fs::write(&golden_path, &output).unwrap();
+    }
+    let golden = std::fs::read(&golden_path).unwrap();
+    assert!(output == golden, "输出与`{golden_name}`不一致");
+}
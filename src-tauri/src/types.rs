@@ -1,5 +1,7 @@
 pub mod audio_quality;
+#[cfg(feature = "gui")]
 pub mod available_media_formats;
+#[cfg(feature = "gui")]
 pub mod bangumi_follow_info;
 pub mod bangumi_info;
 pub mod bangumi_media_url;
@@ -8,28 +10,45 @@ pub mod cheese_info;
 pub mod cheese_media_url;
 pub mod codec_type;
 pub mod create_download_task_params;
+#[cfg(feature = "gui")]
 pub mod fav_folders;
 pub mod fav_info;
+#[cfg(feature = "gui")]
 pub mod get_available_media_formats_params;
+#[cfg(feature = "gui")]
 pub mod get_bangumi_follow_info_params;
 pub mod get_bangumi_info_params;
 pub mod get_cheese_info_params;
 pub mod get_fav_info_params;
+#[cfg(feature = "gui")]
 pub mod get_history_info_params;
 pub mod get_normal_info_params;
 pub mod get_user_video_info_params;
+#[cfg(feature = "gui")]
 pub mod history_info;
+pub mod keyword_search_info;
+pub mod live_danmu_info;
+pub mod live_play_info;
+pub mod live_recording;
+pub mod live_room_info;
+#[cfg(feature = "gui")]
 pub mod log_metadata;
+pub mod mirror_sync;
 pub mod normal_info;
 pub mod normal_media_url;
+pub mod parsed_target;
 pub mod player_info;
 pub mod plugin_info;
 pub mod qrcode_data;
 pub mod qrcode_status;
+pub mod query_download_history_params;
+#[cfg(feature = "gui")]
 pub mod restart_download_task_params;
+pub mod search_by_keyword_params;
 pub mod search_params;
 pub mod search_result;
 pub mod skip_segments;
+pub mod subscription;
 pub mod subtitle;
 pub mod tags;
 pub mod user_info;
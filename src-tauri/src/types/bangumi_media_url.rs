@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+#[cfg(feature = "gui")]
 use crate::types::{
     audio_quality::AudioQuality,
     available_media_formats::{AvailableMediaFormats, VideoQualityAndCodecType},
@@ -137,6 +138,7 @@ pub struct DurlDetailInBangumi {
 }
 
 impl BangumiMediaUrl {
+    #[cfg(feature = "gui")]
     pub fn to_get_available_media_formats_result(&self) -> AvailableMediaFormats {
         let mut video_qualities_and_codec_types: Vec<VideoQualityAndCodecType> = Vec::new();
         let mut audio_qualities: Vec<AudioQuality> = Vec::new();
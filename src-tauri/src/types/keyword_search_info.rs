@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 关键词搜索的一页结果
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct KeywordSearchInfo {
+    pub page: i64,
+    pub pagesize: i64,
+    #[serde(rename = "numResults")]
+    pub num_results: i64,
+    #[serde(rename = "numPages")]
+    pub num_pages: i64,
+    pub items: KeywordSearchItems,
+}
+
+impl KeywordSearchInfo {
+    /// 去掉标题中包裹关键词的`<em class="keyword">`，并还原被转义的字符
+    pub fn remove_keyword_highlight(&mut self) {
+        match &mut self.items {
+            KeywordSearchItems::Video(videos) => {
+                for video in videos {
+                    video.title = remove_highlight(&video.title);
+                }
+            }
+            KeywordSearchItems::Bangumi(medias) | KeywordSearchItems::MediaFt(medias) => {
+                for media in medias {
+                    media.title = remove_highlight(&media.title);
+                    media.org_title = remove_highlight(&media.org_title);
+                }
+            }
+            KeywordSearchItems::User(_) => {}
+        }
+    }
+}
+
+/// 接口中的`result`字段，不同的搜索类型结构不同
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum KeywordSearchItems {
+    Video(Vec<VideoInKeywordSearch>),
+    Bangumi(Vec<MediaInKeywordSearch>),
+    MediaFt(Vec<MediaInKeywordSearch>),
+    User(Vec<UserInKeywordSearch>),
+}
+
+impl Default for KeywordSearchItems {
+    fn default() -> Self {
+        Self::Video(Vec::new())
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct VideoInKeywordSearch {
+    pub aid: i64,
+    pub bvid: String,
+    /// 关键词会被`<em class="keyword">`包裹，解析时已经去掉
+    pub title: String,
+    pub author: String,
+    pub mid: i64,
+    pub typename: String,
+    pub arcurl: String,
+    pub description: String,
+    pub pic: String,
+    pub play: i64,
+    pub video_review: i64,
+    pub favorites: i64,
+    pub review: i64,
+    pub like: i64,
+    pub danmaku: i64,
+    pub tag: String,
+    pub pubdate: i64,
+    pub senddate: i64,
+    /// 格式为`分:秒`，例如`12:34`
+    pub duration: String,
+    pub is_pay: i64,
+    pub is_union_video: i64,
+    pub is_charge_video: i64,
+}
+
+/// 番剧和影视的搜索结果
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct MediaInKeywordSearch {
+    pub media_id: i64,
+    pub season_id: i64,
+    pub title: String,
+    pub org_title: String,
+    pub cover: String,
+    pub media_type: i64,
+    pub season_type: i64,
+    pub season_type_name: String,
+    pub areas: String,
+    pub styles: String,
+    pub cv: String,
+    pub staff: String,
+    pub desc: String,
+    pub pubtime: i64,
+    pub ep_size: i64,
+    pub url: String,
+    pub index_show: String,
+    pub eps: Vec<EpInKeywordSearch>,
+    pub media_score: Option<MediaScoreInKeywordSearch>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct EpInKeywordSearch {
+    pub id: i64,
+    pub cover: String,
+    pub title: String,
+    pub url: String,
+    pub index_title: String,
+    pub long_title: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct MediaScoreInKeywordSearch {
+    pub score: f64,
+    pub user_count: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct UserInKeywordSearch {
+    pub mid: i64,
+    pub uname: String,
+    pub usign: String,
+    pub fans: i64,
+    pub videos: i64,
+    pub upic: String,
+    pub level: i64,
+    pub gender: i64,
+    pub is_upuser: i64,
+    pub is_live: i64,
+    pub room_id: i64,
+    pub official_verify: Option<OfficialVerifyInKeywordSearch>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct OfficialVerifyInKeywordSearch {
+    #[serde(rename = "type")]
+    pub type_field: i64,
+    pub desc: String,
+}
+
+fn remove_highlight(text: &str) -> String {
+    text.replace(r#"<em class="keyword">"#, "")
+        .replace("</em>", "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        // `&amp;`必须最后还原，否则`&amp;lt;`会被还原成`<`
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_keyword_highlight_and_unescape() {
+        let mut info = KeywordSearchInfo {
+            items: KeywordSearchItems::Video(vec![VideoInKeywordSearch {
+                title: r#"【<em class="keyword">原神</em>】A &amp; B &lt;3 &amp;lt;"#.to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        info.remove_keyword_highlight();
+
+        let KeywordSearchItems::Video(videos) = &info.items else {
+            unreachable!()
+        };
+        assert_eq!(videos[0].title, "【原神】A & B <3 &lt;");
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct LiveDanmuInfo {
+    pub group: String,
+    pub business_id: i64,
+    pub refresh_row_factor: f64,
+    pub refresh_rate: i64,
+    pub max_delay: i64,
+    pub token: String,
+    pub host_list: Vec<HostInLiveDanmu>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct HostInLiveDanmu {
+    pub host: String,
+    pub port: u16,
+    pub wss_port: u16,
+    pub ws_port: u16,
+}
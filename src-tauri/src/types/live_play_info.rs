@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct LivePlayInfo {
+    pub room_id: i64,
+    pub short_id: i64,
+    pub uid: i64,
+    /// 0:未开播 1:直播中 2:轮播中
+    pub live_status: i64,
+    pub live_time: i64,
+    /// 未开播时为`None`
+    pub playurl_info: Option<PlayurlInfoInLive>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct PlayurlInfoInLive {
+    pub conf_json: String,
+    pub playurl: PlayurlInLive,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct PlayurlInLive {
+    pub cid: i64,
+    pub stream: Vec<StreamInLive>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct StreamInLive {
+    /// `http_stream`或`http_hls`
+    pub protocol_name: String,
+    pub format: Vec<FormatInLive>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct FormatInLive {
+    /// `flv`、`ts`或`fmp4`
+    pub format_name: String,
+    pub codec: Vec<CodecInLive>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct CodecInLive {
+    /// `avc`或`hevc`
+    pub codec_name: String,
+    pub current_qn: i64,
+    pub accept_qn: Vec<i64>,
+    pub base_url: String,
+    pub url_info: Vec<UrlInfoInLive>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct UrlInfoInLive {
+    pub host: String,
+    pub extra: String,
+    pub stream_ttl: i64,
+}
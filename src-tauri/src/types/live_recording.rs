@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum LiveRecordingState {
+    /// 正在获取直播流
+    #[default]
+    Connecting,
+    Recording,
+    /// 未开播或断线，稍后会重新连接
+    Waiting,
+    Stopped,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct LiveRecording {
+    /// 真实房间号，不是短号
+    pub room_id: i64,
+    pub uid: i64,
+    pub title: String,
+    pub state: LiveRecordingState,
+    pub output_dir: PathBuf,
+    /// 正在写入的文件，没有在录制时为`None`
+    pub current_file: Option<PathBuf>,
+    /// 已经写完的文件数，不包括`current_file`
+    pub file_count: u64,
+    pub recorded_bytes: u64,
+    pub danmaku_count: u64,
+    pub start_ts: i64,
+    /// 最近一次断线或获取直播流失败的原因
+    pub last_error: Option<String>,
+}
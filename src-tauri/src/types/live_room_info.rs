@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct LiveRoomInfo {
+    pub uid: i64,
+    pub room_id: i64,
+    pub short_id: i64,
+    pub attention: i64,
+    pub online: i64,
+    pub description: String,
+    /// 0:未开播 1:直播中 2:轮播中
+    pub live_status: i64,
+    pub area_id: i64,
+    pub area_name: String,
+    pub parent_area_id: i64,
+    pub parent_area_name: String,
+    pub title: String,
+    pub user_cover: String,
+    pub keyframe: String,
+    pub live_time: String,
+    pub tags: String,
+}
@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum MirrorSource {
+    Fav { media_list_id: i64 },
+    WatchLater,
+}
+
+impl MirrorSource {
+    /// 用作`mirror_sync.json`中已归档记录的键
+    pub fn key(&self) -> String {
+        match self {
+            Self::Fav { media_list_id } => format!("fav_{media_list_id}"),
+            Self::WatchLater => "watch_later".to_string(),
+        }
+    }
+}
+
+/// 内容从收藏夹或稍后再看中移除后，如何处理已归档的本地文件
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum RemovedItemAction {
+    /// 什么都不做
+    #[default]
+    Keep,
+    /// 保留本地文件，只记录移除时间
+    Record,
+    /// 删除本地文件和未完成的下载任务，重新加入后会再次下载
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct MirrorSync {
+    pub source: MirrorSource,
+    /// 收藏夹的标题，同步过后才会知道
+    pub title: String,
+    pub enabled: bool,
+    pub removed_item_action: RemovedItemAction,
+    pub create_ts: i64,
+    pub last_sync_ts: Option<i64>,
+}
+
+/// 已经创建过下载任务的`bvid`/`cid`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct ArchivedItem {
+    pub bvid: String,
+    pub cid: i64,
+    pub title: String,
+    /// 因为重复而跳过下载时为`None`，这时本地文件不是这个同步下载的，移除时不会删除
+    pub task_id: Option<String>,
+    pub episode_dir: PathBuf,
+    pub filename: String,
+    pub archive_ts: i64,
+    /// 从收藏夹或稍后再看中移除的时间，只在`RemovedItemAction::Record`时记录
+    pub removed_ts: Option<i64>,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct AddMirrorSyncParams {
+    pub source: MirrorSource,
+    pub removed_item_action: RemovedItemAction,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct UpdateMirrorSyncParams {
+    pub source: MirrorSource,
+    pub enabled: bool,
+    pub removed_item_action: RemovedItemAction,
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::search_params::SearchParams;
+
+/// 从粘贴的文本中解析出的一个搜索目标
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct ParsedTarget {
+    pub params: SearchParams,
+    /// 链接中`?p=`指定的分P，从1开始，只有普通视频会有
+    pub page: Option<i64>,
+}
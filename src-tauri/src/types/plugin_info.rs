@@ -6,6 +6,7 @@ use bilibili_video_downloader_plugin_api::v1::{
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+#[cfg(feature = "gui")]
 use crate::plugin::plugin_types::PluginRuntime;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
@@ -81,6 +82,7 @@ pub struct PluginMetadata {
 }
 
 impl PluginMetadata {
+    #[cfg(feature = "gui")]
     pub fn from_plugin_runtime(runtime: &PluginRuntime) -> Self {
         Self {
             path: runtime.plugin_path.clone(),
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct QueryDownloadHistoryParams {
+    /// 匹配标题、UP昵称、bvid，不区分大小写
+    pub keyword: Option<String>,
+    pub aid: Option<i64>,
+    pub ep_id: Option<i64>,
+    /// 从1开始
+    pub pn: usize,
+    /// 每页的记录数，为0时返回所有记录
+    pub ps: usize,
+}
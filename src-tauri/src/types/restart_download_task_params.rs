@@ -16,10 +16,15 @@ pub struct RestartDownloadTaskParams {
     pub merge_selected: bool,
     pub embed_chapter_selected: bool,
     pub embed_skip_selected: bool,
+    pub embed_highlight_selected: bool,
     pub subtitle_task_selected: bool,
     pub xml_danmaku_selected: bool,
     pub ass_danmaku_selected: bool,
     pub json_danmaku_selected: bool,
+    pub vtt_danmaku_selected: bool,
+    pub srt_danmaku_selected: bool,
+    pub csv_danmaku_selected: bool,
+    pub danmaku_heatmap_selected: bool,
     pub cover_task_selected: bool,
     pub nfo_task_selected: bool,
     pub json_task_selected: bool,
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct SearchByKeywordParams {
+    pub keyword: String,
+    pub search_type: KeywordSearchType,
+    #[serde(default)]
+    pub order: KeywordSearchOrder,
+    /// 只对视频搜索有效
+    #[serde(default)]
+    pub duration: KeywordSearchDuration,
+    pub pn: i64,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeywordSearchType {
+    #[default]
+    Video,
+    Bangumi,
+    /// 影视
+    MediaFt,
+    User,
+}
+
+impl KeywordSearchType {
+    pub fn as_param(self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Bangumi => "media_bangumi",
+            Self::MediaFt => "media_ft",
+            Self::User => "bili_user",
+        }
+    }
+}
+
+/// 番剧和影视搜索不支持排序，会忽略这个参数
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeywordSearchOrder {
+    /// 视频为综合排序，用户为默认排序
+    #[default]
+    Default,
+    /// 最多播放，只对视频有效
+    Click,
+    /// 最新发布，只对视频有效
+    PubDate,
+    /// 最多弹幕，只对视频有效
+    Danmaku,
+    /// 最多收藏，只对视频有效
+    Stow,
+    /// 粉丝数，只对用户有效
+    Fans,
+    /// 等级，只对用户有效
+    Level,
+}
+
+impl KeywordSearchOrder {
+    /// 不适用于`search_type`时返回`None`
+    pub fn as_param(self, search_type: KeywordSearchType) -> Option<&'static str> {
+        use KeywordSearchOrder::{Click, Danmaku, Default, Fans, Level, PubDate, Stow};
+        use KeywordSearchType::{User, Video};
+
+        match (search_type, self) {
+            (Video, Default) => Some("totalrank"),
+            (Video, Click) => Some("click"),
+            (Video, PubDate) => Some("pubdate"),
+            (Video, Danmaku) => Some("dm"),
+            (Video, Stow) => Some("stow"),
+            (User, Default) => Some("0"),
+            (User, Fans) => Some("fans"),
+            (User, Level) => Some("level"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeywordSearchDuration {
+    #[default]
+    All,
+    #[cfg_attr(feature = "cli", value(name = "0-10"))]
+    Under10Min,
+    #[cfg_attr(feature = "cli", value(name = "10-30"))]
+    From10To30Min,
+    #[cfg_attr(feature = "cli", value(name = "30-60"))]
+    From30To60Min,
+    #[cfg_attr(feature = "cli", value(name = "60+"))]
+    Over60Min,
+}
+
+impl KeywordSearchDuration {
+    pub fn as_param(self) -> &'static str {
+        match self {
+            Self::All => "0",
+            Self::Under10Min => "1",
+            Self::From10To30Min => "2",
+            Self::From30To60Min => "3",
+            Self::Over60Min => "4",
+        }
+    }
+}
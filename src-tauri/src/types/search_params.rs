@@ -5,6 +5,7 @@ use super::{
     get_bangumi_info_params::GetBangumiInfoParams, get_cheese_info_params::GetCheeseInfoParams,
     get_fav_info_params::GetFavInfoParams, get_normal_info_params::GetNormalInfoParams,
     get_user_video_info_params::GetUserVideoInfoParams,
+    search_by_keyword_params::SearchByKeywordParams,
 };
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
@@ -14,4 +15,5 @@ pub enum SearchParams {
     Cheese(GetCheeseInfoParams),
     UserVideo(GetUserVideoInfoParams),
     Fav(GetFavInfoParams),
+    Keyword(SearchByKeywordParams),
 }
@@ -5,6 +5,7 @@ use super::{
     bangumi_info::{self, BangumiInfo},
     cheese_info::{self, CheeseInfo},
     fav_info::FavInfo,
+    keyword_search_info::KeywordSearchInfo,
     normal_info::NormalInfo,
     user_video_info::UserVideoInfo,
 };
@@ -17,6 +18,7 @@ pub enum SearchResult {
     Cheese(CheeseSearchResult),
     UserVideo(UserVideoSearchResult),
     Fav(FavSearchResult),
+    Keyword(KeywordSearchResult),
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
@@ -44,3 +46,7 @@ pub struct UserVideoSearchResult(pub UserVideoInfo);
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(default)]
 pub struct FavSearchResult(pub FavInfo);
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct KeywordSearchResult(pub KeywordSearchInfo);
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
-use crate::downloader::chapter_segments::ChapterSegment;
+use crate::{config::SkipSegmentCategory, downloader::chapter_segments::ChapterSegment};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(default)]
@@ -39,6 +39,24 @@ impl SkipSegment {
         }
     }
 
+    pub fn is_in_categories(&self, categories: &[SkipSegmentCategory]) -> bool {
+        categories
+            .iter()
+            .any(|category| category.as_str() == self.category)
+    }
+
+    /// 片段的开始和结束时间(毫秒)
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn time_range_ms(&self) -> Option<(u64, u64)> {
+        let [start, end, ..] = self.segment[..] else {
+            return None; // 确保 segment 包含开始和结束时间
+        };
+        Some((
+            (start.max(0.0) * 1000.0) as u64,
+            (end.max(0.0) * 1000.0) as u64,
+        ))
+    }
+
     #[allow(clippy::cast_possible_truncation)]
     pub fn into_chapter_segment(self) -> Option<ChapterSegment> {
         if self.segment.len() < 2 {
@@ -0,0 +1,159 @@
+use eyre::WrapErr;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::user_video_info::EpInUserVideo;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct Subscription {
+    pub mid: i64,
+    /// 检查过投稿后才会知道UP主的名字
+    pub up_name: String,
+    pub enabled: bool,
+    pub filter: SubscriptionFilter,
+    /// 为`true`时，下次检查只把现有的投稿记为已见，不创建下载任务
+    pub skip_existing: bool,
+    pub create_ts: i64,
+    pub last_check_ts: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct SubscriptionFilter {
+    /// 只下载在这个时间之后发布的视频，Unix时间戳(秒)
+    pub min_pub_ts: Option<i64>,
+    pub min_duration_sec: Option<u64>,
+    pub max_duration_sec: Option<u64>,
+    /// 标题必须包含的关键词，不区分大小写
+    pub title_keyword: Option<String>,
+    /// 标题必须匹配的正则表达式
+    pub title_regex: Option<String>,
+}
+
+impl SubscriptionFilter {
+    /// 编译`title_regex`，正则表达式不合法时返回错误
+    pub fn compile_title_regex(&self) -> eyre::Result<Option<Regex>> {
+        let Some(title_regex) = &self.title_regex else {
+            return Ok(None);
+        };
+        let title_regex =
+            Regex::new(title_regex).wrap_err(format!("正则表达式`{title_regex}`不合法"))?;
+        Ok(Some(title_regex))
+    }
+
+    /// `title_regex`是`compile_title_regex`的结果
+    pub fn matches(&self, video: &EpInUserVideo, title_regex: Option<&Regex>) -> bool {
+        if let Some(min_pub_ts) = self.min_pub_ts
+            && video.created < min_pub_ts
+        {
+            return false;
+        }
+
+        if self.min_duration_sec.is_some() || self.max_duration_sec.is_some() {
+            // 时长未知时无法判断，当作不满足
+            let Some(duration_sec) = parse_length(&video.length) else {
+                return false;
+            };
+            if self.min_duration_sec.is_some_and(|min| duration_sec < min)
+                || self.max_duration_sec.is_some_and(|max| duration_sec > max)
+            {
+                return false;
+            }
+        }
+
+        if let Some(title_keyword) = &self.title_keyword
+            && !video
+                .title
+                .to_lowercase()
+                .contains(&title_keyword.to_lowercase())
+        {
+            return false;
+        }
+
+        title_regex.is_none_or(|title_regex| title_regex.is_match(&video.title))
+    }
+}
+
+/// 将`mm:ss`或`hh:mm:ss`格式的时长转换为秒数
+fn parse_length(length: &str) -> Option<u64> {
+    length
+        .split(':')
+        .try_fold(0, |acc, part| Some(acc * 60 + part.parse::<u64>().ok()?))
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct AddSubscriptionParams {
+    pub mid: i64,
+    pub filter: SubscriptionFilter,
+    /// 为`true`时不下载订阅前已经发布的视频
+    pub skip_existing: bool,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct UpdateSubscriptionParams {
+    pub mid: i64,
+    pub enabled: bool,
+    pub filter: SubscriptionFilter,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(title: &str, created: i64, length: &str) -> EpInUserVideo {
+        EpInUserVideo {
+            title: title.to_string(),
+            created,
+            length: length.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_length_supports_minutes_and_hours() {
+        assert_eq!(parse_length("03:25"), Some(205));
+        assert_eq!(parse_length("1:02:03"), Some(3723));
+        assert_eq!(parse_length(""), None);
+        assert_eq!(parse_length("ab:cd"), None);
+    }
+
+    #[test]
+    fn filter_checks_every_condition() {
+        let filter = SubscriptionFilter {
+            min_pub_ts: Some(1000),
+            min_duration_sec: Some(60),
+            max_duration_sec: Some(600),
+            title_keyword: Some("Rust".to_string()),
+            title_regex: Some(r"第\d+期".to_string()),
+        };
+        let title_regex = filter.compile_title_regex().unwrap();
+        let matches = |video: &EpInUserVideo| filter.matches(video, title_regex.as_ref());
+
+        assert!(matches(&video("rust教程 第3期", 1000, "05:00")));
+        assert!(!matches(&video("rust教程 第3期", 999, "05:00")));
+        assert!(!matches(&video("rust教程 第3期", 1000, "00:59")));
+        assert!(!matches(&video("rust教程 第3期", 1000, "10:01")));
+        assert!(!matches(&video("rust教程 第3期", 1000, "")));
+        assert!(!matches(&video("go教程 第3期", 1000, "05:00")));
+        assert!(!matches(&video("rust教程 番外", 1000, "05:00")));
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = SubscriptionFilter::default();
+        assert!(filter.matches(&video("任意标题", 0, ""), None));
+    }
+
+    #[test]
+    fn invalid_title_regex_is_rejected() {
+        let filter = SubscriptionFilter {
+            title_regex: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.compile_title_regex().is_err());
+    }
+}
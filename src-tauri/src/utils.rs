@@ -144,7 +144,7 @@ impl ToXml for Vec<DmSegMobileReply> {
             .flat_map(|reply| &reply.elems)
             .map(|elem| DamakuXmlDTag {
                 p: format!(
-                    "{},{},{},{},{},{},{},{}",
+                    "{},{},{},{},{},{},{},{},{}",
                     elem.progress / 1000,
                     elem.mode,
                     elem.fontsize,
@@ -153,6 +153,7 @@ impl ToXml for Vec<DmSegMobileReply> {
                     elem.pool,
                     elem.mid_hash.clone(),
                     elem.id_str.clone(),
+                    elem.weight,
                 ),
                 body: Some(elem.content.clone()),
             })
@@ -180,6 +181,11 @@ pub fn seconds_to_srt_time(seconds: f64) -> String {
     format!("{h:02}:{m:02}:{s:02},{ms:03}")
 }
 
+/// WebVTT的时间格式与SRT相同，只是毫秒前用`.`
+pub fn seconds_to_vtt_time(seconds: f64) -> String {
+    seconds_to_srt_time(seconds).replace(',', ".")
+}
+
 #[instrument(level = "error", skip_all)]
 pub fn get_ffmpeg_program() -> eyre::Result<PathBuf> {
     let ffmpeg_program = std::env::current_exe()
@@ -119,6 +119,9 @@ async resumeDownloadTasks(taskIds: string[]) : Promise<void> {
 async deleteDownloadTasks(taskIds: string[]) : Promise<void> {
     await TAURI_INVOKE("delete_download_tasks", { taskIds });
 },
+async setDownloadTasksSpeedLimit(taskIds: string[], speedLimitKbPerSec: number) : Promise<void> {
+    await TAURI_INVOKE("set_download_tasks_speed_limit", { taskIds, speedLimitKbPerSec });
+},
 async restartDownloadTasks(taskIds: string[]) : Promise<void> {
     await TAURI_INVOKE("restart_download_tasks", { taskIds });
 },
@@ -141,6 +144,9 @@ async search(params: SearchParams) : Promise<Result<SearchResult, CommandError>>
     else return { status: "error", error: e  as any };
 }
 },
+async parseLinks(text: string) : Promise<ParsedTarget[]> {
+    return await TAURI_INVOKE("parse_links", { text });
+},
 async getLogsDirSize() : Promise<Result<number, CommandError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_logs_dir_size") };
@@ -165,6 +171,14 @@ async getSkipSegments(bvid: string, cid: number | null) : Promise<Result<SkipSeg
     else return { status: "error", error: e  as any };
 }
 },
+async getDanmakuHistoryDates(cid: number, month: string) : Promise<Result<string[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_danmaku_history_dates", { cid, month }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getAvailableMediaFormats(params: GetAvailableMediaFormatsParams) : Promise<Result<AvailableMediaFormats, CommandError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_available_media_formats", { params }) };
@@ -212,6 +226,91 @@ async setPluginPriority(pluginPath: string, priority: number) : Promise<Result<n
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async getSubscriptions() : Promise<Subscription[]> {
+    return await TAURI_INVOKE("get_subscriptions");
+},
+async addSubscription(params: AddSubscriptionParams) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_subscription", { params }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async updateSubscription(params: UpdateSubscriptionParams) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_subscription", { params }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeSubscription(mid: number) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_subscription", { mid }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async checkSubscriptions() : Promise<void> {
+    await TAURI_INVOKE("check_subscriptions");
+},
+async getMirrorSyncs() : Promise<MirrorSync[]> {
+    return await TAURI_INVOKE("get_mirror_syncs");
+},
+async getArchivedItems(source: MirrorSource) : Promise<ArchivedItem[]> {
+    return await TAURI_INVOKE("get_archived_items", { source });
+},
+async addMirrorSync(params: AddMirrorSyncParams) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_mirror_sync", { params }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async updateMirrorSync(params: UpdateMirrorSyncParams) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_mirror_sync", { params }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeMirrorSync(source: MirrorSource) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_mirror_sync", { source }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async syncMirrors() : Promise<void> {
+    await TAURI_INVOKE("sync_mirrors");
+},
+async queryDownloadHistory(params: QueryDownloadHistoryParams) : Promise<DownloadHistoryQueryResult> {
+    return await TAURI_INVOKE("query_download_history", { params });
+},
+async getLiveRecordings() : Promise<LiveRecording[]> {
+    return await TAURI_INVOKE("get_live_recordings");
+},
+async startLiveRecording(room: string) : Promise<Result<LiveRecording, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_live_recording", { room }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async stopLiveRecording(roomId: number) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("stop_live_recording", { roomId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -236,12 +335,40 @@ pluginEvent: "plugin-event"
 
 export type AbtestInfo = { style_abtest: number }
 export type Activity = { head_bg_url: string; id: number; title: string }
+export type AddMirrorSyncParams = { source: MirrorSource; removed_item_action: RemovedItemAction }
+export type AddSubscriptionParams = { mid: number; filter: SubscriptionFilter; 
+/**
+ * 为`true`时不下载订阅前已经发布的视频
+ */
+skip_existing: boolean }
 export type Arc = { aid: number; videos: number; type_id: number; type_name: string; copyright: number; pic: string; title: string; pubdate: number; ctime: number; desc: string; state: number; duration: number; rights: RightsInNormalEp; author: Author; stat: StatInNormalEp; dynamic: string; dimension: Dimension; is_chargeable_season: boolean; is_blooper: boolean; enable_vt: number; vt_display: string; type_id_v2: number; type_name_v2: string; is_lesson_video: number }
+/**
+ * 已经创建过下载任务的`bvid`/`cid`
+ */
+export type ArchivedItem = { bvid: string; cid: number; title: string; 
+/**
+ * 因为重复而跳过下载时为`None`，这时本地文件不是这个同步下载的，移除时不会删除
+ */
+task_id: string | null; episode_dir: string; filename: string; archive_ts: number; 
+/**
+ * 从收藏夹或稍后再看中移除的时间，只在`RemovedItemAction::Record`时记录
+ */
+removed_ts: number | null }
 export type AreaInBangumi = { id: number; name: string }
 export type AreaInBangumiFollow = { id: number; name: string }
 export type ArgueInfo = { argue_msg: string; argue_type: number; argue_link: string }
+export type AudioLibraryFormat = 
+/**
+ * 不转码，FLAC输出为`.flac`，其他输出为`.m4a`
+ */
+"Original" | 
+/**
+ * 转码为`.mp3`，需要带有`libmp3lame`编码器的`FFmpeg`
+ */
+"Mp3"
+export type AudioLibraryTask = { selected: boolean; format: AudioLibraryFormat; completed: boolean; skipped: boolean }
 export type AudioQuality = "Unknown" | "64K" | "132K" | "192K" | "Dolby" | "HiRes"
-export type AudioTask = { selected: boolean; url: string; audio_quality: AudioQuality; content_length: number; chunks: MediaChunk[]; completed: boolean; skipped: boolean }
+export type AudioTask = { selected: boolean; url: string; mirrors: MediaMirror[]; audio_quality: AudioQuality; content_length: number; chunks: MediaChunk[]; completed: boolean; skipped: boolean }
 export type Author = { mid: number; name: string; face: string }
 export type AvailableMediaFormats = { video_qualities_and_codec_types: VideoQualityAndCodecType[]; audio_qualities: AudioQuality[] }
 export type BadgeInfoInBangumi = { bg_color: string; bg_color_night: string; text: string }
@@ -256,6 +383,10 @@ export type CanvasConfig = {
  * 弹幕在屏幕上的【持续时间】，单位为秒，可以有小数
  */
 duration: number; 
+/**
+ * 顶部、底部弹幕在屏幕上的【持续时间】，单位为秒，可以有小数
+ */
+fixed_duration: number; 
 /**
  * 渲染的屏幕分辨率，这个并不会影响渲染区域的大小，只是字体的相对大小，可以不用改动
  */
@@ -264,6 +395,11 @@ width: number;
  * 渲染的屏幕分辨率，这个并不会影响渲染区域的大小，只是字体的相对大小，可以不用改动
  */
 height: number; 
+/**
+ * 按视频的实际分辨率调整画布，字体、行高、间距和描边按视频短边与`width`、`height`短边的比例缩放，
+ * 此时上面的分辨率和各项尺寸只作为参考值
+ */
+auto_resize: boolean; 
 /**
  * 使用字体名称
  */
@@ -290,6 +426,14 @@ lane_size: number;
  * 【正常弹幕的屏幕填充占比】，默认为 50%，即“半屏填充”。
  */
 float_percentage: number; 
+/**
+ * 屏幕上顶部弹幕最多高度百分比
+ */
+top_percentage: number; 
+/**
+ * 屏幕上底部弹幕最多高度百分比
+ */
+bottom_percentage: number; 
 /**
  * 弹幕的不透明度，越小越透明，越大越不透明
  */
@@ -312,7 +456,120 @@ export type CntInfo = { collect: number; play: number; thumb_up: number; share:
 export type CntInfoInMedia = { collect: number; play: number; danmaku: number; vt: number; play_switch: number; reply: number; view_text_1: string }
 export type CodecType = "Unknown" | "Audio" | "AVC" | "HEVC" | "AV1"
 export type CommandError = { err_title: string; message: string }
-export type Config = { download_dir: string; enable_file_logger: boolean; sessdata: string; video_quality_priority: VideoQuality[]; codec_type_priority: CodecType[]; audio_quality_priority: AudioQuality[]; download_video: boolean; download_audio: boolean; auto_merge: boolean; embed_chapter: boolean; embed_skip: boolean; download_xml_danmaku: boolean; download_ass_danmaku: boolean; download_json_danmaku: boolean; download_subtitle: boolean; download_cover: boolean; download_nfo: boolean; download_json: boolean; dir_fmt: string; dir_fmt_for_part: string; time_fmt: string; proxy_mode: ProxyMode; proxy_host: string; proxy_port: number; task_concurrency: number; task_download_interval_sec: number; chunk_concurrency: number; chunk_download_interval_sec: number; danmaku_config: CanvasConfig; file_exist_action: FileExistAction; auto_start_download_task: boolean }
+export type Config = { download_dir: string; enable_file_logger: boolean; sessdata: string; video_quality_priority: VideoQuality[]; codec_type_priority: CodecType[]; audio_quality_priority: AudioQuality[]; download_video: boolean; download_audio: boolean; auto_merge: boolean; embed_chapter: boolean; embed_skip: boolean; 
+/**
+ * 根据弹幕密度找出高光片段，作为章节嵌入
+ */
+embed_highlight: boolean; download_xml_danmaku: boolean; download_ass_danmaku: boolean; download_json_danmaku: boolean; download_vtt_danmaku: boolean; download_srt_danmaku: boolean; 
+/**
+ * 导出包含时间、类型、颜色、发送者哈希和内容的CSV，方便分析
+ */
+download_csv_danmaku: boolean; 
+/**
+ * 导出每秒弹幕数、关键词爆发和高光片段的JSON
+ */
+download_danmaku_heatmap: boolean; 
+/**
+ * 历史弹幕会与实时弹幕按`id_str`去重合并，需要登录
+ */
+history_danmaku_mode: HistoryDanmakuMode; 
+/**
+ * 格式为`YYYY-MM-DD`，为空时从视频发布的那天开始
+ */
+history_danmaku_start_date: string; 
+/**
+ * 格式为`YYYY-MM-DD`，为空时到今天为止
+ */
+history_danmaku_end_date: string; 
+/**
+ * 获取历史弹幕时每次请求之间休息多久，包括查询每个月哪些天有历史弹幕，避免请求太频繁被风控
+ */
+history_danmaku_interval_sec: number; download_subtitle: boolean; 
+/**
+ * 字幕保存为哪些格式
+ */
+subtitle_formats: SubtitleOutputFormat[]; 
+/**
+ * 只保存这些语言的字幕，如`zh-CN`、`en-US`、`ai-zh`，为空时保存全部
+ */
+subtitle_languages: string[]; 
+/**
+ * 不保存语言代码以`ai-`开头的AI字幕
+ */
+exclude_ai_subtitle: boolean; 
+/**
+ * 双语字幕上面一行的语言，与`bilingual_subtitle_secondary`都不为空时，
+ * 把两种语言合并为`{filename}.{上}+{下}.srt`和`.ass`，只生成`subtitle_formats`中选了的格式
+ */
+bilingual_subtitle_primary: string; 
+/**
+ * 双语字幕下面一行的语言
+ */
+bilingual_subtitle_secondary: string; download_cover: boolean; download_nfo: boolean; download_json: boolean; dir_fmt: string; dir_fmt_for_part: string; time_fmt: string; proxy_mode: ProxyMode; proxy_host: string; proxy_port: number; task_concurrency: number; task_download_interval_sec: number; chunk_concurrency: number; chunk_download_interval_sec: number; speed_limit_kb_per_sec: number; danmaku_config: CanvasConfig; 
+/**
+ * 生成ASS弹幕前的屏蔽规则
+ */
+danmaku_filter: DanmakuFilter; 
+/**
+ * 弹幕热度和高光片段的统计参数
+ */
+danmaku_analysis: DanmakuAnalysisConfig; file_exist_action: FileExistAction; auto_start_download_task: boolean; remote_api_enabled: boolean; 
+/**
+ * 远程API监听的地址，例如`127.0.0.1:5006`，局域网访问需要改为`0.0.0.0:5006`
+ */
+remote_api_bind_address: string; 
+/**
+ * 访问令牌，请求时放在`Authorization`头或`token`查询参数中
+ */
+remote_api_token: string; 
+/**
+ * 允许跨域访问远程API的网页来源，如`https://www.bilibili.com`，为空时不允许任何网页跨域访问
+ */
+remote_api_allowed_origins: string[]; 
+/**
+ * 检查订阅的间隔(分钟)，为0时不自动检查
+ */
+subscription_check_interval_min: number; 
+/**
+ * 同步收藏夹和稍后再看的间隔(分钟)，为0时不自动同步
+ */
+mirror_sync_interval_min: number; 
+/**
+ * 创建下载任务时，如果下载历史中已经有相同的下载该怎么办
+ */
+duplicate_download_action: DuplicateDownloadAction; 
+/**
+ * 录制直播时优先使用的直播流格式，没有时会使用另一种
+ */
+live_stream_format: LiveStreamFormat; 
+/**
+ * 录制的文件超过这个大小(MB)就分段，为0时不按大小分段
+ */
+live_split_size_mb: number; 
+/**
+ * 录制的文件超过这个时长(分钟)就分段，为0时不按时长分段
+ */
+live_split_duration_min: number; 
+/**
+ * 视频处理后输出的容器格式，选MKV时字幕、弹幕和封面也会封装进去
+ */
+output_container: OutputContainer; 
+/**
+ * 音频库模式，只下载音频，并写入标题、UP主、合集、序号、日期、简介和封面等标签
+ */
+audio_library_mode: boolean; 
+/**
+ * 音频库模式输出的格式
+ */
+audio_library_format: AudioLibraryFormat; 
+/**
+ * 从视频中剪掉这些类型的空降助手片段，为空时不剪切
+ */
+cut_skip_categories: SkipSegmentCategory[]; 
+/**
+ * 空降助手的服务器地址
+ */
+skip_segment_server: string }
 export type Consulting = { consulting_flag: boolean; consulting_url: string }
 export type ContentAttr = { text: string; bg_color: string; bg_color_night: string; img: string; multi_img: MultiImg }
 export type ContentList = { bold: boolean; content: string; number: string }
@@ -322,19 +579,124 @@ export type CreateBangumiDownloadTaskParams = { ep_ids: number[]; info: BangumiI
 export type CreateCheeseDownloadTaskParams = { ep_ids: number[]; info: CheeseInfo }
 export type CreateDownloadTaskParams = { Normal: CreateNormalDownloadTaskParams } | { Bangumi: CreateBangumiDownloadTaskParams } | { Cheese: CreateCheeseDownloadTaskParams }
 export type CreateNormalDownloadTaskParams = { info: NormalInfo; aid_cid_pairs: ([number, number | null])[] }
-export type DanmakuTask = { xml_selected: boolean; ass_selected: boolean; json_selected: boolean; completed: boolean; skipped: boolean }
+/**
+ * 从视频中剪掉的一段，单位是毫秒，`start`和`end`都是剪切前的时间
+ */
+export type CutRange = { start: number; end: number }
+/**
+ * 按时间排序且互不重叠的剪切范围
+ */
+export type CutRanges = CutRange[]
+export type DanmakuAnalysisConfig = { 
+/**
+ * 单独统计密度和爆发的关键词，不区分大小写
+ */
+keywords: string[]; 
+/**
+ * 统计高光和爆发时的窗口长度，单位为秒
+ */
+window_s: number; 
+/**
+ * 窗口内的弹幕数达到所有窗口平均值的多少倍才算高光
+ */
+threshold: number; 
+/**
+ * 最多找出几个高光片段，每个关键词最多找出几次爆发
+ */
+max_highlights: number }
+export type DanmakuFilter = { 
+/**
+ * 屏蔽包含这些关键词的弹幕
+ */
+keywords: string[]; 
+/**
+ * 屏蔽匹配这些正则表达式的弹幕
+ */
+regexes: string[]; 
+/**
+ * 屏蔽这些用户发送的弹幕，值为弹幕XML中的`midHash`
+ */
+blocked_mid_hashes: string[]; 
+/**
+ * 屏蔽权重低于这个值的弹幕，B站的权重范围是1~11，为0时不按权重屏蔽
+ */
+min_weight: number; 
+/**
+ * 屏蔽顶部弹幕
+ */
+block_top: boolean; 
+/**
+ * 屏蔽底部弹幕
+ */
+block_bottom: boolean; 
+/**
+ * 屏蔽滚动弹幕，包括逆向弹幕
+ */
+block_scroll: boolean; 
+/**
+ * 屏蔽不是白色的弹幕
+ */
+block_colored: boolean; 
+/**
+ * 把这么多秒内内容相同的弹幕合并为一条，并在后面加上`×N`，为0时不合并
+ */
+dedup_window_s: number }
+export type DanmakuTask = { xml_selected: boolean; ass_selected: boolean; json_selected: boolean; vtt_selected: boolean; srt_selected: boolean; csv_selected: boolean; heatmap_selected: boolean; history_mode: HistoryDanmakuMode; history_start_date: string; history_end_date: string; completed: boolean; skipped: boolean }
 export type DescV2 = { raw_text: string; type: number; biz_id: number }
 export type DeviceType = "All" | "PC" | "Mobile" | "Pad" | "TV"
 export type Dimension = { width: number; height: number; rotate: number }
 export type DimensionInBangumi = { height: number; rotate: number; width: number }
 export type DimensionInWatchLater = { width: number; height: number; rotate: number }
 export type DownloadEvent = { event: "Speed"; data: { speed: string } } | { event: "TaskCreate"; data: { state: DownloadTaskState; progress: DownloadProgress } } | { event: "TaskStateUpdate"; data: { task_id: string; state: DownloadTaskState } } | { event: "TaskSleeping"; data: { task_id: string; remaining_sec: number } } | { event: "TaskDelete"; data: { task_id: string } } | { event: "ProgressPreparing"; data: { task_id: string } } | { event: "ProgressUpdate"; data: { progress: DownloadProgress } }
-export type DownloadProgress = { task_id: string; episode_type: EpisodeType; aid: number; bvid: string | null; cid: number; ep_id: number | null; duration: number; pub_ts: number; collection_title: string; part_title: string | null; part_order: number | null; episode_title: string; episode_order: number; up_name: string | null; up_uid: number | null; up_avatar: string | null; episode_dir: string; filename: string; video_task: VideoTask; audio_task: AudioTask; video_process_task: VideoProcessTask; subtitle_task: SubtitleTask; danmaku_task: DanmakuTask; cover_task: CoverTask; nfo_task: NfoTask; json_task: JsonTask; create_ts: number; completed_ts: number | null; is_drm: boolean; is_preview: boolean }
+export type DownloadHistoryQueryResult = { 
+/**
+ * 满足条件的记录总数
+ */
+total: number; 
+/**
+ * 按完成时间从新到旧排列
+ */
+records: DownloadHistoryRecord[] }
+/**
+ * 一条下载完成的记录
+ */
+export type DownloadHistoryRecord = { task_id: string; episode_type: EpisodeType; aid: number; bvid: string | null; cid: number; ep_id: number | null; collection_title: string; episode_title: string; up_name: string | null; 
+/**
+ * 没有下载视频时为`None`
+ */
+video_quality: VideoQuality | null; codec_type: CodecType | null; 
+/**
+ * 没有下载音频时为`None`
+ */
+audio_quality: AudioQuality | null; outputs: DownloadOutputs; 
+/**
+ * 下载时的画质、编码和音质优先级，优先级变了就不算重复的下载
+ */
+quality_priority: QualityPriority; output_paths: string[]; completed_ts: number }
+/**
+ * 下载任务选择了哪些输出，以及影响输出内容的选项
+ */
+export type DownloadOutputs = { video: boolean; audio: boolean; merge: boolean; embed_chapter: boolean; embed_skip: boolean; embed_highlight: boolean; xml_danmaku: boolean; ass_danmaku: boolean; json_danmaku: boolean; vtt_danmaku: boolean; srt_danmaku: boolean; csv_danmaku: boolean; danmaku_heatmap: boolean; subtitle: boolean; cover: boolean; nfo: boolean; json: boolean; output_container: OutputContainer; audio_library: boolean; audio_library_format: AudioLibraryFormat; cut_skip_categories: SkipSegmentCategory[] }
+export type DownloadProgress = { task_id: string; episode_type: EpisodeType; aid: number; bvid: string | null; cid: number; ep_id: number | null; duration: number; pub_ts: number; collection_title: string; part_title: string | null; part_order: number | null; episode_title: string; episode_order: number; up_name: string | null; up_uid: number | null; up_avatar: string | null; episode_dir: string; filename: string; video_task: VideoTask; audio_task: AudioTask; video_process_task: VideoProcessTask; audio_library_task: AudioLibraryTask; subtitle_task: SubtitleTask; danmaku_task: DanmakuTask; cover_task: CoverTask; nfo_task: NfoTask; json_task: JsonTask; create_ts: number; completed_ts: number | null; is_drm: boolean; is_preview: boolean; speed_limit_kb_per_sec: number }
 export type DownloadTaskState = "Pending" | "Downloading" | "Paused" | "Completed" | "Failed"
+export type DuplicateDownloadAction = 
+/**
+ * 照常创建下载任务，但在日志中警告
+ */
+"Warn" | 
+/**
+ * 不创建下载任务
+ */
+"Skip" | 
+/**
+ * 照常创建下载任务
+ */
+"Redownload"
 export type Ed = { end: number; start: number }
 export type EpInBangumi = { aid: number; badge: string; badge_info: BadgeInfoInBangumi; badge_type: number | null; bvid: string | null; cid: number; cover: string; dimension: DimensionInBangumi | null; duration: number | null; enable_vt: boolean; ep_id: number; from: string | null; id: number; is_view_hide: boolean; link: string; link_type: string | null; long_title: string | null; pub_time: number; pv: number; release_date: string | null; rights: RightsInBangumiEp | null; section_type: number; share_copy: string | null; share_url: string | null; short_link: string | null; showDrmLoginDialog: boolean; show_title: string | null; skip: Skip | null; status: number; subtitle: string | null; title: string; vid: string | null; icon_font: IconFont | null }
 export type EpInBangumiFollow = { season_id: number; media_id: number; season_type: number; season_type_name: string; title: string; cover: string; total_count: number; is_finish: number; is_started: number; is_play: number; badge: string; badge_type: number; rights: RightsInBangumiFollow; stat: StatInBangumiFollow; new_ep: NewEpInBangumiFollow; rating: RatingInBangumiFollow | null; square_cover: string; season_status: number; season_title: string; badge_ep: string; media_attr: number; season_attr: number; evaluate: string; areas: AreaInBangumiFollow[]; subtitle: string; first_ep: number; can_watch: number; release_date_show: string | null; series: SeriesInBangumiFollow; publish: PublishInBangumiFollow; mode: number; section: SectionInBangumiFollow[]; url: string; badge_info: BadgeInfoInBangumiFollow; renewal_time: string | null; first_ep_info: FirstEpInfo; formal_ep_count: number | null; short_url: string; badge_infos: BadgeInfos | null; season_version: string | null; horizontal_cover_16_9: string | null; horizontal_cover_16_10: string | null; subtitle_14: string | null; viewable_crowd_type: number; producers?: Producer[]; summary: string; styles?: string[]; follow_status: number; is_new: number; progress: string; both_follow: boolean; subtitle_25: string | null }
 export type EpInCheese = { aid: number; catalogue_index: number; cid: number; cover: string; duration: number; ep_status: number; episode_can_view: boolean; from: string; id: number; index: number; label: string | null; page: number; play: number; play_way: number; playable: boolean; release_date: number; show_vt: boolean; status: number; subtitle: string; title: string; watched: boolean; watchedHistory: number }
+export type EpInKeywordSearch = { id: number; cover: string; title: string; url: string; index_title: string; long_title: string }
 export type EpInNormal = { season_id: number; section_id: number; id: number; aid: number; cid: number; title: string; attribute: number; arc: Arc; page: PageInNormalEp; bvid: string; pages: PageInNormalEp[] }
 export type EpInUserVideo = { comment: number; typeid: number; play: number; pic: string; subtitle: string; description: string; copyright: string; title: string; review: number; author: string; mid: number; created: number; length: string; video_review: number; aid: number; bvid: string; hide_click: boolean; is_pay: number; is_union_video: number; is_steins_gate: number; is_live_playback: number; is_lesson_video: number; is_lesson_finished: number; lesson_update_info: string; jump_url: string; meta: MetaInUserVideo | null; is_avoided: number; season_id: number; attribute: number; is_charging_arc: boolean; elec_arc_type: number; elec_arc_badge: string; vt: number; enable_vt: number; vt_display: string; playback_position: number; is_self_view: boolean }
 export type EpPage = { next: boolean; num: number; size: number; total: number }
@@ -365,6 +727,19 @@ export type GetNormalAvailableMediaFormatsParams = { bvid: string; cid: number }
 export type GetNormalInfoParams = { Bvid: string } | { Aid: number }
 export type GetUserVideoInfoParams = { pn: number; mid: number }
 export type History = { oid: number; epid: number; bvid: string; page: number; cid: number; part: string; business: string; dt: number }
+export type HistoryDanmakuMode = 
+/**
+ * 只下载实时弹幕
+ */
+"Off" | 
+/**
+ * 下载日期范围内每一天的历史弹幕
+ */
+"DateRange" | 
+/**
+ * 下载从视频发布到今天每一天的历史弹幕，尽可能收集所有发过的弹幕
+ */
+"All"
 export type HistoryDetail = { title: string; long_title: string; cover: string; uri: string; history: History; videos: number; author_name: string; author_face: string; author_mid: number; view_at: number; progress: number; badge: string; show_title: string; duration: number; total: number; new_desc: string; is_finish: number; is_fav: number; kid: number; tag_name: string; live_status: number }
 export type HistoryInfo = { has_more: boolean; page: PageInHistory; list: HistoryDetail[] | null }
 export type Honor = { aid: number; type: number; desc: string; weekly_recommend_num: number }
@@ -374,16 +749,110 @@ export type Img = { aspect_ratio: number; url: string }
 export type Info = { id: number; fid: number; mid: number; attr: number; title: string; cover: string; upper: Upper; cover_type: number; cnt_info: CntInfo; type: number; intro: string; ctime: number; mtime: number; state: number; fav_state: number; like_state: number; media_count: number; is_top: boolean }
 export type JsonTask = { selected: boolean; completed: boolean }
 export type JsonValue = null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue }
+export type KeywordSearchDuration = "All" | "Under10Min" | "From10To30Min" | "From30To60Min" | "Over60Min"
+/**
+ * 关键词搜索的一页结果
+ */
+export type KeywordSearchInfo = { page: number; pagesize: number; numResults: number; numPages: number; items: KeywordSearchItems }
+/**
+ * 接口中的`result`字段，不同的搜索类型结构不同
+ */
+export type KeywordSearchItems = { Video: VideoInKeywordSearch[] } | { Bangumi: MediaInKeywordSearch[] } | { MediaFt: MediaInKeywordSearch[] } | { User: UserInKeywordSearch[] }
+/**
+ * 番剧和影视搜索不支持排序，会忽略这个参数
+ */
+export type KeywordSearchOrder = 
+/**
+ * 视频为综合排序，用户为默认排序
+ */
+"Default" | 
+/**
+ * 最多播放，只对视频有效
+ */
+"Click" | 
+/**
+ * 最新发布，只对视频有效
+ */
+"PubDate" | 
+/**
+ * 最多弹幕，只对视频有效
+ */
+"Danmaku" | 
+/**
+ * 最多收藏，只对视频有效
+ */
+"Stow" | 
+/**
+ * 粉丝数，只对用户有效
+ */
+"Fans" | 
+/**
+ * 等级，只对用户有效
+ */
+"Level"
+export type KeywordSearchResult = KeywordSearchInfo
+export type KeywordSearchType = "Video" | "Bangumi" | 
+/**
+ * 影视
+ */
+"MediaFt" | "User"
 export type LabelInUserInfo = { path: string; text: string; label_theme: string; text_color: string; bg_style: number; bg_color: string; border_color: string; use_img_label: boolean; img_label_uri_hans: string; img_label_uri_hant: string; img_label_uri_hans_static: string; img_label_uri_hant_static: string }
 export type LevelInfoInUserInfo = { current_level: number; current_min: number; current_exp: number }
+export type LiveRecording = { 
+/**
+ * 真实房间号，不是短号
+ */
+room_id: number; uid: number; title: string; state: LiveRecordingState; output_dir: string; 
+/**
+ * 正在写入的文件，没有在录制时为`None`
+ */
+current_file: string | null; 
+/**
+ * 已经写完的文件数，不包括`current_file`
+ */
+file_count: number; recorded_bytes: number; danmaku_count: number; start_ts: number; 
+/**
+ * 最近一次断线或获取直播流失败的原因
+ */
+last_error: string | null }
+export type LiveRecordingState = 
+/**
+ * 正在获取直播流
+ */
+"Connecting" | "Recording" | 
+/**
+ * 未开播或断线，稍后会重新连接
+ */
+"Waiting" | "Stopped"
+export type LiveStreamFormat = "Flv" | "Hls"
 export type LogEvent = { jsonRaw: string }
 export type LogLevel = "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR"
 export type LogMetadata = { timestamp: string; level: LogLevel; fields: { [key in string]: JsonValue }; target: string; filename: string; line_number: number; span?: JsonValue; spans?: LogSpan[] }
 export type LogSpan = ({ [key in string]: null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue } }) & { name: string }
-export type MediaChunk = { start: number; end: number; completed: boolean }
+export type MediaChunk = { start: number; end: number; completed: boolean; 
+/**
+ * 分段视频(durl)中分片所属的段，其余为0
+ */
+segment_index?: number }
 export type MediaInFav = { id: number; type: number; title: string; cover: string; intro: string; page: number; duration: number; upper: UpperInMedia; attr: number; cnt_info: CntInfoInMedia; link: string; ctime: number; pubtime: number; fav_time: number; bv_id: string; bvid: string; ugc: Ugc | null; media_list_link: string }
+/**
+ * 番剧和影视的搜索结果
+ */
+export type MediaInKeywordSearch = { media_id: number; season_id: number; title: string; org_title: string; cover: string; media_type: number; season_type: number; season_type_name: string; areas: string; styles: string; cv: string; staff: string; desc: string; pubtime: number; ep_size: number; url: string; index_show: string; eps: EpInKeywordSearch[]; media_score: MediaScoreInKeywordSearch | null }
 export type MediaInWatchLater = { aid: number; videos: number; tid: number; tname: string; copyright: number; pic: string; title: string; pubdate: number; ctime: number; desc: string; state: number; duration: number; redirect_url: string | null; mission_id: number | null; rights: RightsInWatchLater; owner: OwnerInWatchLater; stat: StatInWatchLater; dynamic: string; dimension: DimensionInWatchLater; short_link_v2: string; up_from_v2: number | null; first_frame: string | null; pub_location: string | null; cover43: string; tidv2: number; tnamev2: string; pid_v2: number; pid_name_v2: string; page: PageInWatchLater; count: number; cid: number; progress: number; add_at: number; bvid: string; uri: string; enable_vt: number; view_text_1: string; card_type: number; left_icon_type: number; left_text: string; right_icon_type: number; right_text: string; arc_state: number; pgc_label: string; show_up: boolean; forbid_fav: boolean; forbid_sort: boolean; season_title: string; long_title: string; index_title: string; c_source: string; season_id: number | null }
+export type MediaMirror = { url: string; success_count: number; failure_count: number; consecutive_failures: number; 
+/**
+ * 被降级的镜像只有在其他镜像都试过后才会被使用
+ */
+demoted: boolean }
+export type MediaScoreInKeywordSearch = { score: number; user_count: number }
 export type MetaInUserVideo = { id: number; title: string; cover: string; mid: number; intro: string; sign_state: number; attribute: number; stat: StatInUserVideo; ep_count: number; first_aid: number | null; ptime: number; ep_num: number }
+export type MirrorSource = { Fav: { media_list_id: number } } | "WatchLater"
+export type MirrorSync = { source: MirrorSource; 
+/**
+ * 收藏夹的标题，同步过后才会知道
+ */
+title: string; enabled: boolean; removed_item_action: RemovedItemAction; create_ts: number; last_sync_ts: number | null }
 export type MultiImg = { color: string; medium_remind: string }
 export type NewEp = { desc: string; id: number; is_new: number; title: string }
 export type NewEpInBangumiFollow = { id: number | null; index_show: string | null; cover: string | null; title: string | null; long_title: string | null; pub_time: string | null; duration: number | null }
@@ -393,7 +862,9 @@ export type NormalInfo = { bvid: string; aid: number; videos: number; tid: numbe
 export type NormalSearchResult = NormalInfo
 export type Official = { role: number; title: string; desc: string; type: number }
 export type OfficialVerify = { type: number; desc: string }
+export type OfficialVerifyInKeywordSearch = { type: number; desc: string }
 export type Op = { end: number; start: number }
+export type OutputContainer = "Mp4" | "Mkv"
 export type OwnerInNormal = { mid: number; name: string; face: string }
 export type OwnerInWatchLater = { mid: number; name: string; face: string }
 export type PageInHistory = { pn: number; total: number }
@@ -402,6 +873,14 @@ export type PageInNormalEp = { cid: number; page: number; from: string; part: st
 export type PageInUserVideo = { pn: number; ps: number; count: number }
 export type PageInWatchLater = { cid: number; page: number; from: string; part: string; duration: number; vid: string; weblink: string; dimension: DimensionInWatchLater; first_frame: string | null; ctime: number }
 export type PaidJump = { jump_url_for_app: string; url: string }
+/**
+ * 从粘贴的文本中解析出的一个搜索目标
+ */
+export type ParsedTarget = { params: SearchParams; 
+/**
+ * 链接中`?p=`指定的分P，从1开始，只有普通视频会有
+ */
+page: number | null }
 export type PayType = { allow_discount: number; allow_pack: number; allow_ticket: number; allow_time_limit: number; allow_vip_discount: number; forbid_bb: number }
 export type Payment = { bp_enough: number; desc: string; my_bp: number; pay_shade: string; price: number; price_format: string; price_unit: string; refresh_text: string; select_text: string }
 export type PaymentInBangumi = { discount: number; pay_type: PayType; price: string; promotion: string; tip: string; view_start_time: number; vip_discount: number; vip_first_promotion: string; vip_price: string; vip_promotion: string }
@@ -425,18 +904,56 @@ export type PurchaseNote = { content: string; link: string; title: string }
 export type PurchaseProtocol = { link: string; title: string }
 export type QrcodeData = { url: string; qrcode_key: string }
 export type QrcodeStatus = { url: string; sessdata: string; refresh_token: string; timestamp: number; code: number; message: string }
+/**
+ * 按优先级选择画质、编码和音质时使用的优先级
+ */
+export type QualityPriority = { video_quality: VideoQuality[]; codec_type: CodecType[]; audio_quality: AudioQuality[] }
+export type QueryDownloadHistoryParams = { 
+/**
+ * 匹配标题、UP昵称、bvid，不区分大小写
+ */
+keyword: string | null; aid: number | null; ep_id: number | null; 
+/**
+ * 从1开始
+ */
+pn: number; 
+/**
+ * 每页的记录数，为0时返回所有记录
+ */
+ps: number }
 export type RatingInBangumi = { count: number; score: number }
 export type RatingInBangumiFollow = { score: number; count: number }
 export type RecommendSeason = { cover: string; ep_count: string; id: number; season_url: string; subtitle: string; title: string; view: number }
-export type RestartDownloadTaskParams = { task_id: string; video_task_selected: boolean; audio_task_selected: boolean; merge_selected: boolean; embed_chapter_selected: boolean; embed_skip_selected: boolean; subtitle_task_selected: boolean; xml_danmaku_selected: boolean; ass_danmaku_selected: boolean; json_danmaku_selected: boolean; cover_task_selected: boolean; nfo_task_selected: boolean; json_task_selected: boolean; video_quality: VideoQuality; codec_type: CodecType; audio_quality: AudioQuality }
+/**
+ * 内容从收藏夹或稍后再看中移除后，如何处理已归档的本地文件
+ */
+export type RemovedItemAction = 
+/**
+ * 什么都不做
+ */
+"Keep" | 
+/**
+ * 保留本地文件，只记录移除时间
+ */
+"Record" | 
+/**
+ * 删除本地文件和未完成的下载任务，重新加入后会再次下载
+ */
+"Delete"
+export type RestartDownloadTaskParams = { task_id: string; video_task_selected: boolean; audio_task_selected: boolean; merge_selected: boolean; embed_chapter_selected: boolean; embed_skip_selected: boolean; embed_highlight_selected: boolean; subtitle_task_selected: boolean; xml_danmaku_selected: boolean; ass_danmaku_selected: boolean; json_danmaku_selected: boolean; vtt_danmaku_selected: boolean; srt_danmaku_selected: boolean; csv_danmaku_selected: boolean; danmaku_heatmap_selected: boolean; cover_task_selected: boolean; nfo_task_selected: boolean; json_task_selected: boolean; video_quality: VideoQuality; codec_type: CodecType; audio_quality: AudioQuality }
 export type Rights = { bp: number; elec: number; download: number; movie: number; pay: number; hd5: number; no_reprint: number; autoplay: number; ugc_pay: number; is_cooperation: number; ugc_pay_preview: number; no_background: number; clean_mode: number; is_stein_gate: number; is_360: number; no_share: number; arc_pay: number; free_watch: number }
 export type RightsInBangumi = { allow_bp: number; allow_bp_rank: number; allow_download: number; allow_review: number; area_limit: number; ban_area_show: number; can_watch: number; copyright: string; forbid_pre: number; freya_white: number; is_cover_show: number; is_preview: number; only_vip_download: number; resource: string; watch_platform: number }
 export type RightsInBangumiEp = { allow_dm: number; allow_download: number; area_limit: number }
 export type RightsInBangumiFollow = { allow_review: number | null; allow_preview: number | null; is_selection: number; selection_style: number; is_rcmd: number | null; allow_bp_rank: number | null; allow_bp: number | null; allow_download: number | null }
 export type RightsInNormalEp = { bp: number; elec: number; download: number; movie: number; pay: number; hd5: number; no_reprint: number; autoplay: number; ugc_pay: number; is_cooperation: number; ugc_pay_preview: number; arc_pay: number; free_watch: number }
 export type RightsInWatchLater = { bp: number; elec: number; download: number; movie: number; pay: number; hd5: number; no_reprint: number; autoplay: number; ugc_pay: number; is_cooperation: number; ugc_pay_preview: number; no_background: number; arc_pay: number; pay_free_watch: number }
-export type SearchParams = { Normal: GetNormalInfoParams } | { Bangumi: GetBangumiInfoParams } | { Cheese: GetCheeseInfoParams } | { UserVideo: GetUserVideoInfoParams } | { Fav: GetFavInfoParams }
-export type SearchResult = { Normal: NormalSearchResult } | { Bangumi: BangumiSearchResult } | { Cheese: CheeseSearchResult } | { UserVideo: UserVideoSearchResult } | { Fav: FavSearchResult }
+export type SearchByKeywordParams = { keyword: string; search_type: KeywordSearchType; order?: KeywordSearchOrder; 
+/**
+ * 只对视频搜索有效
+ */
+duration?: KeywordSearchDuration; pn: number }
+export type SearchParams = { Normal: GetNormalInfoParams } | { Bangumi: GetBangumiInfoParams } | { Cheese: GetCheeseInfoParams } | { UserVideo: GetUserVideoInfoParams } | { Fav: GetFavInfoParams } | { Keyword: SearchByKeywordParams }
+export type SearchResult = { Normal: NormalSearchResult } | { Bangumi: BangumiSearchResult } | { Cheese: CheeseSearchResult } | { UserVideo: UserVideoSearchResult } | { Fav: FavSearchResult } | { Keyword: KeywordSearchResult }
 export type Season = { badge: string; badge_info: BadgeInfoInBangumi; badge_type: number; cover: string; enable_vt: boolean; horizontal_cover_1610: string; horizontal_cover_169: string; icon_font: IconFont; media_id: number; new_ep: NewEpInSeason; season_id: number; season_title: string; season_type: number; stat: StatInSeason }
 export type SectionInBangumi = { attr: number; episodes: EpInBangumi[]; id: number; title: string; type: number; type2: number }
 export type SectionInBangumiFollow = { section_id: number; season_id: number; limit_group: number; watch_platform: number; copyright: string; ban_area_show: number; episode_ids: number[]; type: number | null; title: string | null; attr: number | null }
@@ -446,6 +963,26 @@ export type SeriesInBangumiFollow = { series_id: number | null; title: string |
 export type Show = { wide_screen: number }
 export type Skip = { ed: Ed; op: Op }
 export type SkipSegment = { cid: string; category: string; actionType: string; segment: number[]; UUID: string; videoDuration: number; locked: number; votes: number; description: string }
+/**
+ * 可以从视频中剪掉的空降助手片段类型
+ */
+export type SkipSegmentCategory = 
+/**
+ * 广告
+ */
+"Sponsor" | 
+/**
+ * 过场/开场动画
+ */
+"Intro" | 
+/**
+ * 鸣谢/结束画面
+ */
+"Outro" | 
+/**
+ * 无偿/自我推广
+ */
+"Selfpromo"
 export type SkipSegments = SkipSegment[]
 export type Staff = { mid: number; title: string; name: string; face: string; follower: number; label_style: number }
 export type StatInBangumi = { coins: number; danmakus: number; favorite: number; favorites: number; follow_text: string; likes: number; reply: number; share: number; views: number; vt: number }
@@ -457,26 +994,106 @@ export type StatInNormalSeason = { season_id: number; view: number; danmaku: num
 export type StatInSeason = { favorites: number; series_follow: number; views: number; vt: number }
 export type StatInUserVideo = { season_id: number; view: number; danmaku: number; reply: number; favorite: number; coin: number; share: number; like: number; mtime: number; vt: number; vv: number }
 export type StatInWatchLater = { aid: number; view: number; danmaku: number; reply: number; favorite: number; coin: number; share: number; now_rank: number; his_rank: number; like: number; dislike: number; vt: number; vv: number }
+export type Subscription = { mid: number; 
+/**
+ * 检查过投稿后才会知道UP主的名字
+ */
+up_name: string; enabled: boolean; filter: SubscriptionFilter; 
+/**
+ * 为`true`时，下次检查只把现有的投稿记为已见，不创建下载任务
+ */
+skip_existing: boolean; create_ts: number; last_check_ts: number | null }
+export type SubscriptionFilter = { 
+/**
+ * 只下载在这个时间之后发布的视频，Unix时间戳(秒)
+ */
+min_pub_ts: number | null; min_duration_sec: number | null; max_duration_sec: number | null; 
+/**
+ * 标题必须包含的关键词，不区分大小写
+ */
+title_keyword: string | null; 
+/**
+ * 标题必须匹配的正则表达式
+ */
+title_regex: string | null }
 export type SubtitleDetailInNormal = { id: number; lan: string; lan_doc: string; is_lock: boolean; subtitle_url: string; type: number; id_str: string; ai_type: number; ai_status: number }
 export type SubtitleInNormal = { allow_submit: boolean; list: SubtitleDetailInNormal[] }
-export type SubtitleTask = { selected: boolean; completed: boolean }
+export type SubtitleOutputFormat = "Srt" | 
+/**
+ * 带有B站字幕的字号、颜色和背景样式
+ */
+"Ass" | "Vtt" | 
+/**
+ * 只有开始时间，适合音乐区的歌词
+ */
+"Lrc" | 
+/**
+ * B站返回的JSON，视频被剪切过时时间轴也会调整
+ */
+"Json"
+export type SubtitleTask = { selected: boolean; formats: SubtitleOutputFormat[]; 
+/**
+ * 为空时保存全部语言
+ */
+languages: string[]; exclude_ai: boolean; bilingual_primary: string; bilingual_secondary: string; 
+/**
+ * 已经保存的字幕文件名，删除本地文件时只删除这些字幕
+ */
+saved_files: string[]; completed: boolean }
 export type Ugc = { first_cid: number }
 export type UgcSeason = { id: number; title: string; cover: string; mid: number; intro: string; sign_state: number; attribute: number; sections: SectionInNormal[]; stat: StatInNormalSeason; ep_count: number; season_type: number; is_pay_season: boolean; enable_vt: number }
 export type UpInfoInBangumi = { avatar: string; mid: number; uname: string }
 export type UpInfoInCheese = { avatar: string; brief: string; follower: number; is_follow: number; is_living: boolean; link: string; mid: number; pendant: PendantInCheese; season_count: number; uname: string }
+export type UpdateMirrorSyncParams = { source: MirrorSource; enabled: boolean; removed_item_action: RemovedItemAction }
+export type UpdateSubscriptionParams = { mid: number; enabled: boolean; filter: SubscriptionFilter }
 export type Upper = { mid: number; name: string; face: string; followed: boolean; vip_type: number; vip_statue: number }
 export type UpperInMedia = { mid: number; name: string; face: string; jump_link: string }
 export type UserGarb = { url_image_ani_cut: string }
+export type UserInKeywordSearch = { mid: number; uname: string; usign: string; fans: number; videos: number; upic: string; level: number; gender: number; is_upuser: number; is_live: number; room_id: number; official_verify: OfficialVerifyInKeywordSearch | null }
 export type UserInfo = { isLogin: boolean; email_verified: number; face: string; face_nft: number; face_nft_type: number; level_info: LevelInfoInUserInfo; mid: number; mobile_verified: number; money: number; moral: number; official: Official; officialVerify: OfficialVerify; pendant: PendantInUserInfo; scores: number; uname: string; vipDueDate: number; vipStatus: number; vipType: number; vip_pay_type: number; vip_theme_type: number; vip_label: VipLabel; vip_avatar_subscript: number; vip_nickname_color: string; vip: VipInUserInfo; wallet: Wallet | null; has_shop: boolean; shop_url: string; answer_status: number; is_senior_member: number; wbi_img: WbiImg; is_jury: boolean }
 export type UserStatusInBangumi = { area_limit: number; ban_area_show: number; follow: number; follow_status: number; login: number; pay: number; pay_pack_paid: number; sponsor: number }
 export type UserStatusInCheese = { bp: number; expire_at: number; favored: number; favored_count: number; is_expired: boolean; is_first_paid: boolean; payed: number; user_expiry_content: string }
 export type UserVideoInfo = { list: UserVideoList; page: PageInUserVideo }
 export type UserVideoList = { vlist: EpInUserVideo[] }
 export type UserVideoSearchResult = UserVideoInfo
-export type VideoProcessTask = { merge_selected: boolean; embed_chapter_selected: boolean; embed_skip_selected: boolean; completed: boolean; skipped: boolean }
+export type VideoInKeywordSearch = { aid: number; bvid: string; 
+/**
+ * 关键词会被`<em class="keyword">`包裹，解析时已经去掉
+ */
+title: string; author: string; mid: number; typename: string; arcurl: string; description: string; pic: string; play: number; video_review: number; favorites: number; review: number; like: number; danmaku: number; tag: string; pubdate: number; senddate: number; 
+/**
+ * 格式为`分:秒`，例如`12:34`
+ */
+duration: string; is_pay: number; is_union_video: number; is_charge_video: number }
+export type VideoProcessTask = { merge_selected: boolean; embed_chapter_selected: boolean; embed_skip_selected: boolean; 
+/**
+ * 根据弹幕密度找出的高光片段也作为章节嵌入
+ */
+embed_highlight_selected: boolean; output_container: OutputContainer; 
+/**
+ * 要从视频中剪掉的空降助手片段类型，为空时不剪切
+ */
+cut_skip_categories: SkipSegmentCategory[]; 
+/**
+ * 视频下载完成后根据关键帧计算出的剪切范围，弹幕和字幕也按它调整时间
+ */
+cut_ranges: CutRanges | null; completed: boolean; skipped: boolean }
 export type VideoQuality = "Unknown" | "240P" | "360P" | "480P" | "720P" | "720P60" | "1080P" | "AiRepair" | "1080P+" | "1080P60" | "4K" | "HDR" | "Dolby" | "8K"
 export type VideoQualityAndCodecType = { video_quality: VideoQuality; codec_type: CodecType }
-export type VideoTask = { selected: boolean; url: string; video_quality: VideoQuality; codec_type: CodecType; content_length: number; chunks: MediaChunk[]; completed: boolean; skipped: boolean }
+export type VideoSegment = { url: string; mirrors: MediaMirror[]; content_length: number }
+export type VideoTask = { selected: boolean; url: string; mirrors: MediaMirror[]; video_quality: VideoQuality; codec_type: CodecType; 
+/**
+ * 所选视频流的分辨率，分段视频(durl)为0
+ */
+width: number; height: number; 
+/**
+ * 分段视频(durl)为所有段的大小之和
+ */
+content_length: number; chunks: MediaChunk[]; 
+/**
+ * 旧的FLV/MP4分段视频(durl)的每一段，DASH视频为空
+ */
+segments: VideoSegment[]; completed: boolean; skipped: boolean }
 export type VipInUserInfo = { type: number; status: number; due_date: number; vip_pay_type: number; theme_type: number; label: LabelInUserInfo; avatar_subscript: number; nickname_color: string; role: number; avatar_subscript_url: string; tv_vip_status: number; tv_vip_pay_type: number; tv_due_date: number }
 export type VipLabel = { path: string; text: string; label_theme: string; text_color: string; bg_style: number; bg_color: string; border_color: string; use_img_label: boolean; img_label_uri_hans: string; img_label_uri_hant: string; img_label_uri_hans_static: string; img_label_uri_hant_static: string }
 export type VipOrPay = { text: string; bg_color: string; bg_color_night: string; img: string; multi_img: MultiImg }